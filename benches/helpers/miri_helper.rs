@@ -32,12 +32,48 @@ impl rustc_driver::Callbacks for MiriCompilerCalls<'_> {
                 let config = miri::MiriConfig {
                     validate: true,
                     stacked_borrows: true,
+                    tree_borrows: false,
+                    data_race_detector: true,
+                    weak_memory_emulation: true,
                     communicate: false,
                     ignore_leaks: false,
+                    ignore_leaks_on_immediate_exit: false,
+                    leak_suppressions: vec![],
                     excluded_env_vars: vec![],
+                    forwarded_env_vars: vec![],
+                    set_env_vars: vec![],
                     args: vec![],
                     seed: None,
-                    tracked_pointer_tag: None,
+                    tracked_pointer_tags: Default::default(),
+                    tracked_alloc_ids: Default::default(),
+                    dumped_borrow_stacks: None,
+                    stacked_borrows_exempt: vec![],
+                    raw_pointer_tagging: false,
+                    protectors: true,
+                    permissive_provenance: false,
+                    strict_provenance: false,
+                    tag_gc_interval: Some(miri::DEFAULT_TAG_GC_INTERVAL),
+                    deterministic_readdir: false,
+                    io_error_rate: 0.0,
+                    io_error_script: vec![],
+                    short_io: false,
+                    dump_threads_interval: None,
+                    atomic_stats: false,
+                    alloc_stats: false,
+                    memory_limit: None,
+                    start_time: None,
+                    clock_step: None,
+                    prefer_shims: false,
+                    progress_events: None,
+                    num_cpus: None,
+                    net: miri::NetMode::Virtual,
+                    resource_limits: vec![],
+                    nondet_input: None,
+                    shrink_nondet: None,
+                    record_schedule: None,
+                    replay_schedule: None,
+                    explore_schedules: None,
+                    live_threads: miri::LiveThreads::Warn,
                 };
                 eval_main(tcx, entry_def_id, config);
             });
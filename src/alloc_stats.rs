@@ -0,0 +1,118 @@
+//! Counts the heap allocations of the program by the backtrace they were allocated with, to be
+//! reported once the program finished. Since Miri is deterministic, this makes for a precise heap
+//! profiler for unit tests.
+//!
+//! Only allocations made while a function of the program itself runs are counted, so the ones
+//! the runtime makes before `main` starts or after it returned are left out. Of their backtraces,
+//! the frames below the outermost function of the program are left out as well, since they are
+//! the same for all allocations of a thread.
+
+use std::collections::HashMap;
+
+use rustc::ty::{layout::Size, TyCtxt};
+use rustc_hir::def_id::DefId;
+use rustc_span::Span;
+
+use crate::*;
+
+/// How many allocation sites are shown, for the ranking by count and by bytes each.
+const TOP_SITES: usize = 10;
+
+/// Where an allocation happened: the function of every frame, innermost first, with the span it
+/// was called at if the caller is a function of the program.
+type Site = Vec<(DefId, Option<Span>)>;
+
+#[derive(Debug, Default)]
+struct SiteStats {
+    allocations: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct AllocStats {
+    allocations: u64,
+    deallocations: u64,
+    live_bytes: u64,
+    peak_live_bytes: u64,
+    /// The size of every live allocation.
+    live: HashMap<AllocId, u64>,
+    sites: HashMap<Site, SiteStats>,
+}
+
+impl AllocStats {
+    pub(crate) fn record_allocation(
+        &mut self,
+        id: AllocId,
+        size: Size,
+        frames: &[FrameInfo<'_>],
+    ) {
+        let outermost = match frames.iter().rposition(|frame| frame.instance.def_id().is_local()) {
+            Some(outermost) => outermost,
+            None => return,
+        };
+        let site = (0..=outermost)
+            .map(|idx| {
+                let frame = &frames[idx];
+                // The outermost frame is called by the runtime.
+                let call_site = if idx < outermost && frames[idx + 1].instance.def_id().is_local() {
+                    Some(frame.call_site)
+                } else {
+                    None
+                };
+                (frame.instance.def_id(), call_site)
+            })
+            .collect();
+        let size = size.bytes();
+        self.allocations += 1;
+        self.live_bytes += size;
+        self.peak_live_bytes = std::cmp::max(self.peak_live_bytes, self.live_bytes);
+        self.live.insert(id, size);
+        let stats = self.sites.entry(site).or_default();
+        stats.allocations += 1;
+        stats.bytes += size;
+    }
+
+    pub(crate) fn record_deallocation(&mut self, id: AllocId) {
+        if let Some(size) = self.live.remove(&id) {
+            self.deallocations += 1;
+            self.live_bytes -= size;
+        }
+    }
+
+    /// Renders the statistics, with the sites that allocated most often and the sites that
+    /// allocated the most bytes.
+    pub fn report(&self, tcx: TyCtxt<'_>) -> String {
+        let mut report = format!(
+            "{} heap allocations, {} deallocations, {} bytes live at the peak",
+            self.allocations, self.deallocations, self.peak_live_bytes,
+        );
+        let mut sites: Vec<_> = self.sites.iter().collect();
+        // Make the order of sites with the same statistics deterministic.
+        sites.sort_by_cached_key(|(site, _)| {
+            site.iter()
+                .map(|&(def_id, span)| (tcx.def_path_str(def_id), span.map(|span| span.lo())))
+                .collect::<Vec<_>>()
+        });
+        let mut rank = |title: &str, key: &dyn Fn(&SiteStats) -> u64| {
+            // Sorting is stable, so sites that are equal by `key` stay in the order from above.
+            sites.sort_by_key(|(_, stats)| std::cmp::Reverse(key(stats)));
+            report.push_str(&format!("\ntop allocation sites by {}:", title));
+            for (site, stats) in sites.iter().take(TOP_SITES) {
+                report.push_str(&format!(
+                    "\n    {} allocations, {} bytes",
+                    stats.allocations, stats.bytes
+                ));
+                for &(def_id, span) in site.iter() {
+                    report.push_str(&format!("\n        inside `{}`", tcx.def_path_str(def_id)));
+                    if let Some(span) = span {
+                        let span = tcx.sess.source_map().span_to_string(span);
+                        report.push_str(&format!(" at {}", span));
+                    }
+                }
+            }
+        };
+        rank("count", &|stats| stats.allocations);
+        rank("bytes", &|stats| stats.bytes);
+        report
+    }
+}
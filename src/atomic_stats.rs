@@ -0,0 +1,110 @@
+//! Counts the atomic operations performed on every memory location, to be reported once the
+//! program finished. Failed compare-exchange operations are a good indicator of contention, and so
+//! are spin loops.
+
+use std::collections::BTreeMap;
+
+use rustc::ty::layout::Size;
+
+use crate::*;
+
+/// The kinds of atomic operations we distinguish.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AtomicOp {
+    Load,
+    Store,
+    /// Exchange and the arithmetic read-modify-write operations.
+    ReadModifyWrite,
+    /// A compare-exchange; `true` if the comparison succeeded.
+    CompareExchange(bool),
+}
+
+#[derive(Debug, Default)]
+struct LocationStats {
+    loads: u64,
+    stores: u64,
+    rmws: u64,
+    cas_succeeded: u64,
+    cas_failed: u64,
+}
+
+impl LocationStats {
+    fn total(&self) -> u64 {
+        self.loads + self.stores + self.rmws + self.cas_succeeded + self.cas_failed
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AtomicStats {
+    locations: BTreeMap<(AllocId, Size), LocationStats>,
+    /// How often the program hinted that it is spinning, e.g. with `spin_loop_hint`.
+    spin_loop_hints: u64,
+    /// How often a spin loop without a hint was recognized and cut short, see `crate::spin_loop`.
+    spin_loops: u64,
+}
+
+impl AtomicStats {
+    fn record(&mut self, ptr: Pointer<Tag>, op: AtomicOp) {
+        let stats = self.locations.entry((ptr.alloc_id, ptr.offset)).or_default();
+        match op {
+            AtomicOp::Load => stats.loads += 1,
+            AtomicOp::Store => stats.stores += 1,
+            AtomicOp::ReadModifyWrite => stats.rmws += 1,
+            AtomicOp::CompareExchange(true) => stats.cas_succeeded += 1,
+            AtomicOp::CompareExchange(false) => stats.cas_failed += 1,
+        }
+    }
+
+    pub(crate) fn record_spin_loop(&mut self) {
+        self.spin_loops += 1;
+    }
+
+    /// Renders the statistics, busiest location first.
+    pub fn report(&self) -> String {
+        let mut locations: Vec<_> = self.locations.iter().collect();
+        // Sorting is stable, so locations with the same number of operations stay in address order.
+        locations.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total()));
+        let mut report = format!("atomic operations on {} locations:", locations.len());
+        for ((alloc_id, offset), stats) in locations {
+            report.push_str(&format!(
+                "\n    {:?}+{:#x}: {} loads, {} stores, {} read-modify-writes, {} compare-exchanges ({} failed)",
+                alloc_id,
+                offset.bytes(),
+                stats.loads,
+                stats.stores,
+                stats.rmws,
+                stats.cas_succeeded + stats.cas_failed,
+                stats.cas_failed,
+            ));
+        }
+        if self.spin_loop_hints > 0 {
+            report.push_str(&format!("\n{} spin loop hints", self.spin_loop_hints));
+        }
+        if self.spin_loops > 0 {
+            report.push_str(&format!("\n{} spin loops without hints cut short", self.spin_loops));
+        }
+        report
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Records an atomic operation on `place`, if statistics are enabled.
+    fn record_atomic_op(&mut self, place: MPlaceTy<'tcx, Tag>, op: AtomicOp) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.machine.atomic_stats.is_some() {
+            let ptr = this.force_ptr(place.ptr)?;
+            this.machine.atomic_stats.as_mut().unwrap().record(ptr, op);
+        }
+        Ok(())
+    }
+
+    /// Counts a hint that the program is spinning in a loop, waiting for another thread. The
+    /// shims yield to the threads that did not start yet before calling this.
+    fn spin_loop_hint(&mut self) {
+        let this = self.eval_context_mut();
+        if let Some(stats) = &mut this.machine.atomic_stats {
+            stats.spin_loop_hints += 1;
+        }
+    }
+}
@@ -11,7 +11,7 @@ const XARGO_MIN_VERSION: (u32, u32, u32) = (0, 3, 19);
 const CARGO_MIRI_HELP: &str = r#"Interprets bin crates and tests in Miri
 
 Usage:
-    cargo miri [subcommand] [options] [--] [<miri opts>...] [--] [<program opts>...]
+    cargo miri [subcommand] [options] [--] [<program opts>...]
 
 Subcommands:
     run                      Run binaries (default)
@@ -24,8 +24,11 @@ Common options:
     -V, --version            Print version info and exit
 
 Other [options] are the same as `cargo check`.  Everything after the first "--" is
-passed verbatim to Miri, which will pass everything after the second "--" verbatim
-to the interpreted program.
+passed verbatim to the interpreted program (or test harness).  Flags for Miri itself
+are taken from the MIRIFLAGS environment variable.
+
+For backwards compatibility, if there is a second "--", everything between the two
+is passed to Miri, and only what comes after the second "--" to the program.
 "#;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -35,6 +38,16 @@ enum MiriCommand {
     Setup,
 }
 
+/// Returns the flags for Miri given in the `MIRIFLAGS` environment variable, separated by
+/// whitespace.
+fn miriflags() -> Vec<String> {
+    match std::env::var("MIRIFLAGS") {
+        Ok(flags) => flags.split_whitespace().map(str::to_owned).collect(),
+        Err(std::env::VarError::NotPresent) => vec![],
+        Err(std::env::VarError::NotUnicode(_)) => show_error(format!("MIRIFLAGS is not valid UTF-8")),
+    }
+}
+
 fn show_help() {
     println!("{}", CARGO_MIRI_HELP);
 }
@@ -496,12 +509,20 @@ fn in_cargo_miri() {
             cmd.arg(arg);
         }
 
-        // Serialize the remaining args into a special environemt variable.
+        // The remaining args are for the program, unless there is another `--`. Then, the args
+        // before it are for Miri, like the ones in `MIRIFLAGS`.
+        let user_args: Vec<String> = args.collect();
+        let mut args_vec = miriflags();
+        if !user_args.iter().any(|arg| arg == "--") {
+            args_vec.push("--".to_owned());
+        }
+        args_vec.extend(user_args);
+
+        // Serialize the args for Miri into a special environemt variable.
         // This will be read by `inside_cargo_rustc` when we go to invoke
         // our actual target crate (the binary or the test we are running).
         // Since we're using "cargo check", we have no other way of passing
         // these arguments.
-        let args_vec: Vec<String> = args.collect();
         cmd.env("MIRI_ARGS", serde_json::to_string(&args_vec).expect("failed to serialize args"));
 
         // Set `RUSTC_WRAPPER` to ourselves.  Cargo will prepend that binary to its usual invocation,
@@ -595,6 +616,12 @@ fn inside_cargo_rustc() {
     match command.status() {
         Ok(exit) =>
             if !exit.success() {
+                // Cargo only tells that "compiling" the crate failed. Make clear that the
+                // program itself failed, and not Miri.
+                if exit.code() == Some(miri::ABORT_EXIT_CODE as i32) && target_crate && is_runnable_crate() {
+                    let what = if has_arg_flag("--test") { "test failed" } else { "program failed" };
+                    eprintln!("error: {}: the interpreted program aborted", what);
+                }
                 std::process::exit(exit.code().unwrap_or(42));
             },
         Err(ref e) => panic!("error running {:?}:\n{:?}", command, e),
@@ -15,8 +15,10 @@ extern crate rustc_interface;
 extern crate rustc_metadata;
 extern crate rustc_span;
 
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::env;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use hex::FromHexError;
@@ -131,14 +133,50 @@ fn main() {
     // Parse our arguments and split them across `rustc` and `miri`.
     let mut validate = true;
     let mut stacked_borrows = true;
+    let mut tree_borrows = false;
+    let mut data_race_detector = true;
+    let mut weak_memory_emulation = true;
     let mut communicate = false;
     let mut ignore_leaks = false;
+    let mut ignore_leaks_on_immediate_exit = false;
+    let mut leak_suppressions = vec![];
+    let mut deterministic_readdir = false;
+    let mut io_error_rate = 0.0;
+    let mut io_error_script = vec![];
+    let mut short_io = false;
+    let mut dump_threads_interval: Option<u64> = None;
+    let mut atomic_stats = false;
+    let mut alloc_stats = false;
+    let mut memory_limit: Option<u64> = None;
+    let mut start_time: Option<u64> = None;
+    let mut clock_step: Option<u64> = None;
+    let mut prefer_shims = false;
+    let mut progress_events: Option<PathBuf> = None;
+    let mut num_cpus: Option<u64> = None;
+    let mut net = miri::NetMode::Virtual;
+    let mut live_threads = miri::LiveThreads::Warn;
+    let mut resource_limits = vec![];
+    let mut nondet_input: Option<Vec<u8>> = None;
+    let mut shrink_nondet: Option<PathBuf> = None;
+    let mut record_schedule: Option<PathBuf> = None;
+    let mut replay_schedule: Option<Vec<miri::ThreadId>> = None;
+    let mut explore_schedules: Option<usize> = None;
     let mut seed: Option<u64> = None;
-    let mut tracked_pointer_tag: Option<miri::PtrId> = None;
+    let mut tracked_pointer_tags: HashSet<miri::PtrId> = HashSet::new();
+    let mut tracked_alloc_ids = miri::TrackedAllocs::default();
+    let mut dumped_borrow_stacks: Option<miri::AllocId> = None;
+    let mut stacked_borrows_exempt = vec![];
+    let mut raw_pointer_tagging = false;
+    let mut protectors = true;
+    let mut permissive_provenance = false;
+    let mut strict_provenance = false;
+    let mut tag_gc_interval = Some(miri::DEFAULT_TAG_GC_INTERVAL);
     let mut rustc_args = vec![];
     let mut miri_args = vec![];
     let mut after_dashdash = false;
     let mut excluded_env_vars = vec![];
+    let mut forwarded_env_vars = vec![];
+    let mut set_env_vars = vec![];
     for arg in std::env::args() {
         if rustc_args.is_empty() {
             // Very first arg: for `rustc`.
@@ -154,12 +192,51 @@ fn main() {
                 "-Zmiri-disable-stacked-borrows" => {
                     stacked_borrows = false;
                 }
+                "-Zmiri-tree-borrows" => {
+                    tree_borrows = true;
+                }
+                "-Zmiri-no-protectors" => {
+                    protectors = false;
+                }
+                "-Zmiri-permissive-provenance" => {
+                    permissive_provenance = true;
+                }
+                "-Zmiri-strict-provenance" => {
+                    strict_provenance = true;
+                }
+                "-Zmiri-disable-data-race-detector" => {
+                    data_race_detector = false;
+                }
+                "-Zmiri-disable-weak-memory-emulation" => {
+                    weak_memory_emulation = false;
+                }
                 "-Zmiri-disable-isolation" => {
                     communicate = true;
                 }
                 "-Zmiri-ignore-leaks" => {
                     ignore_leaks = true;
                 }
+                "-Zmiri-ignore-leaks-on-_exit" => {
+                    ignore_leaks_on_immediate_exit = true;
+                }
+                "-Zmiri-deterministic-readdir" => {
+                    deterministic_readdir = true;
+                }
+                "-Zmiri-short-io" => {
+                    short_io = true;
+                }
+                "-Zmiri-atomic-stats" => {
+                    atomic_stats = true;
+                }
+                "-Zmiri-alloc-stats" => {
+                    alloc_stats = true;
+                }
+                "-Zmiri-prefer-shims" => {
+                    prefer_shims = true;
+                }
+                "-Zmiri-explore-schedules" => {
+                    explore_schedules = Some(miri::DEFAULT_MAX_DEVIATIONS);
+                }
                 "--" => {
                     after_dashdash = true;
                 }
@@ -191,19 +268,298 @@ fn main() {
                     excluded_env_vars
                         .push(arg.trim_start_matches("-Zmiri-env-exclude=").to_owned());
                 }
-                arg if arg.starts_with("-Zmiri-track-pointer-tag=") => {
-                    let id: u64 = match arg.trim_start_matches("-Zmiri-track-pointer-tag=").parse()
-                    {
-                        Ok(id) => id,
+                arg if arg.starts_with("-Zmiri-progress-events=") => {
+                    let path = arg.trim_start_matches("-Zmiri-progress-events=");
+                    if path.is_empty() {
+                        panic!("-Zmiri-progress-events requires the path of a file as the argument");
+                    }
+                    progress_events = Some(PathBuf::from(path));
+                }
+                arg if arg.starts_with("-Zmiri-args=") => {
+                    // For when the program arguments cannot be given after `--`.
+                    miri_args.extend(
+                        arg.trim_start_matches("-Zmiri-args=").split_whitespace().map(str::to_owned),
+                    );
+                }
+                arg if arg.starts_with("-Zmiri-env-forward=") => {
+                    forwarded_env_vars
+                        .push(arg.trim_start_matches("-Zmiri-env-forward=").to_owned());
+                }
+                arg if arg.starts_with("-Zmiri-env-set=") => {
+                    let var = arg.trim_start_matches("-Zmiri-env-set=");
+                    match var.find('=') {
+                        Some(idx) if idx > 0 =>
+                            set_env_vars.push((var[..idx].to_owned(), var[idx + 1..].to_owned())),
+                        _ => panic!("-Zmiri-env-set requires an argument of the form `VAR=value`"),
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-io-error-rate=") => {
+                    let rate: f64 = match arg.trim_start_matches("-Zmiri-io-error-rate=").parse() {
+                        Ok(rate) if rate >= 0.0 && rate <= 1.0 => rate,
+                        Ok(_) => panic!("-Zmiri-io-error-rate must be between `0.0` and `1.0`"),
+                        Err(err) => panic!(
+                            "-Zmiri-io-error-rate requires a `f64` between `0.0` and `1.0`: {}",
+                            err
+                        ),
+                    };
+                    io_error_rate = rate;
+                }
+                arg if arg.starts_with("-Zmiri-io-error=") => {
+                    const USAGE: &str = "-Zmiri-io-error requires an argument of the form `OP:N=ERROR`, where `N` counts the calls of `OP` from 1 and `ERROR` is the name of a `libc` error constant";
+                    let arg = arg.trim_start_matches("-Zmiri-io-error=");
+                    let (op, rest) = match arg.find(':') {
+                        Some(idx) => (&arg[..idx], &arg[idx + 1..]),
+                        None => panic!("{}", USAGE),
+                    };
+                    let (call, error) = match rest.find('=') {
+                        Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+                        None => panic!("{}", USAGE),
+                    };
+                    if !miri::SCRIPTABLE_IO_OPS.contains(&op) {
+                        panic!(
+                            "-Zmiri-io-error can only script failures of {}, not `{}`",
+                            miri::SCRIPTABLE_IO_OPS.join(", "),
+                            op
+                        );
+                    }
+                    let call: u64 = match call.parse() {
+                        Ok(call) if call > 0 => call,
+                        _ => panic!("{}", USAGE),
+                    };
+                    if error.is_empty() {
+                        panic!("{}", USAGE);
+                    }
+                    io_error_script.push(miri::ScriptedIoError {
+                        op: op.to_owned(),
+                        call,
+                        error: error.to_owned(),
+                    });
+                }
+                arg if arg.starts_with("-Zmiri-start-time=") || arg.starts_with("-Zmiri-clock-start=") => {
+                    let (flag, secs) = arg.split_at(arg.find('=').unwrap());
+                    let secs: u64 = match secs[1..].parse() {
+                        Ok(secs) => secs,
+                        Err(err) => panic!(
+                            "{} requires the number of seconds since the unix epoch as the argument: {}",
+                            flag, err
+                        ),
+                    };
+                    start_time = Some(secs);
+                }
+                arg if arg.starts_with("-Zmiri-clock-step=") => {
+                    let nanos: u64 = match arg.trim_start_matches("-Zmiri-clock-step=").parse() {
+                        Ok(nanos) => nanos,
                         Err(err) => panic!(
-                            "-Zmiri-track-pointer-tag requires a valid `u64` as the argument: {}",
+                            "-Zmiri-clock-step requires a number of nanoseconds as the argument: {}",
                             err
                         ),
                     };
-                    if let Some(id) = miri::PtrId::new(id) {
-                        tracked_pointer_tag = Some(id);
-                    } else {
-                        panic!("-Zmiri-track-pointer-tag must be a nonzero id");
+                    clock_step = Some(nanos);
+                }
+                arg if arg.starts_with("-Zmiri-dump-threads-interval=") => {
+                    let interval: u64 =
+                        match arg.trim_start_matches("-Zmiri-dump-threads-interval=").parse() {
+                            Ok(interval) => interval,
+                            Err(err) => panic!(
+                                "-Zmiri-dump-threads-interval requires a valid `u64` as the argument: {}",
+                                err
+                            ),
+                        };
+                    if interval == 0 {
+                        panic!("-Zmiri-dump-threads-interval must be nonzero");
+                    }
+                    dump_threads_interval = Some(interval);
+                }
+                arg if arg.starts_with("-Zmiri-memory-limit=") => {
+                    let limit: u64 = match arg.trim_start_matches("-Zmiri-memory-limit=").parse() {
+                        Ok(limit) => limit,
+                        Err(err) => panic!(
+                            "-Zmiri-memory-limit requires a valid `u64` as the argument: {}",
+                            err
+                        ),
+                    };
+                    memory_limit = Some(limit);
+                }
+                arg if arg.starts_with("-Zmiri-tag-gc=") => {
+                    let interval: u64 = match arg.trim_start_matches("-Zmiri-tag-gc=").parse() {
+                        Ok(interval) => interval,
+                        Err(err) => panic!(
+                            "-Zmiri-tag-gc requires a valid `u64` as the argument: {}",
+                            err
+                        ),
+                    };
+                    // Zero turns the garbage collector off.
+                    tag_gc_interval = if interval == 0 { None } else { Some(interval) };
+                }
+                arg if arg.starts_with("-Zmiri-num-cpus=") => {
+                    let cpus: u64 = match arg.trim_start_matches("-Zmiri-num-cpus=").parse() {
+                        Ok(cpus) => cpus,
+                        Err(err) => panic!(
+                            "-Zmiri-num-cpus requires a valid `u64` as the argument: {}",
+                            err
+                        ),
+                    };
+                    if cpus == 0 {
+                        panic!("-Zmiri-num-cpus must be nonzero");
+                    }
+                    num_cpus = Some(cpus);
+                }
+                arg if arg.starts_with("-Zmiri-live-threads=") => {
+                    live_threads = match arg.trim_start_matches("-Zmiri-live-threads=") {
+                        "warn" => miri::LiveThreads::Warn,
+                        "error" => miri::LiveThreads::Error,
+                        mode => panic!(
+                            "-Zmiri-live-threads requires `warn` or `error` as the argument, got `{}`",
+                            mode
+                        ),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-net=") => {
+                    net = match arg.trim_start_matches("-Zmiri-net=") {
+                        "virtual" => miri::NetMode::Virtual,
+                        "deny" => miri::NetMode::Deny,
+                        "error" => miri::NetMode::Error,
+                        mode => panic!(
+                            "-Zmiri-net requires one of `virtual`, `deny` or `error` as the argument, got `{}`",
+                            mode
+                        ),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-rlimit=") => {
+                    const USAGE: &str = "-Zmiri-rlimit requires an argument of the form `RESOURCE=soft[:hard]`, where the limits are numbers or `unlimited`";
+                    let arg = arg.trim_start_matches("-Zmiri-rlimit=");
+                    let (resource, limits) = match arg.find('=') {
+                        Some(idx) => (&arg[..idx], &arg[idx + 1..]),
+                        None => panic!("{}", USAGE),
+                    };
+                    if !miri::DEFAULT_RESOURCE_LIMITS.iter().any(|&(name, _)| name == resource) {
+                        panic!("-Zmiri-rlimit does not know the resource `{}`", resource);
+                    }
+                    let parse_limit = |limit: &str| match limit {
+                        "unlimited" => None,
+                        limit => Some(limit.parse::<u64>().unwrap_or_else(|_| panic!("{}", USAGE))),
+                    };
+                    // Like `ulimit`, set both limits if only one is given.
+                    let (soft, hard) = match limits.find(':') {
+                        Some(idx) => (parse_limit(&limits[..idx]), parse_limit(&limits[idx + 1..])),
+                        None => (parse_limit(limits), parse_limit(limits)),
+                    };
+                    if soft.unwrap_or(u64::max_value()) > hard.unwrap_or(u64::max_value()) {
+                        panic!("-Zmiri-rlimit requires the soft limit to be at most the hard limit");
+                    }
+                    resource_limits.push((resource.to_owned(), miri::ResourceLimit { soft, hard }));
+                }
+                arg if arg.starts_with("-Zmiri-leak-suppressions=") => {
+                    let path = arg.trim_start_matches("-Zmiri-leak-suppressions=");
+                    let text = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                        panic!("-Zmiri-leak-suppressions cannot read `{}`: {}", path, err)
+                    });
+                    // One crate or item path per line; `#` starts a comment.
+                    leak_suppressions.extend(
+                        text.lines()
+                            .map(|line| line.split('#').next().unwrap().trim())
+                            .filter(|line| !line.is_empty())
+                            .map(String::from),
+                    );
+                }
+                arg if arg.starts_with("-Zmiri-nondet-input=") => {
+                    let path = arg.trim_start_matches("-Zmiri-nondet-input=");
+                    let bytes = std::fs::read(path).unwrap_or_else(|err| {
+                        panic!("-Zmiri-nondet-input cannot read `{}`: {}", path, err)
+                    });
+                    nondet_input = Some(bytes);
+                }
+                arg if arg.starts_with("-Zmiri-shrink-nondet=") => {
+                    let path = arg.trim_start_matches("-Zmiri-shrink-nondet=");
+                    if path.is_empty() {
+                        panic!("-Zmiri-shrink-nondet requires the path of a directory as the argument");
+                    }
+                    shrink_nondet = Some(PathBuf::from(path));
+                }
+                arg if arg.starts_with("-Zmiri-record-schedule=") => {
+                    let path = arg.trim_start_matches("-Zmiri-record-schedule=");
+                    if path.is_empty() {
+                        panic!("-Zmiri-record-schedule requires the path of a file as the argument");
+                    }
+                    record_schedule = Some(PathBuf::from(path));
+                }
+                arg if arg.starts_with("-Zmiri-replay-schedule=") => {
+                    let path = arg.trim_start_matches("-Zmiri-replay-schedule=");
+                    let text = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                        panic!("-Zmiri-replay-schedule cannot read `{}`: {}", path, err)
+                    });
+                    let schedule = miri::parse_schedule(&text).unwrap_or_else(|err| {
+                        panic!("-Zmiri-replay-schedule cannot parse `{}`: {}", path, err)
+                    });
+                    replay_schedule = Some(schedule);
+                }
+                arg if arg.starts_with("-Zmiri-explore-schedules=") => {
+                    let max_deviations: usize =
+                        match arg.trim_start_matches("-Zmiri-explore-schedules=").parse() {
+                            Ok(max_deviations) => max_deviations,
+                            Err(err) => panic!(
+                                "-Zmiri-explore-schedules requires a valid `usize` as the bound: {}",
+                                err
+                            ),
+                        };
+                    explore_schedules = Some(max_deviations);
+                }
+                arg if arg.starts_with("-Zmiri-raw-pointer-tagging=") => {
+                    raw_pointer_tagging = match arg.trim_start_matches("-Zmiri-raw-pointer-tagging=") {
+                        "on" => true,
+                        "off" => false,
+                        mode => panic!(
+                            "-Zmiri-raw-pointer-tagging requires `on` or `off` as the argument, got `{}`",
+                            mode
+                        ),
+                    };
+                }
+                arg if arg.starts_with("-Zmiri-disable-stacked-borrows-for=") => {
+                    let paths = arg.trim_start_matches("-Zmiri-disable-stacked-borrows-for=");
+                    stacked_borrows_exempt.extend(paths.split(',').map(String::from));
+                }
+                arg if arg.starts_with("-Zmiri-track-pointer-tag=") => {
+                    for id in arg.trim_start_matches("-Zmiri-track-pointer-tag=").split(',') {
+                        let id: u64 = match id.parse() {
+                            Ok(id) => id,
+                            Err(err) => panic!(
+                                "-Zmiri-track-pointer-tag requires a comma-separated list of valid `u64` arguments: {}",
+                                err
+                            ),
+                        };
+                        if let Some(id) = miri::PtrId::new(id) {
+                            tracked_pointer_tags.insert(id);
+                        } else {
+                            panic!("-Zmiri-track-pointer-tag must only contain nonzero ids");
+                        }
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-track-alloc-id=") => {
+                    let ids = arg.trim_start_matches("-Zmiri-track-alloc-id=");
+                    if ids == "all" {
+                        tracked_alloc_ids = miri::TrackedAllocs::All;
+                    } else if let miri::TrackedAllocs::Ids(tracked) = &mut tracked_alloc_ids {
+                        for id in ids.split(',') {
+                            match id.parse() {
+                                Ok(id) => {
+                                    tracked.insert(miri::AllocId(id));
+                                }
+                                Err(err) => panic!(
+                                    "-Zmiri-track-alloc-id requires `all` or a comma-separated list of valid `u64` arguments: {}",
+                                    err
+                                ),
+                            }
+                        }
+                    }
+                }
+                arg if arg.starts_with("-Zmiri-dump-borrow-stacks=") => {
+                    let id = arg.trim_start_matches("-Zmiri-dump-borrow-stacks=");
+                    match id.parse() {
+                        Ok(id) => dumped_borrow_stacks = Some(miri::AllocId(id)),
+                        Err(err) => panic!(
+                            "-Zmiri-dump-borrow-stacks requires a valid `u64` argument: {}",
+                            err
+                        ),
                     }
                 }
                 _ => {
@@ -213,6 +569,10 @@ fn main() {
         }
     }
 
+    if permissive_provenance && strict_provenance {
+        panic!("-Zmiri-permissive-provenance and -Zmiri-strict-provenance cannot be combined");
+    }
+
     // Determine sysroot if needed.  Make sure we always call `compile_time_sysroot`
     // as that also does some sanity-checks of the environment we were built in.
     // FIXME: Ideally we'd turn a bad build env into a compile-time error, but
@@ -234,12 +594,48 @@ fn main() {
     let miri_config = miri::MiriConfig {
         validate,
         stacked_borrows,
+        tree_borrows,
+        data_race_detector,
+        weak_memory_emulation,
         communicate,
         ignore_leaks,
+        ignore_leaks_on_immediate_exit,
+        leak_suppressions,
         excluded_env_vars,
+        forwarded_env_vars,
+        set_env_vars,
         seed,
         args: miri_args,
-        tracked_pointer_tag,
+        tracked_pointer_tags,
+        tracked_alloc_ids,
+        dumped_borrow_stacks,
+        stacked_borrows_exempt,
+        raw_pointer_tagging,
+        protectors,
+        permissive_provenance,
+        strict_provenance,
+        tag_gc_interval,
+        deterministic_readdir,
+        io_error_rate,
+        io_error_script,
+        short_io,
+        dump_threads_interval,
+        atomic_stats,
+        alloc_stats,
+        memory_limit,
+        start_time,
+        clock_step,
+        prefer_shims,
+        progress_events,
+        num_cpus,
+        net,
+        resource_limits,
+        nondet_input,
+        shrink_nondet,
+        record_schedule,
+        replay_schedule,
+        explore_schedules,
+        live_threads,
     };
     rustc_driver::install_ice_hook();
     let result = rustc_driver::catch_fatal_errors(move || {
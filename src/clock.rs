@@ -0,0 +1,94 @@
+//! The clock observed by the interpreted program. With isolation disabled, this is the host's
+//! clock. Otherwise it is a virtual clock that advances by a fixed amount with every executed
+//! basic block, which makes time deterministic.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::*;
+use shims::time::system_time_to_duration;
+
+/// How much virtual time passes when executing one basic block, unless `-Zmiri-clock-step` says
+/// otherwise.
+pub const NANOSECONDS_PER_BASIC_BLOCK: u64 = 5000;
+
+/// The realtime clock of the virtual clock starts at 2020-01-01 00:00:00 UTC.
+const VIRTUAL_REALTIME_START: Duration = Duration::from_secs(1_577_836_800);
+
+/// The CPU time consumed by executing `basic_block_count` basic blocks. This uses the default rate
+/// of the virtual clock, but does not depend on isolation: CPU time is always synthetic.
+pub fn cpu_time(basic_block_count: u64) -> Duration {
+    Duration::from_nanos(basic_block_count.saturating_mul(NANOSECONDS_PER_BASIC_BLOCK))
+}
+
+#[derive(Debug)]
+pub struct Clock {
+    /// Whether to report the host's time instead of the virtual time.
+    host: bool,
+    /// The host time when the program started; the origin of the host monotonic clock.
+    host_anchor: Instant,
+    /// The virtual time that passed since the program started.
+    virtual_elapsed: Duration,
+    /// How much virtual time passes when executing one basic block.
+    step: Duration,
+    /// The time the program skipped with `miri_advance_clock` while using the host clock.
+    host_skipped: Duration,
+    /// If set, the realtime clock starts at this time since the unix epoch, even for the host
+    /// clock.
+    realtime_start: Option<Duration>,
+}
+
+impl Clock {
+    pub fn new(host: bool, realtime_start: Option<Duration>, step: Duration) -> Self {
+        Clock {
+            host,
+            host_anchor: Instant::now(),
+            virtual_elapsed: Duration::from_secs(0),
+            step,
+            host_skipped: Duration::from_secs(0),
+            realtime_start,
+        }
+    }
+
+    /// Called for every executed basic block.
+    pub fn tick(&mut self) {
+        if !self.host {
+            self.virtual_elapsed += self.step;
+        }
+    }
+
+    /// Lets `duration` pass. The virtual clock just jumps ahead instead of actually sleeping.
+    pub fn sleep(&mut self, duration: Duration) {
+        if self.host {
+            std::thread::sleep(duration);
+        } else {
+            self.virtual_elapsed += duration;
+        }
+    }
+
+    /// Makes `duration` pass instantly, even for the host clock.
+    pub fn advance(&mut self, duration: Duration) {
+        if self.host {
+            self.host_skipped += duration;
+        } else {
+            self.virtual_elapsed += duration;
+        }
+    }
+
+    /// The time elapsed since the program started. This never goes backwards.
+    pub fn monotonic(&self) -> Duration {
+        if self.host { self.host_anchor.elapsed() + self.host_skipped } else { self.virtual_elapsed }
+    }
+
+    /// The time elapsed since the unix epoch.
+    pub fn realtime<'tcx>(&self) -> InterpResult<'tcx, Duration> {
+        if let Some(start) = self.realtime_start {
+            start.checked_add(self.monotonic()).ok_or_else(|| {
+                err_unsup_format!("the realtime clock overflowed").into()
+            })
+        } else if self.host {
+            Ok(system_time_to_duration(&SystemTime::now())? + self.host_skipped)
+        } else {
+            Ok(VIRTUAL_REALTIME_START + self.virtual_elapsed)
+        }
+    }
+}
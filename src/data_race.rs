@@ -0,0 +1,932 @@
+//! A data race detector based on vector clocks.
+//!
+//! Every thread has a vector clock, which holds a timestamp for every thread: its own timestamp
+//! counts its release operations, and the timestamps of the other threads say up to which of their
+//! release operations everything happens before the current point of the thread. Every memory
+//! location remembers when it was last written non-atomically, and when each thread last read it.
+//! An access races with an earlier one if at least one of them is a non-atomic access, at least
+//! one of them writes, and the earlier one does not happen before the later one.
+//!
+//! Threads synchronize when they spawn and join threads, through the locks of
+//! `crate::shims::sync`, and through atomic operations: a release store or read-modify-write
+//! publishes the clock of the thread at the location, and an acquire load or read-modify-write
+//! joins it into the clock of its thread. Fences relay the clocks of relaxed operations.
+//!
+//! Tracking only starts once the program creates a thread, before that there is nobody to race.
+//!
+//! For the race reports, every location also remembers where the accesses it remembers happened,
+//! and every allocation where it was allocated. The interpreter loop notes the site of the next
+//! step before taking it, see `EvalContextExt::note_access_site`.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use rustc::ty::layout::Size;
+use rustc_hir::def_id::DefId;
+use rustc_span::Span;
+
+use crate::*;
+
+pub type MemoryExtra = Rc<RefCell<GlobalState>>;
+
+/// A timestamp for every thread, indexed by the thread id. Missing timestamps are 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VClock(Vec<u32>);
+
+impl VClock {
+    pub fn get(&self, thread: ThreadId) -> u32 {
+        self.0.get(thread.to_u32() as usize).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, thread: ThreadId, timestamp: u32) {
+        let idx = thread.to_u32() as usize;
+        if self.0.len() <= idx {
+            self.0.resize(idx + 1, 0);
+        }
+        self.0[idx] = timestamp;
+    }
+
+    pub fn increment(&mut self, thread: ThreadId) {
+        let timestamp = self.get(thread) + 1;
+        self.set(thread, timestamp);
+    }
+
+    /// Sets every timestamp to the later one of `self` and `other`.
+    pub fn join(&mut self, other: &VClock) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (timestamp, &other) in self.0.iter_mut().zip(&other.0) {
+            *timestamp = (*timestamp).max(other);
+        }
+    }
+
+    /// Returns the first thread whose timestamp is later than in `other`, i.e. whose events
+    /// recorded in `self` do not all happen before `other`.
+    pub fn first_later(&self, other: &VClock) -> Option<ThreadId> {
+        self.0
+            .iter()
+            .enumerate()
+            .find(|&(idx, &timestamp)| timestamp > other.get(ThreadId::from(idx as u32)))
+            .map(|(idx, _)| ThreadId::from(idx as u32))
+    }
+}
+
+/// The kinds of memory accesses that can race.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Allocation,
+    Read,
+    Write,
+    Deallocation,
+    AtomicLoad,
+    AtomicStore,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessKind::Allocation => write!(f, "allocation"),
+            AccessKind::Read => write!(f, "read"),
+            AccessKind::Write => write!(f, "write"),
+            AccessKind::Deallocation => write!(f, "deallocation"),
+            AccessKind::AtomicLoad => write!(f, "atomic load"),
+            AccessKind::AtomicStore => write!(f, "atomic store"),
+        }
+    }
+}
+
+/// The memory ordering of an atomic operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtomicOrdering {
+    Relaxed,
+    Acquire,
+    Release,
+    AcqRel,
+    SeqCst,
+}
+
+impl AtomicOrdering {
+    /// Parses the ordering in the name of an atomic intrinsic, e.g. `acq` in `atomic_load_acq`.
+    /// Intrinsics without an ordering in their name are sequentially consistent.
+    pub fn from_intrinsic_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "" => Some(AtomicOrdering::SeqCst),
+            "relaxed" => Some(AtomicOrdering::Relaxed),
+            "acq" => Some(AtomicOrdering::Acquire),
+            "rel" => Some(AtomicOrdering::Release),
+            "acqrel" => Some(AtomicOrdering::AcqRel),
+            _ => None,
+        }
+    }
+
+    /// The ordering of a failed compare-exchange whose ordering on success is `self`, if the
+    /// intrinsic does not name one.
+    pub fn failure_ordering(self) -> Self {
+        match self {
+            AtomicOrdering::Release => AtomicOrdering::Relaxed,
+            AtomicOrdering::AcqRel => AtomicOrdering::Acquire,
+            ordering => ordering,
+        }
+    }
+
+    pub fn is_acquire(self) -> bool {
+        match self {
+            AtomicOrdering::Acquire | AtomicOrdering::AcqRel | AtomicOrdering::SeqCst => true,
+            AtomicOrdering::Relaxed | AtomicOrdering::Release => false,
+        }
+    }
+
+    pub fn is_release(self) -> bool {
+        match self {
+            AtomicOrdering::Release | AtomicOrdering::AcqRel | AtomicOrdering::SeqCst => true,
+            AtomicOrdering::Relaxed | AtomicOrdering::Acquire => false,
+        }
+    }
+}
+
+/// An earlier access that a new access races with.
+type Race = (AccessKind, ThreadId);
+
+/// A frame of the backtrace of an access, see `AccessSite`.
+#[derive(Debug)]
+pub struct SiteFrame {
+    /// The function that runs in the frame.
+    pub def_id: DefId,
+    /// Where the function was called.
+    pub call_site: Span,
+    /// The frame of the caller, unless this is the first frame of its thread.
+    pub caller: Option<Rc<SiteFrame>>,
+}
+
+/// Where a memory access happened: the statement, and the innermost frame of the backtrace. The
+/// frames are shared by all the accesses that happened in them, so sites are cheap to keep.
+#[derive(Clone, Debug)]
+pub struct AccessSite {
+    pub span: Span,
+    pub frame: Option<Rc<SiteFrame>>,
+}
+
+impl PartialEq for AccessSite {
+    fn eq(&self, other: &Self) -> bool {
+        let same_frame = match (&self.frame, &other.frame) {
+            (Some(frame), Some(other)) => Rc::ptr_eq(frame, other),
+            (None, None) => true,
+            _ => false,
+        };
+        self.span == other.span && same_frame
+    }
+}
+
+impl Eq for AccessSite {}
+
+/// Where the accesses that a location remembers happened.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct AccessSites {
+    /// The last non-atomic write, deallocation or allocation.
+    write: Option<AccessSite>,
+    /// The other accesses, indexed by the thread id like the clocks.
+    read: Vec<Option<AccessSite>>,
+    atomic_load: Vec<Option<AccessSite>>,
+    atomic_store: Vec<Option<AccessSite>>,
+}
+
+impl AccessSites {
+    fn record(&mut self, kind: AccessKind, thread: ThreadId, site: Option<AccessSite>) {
+        let sites = match kind {
+            AccessKind::Allocation | AccessKind::Write | AccessKind::Deallocation => {
+                self.write = site;
+                return;
+            }
+            AccessKind::Read => &mut self.read,
+            AccessKind::AtomicLoad => &mut self.atomic_load,
+            AccessKind::AtomicStore => &mut self.atomic_store,
+        };
+        let idx = thread.to_u32() as usize;
+        if sites.len() <= idx {
+            sites.resize(idx + 1, None);
+        }
+        sites[idx] = site;
+    }
+
+    fn get(&self, (kind, thread): Race) -> Option<&AccessSite> {
+        let sites = match kind {
+            AccessKind::Allocation | AccessKind::Write | AccessKind::Deallocation => {
+                return self.write.as_ref();
+            }
+            AccessKind::Read => &self.read,
+            AccessKind::AtomicLoad => &self.atomic_load,
+            AccessKind::AtomicStore => &self.atomic_store,
+        };
+        sites.get(thread.to_u32() as usize).and_then(Option::as_ref)
+    }
+}
+
+/// The earlier access of the race that was reported last, for the diagnostics.
+#[derive(Debug)]
+pub struct RaceDetails {
+    pub kind: AccessKind,
+    /// The thread that made the access, described for the report.
+    pub thread: String,
+    pub site: Option<AccessSite>,
+    /// Where the memory was allocated, unless it is a static.
+    pub allocation: Option<AccessSite>,
+}
+
+/// What a location remembers about its atomic accesses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct AtomicCellClocks {
+    /// The timestamp of the last atomic load of every thread.
+    read: VClock,
+    /// The timestamp of the last atomic store or read-modify-write of every thread.
+    write: VClock,
+    /// The clock that an acquire load of the location joins into its thread.
+    sync: VClock,
+}
+
+/// What a location remembers about the accesses to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct MemoryCellClocks {
+    /// The thread that last wrote the location non-atomically, or allocated it, and its timestamp
+    /// at the time.
+    write: (ThreadId, u32),
+    write_kind: AccessKind,
+    /// The timestamp of the last non-atomic read of every thread since the last write.
+    read: VClock,
+    /// Only locations that were accessed atomically pay for the atomic clocks.
+    atomic: Option<Box<AtomicCellClocks>>,
+    sites: AccessSites,
+}
+
+impl MemoryCellClocks {
+    fn new(thread: ThreadId, timestamp: u32) -> Self {
+        MemoryCellClocks {
+            write: (thread, timestamp),
+            write_kind: AccessKind::Allocation,
+            read: VClock::default(),
+            atomic: None,
+            sites: AccessSites::default(),
+        }
+    }
+
+    fn check_write_happens_before(&self, clock: &VClock) -> Result<(), Race> {
+        let (thread, timestamp) = self.write;
+        if timestamp <= clock.get(thread) { Ok(()) } else { Err((self.write_kind, thread)) }
+    }
+
+    fn check_reads_happen_before(&self, clock: &VClock) -> Result<(), Race> {
+        match self.read.first_later(clock) {
+            Some(thread) => Err((AccessKind::Read, thread)),
+            None => Ok(()),
+        }
+    }
+
+    fn read(&mut self, thread: ThreadId, clock: &VClock) -> Result<(), Race> {
+        self.check_write_happens_before(clock)?;
+        if let Some(atomic) = &self.atomic {
+            if let Some(other) = atomic.write.first_later(clock) {
+                return Err((AccessKind::AtomicStore, other));
+            }
+        }
+        self.read.set(thread, clock.get(thread));
+        Ok(())
+    }
+
+    fn write(&mut self, thread: ThreadId, clock: &VClock, kind: AccessKind) -> Result<(), Race> {
+        self.check_write_happens_before(clock)?;
+        self.check_reads_happen_before(clock)?;
+        if let Some(atomic) = &self.atomic {
+            if let Some(other) = atomic.write.first_later(clock) {
+                return Err((AccessKind::AtomicStore, other));
+            }
+            if let Some(other) = atomic.read.first_later(clock) {
+                return Err((AccessKind::AtomicLoad, other));
+            }
+        }
+        // Everything we forget here happens before this write, so it cannot race with any later
+        // access that this write does not race with.
+        *self = MemoryCellClocks {
+            write: (thread, clock.get(thread)),
+            write_kind: kind,
+            read: VClock::default(),
+            atomic: None,
+            sites: AccessSites::default(),
+        };
+        Ok(())
+    }
+
+    fn atomic_load(
+        &mut self,
+        thread: ThreadId,
+        clock: &VClock,
+    ) -> Result<&mut AtomicCellClocks, Race> {
+        self.check_write_happens_before(clock)?;
+        let atomic = self.atomic.get_or_insert_with(Default::default);
+        atomic.read.set(thread, clock.get(thread));
+        Ok(atomic)
+    }
+
+    fn atomic_store(
+        &mut self,
+        thread: ThreadId,
+        clock: &VClock,
+    ) -> Result<&mut AtomicCellClocks, Race> {
+        self.check_write_happens_before(clock)?;
+        self.check_reads_happen_before(clock)?;
+        let atomic = self.atomic.get_or_insert_with(Default::default);
+        atomic.write.set(thread, clock.get(thread));
+        Ok(atomic)
+    }
+}
+
+/// The clocks of a thread.
+#[derive(Clone, Debug, Default)]
+struct ThreadClocks {
+    clock: VClock,
+    /// The clock at the last release fence, which relaxed stores publish.
+    fence_release: VClock,
+    /// The clocks published at the locations that relaxed loads read, which the next acquire
+    /// fence joins into `clock`.
+    fence_acquire: VClock,
+    /// The clocks of all threads at the sequentially consistent fences up to the last one of this
+    /// thread. Loads after that fence cannot read stores older than the ones before these fences.
+    fence_seqcst: VClock,
+    /// The timestamps of the last sequentially consistent stores of all threads at the last
+    /// sequentially consistent fence of this thread. Loads after that fence cannot read older
+    /// stores than these.
+    write_seqcst: VClock,
+}
+
+/// Extra global state, available to the memory access hooks.
+#[derive(Debug)]
+pub struct GlobalState {
+    /// Whether the program created a thread, which is when tracking starts.
+    multi_threaded: Cell<bool>,
+    /// Set while the machine accesses memory on behalf of atomic operations and synchronization
+    /// primitives, which take care of the race detector themselves.
+    ongoing_race_free_action: Cell<bool>,
+    /// A copy of the active thread of the `ThreadManager`, because the memory access hooks only
+    /// have access to the memory.
+    active_thread: ThreadId,
+    /// The clocks of every thread, indexed by the thread id.
+    threads: Vec<ThreadClocks>,
+    /// The clocks that threads published by releasing the locks at each location.
+    sync_clocks: HashMap<(AllocId, Size), VClock>,
+    /// Whether atomic loads may read older stores, see `crate::weak_memory`.
+    weak_memory: bool,
+    /// A copy of the names of the threads, for race reports.
+    thread_names: HashMap<ThreadId, Vec<u8>>,
+    /// The join of the clocks of all threads at their sequentially consistent fences so far.
+    last_sc_fence: VClock,
+    /// The timestamp of the last sequentially consistent store of every thread.
+    last_sc_write: VClock,
+    /// A copy of the frames of the interpreter stack, see `note_access_site`.
+    frames: Vec<Rc<SiteFrame>>,
+    /// Where the accesses of the current step of the interpreter happen.
+    current_site: Option<AccessSite>,
+    /// Set when a race is reported.
+    last_race: RefCell<Option<RaceDetails>>,
+}
+
+impl GlobalState {
+    pub fn new(weak_memory: bool) -> Self {
+        let mut main = ThreadClocks::default();
+        main.clock.increment(ThreadId::MAIN);
+        GlobalState {
+            multi_threaded: Cell::new(false),
+            ongoing_race_free_action: Cell::new(false),
+            active_thread: ThreadId::MAIN,
+            threads: vec![main],
+            sync_clocks: HashMap::new(),
+            weak_memory,
+            thread_names: vec![(ThreadId::MAIN, b"main".to_vec())].into_iter().collect(),
+            last_sc_fence: VClock::default(),
+            last_sc_write: VClock::default(),
+            frames: Vec::new(),
+            current_site: None,
+            last_race: RefCell::new(None),
+        }
+    }
+
+    fn active_clocks(&mut self) -> &mut ThreadClocks {
+        &mut self.threads[self.active_thread.to_u32() as usize]
+    }
+
+    pub(crate) fn active_thread(&self) -> ThreadId {
+        self.active_thread
+    }
+
+    pub(crate) fn active_clock(&self) -> &VClock {
+        &self.threads[self.active_thread.to_u32() as usize].clock
+    }
+
+    /// The clocks that limit the stores that sequentially consistent fences and loads of the
+    /// active thread can read: its `fence_seqcst` and `write_seqcst`, and `last_sc_fence`.
+    pub(crate) fn active_seq_cst_clocks(&self) -> (&VClock, &VClock, &VClock) {
+        let clocks = &self.threads[self.active_thread.to_u32() as usize];
+        (&clocks.fence_seqcst, &clocks.write_seqcst, &self.last_sc_fence)
+    }
+
+    pub(crate) fn tracking(&self) -> bool {
+        self.multi_threaded.get() && !self.ongoing_race_free_action.get()
+    }
+
+    pub fn set_active_thread(&mut self, thread: ThreadId) {
+        self.active_thread = thread;
+    }
+
+    pub fn set_thread_name(&mut self, thread: ThreadId, name: Vec<u8>) {
+        self.thread_names.insert(thread, name);
+    }
+
+    /// Called when the active thread creates `child`: everything the active thread did so far
+    /// happens before everything the child does.
+    pub fn thread_created(&mut self, child: ThreadId) {
+        self.multi_threaded.set(true);
+        let parent = self.active_thread;
+        let mut clocks = ThreadClocks { clock: self.active_clock().clone(), ..Default::default() };
+        clocks.clock.increment(child);
+        assert_eq!(self.threads.len(), child.to_u32() as usize, "threads are created in order");
+        self.threads.push(clocks);
+        self.active_clocks().clock.increment(parent);
+    }
+
+    /// Called when the active thread joins the terminated thread `joined`: everything the joined
+    /// thread did happens before everything the active thread does from now on.
+    pub fn thread_joined(&mut self, joined: ThreadId) {
+        let clock = self.threads[joined.to_u32() as usize].clock.clone();
+        self.active_clocks().clock.join(&clock);
+    }
+
+    /// Called when the active thread acquires the lock at `location`.
+    pub fn acquire_sync_clock(&mut self, location: (AllocId, Size)) {
+        if let Some(clock) = self.sync_clocks.get(&location) {
+            let clock = clock.clone();
+            self.active_clocks().clock.join(&clock);
+        }
+    }
+
+    /// Called when the active thread releases the lock at `location`. The clock of a lock is only
+    /// ever joined, so that all the readers of a read-write lock publish their clocks.
+    pub fn release_sync_clock(&mut self, location: (AllocId, Size)) {
+        let thread = self.active_thread;
+        let clock = self.active_clock().clone();
+        self.sync_clocks.entry(location).or_default().join(&clock);
+        self.active_clocks().clock.increment(thread);
+    }
+
+    /// Called on a fence of the active thread. Sequentially consistent fences are totally
+    /// ordered, which limits the stores that the atomic loads after them can read, see
+    /// `crate::weak_memory`.
+    pub fn fence(&mut self, ordering: AtomicOrdering) {
+        let thread = self.active_thread;
+        if ordering == AtomicOrdering::SeqCst {
+            let clock = self.active_clock().clone();
+            self.last_sc_fence.join(&clock);
+            let (last_sc_fence, last_sc_write) =
+                (self.last_sc_fence.clone(), self.last_sc_write.clone());
+            let clocks = self.active_clocks();
+            clocks.fence_seqcst.join(&last_sc_fence);
+            clocks.write_seqcst.join(&last_sc_write);
+        }
+        let clocks = self.active_clocks();
+        if ordering.is_acquire() {
+            let acquired = clocks.fence_acquire.clone();
+            clocks.clock.join(&acquired);
+        }
+        if ordering.is_release() {
+            clocks.fence_release = clocks.clock.clone();
+            clocks.clock.increment(thread);
+        }
+    }
+
+    /// Takes the details of the race that was reported last.
+    pub fn take_race_details(&self) -> Option<RaceDetails> {
+        self.last_race.borrow_mut().take()
+    }
+
+    /// Reports that the access of `kind` by `thread` races with the access `race` that happened
+    /// at `site`, in the allocation that was allocated at `allocation`.
+    fn report_race<'tcx>(
+        &self,
+        kind: AccessKind,
+        thread: ThreadId,
+        race: Race,
+        site: Option<&AccessSite>,
+        allocation: Option<&AccessSite>,
+    ) -> InterpResult<'tcx> {
+        let (other_kind, other_thread) = race;
+        let describe = |thread| {
+            let name = self.thread_names.get(&thread).map(Vec::as_slice);
+            crate::thread::describe_thread(thread, name)
+        };
+        *self.last_race.borrow_mut() = Some(RaceDetails {
+            kind: other_kind,
+            thread: describe(other_thread),
+            site: site.cloned(),
+            allocation: allocation.cloned(),
+        });
+        throw_ub_format!(
+            "data race detected between {} on {} and {} on {}",
+            kind,
+            describe(thread),
+            other_kind,
+            describe(other_thread),
+        )
+    }
+}
+
+/// The race detector state of an allocation.
+#[derive(Debug, Clone)]
+pub struct AllocExtra {
+    // Even reading memory records the read, so we need a `RefCell` here.
+    cells: RefCell<RangeMap<MemoryCellClocks>>,
+    // Pointer to global state
+    global: MemoryExtra,
+    /// The store buffers, if weak memory is emulated.
+    pub(crate) weak_memory: Option<crate::weak_memory::AllocExtra>,
+    /// Where the allocation was allocated, unless it is a static.
+    allocation_site: Option<AccessSite>,
+}
+
+/// Glue code to connect with Miri Machine Hooks
+impl AllocExtra {
+    pub fn new_allocation(
+        size: Size,
+        global: MemoryExtra,
+        kind: MemoryKind<MiriMemoryKind>,
+    ) -> Self {
+        let (cell, allocation_site) = match kind {
+            // Statics exist from the start, no matter which thread first accessed them.
+            MemoryKind::Machine(MiriMemoryKind::Static) =>
+                (MemoryCellClocks::new(ThreadId::MAIN, 0), None),
+            _ => {
+                let global = global.borrow();
+                let thread = global.active_thread;
+                let mut cell = MemoryCellClocks::new(thread, global.active_clock().get(thread));
+                cell.sites.write = global.current_site.clone();
+                (cell, global.current_site.clone())
+            }
+        };
+        let weak_memory =
+            if global.borrow().weak_memory { Some(Default::default()) } else { None };
+        AllocExtra {
+            cells: RefCell::new(RangeMap::new(size, cell)),
+            global,
+            weak_memory,
+            allocation_site,
+        }
+    }
+
+    fn for_each<'tcx>(
+        &self,
+        ptr: Pointer<Tag>,
+        size: Size,
+        kind: AccessKind,
+        f: impl Fn(&mut MemoryCellClocks, ThreadId, &VClock) -> Result<(), Race>,
+    ) -> InterpResult<'tcx> {
+        let global = self.global.borrow();
+        if !global.tracking() {
+            return Ok(());
+        }
+        let thread = global.active_thread;
+        let clock = global.active_clock();
+        let mut cells = self.cells.borrow_mut();
+        for cell in cells.iter_mut(ptr.offset, size) {
+            if let Err(race) = f(cell, thread, clock) {
+                let site = cell.sites.get(race);
+                return global.report_race(kind, thread, race, site, self.allocation_site.as_ref());
+            }
+            cell.sites.record(kind, thread, global.current_site.clone());
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn memory_read<'tcx>(&self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
+        self.for_each(ptr, size, AccessKind::Read, |cell, thread, clock| cell.read(thread, clock))
+    }
+
+    #[inline(always)]
+    pub fn memory_written<'tcx>(&mut self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
+        self.for_each(ptr, size, AccessKind::Write, |cell, thread, clock| {
+            cell.write(thread, clock, AccessKind::Write)
+        })?;
+        if let Some(weak_memory) = &mut self.weak_memory {
+            if self.global.borrow().tracking() {
+                weak_memory.forget(ptr.offset, size);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn memory_deallocated<'tcx>(
+        &mut self,
+        ptr: Pointer<Tag>,
+        size: Size,
+    ) -> InterpResult<'tcx> {
+        self.for_each(ptr, size, AccessKind::Deallocation, |cell, thread, clock| {
+            cell.write(thread, clock, AccessKind::Deallocation)
+        })
+    }
+}
+
+/// Checks an atomic access to `place` for races, and lets `sync` update the clocks of the active
+/// thread and the location.
+fn validate_atomic_access<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    place: MPlaceTy<'tcx, Tag>,
+    kind: AccessKind,
+    sync: impl Fn(&mut ThreadClocks, &mut AtomicCellClocks),
+) -> InterpResult<'tcx> {
+    let data_race = match &ecx.memory.extra.data_race {
+        Some(data_race) if data_race.borrow().tracking() => data_race,
+        _ => return Ok(()),
+    };
+    let ptr = ecx.force_ptr(place.ptr)?;
+    let extra = ecx.memory.get_raw(ptr.alloc_id)?.extra.data_race.as_ref();
+    let extra = extra.expect("we should have race detector data");
+    let mut global = data_race.borrow_mut();
+    let thread = global.active_thread;
+    let mut cells = extra.cells.borrow_mut();
+    for cell in cells.iter_mut(ptr.offset, place.layout.size) {
+        let clocks = global.active_clocks();
+        let atomic = match kind {
+            AccessKind::AtomicLoad => cell.atomic_load(thread, &clocks.clock),
+            _ => cell.atomic_store(thread, &clocks.clock),
+        };
+        match atomic {
+            Ok(atomic) => sync(clocks, atomic),
+            Err(race) => {
+                let site = cell.sites.get(race);
+                let allocation = extra.allocation_site.as_ref();
+                return global.report_race(kind, thread, race, site, allocation);
+            }
+        }
+        cell.sites.record(kind, thread, global.current_site.clone());
+    }
+    Ok(())
+}
+
+/// Checks an atomic load of `place` for races, and synchronizes with the clock `released` that
+/// the store it reads from published, or the latest clock of the location if `None`.
+pub(crate) fn validate_atomic_load_from<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    place: MPlaceTy<'tcx, Tag>,
+    ordering: AtomicOrdering,
+    released: Option<&VClock>,
+) -> InterpResult<'tcx> {
+    validate_atomic_access(ecx, place, AccessKind::AtomicLoad, |clocks, atomic| {
+        let released = released.unwrap_or(&atomic.sync);
+        if ordering.is_acquire() {
+            clocks.clock.join(released);
+        } else {
+            clocks.fence_acquire.join(released);
+        }
+    })
+}
+
+/// The clock that the latest atomic store to `place` published.
+pub(crate) fn atomic_sync_clock<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    place: MPlaceTy<'tcx, Tag>,
+) -> InterpResult<'tcx, VClock> {
+    let ptr = ecx.force_ptr(place.ptr)?;
+    let extra = ecx.memory.get_raw(ptr.alloc_id)?.extra.data_race.as_ref();
+    let extra = extra.expect("we should have race detector data");
+    let mut sync = VClock::default();
+    for cell in extra.cells.borrow().iter(ptr.offset, place.layout.size) {
+        if let Some(atomic) = &cell.atomic {
+            sync.join(&atomic.sync);
+        }
+    }
+    Ok(sync)
+}
+
+/// Called after an atomic store or read-modify-write. Sequentially consistent ones are recorded
+/// for later sequentially consistent fences. After a release, the accesses of the active thread
+/// do not happen before the accesses of the threads that acquire what it released anymore.
+fn atomic_write_done(ecx: &MiriEvalContext<'_, '_>, ordering: AtomicOrdering) {
+    if let Some(data_race) = &ecx.memory.extra.data_race {
+        let mut global = data_race.borrow_mut();
+        let thread = global.active_thread;
+        if ordering == AtomicOrdering::SeqCst {
+            let timestamp = global.active_clock().get(thread);
+            global.last_sc_write.set(thread, timestamp);
+        }
+        if ordering.is_release() {
+            global.active_clocks().clock.increment(thread);
+        }
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Runs `op` without checking its memory accesses for races. The atomic operations and
+    /// synchronization primitives use this for the accesses they check themselves.
+    fn allow_data_races_ref<R>(&self, op: impl FnOnce(&MiriEvalContext<'mir, 'tcx>) -> R) -> R {
+        let this = self.eval_context_ref();
+        let old = this.memory.extra.data_race.as_ref().map(|data_race| {
+            data_race.borrow().ongoing_race_free_action.replace(true)
+        });
+        let result = op(this);
+        if let (Some(data_race), Some(old)) = (&this.memory.extra.data_race, old) {
+            data_race.borrow().ongoing_race_free_action.set(old);
+        }
+        result
+    }
+
+    /// Like `allow_data_races_ref`, for operations that need mutable access.
+    fn allow_data_races_mut<R>(
+        &mut self,
+        op: impl FnOnce(&mut MiriEvalContext<'mir, 'tcx>) -> R,
+    ) -> R {
+        let this = self.eval_context_mut();
+        let old = this.memory.extra.data_race.as_ref().map(|data_race| {
+            data_race.borrow().ongoing_race_free_action.replace(true)
+        });
+        let result = op(this);
+        if let (Some(data_race), Some(old)) = (&this.memory.extra.data_race, old) {
+            data_race.borrow().ongoing_race_free_action.set(old);
+        }
+        result
+    }
+
+    /// Notes where the memory accesses of the next step happen, for the race reports. Called
+    /// before every step by `step_with_hooks`.
+    fn note_access_site(&mut self) {
+        let this = self.eval_context_mut();
+        if let Some(data_race) = &this.memory.extra.data_race {
+            let mut global = data_race.borrow_mut();
+            let stack = this.stack();
+            // Bring the copy of the stack up to date. Usually, the last step pushed or popped at
+            // most one frame, so this only looks at the top of the stack.
+            let frames = &mut global.frames;
+            frames.truncate(stack.len());
+            while let Some(frame) = frames.last() {
+                let actual = &stack[frames.len() - 1];
+                if frame.def_id == actual.instance.def_id() && frame.call_site == actual.span {
+                    break;
+                }
+                frames.pop();
+            }
+            for actual in &stack[frames.len()..] {
+                // The first frame of a thread is where its backtrace ends.
+                let caller = match actual.extra.resumes_thread {
+                    Some(_) => None,
+                    None => frames.last().cloned(),
+                };
+                let def_id = actual.instance.def_id();
+                frames.push(Rc::new(SiteFrame { def_id, call_site: actual.span, caller }));
+            }
+            global.current_site = stack.last().map(|frame| AccessSite {
+                span: frame.current_source_info().map_or(frame.span, |info| info.span),
+                frame: global.frames.last().cloned(),
+            });
+        }
+    }
+
+    /// Checks an atomic load of `place` for races, and synchronizes with the store it reads from.
+    fn validate_atomic_load(
+        &self,
+        place: MPlaceTy<'tcx, Tag>,
+        ordering: AtomicOrdering,
+    ) -> InterpResult<'tcx> {
+        validate_atomic_load_from(self.eval_context_ref(), place, ordering, None)
+    }
+
+    /// Checks an atomic store to `place` for races, and publishes the clock of the active thread
+    /// at the location if it releases.
+    fn validate_atomic_store(
+        &self,
+        place: MPlaceTy<'tcx, Tag>,
+        ordering: AtomicOrdering,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        validate_atomic_access(this, place, AccessKind::AtomicStore, |clocks, atomic| {
+            let released =
+                if ordering.is_release() { &clocks.clock } else { &clocks.fence_release };
+            atomic.sync = released.clone();
+        })?;
+        atomic_write_done(this, ordering);
+        Ok(())
+    }
+
+    /// Checks an atomic read-modify-write of `place` for races. Unlike a store, it continues the
+    /// chain of releases at the location, so it adds to the published clock instead of
+    /// replacing it.
+    fn validate_atomic_rmw(
+        &self,
+        place: MPlaceTy<'tcx, Tag>,
+        ordering: AtomicOrdering,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        validate_atomic_access(this, place, AccessKind::AtomicStore, |clocks, atomic| {
+            if ordering.is_acquire() {
+                clocks.clock.join(&atomic.sync);
+            } else {
+                clocks.fence_acquire.join(&atomic.sync);
+            }
+            if ordering.is_release() {
+                atomic.sync.join(&clocks.clock);
+            } else {
+                atomic.sync.join(&clocks.fence_release);
+            }
+        })?;
+        atomic_write_done(this, ordering);
+        Ok(())
+    }
+
+    fn validate_atomic_fence(&mut self, ordering: AtomicOrdering) {
+        let this = self.eval_context_mut();
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().fence(ordering);
+        }
+    }
+
+    /// Called when the active thread acquires the lock of the synchronization object at
+    /// `location`.
+    fn acquire_sync_clock(&mut self, location: (AllocId, Size)) {
+        let this = self.eval_context_mut();
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().acquire_sync_clock(location);
+        }
+    }
+
+    /// Called when the active thread releases the lock of the synchronization object at
+    /// `location`.
+    fn release_sync_clock(&mut self, location: (AllocId, Size)) {
+        let this = self.eval_context_mut();
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().release_sync_clock(location);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(timestamps: &[u32]) -> VClock {
+        VClock(timestamps.to_vec())
+    }
+
+    #[test]
+    fn join_takes_later_timestamps() {
+        let mut a = clock(&[3, 1]);
+        a.join(&clock(&[2, 4, 1]));
+        assert_eq!(a, clock(&[3, 4, 1]));
+    }
+
+    #[test]
+    fn first_later() {
+        let t = ThreadId::from;
+        assert_eq!(clock(&[1, 2]).first_later(&clock(&[1, 2])), None);
+        assert_eq!(clock(&[1, 2]).first_later(&clock(&[5])), Some(t(1)));
+        assert_eq!(clock(&[0, 0, 3]).first_later(&clock(&[])), Some(t(2)));
+        assert_eq!(clock(&[]).first_later(&clock(&[1])), None);
+    }
+
+    #[test]
+    fn unsynchronized_write_races_with_read() {
+        let (main, child) = (ThreadId::MAIN, ThreadId::from(1));
+        let mut cell = MemoryCellClocks::new(main, 1);
+        // The child was created when main had timestamp 1, which main then incremented.
+        let child_clock = clock(&[1, 1]);
+        let main_clock = clock(&[2]);
+        assert_eq!(cell.read(child, &child_clock), Ok(()));
+        let race = Err((AccessKind::Read, child));
+        assert_eq!(cell.write(main, &main_clock, AccessKind::Write), race);
+        // Once main joined the child, the write does not race anymore.
+        let main_clock = clock(&[2, 1]);
+        assert_eq!(cell.write(main, &main_clock, AccessKind::Write), Ok(()));
+        assert_eq!(cell.read(child, &child_clock), Err((AccessKind::Write, main)));
+    }
+
+    #[test]
+    fn atomics_do_not_race_with_each_other() {
+        let (main, child) = (ThreadId::MAIN, ThreadId::from(1));
+        let mut cell = MemoryCellClocks::new(main, 1);
+        assert!(cell.atomic_store(main, &clock(&[2])).is_ok());
+        assert!(cell.atomic_load(child, &clock(&[1, 1])).is_ok());
+        assert!(cell.atomic_store(child, &clock(&[1, 1])).is_ok());
+        assert_eq!(cell.read(main, &clock(&[2])), Err((AccessKind::AtomicStore, child)));
+    }
+
+    #[test]
+    fn sites_are_remembered_per_thread_and_kind() {
+        let child = ThreadId::from(1);
+        let site = AccessSite { span: rustc_span::DUMMY_SP, frame: None };
+        let mut sites = AccessSites::default();
+        sites.record(AccessKind::Read, child, Some(site.clone()));
+        assert_eq!(sites.get((AccessKind::Read, child)), Some(&site));
+        assert_eq!(sites.get((AccessKind::Read, ThreadId::MAIN)), None);
+        assert_eq!(sites.get((AccessKind::AtomicLoad, child)), None);
+        // Allocations, writes and deallocations replace each other.
+        sites.record(AccessKind::Allocation, ThreadId::MAIN, Some(site.clone()));
+        assert_eq!(sites.get((AccessKind::Write, child)), Some(&site));
+    }
+}
@@ -1,11 +1,45 @@
+use rustc::ty::layout::Size;
 use rustc_mir::interpret::InterpErrorInfo;
+use rustc_span::Span;
 use std::cell::RefCell;
+use std::collections::HashSet;
 
+use crate::data_race::{AccessSite, RaceDetails};
 use crate::*;
 
 /// Miri specific diagnostics
 pub enum NonHaltingDiagnostic {
-    PoppedTrackedPointerTag(Item),
+    /// A tracked tag was created as the base tag of an allocation.
+    CreatedPointerTag(Tag, TagRange),
+    /// A reference was retagged, and either the old or the new tag is tracked.
+    RetaggedPointerTag { new_tag: Tag, derived_from: Tag, kind: RefKind, range: TagRange },
+    /// An item for a tracked tag was popped from (or disabled in) a borrow stack.
+    PoppedTrackedPointerTag(Item, TagRange),
+    /// An allocation tracked by `-Zmiri-track-alloc-id` was created.
+    CreatedAlloc(AllocId),
+    /// An allocation tracked by `-Zmiri-track-alloc-id` was deallocated.
+    FreedAlloc(AllocId),
+    /// A heap allocation was created. Its backtrace is shown if it leaks, and decides whether
+    /// leak suppressions apply to it.
+    CreatedHeapAlloc(AllocId),
+    /// A heap allocation was deallocated, so it cannot leak anymore.
+    FreedHeapAlloc(AllocId),
+    /// Print the state of all threads, requested by `miri_dump_threads` or
+    /// `-Zmiri-dump-threads-interval`.
+    ThreadDump,
+    /// The program calls a foreign item it defines itself, which may shadow one of our shims.
+    ExportedSymbol { link_name: String, definition: String, prefer_shims: bool },
+    /// Stacked Borrows did something to the allocation of `-Zmiri-dump-borrow-stacks`. Describes
+    /// what it did and the borrow stacks afterwards.
+    BorrowStacksDump(String),
+}
+
+/// Where an allocation tracked by `-Zmiri-track-alloc-id` was allocated and deallocated, as
+/// backtraces with the innermost frame first.
+#[derive(Clone)]
+pub struct AllocBacktraces<'tcx> {
+    pub allocated: Vec<FrameInfo<'tcx>>,
+    pub deallocated: Option<Vec<FrameInfo<'tcx>>>,
 }
 
 /// Emit a custom diagnostic without going through the miri-engine machinery
@@ -13,15 +47,66 @@ pub fn report_diagnostic<'tcx, 'mir>(
     ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
     mut e: InterpErrorInfo<'tcx>,
 ) -> Option<i64> {
+    // The exit code, if Miri should not exit like it does for any other error.
+    let mut exit_code = None;
+    let mut deadlock = false;
+    let mut memory_limit_exceeded = false;
     // Special treatment for some error kinds
     let msg = match e.kind {
         InterpError::MachineStop(ref info) => {
             let info = info.downcast_ref::<TerminationInfo>().expect("invalid MachineStop payload");
             match info {
-                TerminationInfo::Exit(code) => return Some(*code),
-                TerminationInfo::Abort => format!("the evaluated program aborted execution"),
+                TerminationInfo::Exit { code, .. } => return Some(*code),
+                TerminationInfo::Abort => {
+                    // This is a failure of the program, not of Miri, so exit like the program.
+                    exit_code = Some(ABORT_EXIT_CODE);
+                    format!("the evaluated program aborted execution")
+                }
+                TerminationInfo::UnwoundOutOfExitHandler(handler) => {
+                    exit_code = Some(ABORT_EXIT_CODE);
+                    format!(
+                        "the evaluated program aborted execution: a panic unwound out of a {}",
+                        handler
+                    )
+                }
+                TerminationInfo::Deadlock { suspended_below } => {
+                    deadlock = true;
+                    let threads = &ecx.machine.threads;
+                    let active = threads.active_thread();
+                    let mut msg = format!(
+                        "the evaluated program deadlocked: {} is {}",
+                        threads.describe(active),
+                        threads.get(active).unwrap().state_description()
+                    );
+                    if let Some(suspended) = suspended_below {
+                        msg.push_str(&format!(
+                            ", but {} is a thread suspended below the active one (unsupported interleaving)",
+                            threads.describe(*suspended)
+                        ));
+                    }
+                    msg
+                }
+                TerminationInfo::AssertMemEq(msg) => format!("`miri_assert_mem_eq` failed: {}", msg),
+                TerminationInfo::MemoryLimitExceeded { limit, live_bytes, requested } => {
+                    memory_limit_exceeded = true;
+                    if *requested == 0 {
+                        format!(
+                            "the evaluated program exceeded the memory limit of {} bytes: {} bytes are allocated",
+                            limit, live_bytes
+                        )
+                    } else {
+                        format!(
+                            "the evaluated program exceeded the memory limit of {} bytes: allocating {} bytes while {} bytes are allocated",
+                            limit, requested, live_bytes
+                        )
+                    }
+                }
             }
         }
+        err_unsup!(DanglingPointerDeref) if ecx.machine.c_reallocations > 0 => format!(
+            "{}. If this pointer was passed to `realloc`, note that `realloc` invalidates it even if the allocation is resized in place and the returned pointer is equal to it",
+            e
+        ),
         err_unsup!(NoMirFor(..)) => format!(
             "{}. Did you set `MIRI_SYSROOT` to a Miri-enabled sysroot? You can prepare one with `cargo miri setup`.",
             e
@@ -29,8 +114,105 @@ pub fn report_diagnostic<'tcx, 'mir>(
         InterpError::InvalidProgram(_) => bug!("This error should be impossible in Miri: {}", e),
         _ => e.to_string(),
     };
+    if let Some(events) = &ecx.machine.progress_events {
+        events.error(&msg);
+    }
     e.print_backtrace();
-    report_msg(ecx, msg, true)
+    let mentioned_allocs = mentioned_alloc_ids(&msg);
+    report_msg(ecx, msg, true);
+    let race = ecx.memory.extra.data_race.as_ref().and_then(|data_race| {
+        data_race.borrow().take_race_details()
+    });
+    if let Some(race) = race {
+        report_race_details(ecx, race);
+    }
+    let tag_history = ecx.memory.extra.stacked_borrows.as_ref().and_then(|stacked_borrows| {
+        stacked_borrows.borrow_mut().take_failed_tag_history()
+    });
+    if let Some((id, history)) = tag_history {
+        report_tag_history(ecx, id, history);
+    }
+    if ecx.memory.extra.intptrcast.borrow().forged_from_int {
+        if let Some(frames) = &ecx.machine.first_ptr_to_int_cast {
+            let msg = "a pointer was first cast to an integer here".to_string();
+            match frames.first() {
+                Some(frame) =>
+                    report_with_backtrace(ecx, frame.call_site, msg, false, backtrace_notes(frames)),
+                None => ecx.tcx.sess.note_without_error(&msg),
+            }
+        }
+    }
+    for id in mentioned_allocs {
+        if let Some(backtraces) = ecx.machine.alloc_backtraces.get(&id) {
+            report_alloc_backtraces(ecx, id, backtraces);
+        }
+    }
+    if let (err_ub!(UbExperimental(_)), Some(stacked_borrows)) =
+        (&e.kind, &ecx.memory.extra.stacked_borrows)
+    {
+        let mode = stacked_borrows.borrow().describe_mode(ecx.memory.extra.tree_borrows);
+        ecx.tcx.sess.note_without_error(&format!("this error was reported by {}", mode));
+    }
+    // If the program aborted because of a panic, e.g. one that started while another one was
+    // unwinding, point to the panic that was unwinding.
+    if let (Some(ABORT_EXIT_CODE), Some(site)) = (exit_code, ecx.machine.panic_site) {
+        ecx.tcx.sess.span_note_without_error(site, "the program was unwinding from this panic");
+    }
+    if memory_limit_exceeded {
+        report_largest_allocs(ecx);
+    }
+    if deadlock {
+        let active = ecx.machine.threads.active_thread();
+        let others: Vec<ThreadId> = ecx
+            .machine
+            .threads
+            .iter()
+            .filter(|(id, thread)| *id != active && !thread.is_terminated())
+            .map(|(id, _)| id)
+            .collect();
+        report_threads(ecx, &others);
+    } else if ecx.machine.threads.iter().count() > 1 {
+        let active = ecx.machine.threads.active_thread();
+        let msg = format!("the error occurred on {}", ecx.machine.threads.describe(active));
+        ecx.tcx.sess.note_without_error(&msg);
+    }
+    exit_code
+}
+
+/// Notes the largest live allocations, with the backtraces they were allocated with if those were
+/// recorded, after the memory limit was exceeded.
+fn report_largest_allocs<'tcx, 'mir>(ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>) {
+    let memory_limit = ecx.memory.extra.memory_limit.as_ref().expect("memory limit must be set");
+    for (id, size) in memory_limit.borrow().largest_allocs() {
+        let msg = format!("{} holds {} bytes", id, size);
+        let frames = ecx.machine.heap_alloc_backtraces.get(&id).map_or(&[][..], |f| &f[..]);
+        match frames.first() {
+            Some(frame) =>
+                report_with_backtrace(ecx, frame.call_site, msg, false, backtrace_notes(frames)),
+            None => ecx.tcx.sess.note_without_error(&msg),
+        }
+    }
+}
+
+/// Notes what the `threads` are doing, with the backtraces of those that have frames on the stack.
+pub fn report_threads<'tcx, 'mir>(ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>, threads: &[ThreadId]) {
+    let stack = ecx.stack();
+    // The innermost frame comes first.
+    let frames = ecx.generate_stacktrace(None);
+    let thread_frames = ecx.machine.threads.thread_frames(stack);
+    for &id in threads {
+        let thread = ecx.machine.threads.get(id).expect("reporting a thread that does not exist");
+        let msg =
+            format!("{} is {}", ecx.machine.threads.describe(id), thread.state_description());
+        match thread_frames.iter().find(|(thread, _)| *thread == id) {
+            Some((_, range)) if !range.is_empty() => {
+                let span = stack[range.end - 1].current_source_info().unwrap().span;
+                let frames = &frames[stack.len() - range.end..stack.len() - range.start];
+                report_with_backtrace(ecx, span, msg, false, backtrace_notes(frames));
+            }
+            _ => ecx.tcx.sess.note_without_error(&msg),
+        }
+    }
 }
 
 /// Report an error or note (depending on the `error` argument) at the current frame's current statement.
@@ -43,27 +225,8 @@ pub fn report_msg<'tcx, 'mir>(
     if let Some(frame) = ecx.stack().last() {
         let span = frame.current_source_info().unwrap().span;
 
-        let mut err = if error {
-            let msg = format!("Miri evaluation error: {}", msg);
-            ecx.tcx.sess.struct_span_err(span, msg.as_str())
-        } else {
-            ecx.tcx.sess.diagnostic().span_note_diag(span, msg.as_str())
-        };
         let frames = ecx.generate_stacktrace(None);
-        err.span_label(span, msg);
-        // We iterate with indices because we need to look at the next frame (the caller).
-        for idx in 0..frames.len() {
-            let frame_info = &frames[idx];
-            let call_site_is_local = frames
-                .get(idx + 1)
-                .map_or(false, |caller_info| caller_info.instance.def_id().is_local());
-            if call_site_is_local {
-                err.span_note(frame_info.call_site, &frame_info.to_string());
-            } else {
-                err.note(&frame_info.to_string());
-            }
-        }
-        err.emit();
+        report_with_backtrace(ecx, span, msg, error, backtrace_notes(&frames));
     } else {
         ecx.tcx.sess.err(&msg);
     }
@@ -80,6 +243,206 @@ pub fn report_msg<'tcx, 'mir>(
     return None;
 }
 
+/// Report an error or note at `span`, with a backtrace given by the `notes` that describe its
+/// frames, innermost first.
+fn report_with_backtrace<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+    span: Span,
+    msg: String,
+    error: bool,
+    notes: Vec<(String, Option<Span>)>,
+) {
+    let mut err = if error {
+        let msg = format!("Miri evaluation error: {}", msg);
+        ecx.tcx.sess.struct_span_err(span, msg.as_str())
+    } else {
+        ecx.tcx.sess.diagnostic().span_note_diag(span, msg.as_str())
+    };
+    err.span_label(span, msg);
+    for (note, call_site) in notes {
+        match call_site {
+            Some(call_site) => err.span_note(call_site, &note),
+            None => err.note(&note),
+        };
+    }
+    err.emit();
+}
+
+/// Describes the `frames` of a backtrace, innermost first, together with their call sites if
+/// those are in the local crate.
+fn backtrace_notes(frames: &[FrameInfo<'_>]) -> Vec<(String, Option<Span>)> {
+    // We iterate with indices because we need to look at the next frame (the caller).
+    (0..frames.len())
+        .map(|idx| {
+            let frame_info = &frames[idx];
+            let call_site_is_local = frames
+                .get(idx + 1)
+                .map_or(false, |caller_info| caller_info.instance.def_id().is_local());
+            let call_site = if call_site_is_local { Some(frame_info.call_site) } else { None };
+            (frame_info.to_string(), call_site)
+        })
+        .collect()
+}
+
+/// Like `backtrace_notes`, for the frames of an access the race detector remembers.
+fn site_backtrace_notes<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+    site: &AccessSite,
+) -> Vec<(String, Option<Span>)> {
+    let mut notes = Vec::new();
+    let mut frame = site.frame.as_ref();
+    while let Some(site_frame) = frame {
+        let mut note = if ecx.tcx.is_closure(site_frame.def_id) {
+            "inside call to closure".to_string()
+        } else {
+            format!("inside call to `{}`", ecx.tcx.def_path_str(site_frame.def_id))
+        };
+        if !site_frame.call_site.is_dummy() {
+            let lo = ecx.tcx.sess.source_map().lookup_char_pos(site_frame.call_site.lo());
+            note.push_str(&format!(" at {}:{}:{}", lo.file.name, lo.line, lo.col.to_usize() + 1));
+        }
+        let call_site_is_local =
+            site_frame.caller.as_ref().map_or(false, |caller| caller.def_id.is_local());
+        notes.push((note, if call_site_is_local { Some(site_frame.call_site) } else { None }));
+        frame = site_frame.caller.as_ref();
+    }
+    notes
+}
+
+/// Notes where the earlier access of a data race happened, and where the memory was allocated.
+fn report_race_details<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+    race: RaceDetails,
+) {
+    let msg = format!("the {} on {} happened here", race.kind, race.thread);
+    match &race.site {
+        Some(site) => {
+            report_with_backtrace(ecx, site.span, msg, false, site_backtrace_notes(ecx, site))
+        }
+        None => ecx.tcx.sess.note_without_error(&msg),
+    }
+    // The allocation may well be the earlier access itself.
+    let allocation = race.allocation.filter(|allocation| Some(allocation) != race.site.as_ref());
+    if let Some(allocation) = allocation {
+        let notes = site_backtrace_notes(ecx, &allocation);
+        let msg = "the memory was allocated here".to_string();
+        report_with_backtrace(ecx, allocation.span, msg, false, notes);
+    }
+}
+
+/// The allocations mentioned as `allocN` in an error message, in order of appearance.
+fn mentioned_alloc_ids(msg: &str) -> Vec<AllocId> {
+    let mut ids = Vec::new();
+    for (idx, _) in msg.match_indices("alloc") {
+        let digits: String =
+            msg[idx + "alloc".len()..].chars().take_while(|c| c.is_ascii_digit()).collect();
+        if let Ok(id) = digits.parse() {
+            if !ids.contains(&AllocId(id)) {
+                ids.push(AllocId(id));
+            }
+        }
+    }
+    ids
+}
+
+/// Notes where an allocation tracked by `-Zmiri-track-alloc-id` was allocated, and where it was
+/// deallocated.
+fn report_alloc_backtraces<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+    id: AllocId,
+    backtraces: &AllocBacktraces<'tcx>,
+) {
+    let report = |frames: &[FrameInfo<'tcx>], msg: String| match frames.first() {
+        Some(frame) => report_with_backtrace(ecx, frame.call_site, msg, false, backtrace_notes(frames)),
+        None => ecx.tcx.sess.note_without_error(&msg),
+    };
+    report(&backtraces.allocated, format!("{} was allocated here", id));
+    if let Some(deallocated) = &backtraces.deallocated {
+        report(deallocated, format!("{} was deallocated here", id));
+    }
+}
+
+/// Describes every leaked allocation: its size, where it was allocated, and whether a static still
+/// points to it (directly or through other allocations), or it was definitely lost.
+pub fn report_leaks<'tcx, 'mir>(ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>, leaks: &[AllocId]) {
+    let reachable = reachable_from_statics(ecx);
+    let mut lost = 0;
+    for &id in leaks {
+        let size = match ecx.memory.get_size_and_align(id, AllocCheck::Live) {
+            Ok((size, _)) => size,
+            Err(_) => continue,
+        };
+        let status = if reachable.contains(&id) {
+            "still reachable from a static"
+        } else {
+            lost += 1;
+            "definitely lost"
+        };
+        let msg = format!("{} ({} bytes) was leaked, it is {}", id, size.bytes(), status);
+        let frames = ecx.machine.heap_alloc_backtraces.get(&id).map_or(&[][..], |f| &f[..]);
+        match frames.first() {
+            Some(frame) =>
+                report_with_backtrace(ecx, frame.call_site, msg, false, backtrace_notes(frames)),
+            None => ecx.tcx.sess.note_without_error(&msg),
+        }
+    }
+    ecx.tcx.sess.note_without_error(&format!(
+        "{} leaked allocations: {} definitely lost, {} still reachable from statics",
+        leaks.len(),
+        lost,
+        leaks.len() - lost,
+    ));
+}
+
+/// The allocations that the pointers in statics point to, directly or through other allocations.
+fn reachable_from_statics<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+) -> HashSet<AllocId> {
+    let mut reachable = HashSet::new();
+    let mut todo: Vec<AllocId> =
+        ecx.memory.extra.static_allocs.borrow().iter().copied().collect();
+    while let Some(id) = todo.pop() {
+        // Function pointers and dead allocations have no memory to look at.
+        if let Ok(alloc) = ecx.memory.get_raw(id) {
+            for &(_, (_, target)) in alloc.relocations().iter() {
+                if reachable.insert(target) {
+                    todo.push(target);
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Notes where the tag that Stacked Borrows complained about was created, and where it was
+/// invalidated.
+fn report_tag_history<'tcx, 'mir>(
+    ecx: &InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+    id: PtrId,
+    history: TagHistory,
+) {
+    let note = |span: Span, msg: String| {
+        if span.is_dummy() {
+            ecx.tcx.sess.note_without_error(&msg);
+        } else {
+            ecx.tcx.sess.span_note_without_error(span, &msg);
+        }
+    };
+    let (span, retag) = history.created;
+    let msg = match retag {
+        Some(kind) => format!("tag <{}> was created here, by a {} reborrow", id, kind),
+        None => format!("tag <{}> was created here, as the base tag of an allocation", id),
+    };
+    note(span, msg);
+    if let Some((span, access)) = history.invalidated {
+        let msg = match access {
+            Some(access) => format!("tag <{}> was later invalidated here, by a {}", id, access),
+            None => format!("tag <{}> was later invalidated here, by a deallocation", id),
+        };
+        note(span, msg);
+    }
+}
+
 thread_local! {
     static DIAGNOSTICS: RefCell<Vec<NonHaltingDiagnostic>> = RefCell::new(Vec::new());
 }
@@ -92,14 +455,102 @@ pub fn register_diagnostic(e: NonHaltingDiagnostic) {
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
-    /// Emit all diagnostics that were registed with `register_diagnostics`
-    fn process_diagnostics(&self) {
+    /// Describes the state of every thread. The backtrace of the active thread is attached by
+    /// `report_msg`.
+    fn thread_dump(&self) -> String {
         let this = self.eval_context_ref();
+        let mut dump =
+            format!("thread dump after {} basic blocks:", this.machine.basic_block_count);
+        for (id, thread) in this.machine.threads.iter() {
+            let name = thread.name().map_or("<unnamed>".into(), String::from_utf8_lossy);
+            let active = if id == this.machine.threads.active_thread() { " (active)" } else { "" };
+            // Other locks, like the ones of `pthread_rwlock_t` and SRW locks, are not listed.
+            let locks = this.machine.held_locks.held_by(id);
+            let locks = if locks.is_empty() {
+                "holds no mutexes or critical sections".to_owned()
+            } else {
+                format!("holds {}", locks.join(", "))
+            };
+            dump.push_str(&format!(
+                "\n    thread {} `{}`{}: {}, {}",
+                id,
+                name,
+                active,
+                thread.state_description(),
+                locks,
+            ));
+        }
+        dump
+    }
+
+    /// Emit all diagnostics that were registed with `register_diagnostics`
+    fn process_diagnostics(&mut self) {
+        let this = self.eval_context_mut();
         DIAGNOSTICS.with(|diagnostics| {
-            for e in diagnostics.borrow_mut().drain(..) {
+            let mut diagnostics = diagnostics.borrow_mut();
+            if this.machine.discard_output {
+                diagnostics.clear();
+                return;
+            }
+            for e in diagnostics.drain(..) {
                 let msg = match e {
-                    NonHaltingDiagnostic::PoppedTrackedPointerTag(item) =>
-                        format!("popped tracked tag for item {:?}", item),
+                    NonHaltingDiagnostic::CreatedPointerTag(tag, range) =>
+                        format!("created tracked tag {:?} for {}", tag, range),
+                    NonHaltingDiagnostic::RetaggedPointerTag { new_tag, derived_from, kind, range } =>
+                        format!(
+                            "retagged {:?} into {:?} ({} reference) for {}",
+                            derived_from, new_tag, kind, range,
+                        ),
+                    NonHaltingDiagnostic::PoppedTrackedPointerTag(item, range) =>
+                        format!("popped tracked tag for item {:?} in {}", item, range),
+                    NonHaltingDiagnostic::CreatedAlloc(id) => {
+                        let allocated = this.generate_stacktrace(None);
+                        this.machine
+                            .alloc_backtraces
+                            .insert(id, AllocBacktraces { allocated, deallocated: None });
+                        format!("created tracked allocation {}", id)
+                    }
+                    NonHaltingDiagnostic::FreedAlloc(id) => {
+                        let deallocated = this.generate_stacktrace(None);
+                        if let Some(backtraces) = this.machine.alloc_backtraces.get_mut(&id) {
+                            backtraces.deallocated = Some(deallocated);
+                        }
+                        format!("freed tracked allocation {}", id)
+                    }
+                    NonHaltingDiagnostic::CreatedHeapAlloc(id) => {
+                        let allocated = this.generate_stacktrace(None);
+                        if let Some(stats) = &mut this.machine.alloc_stats {
+                            // The allocation might already be freed again.
+                            let size = this
+                                .memory
+                                .get_size_and_align(id, AllocCheck::MaybeDead)
+                                .map_or(Size::ZERO, |(size, _)| size);
+                            stats.record_allocation(id, size, &allocated);
+                        }
+                        this.machine.heap_alloc_backtraces.insert(id, allocated);
+                        continue;
+                    }
+                    NonHaltingDiagnostic::FreedHeapAlloc(id) => {
+                        if let Some(stats) = &mut this.machine.alloc_stats {
+                            stats.record_deallocation(id);
+                        }
+                        this.machine.heap_alloc_backtraces.remove(&id);
+                        continue;
+                    }
+                    NonHaltingDiagnostic::ThreadDump => this.thread_dump(),
+                    NonHaltingDiagnostic::BorrowStacksDump(dump) => dump,
+                    NonHaltingDiagnostic::ExportedSymbol { link_name, definition, prefer_shims } =>
+                        if prefer_shims {
+                            format!(
+                                "`{}` is defined by `{}`, which is only called if Miri has no shim for it",
+                                link_name, definition,
+                            )
+                        } else {
+                            format!(
+                                "calling `{}` runs its definition `{}`, even if Miri has a shim for it (pass `-Zmiri-prefer-shims` to prefer the shim)",
+                                link_name, definition,
+                            )
+                        },
                 };
                 report_msg(this, msg, false);
             }
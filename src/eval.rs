@@ -1,9 +1,8 @@
 //! Main evaluator loop and setting up the initial stack frame.
 
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::OsStr;
-
-use rand::rngs::StdRng;
-use rand::SeedableRng;
+use std::path::PathBuf;
 
 use rustc_hir::def_id::DefId;
 use rustc::ty::layout::{LayoutOf, Size};
@@ -18,24 +17,148 @@ pub struct MiriConfig {
     pub validate: bool,
     /// Determines if Stacked Borrows is enabled.
     pub stacked_borrows: bool,
+    /// Determines if the experimental Tree Borrows model is used instead of Stacked Borrows.
+    pub tree_borrows: bool,
+    /// Determines if the data race detector is enabled.
+    pub data_race_detector: bool,
+    /// Determines if atomic loads may read older stores. Requires the data race detector.
+    pub weak_memory_emulation: bool,
     /// Determines if communication with the host environment is enabled.
     pub communicate: bool,
     /// Determines if memory leaks should be ignored.
     pub ignore_leaks: bool,
+    /// Determines if memory leaks should be ignored when the program ends with `_exit` or `_Exit`.
+    pub ignore_leaks_on_immediate_exit: bool,
+    /// Leaked allocations are not reported if a function in these crates or below these item
+    /// paths was running when they were allocated.
+    pub leak_suppressions: Vec<String>,
     /// Environment variables that should always be isolated from the host.
     pub excluded_env_vars: Vec<String>,
+    /// Environment variables that should be taken from the host even with isolation.
+    pub forwarded_env_vars: Vec<String>,
+    /// Environment variables the interpreted program sees with the given values.
+    pub set_env_vars: Vec<(String, String)>,
     /// Command-line arguments passed to the interpreted program.
     pub args: Vec<String>,
     /// The seed to use when non-determinism or randomness are required (e.g. ptr-to-int cast, `getrandom()`).
     pub seed: Option<u64>,
-    /// The stacked borrow id to report about
-    pub tracked_pointer_tag: Option<PtrId>,
+    /// The stacked borrow ids to report about
+    pub tracked_pointer_tags: HashSet<PtrId>,
+    /// The allocations whose creation and deallocation are reported, with backtraces.
+    pub tracked_alloc_ids: TrackedAllocs,
+    /// The allocation whose borrow stack events are printed.
+    pub dumped_borrow_stacks: Option<AllocId>,
+    /// Crates and item paths whose code is not checked by Stacked Borrows.
+    pub stacked_borrows_exempt: Vec<String>,
+    /// Determines if Stacked Borrows gives raw pointers their own tags.
+    pub raw_pointer_tagging: bool,
+    /// Determines if references passed to functions are protected until the call returns.
+    pub protectors: bool,
+    /// Determines if pointers cast from integers may access the memory of all pointers that were
+    /// cast to integers.
+    pub permissive_provenance: bool,
+    /// Determines if using pointers cast from integers is an error.
+    pub strict_provenance: bool,
+    /// If set, dead tags are removed from the borrow stacks whenever this many basic blocks were
+    /// executed.
+    pub tag_gc_interval: Option<u64>,
+    /// Determines if directory entries are returned sorted by name instead of in host order.
+    pub deterministic_readdir: bool,
+    /// The probability with which file system operations fail with an injected error.
+    pub io_error_rate: f64,
+    /// File system operations that fail on a specific call, regardless of `io_error_rate`.
+    pub io_error_script: Vec<ScriptedIoError>,
+    /// Determines if file reads and writes may transfer fewer bytes than requested.
+    pub short_io: bool,
+    /// If set, dump the state of all threads whenever this many basic blocks were executed.
+    pub dump_threads_interval: Option<u64>,
+    /// Whether to report how often each location was accessed atomically once the program finished.
+    pub atomic_stats: bool,
+    /// Whether to report statistics about the heap allocations once the program finished.
+    pub alloc_stats: bool,
+    /// If set, the program is stopped once its allocations take up more than this many bytes.
+    pub memory_limit: Option<u64>,
+    /// If set, the realtime clock starts at this many seconds after the unix epoch.
+    pub start_time: Option<u64>,
+    /// If set, the virtual clock advances by this many nanoseconds with every basic block.
+    pub clock_step: Option<u64>,
+    /// Whether Miri's shims take precedence over functions the program exports under the same name.
+    pub prefer_shims: bool,
+    /// If set, progress events are written to this file as JSON lines.
+    pub progress_events: Option<PathBuf>,
+    /// The number of CPUs reported to the program, if not the default of 1.
+    pub num_cpus: Option<u64>,
+    /// How the interpreted program's network operations are handled.
+    pub net: NetMode,
+    /// Resource limits that replace the default ones, by the name of the resource.
+    pub resource_limits: Vec<(String, ResourceLimit)>,
+    /// If set, `miri_nondet_bytes` replays these bytes instead of generating random ones.
+    pub nondet_input: Option<Vec<u8>>,
+    /// If set, the nondeterministic input of a failing run is minimized, and the result is
+    /// written to this directory.
+    pub shrink_nondet: Option<PathBuf>,
+    /// If set, the threads the scheduler chose to start are written to this file.
+    pub record_schedule: Option<PathBuf>,
+    /// If set, the scheduler starts these threads, in order, instead of the oldest pending one.
+    pub replay_schedule: Option<Vec<ThreadId>>,
+    /// If set, schedules in which at most this many decisions deviate from the default one are
+    /// explored until one of them fails.
+    pub explore_schedules: Option<usize>,
+    /// How threads that are still live when `main` returns are reported.
+    pub live_threads: LiveThreads,
+}
+
+/// How threads that are still live when `main` returns are reported. Either way, the program is
+/// not checked for leaks, because those threads may still own memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LiveThreads {
+    Warn,
+    Error,
+}
+
+/// The allocations `-Zmiri-track-alloc-id` reports about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrackedAllocs {
+    Ids(HashSet<AllocId>),
+    All,
+}
+
+impl TrackedAllocs {
+    pub fn contains(&self, id: AllocId) -> bool {
+        match self {
+            TrackedAllocs::Ids(ids) => ids.contains(&id),
+            TrackedAllocs::All => true,
+        }
+    }
+}
+
+impl Default for TrackedAllocs {
+    fn default() -> Self {
+        TrackedAllocs::Ids(HashSet::new())
+    }
 }
 
+/// The exit code of Miri when the program aborted. Like the one a shell reports for a process
+/// killed by `SIGABRT`, it is distinct from the exit code of Miri reporting an error.
+pub const ABORT_EXIT_CODE: i64 = 128 + 6;
+
 /// Details of premature program termination.
 pub enum TerminationInfo {
-    Exit(i64),
+    /// The program called `exit` or one of its variants. `leak_check` says whether memory leaks
+    /// should still be checked for.
+    Exit { code: i64, leak_check: bool },
     Abort,
+    /// A panic unwound out of a TLS destructor or an `atexit` handler, which aborts the program.
+    UnwoundOutOfExitHandler(ExitHandlerKind),
+    /// The active thread blocked, and no thread can ever wake it up. If it waits for a thread
+    /// that is suspended below it, that thread: Miri does not support the interleaving that would
+    /// let it continue.
+    Deadlock { suspended_below: Option<ThreadId> },
+    /// A `miri_assert_mem_eq` failed, with the details of the mismatch.
+    AssertMemEq(String),
+    /// Allocating `requested` more bytes, or 0 bytes if they were already allocated, exceeded
+    /// `-Zmiri-memory-limit`.
+    MemoryLimitExceeded { limit: u64, live_bytes: u64, requested: u64 },
 }
 
 /// Returns a freshly created `InterpCx`, along with an `MPlaceTy` representing
@@ -50,18 +173,17 @@ pub fn create_ecx<'mir, 'tcx: 'mir>(
     let mut ecx = InterpCx::new(
         tcx.at(rustc_span::source_map::DUMMY_SP),
         ty::ParamEnv::reveal_all(),
-        Evaluator::new(
-            config.communicate,
-            config.validate,
-        ),
-        MemoryExtra::new(
-            StdRng::seed_from_u64(config.seed.unwrap_or(0)),
-            config.stacked_borrows,
-            config.tracked_pointer_tag,
-        ),
+        Evaluator::new(&config),
+        MemoryExtra::new(&config),
     );
     // Complete initialization.
-    EnvVars::init(&mut ecx, config.excluded_env_vars);
+    EnvVars::init(
+        &mut ecx,
+        config.excluded_env_vars,
+        config.forwarded_env_vars,
+        config.set_env_vars,
+    )?;
+    MemoryExtra::init_extern_statics(&mut ecx)?;
 
     // Setup first stack-frame
     let main_instance = ty::Instance::mono(tcx, main_id);
@@ -171,12 +293,25 @@ pub fn create_ecx<'mir, 'tcx: 'mir>(
 /// Evaluates the main function specified by `main_id`.
 /// Returns `Some(return_code)` if program executed completed.
 /// Returns `None` if an evaluation error occured.
-pub fn eval_main<'tcx>(tcx: TyCtxt<'tcx>, main_id: DefId, config: MiriConfig) -> Option<i64> {
-    // FIXME: We always ignore leaks on some platforms where we do not
+pub fn eval_main<'tcx>(tcx: TyCtxt<'tcx>, main_id: DefId, mut config: MiriConfig) -> Option<i64> {
+    // FIXME: We always ignore leaks on Windows, where we do not
     // correctly implement TLS destructors.
     let target_os = tcx.sess.target.target.target_os.as_str();
-    let ignore_leaks = config.ignore_leaks || target_os == "windows" || target_os == "macos";
+    let ignore_leaks = config.ignore_leaks || target_os == "windows";
+
+    // The run below reports the failure the exploration found, if any.
+    if let Some(max_deviations) = config.explore_schedules {
+        if let Some(decisions) =
+            crate::schedule::explore_schedules(tcx, main_id, &config, max_deviations)
+        {
+            config.replay_schedule = Some(decisions);
+        }
+    }
 
+    let shrink_config = config.shrink_nondet.as_ref().map(|_| config.clone());
+    let record_schedule = config.record_schedule.clone();
+    let live_threads_check = config.live_threads;
+    let leak_suppressions = config.leak_suppressions.clone();
     let (mut ecx, ret_place) = match create_ecx(tcx, main_id, config) {
         Ok(v) => v,
         Err(mut err) => {
@@ -185,32 +320,230 @@ pub fn eval_main<'tcx>(tcx: TyCtxt<'tcx>, main_id: DefId, config: MiriConfig) ->
         }
     };
 
+    if let Some(events) = &ecx.machine.progress_events {
+        events.started();
+    }
+
     // Perform the main execution.
+    let res = run_main(&mut ecx, ret_place);
+    let mut failure = match &res {
+        Ok(_) => None,
+        Err(e) => Some(FailureClass::of_error(e)),
+    };
+
+    // Process the result.
+    let return_code = match res {
+        Ok((return_code, leak_check)) => {
+            if let Some(stats) = &ecx.machine.atomic_stats {
+                tcx.sess.note_without_error(&stats.report());
+            }
+            if ecx.machine.alloc_stats.is_some() {
+                // Record the heap allocations of the last steps.
+                ecx.process_diagnostics();
+                let stats = ecx.machine.alloc_stats.as_ref().unwrap();
+                tcx.sess.note_without_error(&stats.report(tcx));
+            }
+            // Threads that did not finish may still own memory, and there is no telling whether
+            // they would have freed it.
+            let live_threads: Vec<ThreadId> = ecx
+                .machine
+                .threads
+                .iter()
+                .filter(|(id, thread)| *id != ThreadId::MAIN && !thread.is_terminated())
+                .map(|(id, _)| id)
+                .collect();
+            let threads_live = leak_check && !live_threads.is_empty();
+            if threads_live {
+                let msg = "the main thread terminated without waiting for all remaining threads";
+                match live_threads_check {
+                    LiveThreads::Warn => tcx.sess.warn(&format!("{}; not checking for leaks", msg)),
+                    LiveThreads::Error => tcx.sess.err(msg),
+                }
+                report_threads(&ecx, &live_threads);
+            }
+            let leaks = if leak_check && !threads_live && !ignore_leaks {
+                // Record the heap allocations of the last steps.
+                ecx.process_diagnostics();
+                leaked_allocations(&ecx, &leak_suppressions)
+            } else {
+                vec![]
+            };
+            if return_code != 0 {
+                failure = Some(FailureClass::ExitCode(return_code));
+            }
+            if !leaks.is_empty() {
+                tcx.sess.err("the evaluated program leaked memory");
+                report_leaks(&ecx, &leaks);
+                if let Some(summary) = leaks_by_tag(&ecx, &leaks) {
+                    tcx.sess.note_without_error(&summary);
+                }
+                // Ignore the provided return code - let the reported error
+                // determine the return code.
+                None
+            } else if threads_live && live_threads_check == LiveThreads::Error {
+                None
+            } else {
+                Some(return_code)
+            }
+        }
+        Err(e) => {
+            // Report what happened in the failing step, e.g. what Stacked Borrows did to the
+            // dumped borrow stacks before the error.
+            ecx.process_diagnostics();
+            report_diagnostic(&ecx, e)
+        }
+    };
+    if let Some(events) = &ecx.machine.progress_events {
+        events.finished(return_code);
+    }
+    if let Some(path) = record_schedule {
+        if let Err(err) = std::fs::write(&path, ecx.machine.schedule.to_text()) {
+            tcx.sess.warn(&format!("cannot write the schedule to {}: {}", path.display(), err));
+        }
+    }
+    if let (Some(config), Some(failure)) = (shrink_config, failure) {
+        let input = ecx.machine.nondet.recorded.clone();
+        crate::shrink::shrink_nondet_input(tcx, main_id, config, input, failure);
+    }
+    return_code
+}
+
+/// Runs the program that `create_ecx` set up until it finishes. Returns its exit code, and whether
+/// memory leaks should be checked for.
+fn run_main<'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    ret_place: MPlaceTy<'tcx, Tag>,
+) -> InterpResult<'tcx, (i64, bool)> {
     let res: InterpResult<'_, i64> = (|| {
-        while ecx.step()? {
+        while ecx.step_with_hooks()? {
             ecx.process_diagnostics();
         }
         // Read the return code pointer *before* we run TLS destructors, to assert
         // that it was written to by the time that `start` lang item returned.
-        let return_code = ecx.read_scalar(ret_place.into())?.not_undef()?.to_machine_isize(&ecx)?;
+        let return_code = ecx.read_scalar(ret_place.into())?.not_undef()?.to_machine_isize(ecx)?;
         ecx.run_tls_dtors()?;
+        // Returning from `main` calls `exit`, which runs the `atexit` handlers.
+        ecx.run_atexit_handlers()?;
         Ok(return_code)
     })();
 
-    // Process the result.
-    match res {
-        Ok(return_code) => {
-            if !ignore_leaks {
-                let leaks = ecx.memory.leak_report();
-                if leaks != 0 {
-                    tcx.sess.err("the evaluated program leaked memory");
-                    // Ignore the provided return code - let the reported error
-                    // determine the return code.
-                    return None;
-                }
-            }
-            Some(return_code)
+    // A call to `exit` also ends the program successfully. The frames that called it are still
+    // live, so what their locals own is reported as leaked, like it would be by other leak
+    // checkers.
+    res.map(|return_code| (return_code, /*leak_check:*/ true)).or_else(|e| {
+        let exit = match e.kind {
+            InterpError::MachineStop(ref info) => match info.downcast_ref::<TerminationInfo>() {
+                Some(&TerminationInfo::Exit { code, leak_check }) => Some((code, leak_check)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match exit {
+            Some(exit) => Ok(exit),
+            None => Err(e),
+        }
+    })
+}
+
+/// Runs the program again, with `input` as the nondeterministic input and without any output,
+/// and returns how it failed, if it did. Leaks are not checked for.
+pub(crate) fn rerun_with_nondet_input<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    main_id: DefId,
+    mut config: MiriConfig,
+    input: &[u8],
+) -> Option<FailureClass> {
+    config.nondet_input = Some(input.to_vec());
+    config.shrink_nondet = None;
+    config.progress_events = None;
+    config.record_schedule = None;
+    let (mut ecx, ret_place) = create_ecx(tcx, main_id, config).ok()?;
+    run_silently(&mut ecx, ret_place)
+}
+
+/// Runs the program again, with a schedule that starts with the decisions of `prefix`, and without
+/// any output. Returns how it failed, if it did, and the schedule it ended up with.
+pub(crate) fn rerun_with_schedule<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    main_id: DefId,
+    mut config: MiriConfig,
+    prefix: Vec<ThreadId>,
+) -> (Option<FailureClass>, Schedule) {
+    config.shrink_nondet = None;
+    config.progress_events = None;
+    config.record_schedule = None;
+    let (mut ecx, ret_place) = match create_ecx(tcx, main_id, config) {
+        Ok(v) => v,
+        Err(e) => return (Some(FailureClass::of_error(&e)), Schedule::default()),
+    };
+    ecx.machine.schedule = Schedule::with_prefix(prefix);
+    let failure = run_silently(&mut ecx, ret_place);
+    (failure, std::mem::take(&mut ecx.machine.schedule))
+}
+
+/// Runs the program that `create_ecx` set up, dropping its output, and returns how it failed, if
+/// it did. Leaks are not checked for.
+fn run_silently<'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    ret_place: MPlaceTy<'tcx, Tag>,
+) -> Option<FailureClass> {
+    ecx.machine.discard_output = true;
+    match run_main(ecx, ret_place) {
+        Ok((0, _)) => None,
+        Ok((return_code, _)) => Some(FailureClass::ExitCode(return_code)),
+        Err(e) => Some(FailureClass::of_error(&e)),
+    }
+}
+
+/// The heap allocations that are still live, except for those that were allocated while a
+/// function matching one of the leak suppressions was running.
+fn leaked_allocations<'mir, 'tcx>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    leak_suppressions: &[String],
+) -> Vec<AllocId> {
+    let tcx = *ecx.tcx;
+    let mut leaks: Vec<AllocId> = ecx
+        .machine
+        .heap_alloc_backtraces
+        .iter()
+        .filter(|&(&id, _)| ecx.memory.get_size_and_align(id, AllocCheck::Live).is_ok())
+        .filter(|(_, frames)| {
+            !frames.iter().any(|frame| {
+                crate::helpers::is_in_paths(tcx, frame.instance.def_id(), leak_suppressions)
+            })
+        })
+        .map(|(&id, _)| id)
+        .collect();
+    leaks.sort();
+    leaks
+}
+
+/// Summarizes the `leaks` leaked allocations by the labels given to them with
+/// `miri_tag_allocation`. Returns `None` if none of them has a label.
+fn leaks_by_tag<'mir, 'tcx>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    leaks: &[AllocId],
+) -> Option<String> {
+    let mut tags: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+    for &alloc_id in leaks {
+        if let Some(tag) = ecx.machine.allocation_tags.get(&alloc_id) {
+            let (size, _) = ecx
+                .memory
+                .get_size_and_align(alloc_id, AllocCheck::Live)
+                .expect("leaked allocations are live");
+            let (count, bytes) = tags.entry(tag.as_str()).or_default();
+            *count += 1;
+            *bytes += size.bytes();
         }
-        Err(e) => report_diagnostic(&ecx, e),
     }
+    if tags.is_empty() {
+        return None;
+    }
+    let tagged: usize = tags.values().map(|&(count, _)| count).sum();
+    let mut summary = "leaked allocations by tag:".to_owned();
+    for (tag, (count, bytes)) in tags {
+        summary.push_str(&format!("\n    {}: {} allocations, {} bytes", tag, count, bytes));
+    }
+    summary.push_str(&format!("\n    (untagged): {} allocations", leaks.len() - tagged));
+    Some(summary)
 }
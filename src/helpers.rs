@@ -1,4 +1,5 @@
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
+use std::time::Duration;
 use std::{iter, mem};
 
 use rustc_hir::def_id::{DefId, CRATE_DEF_INDEX};
@@ -46,6 +47,20 @@ fn resolve_did<'mir, 'tcx>(tcx: TyCtxt<'tcx>, path: &[&str]) -> InterpResult<'tc
         })
 }
 
+/// Whether `def_id` belongs to one of the given crates (e.g. `foo`) or is below one of the given
+/// item paths (e.g. `foo::module`).
+pub(crate) fn is_in_paths(tcx: TyCtxt<'_>, def_id: DefId, paths: &[String]) -> bool {
+    let krate = tcx.crate_name(def_id.krate);
+    let path = tcx.def_path_str(def_id);
+    // Local items are printed without their crate.
+    let full_path = if def_id.is_local() { format!("{}::{}", krate, path) } else { path };
+    paths.iter().any(|path| {
+        &*krate.as_str() == path.as_str()
+            || full_path == *path
+            || full_path.starts_with(&format!("{}::", path))
+    })
+}
+
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     /// Gets an instance for a path.
     fn resolve_path(&self, path: &[&str]) -> InterpResult<'tcx, ty::Instance<'tcx>> {
@@ -99,7 +114,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             getrandom::getrandom(&mut data)
                 .map_err(|err| err_unsup_format!("getrandom failed: {}", err))?;
         } else {
-            let rng = this.memory.extra.rng.get_mut();
+            let rng = this.memory.extra.rngs.get_mut(RngPurpose::Program);
             rng.fill_bytes(&mut data);
         }
 
@@ -189,17 +204,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         {
             let mut visitor = UnsafeCellVisitor {
                 ecx: this,
-                unsafe_cell_action: |place| {
-                    trace!("unsafe_cell_action on {:?}", place.ptr);
-                    // We need a size to go on.
-                    let unsafe_cell_size = this
-                        .size_and_align_of_mplace(place)?
-                        .map(|(size, _)| size)
-                        // for extern types, just cover what we can
-                        .unwrap_or_else(|| place.layout.size);
+                unsafe_cell_action: |ptr, unsafe_cell_size| {
+                    trace!("unsafe_cell_action on {:?}", ptr);
                     // Now handle this `UnsafeCell`, unless it is empty.
                     if unsafe_cell_size != Size::ZERO {
-                        unsafe_cell_action(place.ptr, unsafe_cell_size)
+                        unsafe_cell_action(ptr, unsafe_cell_size)
                     } else {
                         Ok(())
                     }
@@ -217,16 +226,111 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         /// whether we are inside an `UnsafeCell` or not.
         struct UnsafeCellVisitor<'ecx, 'mir, 'tcx, F>
         where
-            F: FnMut(MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx>,
+            F: FnMut(Scalar<Tag>, Size) -> InterpResult<'tcx>,
         {
             ecx: &'ecx MiriEvalContext<'mir, 'tcx>,
             unsafe_cell_action: F,
         }
 
+        impl<'ecx, 'mir, 'tcx, F> UnsafeCellVisitor<'ecx, 'mir, 'tcx, F>
+        where
+            F: FnMut(Scalar<Tag>, Size) -> InterpResult<'tcx>,
+        {
+            fn is_unsafe_cell(&self, layout: TyLayout<'tcx>) -> bool {
+                match layout.ty.kind {
+                    ty::Adt(adt, _) =>
+                        Some(adt.did) == self.ecx.tcx.lang_items().unsafe_cell_type(),
+                    _ => false,
+                }
+            }
+
+            /// Calls the `UnsafeCell` action for every range of `v` that is inside an
+            /// `UnsafeCell` in *some* variant of the enums (and in *some* field of the unions)
+            /// in `v`. This is computed from the layout alone: reading the discriminant would be
+            /// subject to Stacked Borrows rules, leading to all sorts of "funny" recursion.
+            fn visit_variants(&mut self, v: MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
+                let mut ranges = Vec::new();
+                self.unsafe_cell_ranges(v.layout, Size::ZERO, &mut ranges)?;
+                ranges.sort_by_key(|&(start, _)| start);
+                // The ranges of different variants or union fields can overlap.
+                let mut merged: Vec<(Size, Size)> = Vec::new();
+                for (start, end) in ranges {
+                    match merged.last_mut() {
+                        Some((_, last_end)) if start <= *last_end =>
+                            *last_end = std::cmp::max(*last_end, end),
+                        _ => merged.push((start, end)),
+                    }
+                }
+                for (start, end) in merged {
+                    let ptr = v.ptr.ptr_wrapping_offset(start, self.ecx);
+                    (self.unsafe_cell_action)(ptr, end - start)?;
+                }
+                Ok(())
+            }
+
+            /// Collects the start and end offsets of the `UnsafeCell`s that a value of the
+            /// sized `layout` at `offset` may contain.
+            fn unsafe_cell_ranges(
+                &self,
+                layout: TyLayout<'tcx>,
+                offset: Size,
+                ranges: &mut Vec<(Size, Size)>,
+            ) -> InterpResult<'tcx> {
+                if self.is_unsafe_cell(layout) {
+                    if layout.size != Size::ZERO {
+                        ranges.push((offset, offset + layout.size));
+                    }
+                    return Ok(());
+                }
+                if self.ecx.type_is_freeze(layout.ty) {
+                    return Ok(());
+                }
+                let variants: Vec<TyLayout<'tcx>> = match &layout.variants {
+                    layout::Variants::Multiple { variants, .. } => variants
+                        .indices()
+                        .map(|index| layout.for_variant(self.ecx, index))
+                        .collect(),
+                    layout::Variants::Single { .. } => vec![layout],
+                };
+                for variant in variants {
+                    match variant.fields {
+                        layout::FieldPlacement::Array { stride, count } if count > 0 => {
+                            // Only look at the element type once, arrays can be huge.
+                            let mut elem_ranges = Vec::new();
+                            self.unsafe_cell_ranges(
+                                variant.field(self.ecx, 0)?,
+                                Size::ZERO,
+                                &mut elem_ranges,
+                            )?;
+                            if elem_ranges == [(Size::ZERO, stride)] {
+                                ranges.push((offset, offset + stride * count));
+                            } else if !elem_ranges.is_empty() {
+                                for i in 0..count {
+                                    let elem_offset = offset + stride * i;
+                                    ranges.extend(elem_ranges.iter().map(|&(start, end)| {
+                                        (elem_offset + start, elem_offset + end)
+                                    }));
+                                }
+                            }
+                        }
+                        _ =>
+                            for i in 0..variant.fields.count() {
+                                self.unsafe_cell_ranges(
+                                    variant.field(self.ecx, i)?,
+                                    offset + variant.fields.offset(i),
+                                    ranges,
+                                )?;
+                            },
+                    }
+                }
+                Ok(())
+            }
+        }
+
         impl<'ecx, 'mir, 'tcx, F> ValueVisitor<'mir, 'tcx, Evaluator<'tcx>>
             for UnsafeCellVisitor<'ecx, 'mir, 'tcx, F>
         where
-            F: FnMut(MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx>,
+            F: FnMut(Scalar<Tag>, Size) -> InterpResult<'tcx>,
         {
             type V = MPlaceTy<'tcx, Tag>;
 
@@ -238,14 +342,16 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // Hook to detect `UnsafeCell`.
             fn visit_value(&mut self, v: MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
                 trace!("UnsafeCellVisitor: {:?} {:?}", *v, v.layout.ty);
-                let is_unsafe_cell = match v.layout.ty.kind {
-                    ty::Adt(adt, _) =>
-                        Some(adt.did) == self.ecx.tcx.lang_items().unsafe_cell_type(),
-                    _ => false,
-                };
-                if is_unsafe_cell {
+                if self.is_unsafe_cell(v.layout) {
                     // We do not have to recurse further, this is an `UnsafeCell`.
-                    (self.unsafe_cell_action)(v)
+                    // We need a size to go on.
+                    let unsafe_cell_size = self
+                        .ecx
+                        .size_and_align_of_mplace(v)?
+                        .map(|(size, _)| size)
+                        // for extern types, just cover what we can
+                        .unwrap_or_else(|| v.layout.size);
+                    (self.unsafe_cell_action)(v.ptr, unsafe_cell_size)
                 } else if self.ecx.type_is_freeze(v.layout.ty) {
                     // This is `Freeze`, there cannot be an `UnsafeCell`
                     Ok(())
@@ -255,14 +361,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     // `Variants::Multiple`.
                     match v.layout.variants {
                         layout::Variants::Multiple { .. } => {
-                            // A multi-variant enum, or generator, or so.
-                            // Treat this like a union: without reading from memory,
-                            // we cannot determine the variant we are in. Reading from
-                            // memory would be subject to Stacked Borrows rules, leading
-                            // to all sorts of "funny" recursion.
-                            // We only end up here if the type is *not* freeze, so we just call the
-                            // `UnsafeCell` action.
-                            (self.unsafe_cell_action)(v)
+                            // A multi-variant enum, or generator, or so. Without reading from
+                            // memory, we cannot determine the variant we are in, so we consider
+                            // the `UnsafeCell`s of all variants.
+                            self.visit_variants(v)
                         }
                         layout::Variants::Single { .. } => {
                             // Proceed further, try to find where exactly that `UnsafeCell`
@@ -299,13 +401,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 }
             }
 
-            // We have to do *something* for unions.
+            // Like with enums, we do not know which union field is active, so we consider the
+            // `UnsafeCell`s of all fields.
             fn visit_union(&mut self, v: MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
-                // With unions, we fall back to whatever the type says, to hopefully be consistent
-                // with LLVM IR.
-                // FIXME: are we consistent, and is this really the behavior we want?
-                let frozen = self.ecx.type_is_freeze(v.layout.ty);
-                if frozen { Ok(()) } else { (self.unsafe_cell_action)(v) }
+                self.visit_variants(v)
             }
 
             // We should never get to a primitive, but always short-circuit somewhere above.
@@ -335,6 +434,24 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         this.layout_of(ty)
     }
 
+    /// Helper function to project to a field of a `libc` struct by name, for structs whose fields
+    /// are ordered differently on different targets.
+    fn mplace_field_named(
+        &self,
+        place: MPlaceTy<'tcx, Tag>,
+        name: &str,
+    ) -> InterpResult<'tcx, MPlaceTy<'tcx, Tag>> {
+        let this = self.eval_context_ref();
+        let adt = place.layout.ty.ty_adt_def().unwrap();
+        let idx = adt
+            .non_enum_variant()
+            .fields
+            .iter()
+            .position(|field| field.ident.name.as_str() == name)
+            .unwrap_or_else(|| bug!("no field named {} in {:?}", name, place.layout.ty));
+        this.mplace_field(place, idx as u64)
+    }
+
     // Writes several `ImmTy`s contiguosly into memory. This is useful when you have to pack
     // different values into a struct.
     fn write_packed_immediates(
@@ -368,6 +485,33 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
         Ok(())
     }
+    /// Reads the `timespec` stored in `tp`. Returns `None` if it does not describe a valid,
+    /// non-negative duration.
+    fn read_timespec(&mut self, tp: MPlaceTy<'tcx, Tag>) -> InterpResult<'tcx, Option<Duration>> {
+        let this = self.eval_context_mut();
+
+        let tv_sec = this.mplace_field(tp, 0)?;
+        let tv_sec = this.read_scalar(tv_sec.into())?.to_machine_isize(this)?;
+        let tv_nsec = this.mplace_field(tp, 1)?;
+        let tv_nsec = this.read_scalar(tv_nsec.into())?.to_machine_isize(this)?;
+
+        if tv_sec < 0 || tv_nsec < 0 || tv_nsec >= 1_000_000_000 {
+            return Ok(None);
+        }
+        Ok(Some(Duration::new(tv_sec as u64, tv_nsec as u32)))
+    }
+
+    /// Stores `duration` in the `timespec` `tp`.
+    fn write_timespec(&mut self, tp: MPlaceTy<'tcx, Tag>, duration: Duration) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let imms = [
+            immty_from_int_checked(duration.as_secs(), this.libc_ty_layout("time_t")?)?,
+            immty_from_int_checked(duration.subsec_nanos(), this.libc_ty_layout("c_long")?)?,
+        ];
+        this.write_packed_immediates(tp, &imms)
+    }
+
     /// Helper function used inside the shims of foreign functions to assert that the target
     /// platform is `platform`. It panics showing a message with the `name` of the foreign function
     /// if this is not the case.
@@ -421,8 +565,19 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     throw_unsup_format!("The {} error cannot be transformed into a raw os error", e)
                 }
             })?
+        } else if target.target_os == "windows" {
+            // FIXME: we have to complete this list of Windows error codes.
+            Scalar::from_u32(match e.kind() {
+                NotFound => 2, // ERROR_FILE_NOT_FOUND
+                PermissionDenied => 5, // ERROR_ACCESS_DENIED
+                InvalidInput => 87, // ERROR_INVALID_PARAMETER
+                BrokenPipe => 109, // ERROR_BROKEN_PIPE
+                AlreadyExists => 183, // ERROR_ALREADY_EXISTS
+                _ => {
+                    throw_unsup_format!("The {} error cannot be transformed into a raw os error", e)
+                }
+            })
         } else {
-            // FIXME: we have to implement the Windows equivalent of this.
             throw_unsup_format!(
                 "Setting the last OS error from an io::Error is unsupported for {}.",
                 target.target_os
@@ -512,6 +667,69 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         Ok((true, string_length))
     }
 
+    /// Helper function to read a null-terminated sequence of `u16`s, which is what the Windows
+    /// APIs usually handle. The null terminator is not part of the result.
+    fn read_wide_str(&self, scalar: Scalar<Tag>) -> InterpResult<'tcx, Vec<u16>> {
+        let this = self.eval_context_ref();
+        let u16_size = Size::from_bytes(2);
+
+        let mut wide = Vec::new();
+        let mut ptr = scalar;
+        loop {
+            // Windows is always little-endian.
+            let bytes = this.memory.read_bytes(ptr, u16_size)?;
+            let c = u16::from_le_bytes([bytes[0], bytes[1]]);
+            if c == 0 {
+                return Ok(wide);
+            }
+            wide.push(c);
+            ptr = ptr.ptr_offset(u16_size, this)?;
+        }
+    }
+
+    /// Helper function to write a sequence of `u16`s followed by a null terminator, which is what
+    /// the Windows APIs usually handle. Like `write_os_str_to_c_str`, this returns
+    /// `Ok((false, length))` without writing anything if `size` (counted in `u16`s) is not large
+    /// enough to fit `wide` plus the null terminator, and `Ok((true, length))` otherwise. The
+    /// length does not include the null terminator.
+    fn write_wide_str(
+        &mut self,
+        wide: &[u16],
+        scalar: Scalar<Tag>,
+        size: u64,
+    ) -> InterpResult<'tcx, (bool, u64)> {
+        let string_length = wide.len() as u64;
+        if size <= string_length {
+            return Ok((false, string_length));
+        }
+        let bytes = wide.iter().chain(iter::once(&0u16)).flat_map(|c| c.to_le_bytes().to_vec());
+        self.eval_context_mut().memory.write_bytes(scalar, bytes)?;
+        Ok((true, string_length))
+    }
+
+    /// Helper function to read an OsString from a null-terminated sequence of `u16`s.
+    fn read_os_str_from_wide_str(&self, scalar: Scalar<Tag>) -> InterpResult<'tcx, OsString> {
+        let wide = self.eval_context_ref().read_wide_str(scalar)?;
+        wide_to_os_string(&wide).ok_or_else(|| {
+            err_unsup_format!("{:?} is not a valid utf-16 string", String::from_utf16_lossy(&wide))
+                .into()
+        })
+    }
+
+    /// Helper function to write an OsStr as a null-terminated sequence of `u16`s. Like
+    /// `write_os_str_to_c_str`, nothing gets written and `Ok((false, length))` is returned if
+    /// `size` is not large enough; both are counted in `u16`s.
+    fn write_os_str_to_wide_str(
+        &mut self,
+        os_str: &OsStr,
+        scalar: Scalar<Tag>,
+        size: u64,
+    ) -> InterpResult<'tcx, (bool, u64)> {
+        let wide = os_str_to_wide(os_str)
+            .ok_or_else(|| err_unsup_format!("{:?} is not a valid utf-8 string", os_str))?;
+        self.eval_context_mut().write_wide_str(&wide, scalar, size)
+    }
+
     fn alloc_os_str_as_c_str(
         &mut self,
         os_str: &OsStr,
@@ -525,6 +743,38 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         self.write_os_str_to_c_str(os_str, arg_place.ptr, size).unwrap();
         arg_place.ptr.assert_ptr()
     }
+
+    fn alloc_os_str_as_wide_str(
+        &mut self,
+        os_str: &OsStr,
+        memkind: MemoryKind<MiriMemoryKind>
+    ) -> InterpResult<'tcx, Pointer<Tag>> {
+        let wide = os_str_to_wide(os_str)
+            .ok_or_else(|| err_unsup_format!("{:?} is not a valid utf-8 string", os_str))?;
+        let size = wide.len() as u64 + 1; // Make space for `0` terminator.
+        let this = self.eval_context_mut();
+
+        let arg_type = this.tcx.mk_array(this.tcx.types.u16, size);
+        let arg_place = this.allocate(this.layout_of(arg_type)?, memkind);
+        this.write_wide_str(&wide, arg_place.ptr, size)?;
+        Ok(arg_place.ptr.assert_ptr())
+    }
+}
+
+/// Converts a host string into the UTF-16 representation Windows programs expect.
+pub fn os_str_to_wide(os_str: &OsStr) -> Option<Vec<u16>> {
+    #[cfg(windows)]
+    return Some(std::os::windows::ffi::OsStrExt::encode_wide(os_str).collect());
+    #[cfg(not(windows))]
+    return os_str.to_str().map(|s| s.encode_utf16().collect());
+}
+
+/// Converts a UTF-16 string of a Windows program into a host string.
+pub fn wide_to_os_string(wide: &[u16]) -> Option<OsString> {
+    #[cfg(windows)]
+    return Some(std::os::windows::ffi::OsStringExt::from_wide(wide));
+    #[cfg(not(windows))]
+    return String::from_utf16(wide).ok().map(OsString::from);
 }
 
 pub fn immty_from_int_checked<'tcx>(
@@ -1,14 +1,18 @@
 use std::cell::RefCell;
 use std::cmp::max;
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 use rand::Rng;
 
-use rustc::ty::layout::HasDataLayout;
-use rustc_mir::interpret::{AllocCheck, AllocId, InterpResult, Memory, Pointer, PointerArithmetic};
-use rustc_target::abi::Size;
+use rustc::mir;
+use rustc::ty::{self, layout::HasDataLayout};
+use rustc_mir::interpret::{
+    AllocCheck, AllocId, InterpResult, Memory, Pointer, PointerArithmetic, Scalar,
+    ScalarMaybeUndef,
+};
+use rustc_target::abi::{Align, Size};
 
-use crate::{Evaluator, Tag, STACK_ADDR};
+use crate::{Evaluator, MiriEvalContextExt, Tag, STACK_ADDR};
 
 pub type MemoryExtra = RefCell<GlobalState>;
 
@@ -25,6 +29,16 @@ pub struct GlobalState {
     /// This is used as a memory address when a new pointer is casted to an integer. It
     /// is always larger than any address that was previously made part of a block.
     pub next_base_addr: u64,
+    /// Whether integers cast to pointers get wildcard tags, see `-Zmiri-permissive-provenance`.
+    pub permissive_provenance: bool,
+    /// The exposed allocations: those that a pointer was cast to an integer for. Only these can
+    /// be accessed through pointers cast from integers.
+    pub exposed: HashSet<AllocId>,
+    /// Whether using pointers cast from integers is an error, see `-Zmiri-strict-provenance`.
+    pub strict_provenance: bool,
+    /// Whether the program used a pointer cast from an integer, which was an error because of
+    /// strict provenance.
+    pub forged_from_int: bool,
 }
 
 impl Default for GlobalState {
@@ -33,11 +47,122 @@ impl Default for GlobalState {
             int_to_ptr_map: Vec::default(),
             base_addr: HashMap::default(),
             next_base_addr: STACK_ADDR,
+            permissive_provenance: false,
+            exposed: HashSet::default(),
+            strict_provenance: false,
+            forged_from_int: false,
+        }
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Handles the pointer-integer cast done by the statement that is about to be executed, if
+    /// there is one. Casting a pointer to an integer exposes its allocation. Integers that are
+    /// cast to pointers often still carry the provenance of the pointer they were cast from (as
+    /// long as no arithmetic forced them into plain addresses); then the cast is only allowed
+    /// if that allocation is exposed, and the resulting pointer has to be fixed up afterwards
+    /// with `finish_int_to_ptr_cast`.
+    fn before_ptr_int_cast(
+        &mut self,
+    ) -> InterpResult<'tcx, Option<(mir::Place<'tcx>, Pointer<Tag>)>> {
+        let this = self.eval_context_mut();
+        let (place, operand, cast_ty) = {
+            let frame = match this.stack().last() {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            let statement = match frame.block {
+                Some(block) => frame.body.basic_blocks()[block].statements.get(frame.stmt),
+                None => None,
+            };
+            let assign = match statement.map(|statement| &statement.kind) {
+                Some(mir::StatementKind::Assign(assign)) => &**assign,
+                _ => return Ok(None),
+            };
+            match assign {
+                (place, mir::Rvalue::Cast(mir::CastKind::Misc, operand, cast_ty)) =>
+                    (place.clone(), operand.clone(), *cast_ty),
+                _ => return Ok(None),
+            }
+        };
+        let to_int = match cast_ty.kind {
+            ty::Int(_) | ty::Uint(_) => true,
+            ty::RawPtr(_) => false,
+            _ => return Ok(None),
+        };
+        let operand = this.eval_operand(&operand, None)?;
+        let from_int = match operand.layout.ty.kind {
+            ty::Int(_) | ty::Uint(_) => true,
+            ty::RawPtr(_) | ty::FnPtr(_) => false,
+            _ => return Ok(None),
+        };
+        let ptr = match this.read_scalar(operand)? {
+            ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) => ptr,
+            _ => return Ok(None),
+        };
+        match (from_int, to_int) {
+            (false, true) => {
+                this.expose_ptr(ptr);
+                return Ok(None);
+            }
+            (true, false) => {}
+            _ => return Ok(None),
+        }
+        let mut global_state = this.memory.extra.intptrcast.borrow_mut();
+        if global_state.strict_provenance {
+            global_state.forged_from_int = true;
+            throw_unsup_format!(
+                "casting an integer to a pointer, which strict provenance does not allow; derive the pointer from an existing one with `wrapping_offset` instead"
+            );
+        }
+        if !global_state.exposed.contains(&ptr.alloc_id) {
+            throw_ub_format!(
+                "integer is cast to a pointer to {}, but no pointer to that allocation was cast to an integer before",
+                ptr.alloc_id
+            );
+        }
+        let ptr = Pointer::new_with_tag(ptr.alloc_id, ptr.offset, global_state.cast_tag());
+        Ok(Some((place, ptr)))
+    }
+
+    /// Replaces the result of the integer-to-pointer cast that was just executed with `ptr`, the
+    /// pointer computed by `before_ptr_int_cast`.
+    fn finish_int_to_ptr_cast(
+        &mut self,
+        place: mir::Place<'tcx>,
+        ptr: Pointer<Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let dest = this.eval_place(&place)?;
+        this.write_scalar(Scalar::Ptr(ptr), dest)
+    }
+
+    /// Remembers that `ptr` was cast to an integer, so that pointers cast from integers may use
+    /// its allocation (and with permissive provenance, its tag).
+    fn expose_ptr(&mut self, ptr: Pointer<Tag>) {
+        let this = self.eval_context_mut();
+        let strict_provenance = this.memory.extra.intptrcast.borrow().strict_provenance;
+        if strict_provenance && this.machine.first_ptr_to_int_cast.is_none() {
+            // Errors about pointers cast from integers point here.
+            this.machine.first_ptr_to_int_cast = Some(this.generate_stacktrace(None));
+        }
+        let mut global_state = this.memory.extra.intptrcast.borrow_mut();
+        global_state.exposed.insert(ptr.alloc_id);
+        if !global_state.permissive_provenance {
+            return;
+        }
+        if let Some(stacked_borrows) = &this.memory.extra.stacked_borrows {
+            stacked_borrows.borrow_mut().expose(ptr.tag);
         }
     }
 }
 
 impl<'mir, 'tcx> GlobalState {
+    pub fn new(permissive_provenance: bool, strict_provenance: bool) -> Self {
+        GlobalState { permissive_provenance, strict_provenance, ..Default::default() }
+    }
+
     pub fn int_to_ptr(
         int: u64,
         memory: &Memory<'mir, 'tcx, Evaluator<'tcx>>,
@@ -45,11 +170,18 @@ impl<'mir, 'tcx> GlobalState {
         if int == 0 {
             throw_unsup!(InvalidNullPointerUsage);
         }
+        if memory.extra.intptrcast.borrow().strict_provenance {
+            memory.extra.intptrcast.borrow_mut().forged_from_int = true;
+            throw_unsup_format!(
+                "using integer {:#x} as a pointer, which strict provenance does not allow; derive the pointer from an existing one with `wrapping_offset` instead",
+                int
+            );
+        }
 
         let global_state = memory.extra.intptrcast.borrow();
         let pos = global_state.int_to_ptr_map.binary_search_by_key(&int, |(addr, _)| *addr);
 
-        Ok(match pos {
+        let ptr = match pos {
             Ok(pos) => {
                 let (_, alloc_id) = global_state.int_to_ptr_map[pos];
                 // `int` is equal to the starting address for an allocation, the offset should be
@@ -71,7 +203,22 @@ impl<'mir, 'tcx> GlobalState {
                     throw_unsup!(DanglingPointerDeref)
                 }
             }
-        })
+        };
+        // Pointers cast from integers may only access allocations whose address was exposed, and
+        // the borrow stacks decide which of the exposed pointers they act like.
+        if !global_state.exposed.contains(&ptr.alloc_id) {
+            throw_ub_format!(
+                "pointer cast from integer {:#x} is used to access {}, but no pointer to that allocation was cast to an integer before",
+                int,
+                ptr.alloc_id
+            );
+        }
+        Ok(Pointer::new_with_tag(ptr.alloc_id, ptr.offset, global_state.cast_tag()))
+    }
+
+    /// The tag of pointers cast from integers.
+    fn cast_tag(&self) -> Tag {
+        if self.permissive_provenance { Tag::Wildcard } else { Tag::Untagged }
     }
 
     pub fn ptr_to_int(
@@ -91,7 +238,7 @@ impl<'mir, 'tcx> GlobalState {
                 // This allocation does not have a base address yet, pick one.
                 // Leave some space to the previous allocation, to give it some chance to be less aligned.
                 let slack = {
-                    let mut rng = memory.extra.rng.borrow_mut();
+                    let mut rng = memory.extra.rngs.get(RngPurpose::Addresses).borrow_mut();
                     // This means that `(global_state.next_base_addr + slack) % 16` is uniformly distributed.
                     rng.gen_range(0, 16)
                 };
@@ -125,6 +272,33 @@ impl<'mir, 'tcx> GlobalState {
         Ok(dl.overflowing_offset(base_addr, ptr.offset.bytes()).0)
     }
 
+    /// Lets `new_id`, the allocation that replaced `old_id` in a `realloc`, take over the address
+    /// of `old_id`, which must have one. Does nothing if `new_id` needs a stricter alignment, or if
+    /// it is larger and would then overlap with allocations that got their address later.
+    pub fn take_over_address(
+        &mut self,
+        old_id: AllocId,
+        new_id: AllocId,
+        new_size: Size,
+        new_align: Align,
+    ) {
+        let base_addr = self.base_addr[&old_id];
+        if base_addr % new_align.bytes() != 0 {
+            return;
+        }
+        // Like in `ptr_to_int`, zero-sized allocations still take up one address.
+        let end_addr = base_addr.checked_add(max(new_size.bytes(), 1)).unwrap();
+        let pos = self.int_to_ptr_map.binary_search_by_key(&base_addr, |(addr, _)| *addr).unwrap();
+        match self.int_to_ptr_map.get(pos + 1) {
+            Some(&(next_addr, _)) if end_addr > next_addr => return,
+            Some(_) => {}
+            None => self.next_base_addr = max(self.next_base_addr, end_addr),
+        }
+        // The old allocation is dead, so integers in its range now refer to the new one.
+        self.int_to_ptr_map[pos].1 = new_id;
+        self.base_addr.insert(new_id, base_addr);
+    }
+
     /// Shifts `addr` to make it aligned with `align` by rounding `addr` to the smallest multiple
     /// of `align` that is larger or equal to `addr`
     fn align_addr(addr: u64, align: u64) -> u64 {
@@ -17,48 +17,111 @@ extern crate rustc_data_structures;
 extern crate rustc_mir;
 extern crate rustc_target;
 
+mod alloc_stats;
+mod atomic_stats;
+mod clock;
+mod data_race;
 mod diagnostics;
 mod eval;
 mod helpers;
 mod intptrcast;
 mod machine;
+mod memory_limit;
 mod mono_hash_map;
 mod operator;
+mod progress;
 mod range_map;
+mod reinterpret;
+mod rng;
+mod schedule;
 mod shims;
+mod shrink;
+mod spin_loop;
 mod stacked_borrows;
+mod tag_gc;
+mod thread;
+mod tree_borrows;
+mod vtable;
+mod weak_memory;
 
 // Make all those symbols available in the same place as our own.
 pub use rustc_mir::interpret::*;
 // Resolve ambiguity.
 pub use rustc_mir::interpret::{self, AllocMap, PlaceTy};
 
+pub use crate::shims::atexit::{AtExitHandler, EvalContextExt as AtExitEvalContextExt};
 pub use crate::shims::dlsym::{Dlsym, EvalContextExt as DlsymEvalContextExt};
 pub use crate::shims::env::{EnvVars, EvalContextExt as EnvEvalContextExt};
-pub use crate::shims::foreign_items::EvalContextExt as ForeignItemsEvalContextExt;
-pub use crate::shims::fs::{DirHandler, EvalContextExt as FileEvalContextExt, FileHandler};
+pub use crate::shims::foreign_items::{
+    EmulateByNameResult, EvalContextExt as ForeignItemsEvalContextExt,
+};
+pub use crate::shims::fs::{
+    DirHandler, EvalContextExt as FileEvalContextExt, FileHandler, ScriptedIoError,
+    SCRIPTABLE_IO_OPS,
+};
 pub use crate::shims::intrinsics::EvalContextExt as IntrinsicsEvalContextExt;
-pub use crate::shims::panic::{CatchUnwindData, EvalContextExt as PanicEvalContextExt};
+pub use crate::shims::net::{EvalContextExt as NetEvalContextExt, NetMode};
+pub use crate::shims::nondet::{EvalContextExt as NondetEvalContextExt, NondetBytes};
+pub use crate::shims::panic::{
+    CatchUnwindData, EvalContextExt as PanicEvalContextExt, ExitHandlerKind,
+};
+pub use crate::shims::pipe::PipeHandler;
+pub use crate::shims::rlimit::{
+    EvalContextExt as RlimitEvalContextExt, ResourceLimit, DEFAULT_RESOURCE_LIMITS,
+};
+pub use crate::shims::signal::{EvalContextExt as SignalEvalContextExt, SignalAction};
+pub use crate::shims::strconv::EvalContextExt as StrconvEvalContextExt;
+pub use crate::shims::sync::{
+    BarrierWaiter, CondWaiter, EvalContextExt as SyncEvalContextExt, FutexWaiter, HeldLocks,
+    OnceRun, RwLockReaders, SemWaiter,
+};
+pub use crate::shims::thread::EvalContextExt as PthreadEvalContextExt;
 pub use crate::shims::time::EvalContextExt as TimeEvalContextExt;
 pub use crate::shims::tls::{EvalContextExt as TlsEvalContextExt, TlsData};
 pub use crate::shims::EvalContextExt as ShimsEvalContextExt;
 
+pub use crate::alloc_stats::AllocStats;
+pub use crate::atomic_stats::{AtomicOp, AtomicStats, EvalContextExt as AtomicStatsEvalContextExt};
+pub use crate::clock::{cpu_time, Clock};
+pub use crate::data_race::{AtomicOrdering, EvalContextExt as DataRaceEvalContextExt};
 pub use crate::diagnostics::{
-    register_diagnostic, report_diagnostic, EvalContextExt as DiagnosticsEvalContextExt, NonHaltingDiagnostic,
+    register_diagnostic, report_diagnostic, report_leaks, report_threads, AllocBacktraces,
+    EvalContextExt as DiagnosticsEvalContextExt, NonHaltingDiagnostic,
+};
+pub use crate::eval::{
+    create_ecx, eval_main, LiveThreads, MiriConfig, TerminationInfo, TrackedAllocs,
+    ABORT_EXIT_CODE,
 };
-pub use crate::eval::{create_ecx, eval_main, MiriConfig, TerminationInfo};
 pub use crate::helpers::EvalContextExt as HelpersEvalContextExt;
+pub use crate::intptrcast::EvalContextExt as IntPtrCastEvalContextExt;
 pub use crate::machine::{
-    AllocExtra, Evaluator, FrameData, MemoryExtra, MiriEvalContext, MiriEvalContextExt,
-    MiriMemoryKind, NUM_CPUS, PAGE_SIZE, STACK_ADDR, STACK_SIZE,
+    AllocExtra, EvalContextExt as MachineEvalContextExt, Evaluator, FrameData, MemoryExtra,
+    MiriEvalContext, MiriEvalContextExt, MiriMemoryKind, NUM_CPUS, PAGE_SIZE, PID, STACK_ADDR,
+    STACK_SIZE,
 };
+pub use crate::memory_limit::EvalContextExt as MemoryLimitEvalContextExt;
 pub use crate::mono_hash_map::MonoHashMap;
 pub use crate::operator::EvalContextExt as OperatorEvalContextExt;
 pub use crate::range_map::RangeMap;
+pub use crate::reinterpret::EvalContextExt as ReinterpretEvalContextExt;
+pub use crate::rng::{RngPurpose, Rngs};
+pub use crate::schedule::{parse_schedule, Schedule, DEFAULT_MAX_DEVIATIONS};
+pub use crate::shrink::FailureClass;
+pub use crate::spin_loop::EvalContextExt as SpinLoopEvalContextExt;
 pub use crate::stacked_borrows::{
-    EvalContextExt as StackedBorEvalContextExt, Item, Permission, PtrId, Stack,
-    Stacks, Tag,
+    EvalContextExt as StackedBorEvalContextExt, Item, Permission, PtrId, RefKind, Stack,
+    Stacks, Tag, TagHistory, TagRange,
+};
+pub use crate::tag_gc::{
+    EvalContextExt as TagGcEvalContextExt, VisitTags, DEFAULT_TAG_GC_INTERVAL,
+};
+pub use crate::tree_borrows::{EvalContextExt as TreeBorEvalContextExt, Tree};
+pub use crate::vtable::EvalContextExt as VtableEvalContextExt;
+pub use crate::thread::{
+    BlockReason, BlockResult, EvalContextExt as ThreadEvalContextExt, Thread, ThreadId,
+    ThreadLocalStatics, ThreadManager, ThreadState,
 };
+pub use crate::weak_memory::EvalContextExt as WeakMemoryEvalContextExt;
 
 /// Insert rustc arguments at the beginning of the argument list that Miri wants to be
 /// set per default, for maximal validation power.
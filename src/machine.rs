@@ -3,10 +3,11 @@
 
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::num::NonZeroU64;
+use std::time::Duration;
 
-use rand::rngs::StdRng;
 
 use rustc_hir::def_id::DefId;
 use rustc::mir;
@@ -15,9 +16,13 @@ use rustc::ty::{
     layout::{LayoutOf, Size},
     Ty, TyCtxt,
 };
-use rustc_span::{source_map::Span, symbol::sym};
+use rustc_span::{
+    source_map::Span,
+    symbol::{sym, Symbol},
+};
 use syntax::attr;
 
+use crate::progress::{self, ProgressEvents};
 use crate::*;
 
 // Some global facts about the emulated machine.
@@ -25,6 +30,9 @@ pub const PAGE_SIZE: u64 = 4 * 1024; // FIXME: adjust to target architecture
 pub const STACK_ADDR: u64 = 32 * PAGE_SIZE; // not really about the "stack", but where we start assigning integer addresses to allocations
 pub const STACK_SIZE: u64 = 16 * PAGE_SIZE; // whatever
 pub const NUM_CPUS: u64 = 1;
+/// The process id reported to the program. This is not the id of the host process, so that
+/// programs that embed it in their output or in file names behave deterministically.
+pub const PID: u32 = 1000;
 
 /// Extra data stored with each stack frame
 #[derive(Debug)]
@@ -37,6 +45,27 @@ pub struct FrameData<'tcx> {
     /// we stop unwinding, use the `CatchUnwindData` to
     /// store the panic payload, and continue execution in the parent frame.
     pub catch_panic: Option<CatchUnwindData<'tcx>>,
+
+    /// If this frame runs a test, the name of that test, for progress events.
+    pub test: Option<String>,
+
+    /// Memory allocated by `alloca` in this frame. It is freed when the frame is popped.
+    pub alloca: Vec<Pointer<Tag>>,
+
+    /// If this frame was pushed to run another thread, the thread that waits for it. That thread
+    /// becomes the active thread again when this frame is popped.
+    pub resumes_thread: Option<ThreadId>,
+
+    /// If Miri pushed this frame to run a TLS destructor or an `atexit` handler, which of the two.
+    /// Unwinding out of such a frame aborts the program.
+    pub exit_handler: Option<ExitHandlerKind>,
+}
+
+impl<'tcx> VisitTags for FrameData<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        self.catch_panic.visit_tags(visit);
+        self.alloca.visit_tags(visit);
+    }
 }
 
 /// Extra memory kinds
@@ -48,6 +77,8 @@ pub enum MiriMemoryKind {
     C,
     /// Windows `HeapAlloc` memory.
     WinHeap,
+    /// `alloca` memory, freed when the frame that allocated it is popped.
+    Alloca,
     /// Memory for env vars and args, errno and other parts of the machine-managed environment.
     Env,
     /// Rust statics.
@@ -66,30 +97,106 @@ impl Into<MemoryKind<MiriMemoryKind>> for MiriMemoryKind {
 pub struct AllocExtra {
     /// Stacked Borrows state is only added if it is enabled.
     pub stacked_borrows: Option<stacked_borrows::AllocExtra>,
+    /// Tree Borrows state is added instead of Stacked Borrows state if it is enabled.
+    pub tree_borrows: Option<tree_borrows::AllocExtra>,
+    /// Data race detection state is only added if it is enabled.
+    pub data_race: Option<data_race::AllocExtra>,
+    /// Whether `-Zmiri-track-alloc-id` reports about this allocation.
+    pub(crate) tracked: bool,
+    /// Whether the backtrace of this heap allocation is recorded for the leak report.
+    pub(crate) heap_backtrace: bool,
+    /// The memory usage this allocation is counted in, if `-Zmiri-memory-limit` is set.
+    pub(crate) memory_limit: Option<memory_limit::MemoryExtra>,
+    /// Where the writes to this allocation are counted, to recognize spin loops.
+    pub(crate) spin_loop: spin_loop::MemoryExtra,
 }
 
 /// Extra global memory data
 #[derive(Clone, Debug)]
 pub struct MemoryExtra {
+    /// Shared by Stacked Borrows and Tree Borrows.
     pub stacked_borrows: Option<stacked_borrows::MemoryExtra>,
+    /// Whether Tree Borrows is used instead of Stacked Borrows.
+    pub tree_borrows: bool,
+    pub data_race: Option<data_race::MemoryExtra>,
     pub intptrcast: intptrcast::MemoryExtra,
+    /// Only present if `-Zmiri-memory-limit` is set.
+    pub memory_limit: Option<memory_limit::MemoryExtra>,
+    pub(crate) spin_loop: spin_loop::MemoryExtra,
+
+    /// The allocations whose creation and deallocation are reported.
+    pub(crate) tracked_alloc_ids: TrackedAllocs,
+
+    /// Whether the backtraces of heap allocations are recorded, for the leak report.
+    pub(crate) record_heap_allocs: bool,
+
+    /// The allocations of statics, from which the leak report looks for leaked allocations that
+    /// are still reachable.
+    pub(crate) static_allocs: RefCell<HashSet<AllocId>>,
+
+    /// The random number generators used for resolving non-determinism.
+    pub(crate) rngs: Rngs,
+
+    /// The machine-managed allocations backing extern statics, by link name.
+    pub(crate) extern_statics: HashMap<Symbol, AllocId>,
 
-    /// The random number generator used for resolving non-determinism.
-    pub(crate) rng: RefCell<StdRng>,
+    /// The allocations backing `#[thread_local]` statics in threads other than the main thread.
+    pub(crate) thread_local_statics: ThreadLocalStatics,
 }
 
 impl MemoryExtra {
-    pub fn new(rng: StdRng, stacked_borrows: bool, tracked_pointer_tag: Option<PtrId>) -> Self {
-        let stacked_borrows = if stacked_borrows {
-            Some(Rc::new(RefCell::new(stacked_borrows::GlobalState::new(tracked_pointer_tag))))
+    pub fn new(config: &MiriConfig) -> Self {
+        let stacked_borrows = if config.stacked_borrows || config.tree_borrows {
+            Some(Rc::new(RefCell::new(stacked_borrows::GlobalState::new(
+                config.tracked_pointer_tags.clone(),
+                config.dumped_borrow_stacks,
+                config.stacked_borrows_exempt.clone(),
+                config.raw_pointer_tagging,
+                config.protectors,
+            ))))
+        } else {
+            None
+        };
+        let data_race = if config.data_race_detector {
+            Some(Rc::new(RefCell::new(data_race::GlobalState::new(config.weak_memory_emulation))))
         } else {
             None
         };
         MemoryExtra {
             stacked_borrows,
-            intptrcast: Default::default(),
-            rng: RefCell::new(rng),
+            tree_borrows: config.tree_borrows,
+            data_race,
+            intptrcast: RefCell::new(intptrcast::GlobalState::new(
+                config.permissive_provenance,
+                config.strict_provenance,
+            )),
+            memory_limit: config
+                .memory_limit
+                .map(|limit| Rc::new(RefCell::new(memory_limit::GlobalState::new(limit)))),
+            spin_loop: Default::default(),
+            tracked_alloc_ids: config.tracked_alloc_ids.clone(),
+            // The leak report and `-Zmiri-alloc-stats` show where heap allocations were made.
+            record_heap_allocs: !config.ignore_leaks || config.alloc_stats,
+            static_allocs: RefCell::new(HashSet::new()),
+            rngs: Rngs::new(config.seed.unwrap_or(0)),
+            extern_statics: HashMap::new(),
+            thread_local_statics: Default::default(),
+        }
+    }
+
+    /// Registers the extern statics whose contents are managed by the machine. Must be called
+    /// after the environment was initialized.
+    pub fn init_extern_statics<'mir, 'tcx>(
+        this: &mut MiriEvalContext<'mir, 'tcx>,
+    ) -> InterpResult<'tcx> {
+        if this.tcx.sess.target.target.target_os == "linux" {
+            let environ = this.machine.env_vars.environ.expect("environment must be initialized");
+            this.memory
+                .extra
+                .extern_statics
+                .insert(Symbol::intern("environ"), environ.ptr.assert_ptr().alloc_id);
         }
+        Ok(())
     }
 }
 
@@ -97,7 +204,7 @@ impl MemoryExtra {
 pub struct Evaluator<'tcx> {
     /// Environment variables set by `setenv`.
     /// Miri does not expose env vars from the host to the emulated program.
-    pub(crate) env_vars: EnvVars,
+    pub(crate) env_vars: EnvVars<'tcx>,
 
     /// Program arguments (`Option` because we can only initialize them after creating the ecx).
     /// These are *pointers* to argc/argv because macOS.
@@ -106,12 +213,51 @@ pub struct Evaluator<'tcx> {
     pub(crate) argv: Option<Scalar<Tag>>,
     pub(crate) cmd_line: Option<Scalar<Tag>>,
 
-    /// Last OS error location in memory. It is a 32-bit integer.
+    /// Last OS error location in memory of the active thread. It is a 32-bit integer.
     pub(crate) last_error: Option<MPlaceTy<'tcx, Tag>>,
 
     /// TLS state.
     pub(crate) tls: TlsData<'tcx>,
 
+    /// The threads of the program.
+    pub(crate) threads: ThreadManager<'tcx>,
+
+    /// The read locks that the threads hold on each `pthread_rwlock_t`.
+    pub(crate) rwlock_readers: RwLockReaders,
+
+    /// The pthread mutexes and critical sections that the threads hold.
+    pub(crate) held_locks: HeldLocks,
+
+    /// The threads that wait for a condition variable, in the order they started waiting.
+    pub(crate) cond_waiters: Vec<CondWaiter>,
+
+    /// The threads that wait in `futex(FUTEX_WAIT)`, in the order they started waiting.
+    pub(crate) futex_waiters: Vec<FutexWaiter>,
+
+    /// The threads that wait for a semaphore, in the order they started waiting.
+    pub(crate) sem_waiters: Vec<SemWaiter>,
+
+    /// The threads that wait at a `pthread_barrier_t`, in the order they arrived.
+    pub(crate) barrier_waiters: Vec<BarrierWaiter>,
+
+    /// The once-only initializations whose initialization routine is running.
+    pub(crate) once_runs: Vec<OnceRun>,
+
+    /// Which thread the scheduler started whenever it had a choice, and the choices to replay.
+    pub(crate) schedule: Schedule,
+
+    /// The vtables that were found to be valid for a `dyn` type.
+    pub(crate) checked_vtables: HashSet<(AllocId, Ty<'tcx>)>,
+
+    /// Where the allocations tracked by `-Zmiri-track-alloc-id` were allocated and deallocated.
+    pub(crate) alloc_backtraces: HashMap<AllocId, AllocBacktraces<'tcx>>,
+
+    /// Where the live heap allocations were allocated, for the leak report.
+    pub(crate) heap_alloc_backtraces: HashMap<AllocId, Vec<FrameInfo<'tcx>>>,
+
+    /// Where a pointer was first cast to an integer, if `-Zmiri-strict-provenance` is set.
+    pub(crate) first_ptr_to_int_cast: Option<Vec<FrameInfo<'tcx>>>,
+
     /// If enabled, the `env_vars` field is populated with the host env vars during initialization
     /// and random number generation is delegated to the host.
     pub(crate) communicate: bool,
@@ -119,16 +265,113 @@ pub struct Evaluator<'tcx> {
     /// Whether to enforce the validity invariant.
     pub(crate) validate: bool,
 
+    /// The clock observed by the program, either the host's or a virtual one.
+    pub(crate) clock: Clock,
+
+    /// The number of basic blocks executed so far.
+    pub(crate) basic_block_count: u64,
+
+    /// If set, dump the state of all threads whenever this many basic blocks were executed.
+    pub(crate) dump_threads_interval: Option<u64>,
+
+    /// If set, remove dead tags from the borrow stacks whenever this many basic blocks were
+    /// executed.
+    pub(crate) tag_gc_interval: Option<u64>,
+
+    /// The probability with which file system operations fail with an injected error.
+    pub(crate) io_error_rate: f64,
+
+    /// The failures scripted with `-Zmiri-io-error`.
+    pub(crate) io_error_script: Vec<ScriptedIoError>,
+
+    /// How often each operation in `SCRIPTABLE_IO_OPS` was called on a file, so that scripted
+    /// failures can be matched.
+    pub(crate) io_calls: HashMap<&'static str, u64>,
+
+    /// Whether file reads and writes may transfer fewer bytes than requested.
+    pub(crate) short_io: bool,
+
     pub(crate) file_handler: FileHandler,
     pub(crate) dir_handler: DirHandler,
+    pub(crate) pipe_handler: PipeHandler,
+
+    /// Whether Miri's shims take precedence over functions the program exports under the same name.
+    pub(crate) prefer_shims: bool,
+
+    /// Whether memory leaks are ignored when the program ends with `_exit` or `_Exit`.
+    pub(crate) ignore_leaks_on_immediate_exit: bool,
+
+    /// The functions the program exports, by symbol name, as found by `lookup_exported_symbol`.
+    pub(crate) exported_symbols_cache: HashMap<String, Option<ty::Instance<'tcx>>>,
+
+    /// How many allocations C's `realloc` replaced, invalidating the pointers passed to it.
+    pub(crate) c_reallocations: u64,
+
+    /// Where to report progress to, if `-Zmiri-progress-events` is set.
+    pub(crate) progress_events: Option<ProgressEvents>,
+
+    /// The number of CPUs the program sees, e.g. through `sysconf(_SC_NPROCESSORS_ONLN)`.
+    pub(crate) num_cpus: u64,
+
+    /// How the network shims behave, as set by `-Zmiri-net`.
+    pub(crate) net: NetMode,
+
+    /// The limits reported by `getrlimit`, by the name of the resource.
+    pub(crate) resource_limits: HashMap<String, ResourceLimit>,
+
+    /// The bytes handed out by `miri_nondet_bytes`, and the ones to replay.
+    pub(crate) nondet: NondetBytes,
+
+    /// Whether the output of the program and Miri's non-error diagnostics are dropped, when the
+    /// program is only run to find out whether it fails.
+    pub(crate) discard_output: bool,
+
+    /// Counts of the atomic operations per location, if `-Zmiri-atomic-stats` is set.
+    pub(crate) atomic_stats: Option<AtomicStats>,
+
+    /// Counts of the heap allocations per allocation site, if `-Zmiri-alloc-stats` is set.
+    pub(crate) alloc_stats: Option<AllocStats>,
+
+    /// The labels given to allocations with `miri_tag_allocation`, for the leak report.
+    pub(crate) allocation_tags: HashMap<AllocId, String>,
+
+    /// The handlers registered with `atexit` or `__cxa_atexit` that did not run yet.
+    pub(crate) atexit_handlers: Vec<AtExitHandler<'tcx>>,
+
+    /// The actions installed with `signal` or `sigaction`, by signal number.
+    pub(crate) signal_actions: HashMap<i32, SignalAction<'tcx>>,
+    /// The alternate signal stack installed with `sigaltstack`.
+    pub(crate) signal_stack: Option<MPlaceTy<'tcx, Tag>>,
 
     /// The temporary used for storing the argument of
     /// the call to `miri_start_panic` (the panic payload) when unwinding.
     pub(crate) panic_payload: Option<ImmTy<'tcx, Tag>>,
+    /// Where the panic whose payload is `panic_payload` started.
+    pub(crate) panic_site: Option<Span>,
+}
+
+impl<'tcx> VisitTags for Evaluator<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        self.env_vars.visit_tags(visit);
+        self.argc.visit_tags(visit);
+        self.argv.visit_tags(visit);
+        self.cmd_line.visit_tags(visit);
+        self.last_error.visit_tags(visit);
+        self.tls.visit_tags(visit);
+        self.threads.visit_tags(visit);
+        for (_, arg) in &self.atexit_handlers {
+            arg.visit_tags(visit);
+        }
+        for action in self.signal_actions.values() {
+            action.visit_tags(visit);
+        }
+        self.signal_stack.visit_tags(visit);
+        self.panic_payload.visit_tags(visit);
+    }
 }
 
 impl<'tcx> Evaluator<'tcx> {
-    pub(crate) fn new(communicate: bool, validate: bool) -> Self {
+    pub(crate) fn new(config: &MiriConfig) -> Self {
         Evaluator {
             // `env_vars` could be initialized properly here if `Memory` were available before
             // calling this method.
@@ -138,11 +381,56 @@ impl<'tcx> Evaluator<'tcx> {
             cmd_line: None,
             last_error: None,
             tls: TlsData::default(),
-            communicate,
-            validate,
+            threads: ThreadManager::default(),
+            rwlock_readers: RwLockReaders::default(),
+            held_locks: HeldLocks::default(),
+            cond_waiters: Vec::new(),
+            futex_waiters: Vec::new(),
+            sem_waiters: Vec::new(),
+            barrier_waiters: Vec::new(),
+            once_runs: Vec::new(),
+            schedule: Schedule::new(config.replay_schedule.clone()),
+            checked_vtables: HashSet::new(),
+            alloc_backtraces: HashMap::new(),
+            heap_alloc_backtraces: HashMap::new(),
+            first_ptr_to_int_cast: None,
+            communicate: config.communicate,
+            validate: config.validate,
+            clock: Clock::new(
+                config.communicate,
+                config.start_time.map(Duration::from_secs),
+                Duration::from_nanos(
+                    config.clock_step.unwrap_or(crate::clock::NANOSECONDS_PER_BASIC_BLOCK),
+                ),
+            ),
+            basic_block_count: 0,
+            dump_threads_interval: config.dump_threads_interval,
+            tag_gc_interval: config.tag_gc_interval,
+            io_error_rate: config.io_error_rate,
+            io_error_script: config.io_error_script.clone(),
+            io_calls: HashMap::new(),
+            short_io: config.short_io,
             file_handler: Default::default(),
-            dir_handler: Default::default(),
+            dir_handler: DirHandler::new(config.deterministic_readdir),
+            pipe_handler: Default::default(),
+            prefer_shims: config.prefer_shims,
+            ignore_leaks_on_immediate_exit: config.ignore_leaks_on_immediate_exit,
+            exported_symbols_cache: HashMap::new(),
+            c_reallocations: 0,
+            progress_events: config.progress_events.as_ref().map(|path| ProgressEvents::create(path)),
+            num_cpus: config.num_cpus.unwrap_or(NUM_CPUS),
+            net: config.net,
+            resource_limits: crate::shims::rlimit::resource_limits(config.resource_limits.clone()),
+            nondet: NondetBytes { input: config.nondet_input.clone(), recorded: Vec::new() },
+            discard_output: false,
+            atomic_stats: if config.atomic_stats { Some(AtomicStats::default()) } else { None },
+            alloc_stats: if config.alloc_stats { Some(AllocStats::default()) } else { None },
+            allocation_tags: HashMap::new(),
+            atexit_handlers: Vec::new(),
+            signal_actions: HashMap::new(),
+            signal_stack: None,
             panic_payload: None,
+            panic_site: None,
         }
     }
 }
@@ -166,6 +454,34 @@ impl<'mir, 'tcx> MiriEvalContextExt<'mir, 'tcx> for MiriEvalContext<'mir, 'tcx>
     }
 }
 
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: MiriEvalContextExt<'mir, 'tcx> {
+    /// Executes the next statement or terminator like `InterpCx::step`, along with the
+    /// bookkeeping and checks Miri does around every step. Everything that runs the program uses
+    /// this instead of `step`.
+    fn step_with_hooks(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        // Popping a frame of a thread that exits early makes its caller continue with its cleanup
+        // block, but the thread must not run any more code.
+        if this.machine.threads.active_thread_exits_early() {
+            this.frame_mut().block = None;
+        }
+        this.note_access_site();
+        this.sb_note_step();
+        this.check_reinterpreting_read()?;
+        // The provenance of casts between pointers and integers is handled around the cast, see
+        // `crate::intptrcast`.
+        let int_to_ptr_cast = this.before_ptr_int_cast()?;
+        let more = this.step()?;
+        if let Some((place, ptr)) = int_to_ptr_cast {
+            this.finish_int_to_ptr_cast(place, ptr)?;
+        }
+        // Allocations that no shim checked beforehand are noticed here.
+        this.check_memory_limit(0)?;
+        Ok(more)
+    }
+}
+
 /// Machine hook implementations.
 impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'tcx> {
     type MemoryKinds = MiriMemoryKind;
@@ -293,10 +609,54 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'tcx> {
         Ok(Cow::Owned(alloc))
     }
 
+    fn canonical_alloc_id(mem: &Memory<'mir, 'tcx, Self>, id: AllocId) -> AllocId {
+        let tcx = mem.tcx;
+        // Only extern statics the machine manages itself and thread-local statics need to be
+        // redirected.
+        let global_alloc = tcx.alloc_map.lock().get(id);
+        let def_id = match global_alloc {
+            Some(GlobalAlloc::Static(def_id)) if tcx.is_foreign_item(def_id) => def_id,
+            Some(GlobalAlloc::Static(def_id)) if tcx.has_attr(def_id, sym::thread_local) => {
+                let thread_local_statics = &mem.extra.thread_local_statics;
+                return thread_local_statics.allocation_for_active_thread(*tcx, def_id, id);
+            }
+            _ => return id,
+        };
+        let attrs = tcx.get_attrs(def_id);
+        let link_name = match attr::first_attr_value_str_by_name(&attrs, sym::link_name) {
+            Some(name) => name,
+            None => tcx.item_name(def_id),
+        };
+        // Unknown statics keep their id, so that `find_foreign_static` reports them.
+        mem.extra.extern_statics.get(&link_name).copied().unwrap_or(id)
+    }
+
     #[inline(always)]
-    fn before_terminator(_ecx: &mut InterpCx<'mir, 'tcx, Self>) -> InterpResult<'tcx> {
-        // We are not interested in detecting loops.
-        Ok(())
+    fn before_terminator(ecx: &mut InterpCx<'mir, 'tcx, Self>) -> InterpResult<'tcx> {
+        // We are not interested in detecting loops, but the virtual clock advances with every
+        // basic block.
+        ecx.machine.clock.tick();
+        ecx.machine.basic_block_count += 1;
+        if let Some(interval) = ecx.machine.dump_threads_interval {
+            if ecx.machine.basic_block_count % interval == 0 {
+                register_diagnostic(NonHaltingDiagnostic::ThreadDump);
+            }
+        }
+        if let Some(interval) = ecx.machine.tag_gc_interval {
+            if ecx.machine.basic_block_count % interval == 0 {
+                ecx.garbage_collect_tags()?;
+            }
+        }
+        if let Some(events) = &ecx.machine.progress_events {
+            if ecx.machine.basic_block_count % progress::PROGRESS_INTERVAL == 0 {
+                let frame = ecx.frame();
+                events.progress(
+                    ecx.machine.basic_block_count,
+                    &ecx.tcx.def_path_str(frame.instance.def_id()),
+                );
+            }
+        }
+        ecx.check_terminator_vtable()
     }
 
     fn init_allocation_extra<'b>(
@@ -307,18 +667,48 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'tcx> {
     ) -> (Cow<'b, Allocation<Self::PointerTag, Self::AllocExtra>>, Self::PointerTag) {
         let kind = kind.expect("we set our STATIC_KIND so this cannot be None");
         let alloc = alloc.into_owned();
-        let (stacks, base_tag) = if let Some(stacked_borrows) = memory_extra.stacked_borrows.as_ref() {
-            let (stacks, base_tag) = Stacks::new_allocation(
-                id,
-                alloc.size,
-                Rc::clone(stacked_borrows),
-                kind,
-            );
-            (Some(stacks), base_tag)
-        } else {
+        let tracked = memory_extra.tracked_alloc_ids.contains(id);
+        if tracked {
+            register_diagnostic(NonHaltingDiagnostic::CreatedAlloc(id));
+        }
+        // Only heap allocations: stack allocations are freed along with their frame.
+        let heap_backtrace = memory_extra.record_heap_allocs
+            && match kind {
+                MemoryKind::Machine(MiriMemoryKind::Rust)
+                | MemoryKind::Machine(MiriMemoryKind::C)
+                | MemoryKind::Machine(MiriMemoryKind::WinHeap) => true,
+                _ => false,
+            };
+        if heap_backtrace {
+            register_diagnostic(NonHaltingDiagnostic::CreatedHeapAlloc(id));
+        }
+        if kind == MiriMemoryKind::Static.into() {
+            memory_extra.static_allocs.borrow_mut().insert(id);
+        }
+        if let Some(memory_limit) = &memory_extra.memory_limit {
+            memory_limit.borrow_mut().allocated(id, alloc.size);
+        }
+        let (stacks, tree, base_tag) = match memory_extra.stacked_borrows.as_ref() {
+            Some(stacked_borrows) if memory_extra.tree_borrows => {
+                let (tree, base_tag) =
+                    Tree::new_allocation(id, alloc.size, Rc::clone(stacked_borrows), kind);
+                (None, Some(tree), base_tag)
+            }
+            Some(stacked_borrows) => {
+                let (stacks, base_tag) = Stacks::new_allocation(
+                    id,
+                    alloc.size,
+                    Rc::clone(stacked_borrows),
+                    kind,
+                );
+                (Some(stacks), None, base_tag)
+            }
             // No stacks, no tag.
-            (None, Tag::Untagged)
+            None => (None, None, Tag::Untagged),
         };
+        let race_alloc = memory_extra.data_race.as_ref().map(|data_race| {
+            data_race::AllocExtra::new_allocation(alloc.size, Rc::clone(data_race), kind)
+        });
         let mut stacked_borrows = memory_extra.stacked_borrows.as_ref().map(|sb| sb.borrow_mut());
         let alloc: Allocation<Tag, Self::AllocExtra> = alloc.with_tags_and_extra(
             |alloc| {
@@ -330,7 +720,15 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'tcx> {
                     Tag::Untagged
                 }
             },
-            AllocExtra { stacked_borrows: stacks },
+            AllocExtra {
+                stacked_borrows: stacks,
+                tree_borrows: tree,
+                data_race: race_alloc,
+                tracked,
+                heap_backtrace,
+                memory_limit: memory_extra.memory_limit.clone(),
+                spin_loop: memory_extra.spin_loop.clone(),
+            },
         );
         (Cow::Owned(alloc), base_tag)
     }
@@ -364,9 +762,15 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'tcx> {
             NonZeroU64::new(1).unwrap(),
             |stacked_borrows| stacked_borrows.borrow_mut().new_call(),
         );
+        // `find_mir_or_eval_fn` just found the body of this frame, and noted if it is a test.
+        let test = ecx.machine.progress_events.as_mut().and_then(|events| events.entering_test.take());
         Ok(FrameData {
             call_id,
             catch_panic: None,
+            test,
+            alloca: Vec::new(),
+            resumes_thread: None,
+            exit_handler: None,
         })
     }
 
@@ -376,7 +780,21 @@ impl<'mir, 'tcx> Machine<'mir, 'tcx> for Evaluator<'tcx> {
         extra: FrameData<'tcx>,
         unwinding: bool,
     ) -> InterpResult<'tcx, StackPopInfo> {
-        ecx.handle_stack_pop(extra, unwinding)
+        if let (Some(test), Some(events)) = (&extra.test, &ecx.machine.progress_events) {
+            events.test_finished(test, unwinding);
+        }
+        // The frames of a thread that exits early are popped as if they were unwinding, but there
+        // is no panic to catch, and the thread's first frame returns normally. See `exit_thread`.
+        let exits_early = ecx.machine.threads.active_thread_exits_early();
+        let resumes_thread = extra.resumes_thread;
+        let mut res = ecx.handle_stack_pop(extra, unwinding && !exits_early)?;
+        if let Some(thread) = resumes_thread {
+            ecx.resume_thread(thread, unwinding)?;
+            if exits_early {
+                res = StackPopInfo::StopUnwinding;
+            }
+        }
+        Ok(res)
     }
 
     #[inline(always)]
@@ -403,8 +821,13 @@ impl AllocationExtra<Tag> for AllocExtra {
         ptr: Pointer<Tag>,
         size: Size,
     ) -> InterpResult<'tcx> {
+        if let Some(ref data_race) = alloc.extra.data_race {
+            data_race.memory_read(ptr, size)?;
+        }
         if let Some(ref stacked_borrows) = alloc.extra.stacked_borrows {
             stacked_borrows.memory_read(ptr, size)
+        } else if let Some(ref tree_borrows) = alloc.extra.tree_borrows {
+            tree_borrows.memory_read(ptr, size)
         } else {
             Ok(())
         }
@@ -416,8 +839,14 @@ impl AllocationExtra<Tag> for AllocExtra {
         ptr: Pointer<Tag>,
         size: Size,
     ) -> InterpResult<'tcx> {
+        alloc.extra.spin_loop.borrow_mut().memory_written();
+        if let Some(ref mut data_race) = alloc.extra.data_race {
+            data_race.memory_written(ptr, size)?;
+        }
         if let Some(ref mut stacked_borrows) = alloc.extra.stacked_borrows {
             stacked_borrows.memory_written(ptr, size)
+        } else if let Some(ref mut tree_borrows) = alloc.extra.tree_borrows {
+            tree_borrows.memory_written(ptr, size)
         } else {
             Ok(())
         }
@@ -429,8 +858,22 @@ impl AllocationExtra<Tag> for AllocExtra {
         ptr: Pointer<Tag>,
         size: Size,
     ) -> InterpResult<'tcx> {
+        if alloc.extra.tracked {
+            register_diagnostic(NonHaltingDiagnostic::FreedAlloc(ptr.alloc_id));
+        }
+        if alloc.extra.heap_backtrace {
+            register_diagnostic(NonHaltingDiagnostic::FreedHeapAlloc(ptr.alloc_id));
+        }
+        if let Some(memory_limit) = &alloc.extra.memory_limit {
+            memory_limit.borrow_mut().deallocated(ptr.alloc_id);
+        }
+        if let Some(ref mut data_race) = alloc.extra.data_race {
+            data_race.memory_deallocated(ptr, size)?;
+        }
         if let Some(ref mut stacked_borrows) = alloc.extra.stacked_borrows {
             stacked_borrows.memory_deallocated(ptr, size)
+        } else if let Some(ref mut tree_borrows) = alloc.extra.tree_borrows {
+            tree_borrows.memory_deallocated(ptr, size)
         } else {
             Ok(())
         }
@@ -443,7 +886,8 @@ impl MayLeak for MiriMemoryKind {
         use self::MiriMemoryKind::*;
         match self {
             Rust | C | WinHeap => false,
-            Env | Static => true,
+            // Freed automatically, so there is nothing the program could forget to free.
+            Alloca | Env | Static => true,
         }
     }
 }
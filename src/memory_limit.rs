@@ -0,0 +1,78 @@
+//! Enforces `-Zmiri-memory-limit`. The bytes of all allocations the interpreter currently holds
+//! are counted, so a program that allocates too much is stopped with a report of where its memory
+//! went, instead of Miri being killed by the OS.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rustc::ty::layout::Size;
+
+use crate::*;
+
+/// How many of the largest allocations are listed when the limit is exceeded.
+pub const REPORTED_ALLOCS: usize = 5;
+
+pub type MemoryExtra = Rc<RefCell<GlobalState>>;
+
+/// The limit, and the sizes of the allocations that currently count against it.
+#[derive(Debug)]
+pub struct GlobalState {
+    /// The most bytes that may be allocated at the same time.
+    pub(crate) limit: u64,
+    /// The bytes of all live allocations.
+    pub(crate) live_bytes: u64,
+    /// The size of every live allocation.
+    live: HashMap<AllocId, u64>,
+}
+
+impl GlobalState {
+    pub fn new(limit: u64) -> Self {
+        GlobalState { limit, live_bytes: 0, live: HashMap::new() }
+    }
+
+    /// Counts the allocation `id`. Immutable statics get their extra state created every time
+    /// they are used, so this may be called repeatedly for the same allocation.
+    pub(crate) fn allocated(&mut self, id: AllocId, size: Size) {
+        if !self.live.contains_key(&id) {
+            self.live.insert(id, size.bytes());
+            self.live_bytes += size.bytes();
+        }
+    }
+
+    pub(crate) fn deallocated(&mut self, id: AllocId) {
+        if let Some(size) = self.live.remove(&id) {
+            self.live_bytes -= size;
+        }
+    }
+
+    /// The largest live allocations with their sizes, largest first.
+    pub(crate) fn largest_allocs(&self) -> Vec<(AllocId, u64)> {
+        let mut allocs: Vec<(AllocId, u64)> =
+            self.live.iter().map(|(&id, &size)| (id, size)).collect();
+        allocs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        allocs.truncate(REPORTED_ALLOCS);
+        allocs
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Stops the program if allocating another `requested` bytes would exceed the memory limit.
+    /// Shims call this before allocating, so that a huge allocation is never made; with
+    /// `requested` being 0, this checks whether the limit was already exceeded.
+    fn check_memory_limit(&self, requested: u64) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        if let Some(memory_limit) = &this.memory.extra.memory_limit {
+            let global = memory_limit.borrow();
+            if global.live_bytes.saturating_add(requested) > global.limit {
+                throw_machine_stop!(TerminationInfo::MemoryLimitExceeded {
+                    limit: global.limit,
+                    live_bytes: global.live_bytes,
+                    requested,
+                });
+            }
+        }
+        Ok(())
+    }
+}
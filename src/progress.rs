@@ -0,0 +1,125 @@
+//! Machine-readable progress events for tools like IDE plugins. With
+//! `-Zmiri-progress-events=<file>`, every event is written to that file as one line of JSON, as
+//! soon as it happens.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
+
+use rustc::ty::TyCtxt;
+use rustc_hir::def_id::DefId;
+use rustc_span::symbol::sym;
+
+/// How many basic blocks are executed between two `progress` events.
+pub const PROGRESS_INTERVAL: u64 = 1_000_000;
+
+pub struct ProgressEvents {
+    file: File,
+    /// The name of the test whose stack frame is about to be pushed. This is set when the call
+    /// to the test is found, and taken when the frame is created.
+    pub(crate) entering_test: Option<String>,
+}
+
+impl ProgressEvents {
+    pub fn create(path: &Path) -> Self {
+        let file = File::create(path).unwrap_or_else(|err| {
+            panic!("cannot create the progress event file {}: {}", path.display(), err)
+        });
+        ProgressEvents { file, entering_test: None }
+    }
+
+    /// The program is about to start.
+    pub fn started(&self) {
+        self.emit("started", &[]);
+    }
+
+    /// Regular sign of life, with the function that is currently executing.
+    pub fn progress(&self, basic_blocks: u64, function: &str) {
+        self.emit(
+            "progress",
+            &[("basic_blocks", basic_blocks.to_string()), ("function", json_string(function))],
+        );
+    }
+
+    pub fn test_started(&self, name: &str) {
+        self.emit("test_started", &[("name", json_string(name))]);
+    }
+
+    /// A test returned, or a panic unwound out of it.
+    pub fn test_finished(&self, name: &str, panicked: bool) {
+        self.emit(
+            "test_finished",
+            &[("name", json_string(name)), ("panicked", panicked.to_string())],
+        );
+    }
+
+    /// An error is about to be reported.
+    pub fn error(&self, message: &str) {
+        self.emit("error", &[("message", json_string(message))]);
+    }
+
+    /// The program finished, with `None` as the exit code if Miri reported an error.
+    pub fn finished(&self, exit_code: Option<i64>) {
+        let exit_code = exit_code.map_or("null".to_owned(), |code| code.to_string());
+        self.emit("finished", &[("exit_code", exit_code)]);
+    }
+
+    /// Writes one event, with the given fields that are already JSON values.
+    fn emit(&self, event: &str, fields: &[(&str, String)]) {
+        let mut line = format!("{{\"event\":{}", json_string(event));
+        for (name, value) in fields {
+            write!(line, ",{}:{}", json_string(name), value).unwrap();
+        }
+        line.push_str("}\n");
+        // Write the whole line at once, so that readers never see half an event. Failing to
+        // report progress is no reason to stop the program, so errors are ignored.
+        let _ = (&self.file).write_all(line.as_bytes());
+    }
+}
+
+/// If `def_id` is the closure that the test harness calls to run a test, returns the name of
+/// that test. The closure is defined inside the constant that describes the test.
+pub fn test_name(tcx: TyCtxt<'_>, def_id: DefId) -> Option<String> {
+    if !tcx.is_closure(def_id) {
+        return None;
+    }
+    let test_desc = tcx.parent(def_id)?;
+    if tcx.has_attr(test_desc, sym::rustc_test_marker) {
+        Some(tcx.def_path_str(test_desc))
+    } else {
+        None
+    }
+}
+
+/// Quotes and escapes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("main"), "\"main\"");
+        assert_eq!(json_string("a \"quoted\" \\ path"), "\"a \\\"quoted\\\" \\\\ path\"");
+        assert_eq!(json_string("line\nbreak\u{1}"), "\"line\\nbreak\\u0001\"");
+        assert_eq!(json_string("ünïcödé"), "\"ünïcödé\"");
+    }
+}
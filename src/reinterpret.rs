@@ -0,0 +1,168 @@
+//! Checks for reinterpreting a value as a type of a different size. A `transmute` is rejected by
+//! the type checker unless the sizes match, but reading a union field and reading through a cast
+//! raw pointer reinterpret bytes without such a check. Reading past the bytes that belong to the
+//! value would fail later anyway, with an error about an out-of-bounds or uninitialized byte; this
+//! reports both layouts involved at the read instead.
+
+use rustc::mir;
+use rustc::ty::{self, layout::{LayoutOf, Size, TyLayout}, List};
+
+use crate::*;
+
+/// Describes a layout for the error messages, e.g. "`u32` (size 4, align 4)".
+fn describe_layout(layout: TyLayout<'_>) -> String {
+    format!("`{}` (size {}, align {})", layout.ty, layout.size.bytes(), layout.align.abi.bytes())
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Checks that a value of layout `src` can be reinterpreted as a value of layout `dest`,
+    /// i.e., that they have the same size. Both layouts are reported otherwise.
+    fn check_transmute_size(
+        &self,
+        src: TyLayout<'tcx>,
+        dest: TyLayout<'tcx>,
+    ) -> InterpResult<'tcx> {
+        if src.size != dest.size {
+            throw_ub_format!(
+                "transmuting between types of different sizes: {} to {}",
+                describe_layout(src),
+                describe_layout(dest),
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the read of the statement the active thread executes next, if it copies or moves a
+    /// union field or the target of a raw pointer. Called before every step.
+    fn check_reinterpreting_read(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let (src, union_field) = {
+            let frame = match this.stack().last() {
+                Some(frame) => frame,
+                None => return Ok(()),
+            };
+            let statement = match frame.block {
+                Some(block) => frame.body.basic_blocks()[block].statements.get(frame.stmt),
+                None => None,
+            };
+            let assign = match statement.map(|statement| &statement.kind) {
+                Some(mir::StatementKind::Assign(assign)) => &**assign,
+                _ => return Ok(()),
+            };
+            let src = match assign {
+                (_, mir::Rvalue::Use(mir::Operand::Copy(src)))
+                | (_, mir::Rvalue::Use(mir::Operand::Move(src))) => src,
+                _ => return Ok(()),
+            };
+            // Only look at the field projections of unions, which the type tells without
+            // evaluating the place.
+            let union_field = match src.projection.split_last() {
+                Some((mir::ProjectionElem::Field(field, _), base)) => {
+                    let base = mir::Place::ty_from(&src.local, base, frame.body, *this.tcx);
+                    match base.ty.kind {
+                        ty::Adt(adt, _) if adt.is_union() => Some(*field),
+                        _ => return Ok(()),
+                    }
+                }
+                _ => None,
+            };
+            (src.clone(), union_field)
+        };
+        match union_field {
+            Some(field) => this.check_union_field_read(&src, field),
+            None if src.projection[..] == [mir::ProjectionElem::Deref] =>
+                this.check_raw_ptr_read(src.local),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks reading `field` of a union, the place `src`. If only a prefix of the field is
+    /// initialized, and it is as large as another field, the read reinterprets that field.
+    fn check_union_field_read(
+        &self,
+        src: &mir::Place<'tcx>,
+        field: mir::Field,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        let base = mir::Place {
+            local: src.local,
+            projection: this.tcx.intern_place_elems(&src.projection[..src.projection.len() - 1]),
+        };
+        let base = this.eval_place_to_op(&base, None)?;
+        let adt = match base.layout.ty.kind {
+            ty::Adt(adt, _) => adt,
+            _ => bug!("union field read from a place of type {}", base.layout.ty),
+        };
+        // Unions held as immediates have no uninitialized bytes to look at.
+        let ptr = match base.try_as_mplace() {
+            Ok(mplace) => match mplace.ptr {
+                Scalar::Ptr(ptr) => ptr,
+                _ => return Ok(()),
+            },
+            Err(_) => return Ok(()),
+        };
+        let read = base.layout.field(this, field.index())?;
+        let alloc = this.memory.get_raw(ptr.alloc_id)?;
+        if ptr.offset + read.size > alloc.size {
+            return Ok(());
+        }
+        // All fields of a union start at offset 0.
+        let initialized = (0..read.size.bytes())
+            .take_while(|&offset| alloc.undef_mask().get(ptr.offset + Size::from_bytes(offset)))
+            .count() as u64;
+        if initialized == 0 || initialized == read.size.bytes() {
+            return Ok(());
+        }
+        let fields = &adt.non_enum_variant().fields;
+        for (idx, written) in fields.iter().enumerate() {
+            let layout = base.layout.field(this, idx)?;
+            if idx != field.index() && layout.size.bytes() == initialized {
+                throw_ub_format!(
+                    "reading union field `{}` of type {} reinterprets field `{}` of type {}: the remaining {} bytes are uninitialized",
+                    fields[field.index()].ident,
+                    describe_layout(read),
+                    written.ident,
+                    describe_layout(layout),
+                    read.size.bytes() - initialized,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks reading the target of the raw pointer in `local`. If the allocation it points to
+    /// ends within the value read, the pointer was cast to a pointer to a larger type.
+    fn check_raw_ptr_read(&self, local: mir::Local) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        let ptr = this.eval_place_to_op(&mir::Place { local, projection: List::empty() }, None)?;
+        let pointee = match ptr.layout.ty.kind {
+            ty::RawPtr(ty::TypeAndMut { ty, .. }) => this.layout_of(ty)?,
+            _ => return Ok(()),
+        };
+        if pointee.is_unsized() {
+            return Ok(());
+        }
+        let ptr = match this.read_scalar(ptr)? {
+            ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) => ptr,
+            _ => return Ok(()),
+        };
+        // Dead allocations and pointers that are entirely out of bounds are reported as usual.
+        let (size, align) = match this.memory.get_size_and_align(ptr.alloc_id, AllocCheck::Live) {
+            Ok(size_and_align) => size_and_align,
+            Err(_) => return Ok(()),
+        };
+        if ptr.offset >= size || ptr.offset + pointee.size <= size {
+            return Ok(());
+        }
+        throw_ub_format!(
+            "reading {} through a raw pointer reinterprets the last {} bytes of {} (size {}, align {}), starting at offset {}",
+            describe_layout(pointee),
+            (size - ptr.offset).bytes(),
+            ptr.alloc_id,
+            size.bytes(),
+            align.bytes(),
+            ptr.offset.bytes(),
+        )
+    }
+}
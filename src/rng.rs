@@ -0,0 +1,92 @@
+//! The random number generators used for resolving non-determinism. Every purpose gets its own
+//! generator, derived from the seed, so that e.g. creating one more `HashMap` (which consumes
+//! random bytes for its keys) does not change which addresses allocations get or which I/O
+//! operations fail.
+
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// What random numbers are needed for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RngPurpose {
+    /// Random bytes the program asks for, e.g. with `getrandom` to seed `HashMap`s.
+    Program,
+    /// Where allocations are placed, and whether `realloc` moves them.
+    Addresses,
+    /// Injected failures and other deliberately unusual behavior of the shims, like
+    /// `-Zmiri-io-error-rate` and `-Zmiri-short-io`.
+    FaultInjection,
+    /// The bytes the program asks for with `miri_nondet_bytes`, unless they are replayed from
+    /// `-Zmiri-nondet-input`.
+    Nondet,
+    /// Which of the stores in its store buffer an atomic load reads.
+    WeakMemory,
+    /// The names of the host files that Miri creates to emulate `O_TMPFILE`.
+    TempFiles,
+}
+
+impl RngPurpose {
+    /// Mixed into the seed to derive the generator for this purpose. These must never change,
+    /// or the same seed would give different executions.
+    fn seed_offset(self) -> u64 {
+        match self {
+            RngPurpose::Program => 0,
+            RngPurpose::Addresses => 0x9e37_79b9_7f4a_7c15,
+            RngPurpose::FaultInjection => 0x3c6e_f372_fe94_f82a,
+            RngPurpose::Nondet => 0xdaa6_6d2b_7a1c_4e59,
+            RngPurpose::WeakMemory => 0x78dd_e6e5_fd29_f054,
+            RngPurpose::TempFiles => 0x1715_609d_7c2e_3b8f,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rngs {
+    program: RefCell<StdRng>,
+    addresses: RefCell<StdRng>,
+    fault_injection: RefCell<StdRng>,
+    nondet: RefCell<StdRng>,
+    weak_memory: RefCell<StdRng>,
+    temp_files: RefCell<StdRng>,
+}
+
+impl Rngs {
+    pub fn new(seed: u64) -> Self {
+        let rng = |purpose: RngPurpose| {
+            RefCell::new(StdRng::seed_from_u64(seed.wrapping_add(purpose.seed_offset())))
+        };
+        Rngs {
+            program: rng(RngPurpose::Program),
+            addresses: rng(RngPurpose::Addresses),
+            fault_injection: rng(RngPurpose::FaultInjection),
+            nondet: rng(RngPurpose::Nondet),
+            weak_memory: rng(RngPurpose::WeakMemory),
+            temp_files: rng(RngPurpose::TempFiles),
+        }
+    }
+
+    /// The generator for `purpose`, for when only shared access to the memory is available.
+    pub fn get(&self, purpose: RngPurpose) -> &RefCell<StdRng> {
+        match purpose {
+            RngPurpose::Program => &self.program,
+            RngPurpose::Addresses => &self.addresses,
+            RngPurpose::FaultInjection => &self.fault_injection,
+            RngPurpose::Nondet => &self.nondet,
+            RngPurpose::WeakMemory => &self.weak_memory,
+            RngPurpose::TempFiles => &self.temp_files,
+        }
+    }
+
+    pub fn get_mut(&mut self, purpose: RngPurpose) -> &mut StdRng {
+        match purpose {
+            RngPurpose::Program => self.program.get_mut(),
+            RngPurpose::Addresses => self.addresses.get_mut(),
+            RngPurpose::FaultInjection => self.fault_injection.get_mut(),
+            RngPurpose::Nondet => self.nondet.get_mut(),
+            RngPurpose::WeakMemory => self.weak_memory.get_mut(),
+            RngPurpose::TempFiles => self.temp_files.get_mut(),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! The decisions of the scheduler. Threads run one after the other, so the only decision is which
+//! of the threads that did not start yet runs when the active thread blocks (including when it
+//! joins a thread that did not start yet) or yields. The decisions can be written to a file with
+//! `-Zmiri-record-schedule`, one thread id per line, and `-Zmiri-replay-schedule` makes the same
+//! decisions again.
+//!
+//! `-Zmiri-explore-schedules` runs the program again and again, making different decisions each
+//! time, until it fails or all schedules are explored. Every run starts from scratch and repeats
+//! the decisions of an earlier run up to some point, so that (as long as the program is
+//! deterministic) it reaches the same state there and can then take a different turn. A bound on
+//! the number of decisions that differ from the default one keeps the number of runs manageable.
+//! This is not a preemption bound like the one of loom: threads are never preempted here, they
+//! only switch at the decisions above, so the deviations are what there is to count.
+//!
+//! Before the failing schedule is reported, its deviations are reduced: each one is reverted to
+//! the default decision, and the change is kept if the program still fails the same way. The
+//! reported schedule thus only deviates where that matters for the failure.
+
+use rustc::ty::TyCtxt;
+use rustc_hir::def_id::DefId;
+
+use crate::*;
+
+/// The number of deviations `-Zmiri-explore-schedules` allows unless it is given another bound.
+pub const DEFAULT_MAX_DEVIATIONS: usize = 2;
+
+#[derive(Debug, Default)]
+pub struct Schedule {
+    /// The decisions to make instead of the default ones.
+    replay: Option<Vec<ThreadId>>,
+    /// Whether the default decisions are made once the replayed ones run out, instead of failing.
+    replay_is_prefix: bool,
+    /// All decisions made so far.
+    pub(crate) decisions: Vec<ThreadId>,
+    /// The threads there were to choose from for each decision.
+    pub(crate) options: Vec<Vec<ThreadId>>,
+}
+
+impl Schedule {
+    pub fn new(replay: Option<Vec<ThreadId>>) -> Self {
+        Schedule { replay, ..Schedule::default() }
+    }
+
+    /// A schedule that starts with the decisions of `prefix` and then makes the default ones.
+    pub fn with_prefix(prefix: Vec<ThreadId>) -> Self {
+        Schedule { replay: Some(prefix), replay_is_prefix: true, ..Schedule::default() }
+    }
+
+    /// Chooses which of the `pending` threads runs next. By default, that is the first one, which
+    /// is the one that was created first unless the caller prefers another one. Returns an error
+    /// if the replayed schedule does not fit the program.
+    pub fn choose(&mut self, pending: &[ThreadId]) -> Result<ThreadId, String> {
+        assert!(!pending.is_empty(), "there has to be a thread to choose");
+        let decision = self.decisions.len();
+        let chosen = match &self.replay {
+            Some(replay) => match replay.get(decision) {
+                Some(&id) if pending.contains(&id) => id,
+                Some(&id) => {
+                    return Err(format!(
+                        "decision {} of the replayed schedule starts thread {}, which cannot start here",
+                        decision + 1,
+                        id
+                    ));
+                }
+                None if self.replay_is_prefix => pending[0],
+                None => {
+                    return Err(format!(
+                        "the replayed schedule ends after {} decisions, but the program makes more",
+                        decision
+                    ));
+                }
+            },
+            None => pending[0],
+        };
+        self.decisions.push(chosen);
+        self.options.push(pending.to_vec());
+        Ok(chosen)
+    }
+
+    /// The decisions made so far, in the format that `parse_schedule` reads.
+    pub fn to_text(&self) -> String {
+        self.decisions.iter().map(|id| format!("{}\n", id)).collect()
+    }
+}
+
+/// Reads the decisions of a recorded schedule.
+pub fn parse_schedule(text: &str) -> Result<Vec<ThreadId>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .parse::<u32>()
+                .map(ThreadId::from)
+                .map_err(|_| format!("`{}` is not a thread id", line.trim()))
+        })
+        .collect()
+}
+
+/// The number of decisions that differ from the default one.
+fn deviations(decisions: &[ThreadId], options: &[Vec<ThreadId>]) -> usize {
+    decisions.iter().zip(options).filter(|(chosen, options)| **chosen != options[0]).count()
+}
+
+/// Returns the prefix of the run to explore after the one that made `decisions`, choosing from
+/// `options`, or `None` if every schedule with at most `max_deviations` deviations from the
+/// default decisions has been explored. This is a depth-first search: the last decision that has an
+/// alternative left is changed to the next thread, and everything after it is left to the
+/// defaults.
+pub fn next_prefix(
+    decisions: &[ThreadId],
+    options: &[Vec<ThreadId>],
+    max_deviations: usize,
+) -> Option<Vec<ThreadId>> {
+    for idx in (0..decisions.len()).rev() {
+        if deviations(&decisions[..idx], &options[..idx]) >= max_deviations {
+            continue;
+        }
+        let current = options[idx].iter().position(|id| *id == decisions[idx]).unwrap();
+        if let Some(&next) = options[idx].get(current + 1) {
+            let mut prefix = decisions[..idx].to_vec();
+            prefix.push(next);
+            return Some(prefix);
+        }
+    }
+    None
+}
+
+/// Reduces the deviations of a failing run that made `decisions`, choosing from `options`.
+/// `rerun(prefix)` runs the program with a schedule that starts with `prefix`, and returns the
+/// decisions and options of that run if it made all decisions of `prefix` and failed the same
+/// way. Each deviation is reverted to the default decision, first together with all later
+/// decisions and then on its own, and the resulting run is kept if it still fails. Returns the
+/// decisions of a failing run none of whose deviations can be reverted like this.
+pub fn reduce_deviations(
+    mut decisions: Vec<ThreadId>,
+    mut options: Vec<Vec<ThreadId>>,
+    mut rerun: impl FnMut(&[ThreadId]) -> Option<(Vec<ThreadId>, Vec<Vec<ThreadId>>)>,
+) -> Vec<ThreadId> {
+    let mut idx = 0;
+    while idx < decisions.len() {
+        let default = options[idx][0];
+        if decisions[idx] == default {
+            idx += 1;
+            continue;
+        }
+        let mut truncated = decisions[..idx].to_vec();
+        truncated.push(default);
+        let reduced = rerun(&truncated).or_else(|| {
+            if idx + 1 == decisions.len() {
+                // Reverting on its own is the same as above.
+                return None;
+            }
+            let mut reverted = decisions.clone();
+            reverted[idx] = default;
+            rerun(&reverted)
+        });
+        match reduced {
+            // The decision at `idx` is the default one now, so we move on in the next iteration.
+            Some((reduced_decisions, reduced_options)) => {
+                decisions = reduced_decisions;
+                options = reduced_options;
+            }
+            None => idx += 1,
+        }
+    }
+    decisions
+}
+
+/// Runs the program with every schedule that deviates from the default decisions at most
+/// `max_deviations` times, until one fails. Returns the decisions of the failing run, if there is
+/// one, after reducing its deviations with `reduce_deviations`.
+pub(crate) fn explore_schedules<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    main_id: DefId,
+    config: &MiriConfig,
+    max_deviations: usize,
+) -> Option<Vec<ThreadId>> {
+    let mut prefix = Vec::new();
+    let mut runs = 0;
+    loop {
+        let (failure, schedule) =
+            crate::eval::rerun_with_schedule(tcx, main_id, config.clone(), prefix);
+        runs += 1;
+        if let Some(failure) = failure {
+            let found = runs;
+            let original = deviations(&schedule.decisions, &schedule.options);
+            let decisions = reduce_deviations(schedule.decisions, schedule.options, |prefix| {
+                let (reduced_failure, reduced) =
+                    crate::eval::rerun_with_schedule(tcx, main_id, config.clone(), prefix.to_vec());
+                runs += 1;
+                if reduced_failure == Some(failure) && reduced.decisions.starts_with(prefix) {
+                    Some((reduced.decisions, reduced.options))
+                } else {
+                    None
+                }
+            });
+            let text: Vec<String> = decisions.iter().map(ToString::to_string).collect();
+            tcx.sess.note_without_error(&format!(
+                "schedule {} of the exploration fails ({}); after reducing its deviations from \
+                 {} in {} more runs, running it again: {}",
+                found,
+                failure,
+                original,
+                runs - found,
+                text.join(","),
+            ));
+            return Some(decisions);
+        }
+        match next_prefix(&schedule.decisions, &schedule.options, max_deviations) {
+            Some(next) => prefix = next,
+            None => {
+                tcx.sess.note_without_error(&format!(
+                    "explored {} schedules with at most {} deviations from the default one, and none fails",
+                    runs, max_deviations,
+                ));
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_oldest_thread() {
+        let t = ThreadId::from;
+        let mut schedule = Schedule::new(None);
+        assert_eq!(schedule.choose(&[t(2), t(3)]), Ok(t(2)));
+        assert_eq!(schedule.choose(&[t(3)]), Ok(t(3)));
+        assert_eq!(schedule.to_text(), "2\n3\n");
+    }
+
+    #[test]
+    fn replays_recorded_decisions() {
+        let t = ThreadId::from;
+        let recorded = parse_schedule("3\n2\n").unwrap();
+        let mut schedule = Schedule::new(Some(recorded));
+        assert_eq!(schedule.choose(&[t(2), t(3)]), Ok(t(3)));
+        assert!(schedule.choose(&[t(1)]).is_err());
+        assert_eq!(schedule.decisions, [t(3)]);
+    }
+
+    #[test]
+    fn replay_fails_once_decisions_run_out() {
+        let t = ThreadId::from;
+        let mut schedule = Schedule::new(Some(vec![t(1)]));
+        assert_eq!(schedule.choose(&[t(1)]), Ok(t(1)));
+        assert!(schedule.choose(&[t(2)]).is_err());
+    }
+
+    #[test]
+    fn prefix_falls_back_to_defaults() {
+        let t = ThreadId::from;
+        let mut schedule = Schedule::with_prefix(vec![t(3)]);
+        assert_eq!(schedule.choose(&[t(2), t(3)]), Ok(t(3)));
+        assert_eq!(schedule.choose(&[t(1), t(2)]), Ok(t(1)));
+        assert_eq!(schedule.options, [vec![t(2), t(3)], vec![t(1), t(2)]]);
+    }
+
+    #[test]
+    fn explores_depth_first_within_the_bound() {
+        let t = ThreadId::from;
+        let options = vec![vec![t(1), t(2), t(3)], vec![t(2), t(3)]];
+        let explore = |decisions: &[ThreadId], max_deviations| {
+            next_prefix(decisions, &options, max_deviations)
+        };
+        assert_eq!(explore(&[t(1), t(2)], 2), Some(vec![t(1), t(3)]));
+        assert_eq!(explore(&[t(1), t(3)], 2), Some(vec![t(2)]));
+        assert_eq!(explore(&[t(2), t(2)], 2), Some(vec![t(2), t(3)]));
+        assert_eq!(explore(&[t(3), t(3)], 2), None);
+        // With a single deviation, the second decision may only deviate if the first does not.
+        assert_eq!(explore(&[t(2), t(2)], 1), Some(vec![t(3)]));
+        assert_eq!(explore(&[t(3), t(2)], 1), None);
+        assert_eq!(explore(&[t(1), t(2)], 0), None);
+    }
+
+    #[test]
+    fn reduces_deviations_that_do_not_matter() {
+        let t = ThreadId::from;
+        let options = vec![vec![t(1), t(2)], vec![t(2), t(3)], vec![t(1), t(3)]];
+        // The program fails whenever the second decision deviates. Runs complete the prefix with
+        // the default decisions.
+        let mut runs = 0;
+        let rerun = |prefix: &[ThreadId]| {
+            runs += 1;
+            let mut decisions = prefix.to_vec();
+            decisions.extend(options[prefix.len()..].iter().map(|options| options[0]));
+            if decisions[1] == t(3) { Some((decisions, options.clone())) } else { None }
+        };
+        let reduced = reduce_deviations(vec![t(2), t(3), t(3)], options.clone(), rerun);
+        assert_eq!(reduced, [t(1), t(3), t(1)]);
+        // The first deviation can only be reverted on its own, the second one not at all, and the
+        // third one together with everything after it.
+        assert_eq!(runs, 5);
+    }
+
+    #[test]
+    fn rejects_malformed_schedules() {
+        assert_eq!(parse_schedule("1\n\n2\n"), Ok(vec![ThreadId::from(1), ThreadId::from(2)]));
+        assert!(parse_schedule("1\nmain\n").is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! Implement `atexit` handlers, which run when `main` returns or `exit` is called.
+
+use rustc::ty;
+use rustc_target::abi::LayoutOf;
+
+use crate::*;
+
+/// A registered handler, with the argument to call it with if it was registered by
+/// `__cxa_atexit`.
+pub type AtExitHandler<'tcx> = (ty::Instance<'tcx>, Option<Scalar<Tag>>);
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn register_atexit_handler(
+        &mut self,
+        func: Scalar<Tag>,
+        arg: Option<Scalar<Tag>>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let instance = this.memory.get_fn(func)?.as_instance()?;
+        trace!("Registered atexit handler {:?} with argument {:?}", instance, arg);
+        this.machine.atexit_handlers.push((instance, arg));
+        Ok(())
+    }
+
+    /// Pushes the frame of the most recently registered handler that did not run yet, and
+    /// returns whether there was one. The handler returns to whatever was executing before,
+    /// without moving on to the next statement.
+    fn call_next_atexit_handler(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let (instance, arg) = match this.machine.atexit_handlers.pop() {
+            Some(handler) => handler,
+            None => return Ok(false),
+        };
+        trace!("Running atexit handler {:?} with argument {:?}", instance, arg);
+        let args: Vec<Immediate<Tag>> = arg.into_iter().map(|arg| arg.into()).collect();
+        let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
+        this.call_function(instance, &args, Some(ret_place), StackPopCleanup::None { cleanup: true })?;
+        this.frame_mut().extra.exit_handler = Some(ExitHandlerKind::AtExit);
+        Ok(true)
+    }
+
+    /// Runs all handlers in the reverse order of their registration, including those that get
+    /// registered while the handlers run. Must be called when the stack is empty.
+    fn run_atexit_handlers(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        while this.call_next_atexit_handler()? {
+            // Step until out of stackframes.
+            while this.step_with_hooks()? {}
+        }
+        Ok(())
+    }
+}
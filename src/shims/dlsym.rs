@@ -5,8 +5,30 @@ use crate::*;
 #[derive(Debug, Copy, Clone)]
 pub enum Dlsym {
     GetEntropy,
+    /// A Windows API function that is looked up with `GetProcAddress`, like the standard library
+    /// does for the functions that older versions of Windows lack. It is emulated by the shim of
+    /// the foreign item with the same name.
+    Windows(&'static str),
 }
 
+/// The functions that `GetProcAddress` finds.
+const WINDOWS_FUNCTIONS: &[&str] = &[
+    "AcquireSRWLockExclusive",
+    "AcquireSRWLockShared",
+    "ReleaseSRWLockExclusive",
+    "ReleaseSRWLockShared",
+    "TryAcquireSRWLockExclusive",
+    "TryAcquireSRWLockShared",
+    "InitializeConditionVariable",
+    "SleepConditionVariableSRW",
+    "SleepConditionVariableCS",
+    "WakeConditionVariable",
+    "WakeAllConditionVariable",
+    "WaitOnAddress",
+    "WakeByAddressSingle",
+    "WakeByAddressAll",
+];
+
 impl Dlsym {
     // Returns an error for unsupported symbols, and None if this symbol
     // should become a NULL pointer (pretend it does not exist).
@@ -18,6 +40,13 @@ impl Dlsym {
             _ => throw_unsup_format!("Unsupported dlsym: {}", name),
         })
     }
+
+    /// Returns the function `GetProcAddress` finds under `name`, if there is one.
+    pub fn from_windows_name(name: &str) -> Option<Dlsym> {
+        WINDOWS_FUNCTIONS.iter().find(|&&function| function == name).map(|&function| {
+            Dlsym::Windows(function)
+        })
+    }
 }
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
@@ -40,6 +69,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.gen_random(ptr, len as usize)?;
                 this.write_null(dest)?;
             }
+            Windows(name) => {
+                match this.emulate_foreign_item_by_name(name, args, dest, ret)? {
+                    EmulateByNameResult::NeedsJumping => {}
+                    EmulateByNameResult::AlreadyJumped => return Ok(()),
+                    EmulateByNameResult::NotSupported => bug!("no shim for `{}`", name),
+                }
+            }
         }
 
         this.dump_place(*dest);
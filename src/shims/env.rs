@@ -1,34 +1,62 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ffi::{OsString, OsStr};
 use std::env;
 
 use crate::stacked_borrows::Tag;
 use crate::*;
 
-use rustc::ty::layout::Size;
+use helpers::os_str_to_wide;
+use rustc::ty::layout::{LayoutOf, Size};
 use rustc_mir::interpret::Pointer;
 
 #[derive(Default)]
-pub struct EnvVars {
+pub struct EnvVars<'tcx> {
     /// Stores pointers to the environment variables. These variables must be stored as
-    /// null-terminated C strings with the `"{name}={value}"` format.
+    /// null-terminated C strings with the `"{name}={value}"` format, or as null-terminated
+    /// UTF-16 strings of that format on Windows targets.
     map: HashMap<OsString, Pointer<Tag>>,
+
+    /// Place where the `environ` static is stored, on Unix targets. It points to a null-terminated
+    /// array of the pointers in `map`, which is rebuilt whenever `map` changes.
+    pub(crate) environ: Option<MPlaceTy<'tcx, Tag>>,
 }
 
-impl EnvVars {
-    pub(crate) fn init<'mir, 'tcx>(
+impl<'tcx> EnvVars<'tcx> {
+    pub(crate) fn init<'mir>(
         ecx: &mut InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
         excluded_env_vars: Vec<String>,
-    ) {
-        if ecx.machine.communicate {
-            for (name, value) in env::vars() {
-                if !excluded_env_vars.contains(&name) {
-                    let var_ptr =
-                        alloc_env_var_as_c_str(name.as_ref(), value.as_ref(), ecx);
-                    ecx.machine.env_vars.map.insert(OsString::from(name), var_ptr);
-                }
-            }
+        forwarded_env_vars: Vec<String>,
+        set_env_vars: Vec<(String, String)>,
+    ) -> InterpResult<'tcx> {
+        // Without isolation, the program sees all host variables that are not excluded. With
+        // isolation, it only sees the forwarded ones.
+        let communicate = ecx.machine.communicate;
+        let mut vars: Vec<(String, String)> = env::vars()
+            .filter(|(name, _)| {
+                forwarded_env_vars.contains(name)
+                    || (communicate && !excluded_env_vars.contains(name))
+            })
+            .collect();
+        // Explicitly set variables take precedence over the host ones.
+        for (name, value) in set_env_vars {
+            vars.retain(|(host_name, _)| *host_name != name);
+            vars.push((name, value));
+        }
+
+        let target_os = ecx.tcx.sess.target.target.target_os.clone();
+        for (name, value) in vars {
+            let var_ptr = if target_os == "windows" {
+                alloc_env_var_as_wide_str(name.as_ref(), value.as_ref(), ecx)?
+            } else {
+                alloc_env_var_as_c_str(name.as_ref(), value.as_ref(), ecx)
+            };
+            ecx.machine.env_vars.map.insert(OsString::from(name), var_ptr);
         }
+        if target_os != "windows" {
+            ecx.update_environ()?;
+        }
+        Ok(())
     }
 }
 
@@ -43,6 +71,31 @@ fn alloc_env_var_as_c_str<'mir, 'tcx>(
     ecx.alloc_os_str_as_c_str(name_osstring.as_os_str(), MiriMemoryKind::Env.into())
 }
 
+fn alloc_env_var_as_wide_str<'mir, 'tcx>(
+    name: &OsStr,
+    value: &OsStr,
+    ecx: &mut InterpCx<'mir, 'tcx, Evaluator<'tcx>>,
+) -> InterpResult<'tcx, Pointer<Tag>> {
+    let mut name_osstring = name.to_os_string();
+    name_osstring.push("=");
+    name_osstring.push(value);
+    ecx.alloc_os_str_as_wide_str(name_osstring.as_os_str(), MiriMemoryKind::Env.into())
+}
+
+/// `ERROR_INVALID_PARAMETER`, reported for names that cannot be environment variables.
+const ERROR_INVALID_PARAMETER: u32 = 87;
+/// `ERROR_ENVVAR_NOT_FOUND`
+const ERROR_ENVVAR_NOT_FOUND: u32 = 203;
+
+impl<'tcx> VisitTags for EnvVars<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        for ptr in self.map.values() {
+            ptr.visit_tags(visit);
+        }
+        self.environ.visit_tags(visit);
+    }
+}
+
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     fn getenv(&mut self, name_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, Scalar<Tag>> {
@@ -82,6 +135,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.memory
                     .deallocate(var, None, MiriMemoryKind::Env.into())?;
             }
+            this.update_environ()?;
             Ok(0)
         } else {
             Ok(-1)
@@ -104,12 +158,156 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.memory
                     .deallocate(var, None, MiriMemoryKind::Env.into())?;
             }
+            this.update_environ()?;
             Ok(0)
         } else {
             Ok(-1)
         }
     }
 
+    /// Rebuilds the array the `environ` static points to from the environment map, allocating
+    /// the static itself the first time.
+    fn update_environ(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // Deallocate the old array, if any.
+        if let Some(environ) = this.machine.env_vars.environ {
+            let old_vars_ptr = this.read_scalar(environ.into())?.not_undef()?;
+            let old_vars_ptr = this.force_ptr(old_vars_ptr)?;
+            this.memory.deallocate(old_vars_ptr, None, MiriMemoryKind::Env.into())?;
+        } else {
+            let layout = this.layout_of(this.tcx.mk_imm_ptr(this.tcx.types.usize))?;
+            let place = this.allocate(layout, MiriMemoryKind::Env.into());
+            this.machine.env_vars.environ = Some(place);
+        }
+
+        // Collect the pointers to all variables, followed by a null pointer.
+        let mut vars: Vec<Scalar<Tag>> =
+            this.machine.env_vars.map.values().map(|&ptr| ptr.into()).collect();
+        vars.push(Scalar::ptr_null(&*this.tcx));
+        let vars_type = this.tcx.mk_array(this.tcx.mk_imm_ptr(this.tcx.types.u8), vars.len() as u64);
+        let vars_place = this.allocate(this.layout_of(vars_type)?, MiriMemoryKind::Env.into());
+        for (idx, var) in vars.into_iter().enumerate() {
+            let place = this.mplace_field(vars_place, idx as u64)?;
+            this.write_scalar(var, place.into())?;
+        }
+        let environ = this.machine.env_vars.environ.unwrap();
+        this.write_scalar(vars_place.ptr, environ.into())?;
+        Ok(())
+    }
+
+    /// The OS-level id of the active thread, as returned by `gettid` and `GetCurrentThreadId`.
+    /// Like on Linux, the id of the main thread is the process id, and the other threads get the
+    /// ids that follow it.
+    fn current_thread_os_id(&self) -> u32 {
+        let this = self.eval_context_ref();
+        PID + this.machine.threads.active_thread().to_u32()
+    }
+
+    fn windows_get_environment_variable(
+        &mut self,
+        name_op: OpTy<'tcx, Tag>,
+        buf_op: OpTy<'tcx, Tag>,
+        size_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let name_ptr = this.read_scalar(name_op)?.not_undef()?;
+        let name = this.read_os_str_from_wide_str(name_ptr)?;
+        let var_ptr = match this.machine.env_vars.map.get(&name) {
+            Some(var_ptr) => *var_ptr,
+            None => {
+                this.set_last_error(Scalar::from_u32(ERROR_ENVVAR_NOT_FOUND))?;
+                return Ok(0);
+            }
+        };
+        // Strip the "{name}=" part of the string, counted in `u16`s.
+        let var = this.read_wide_str(var_ptr.into())?;
+        let name_len = os_str_to_wide(&name).map_or(0, |name| name.len());
+        let value = &var[name_len + 1..];
+
+        let buf = this.read_scalar(buf_op)?.not_undef()?;
+        // The size of the buffer is counted in `u16`s.
+        let size = this.read_scalar(size_op)?.to_u32()?;
+        let (written, len) = this.write_wide_str(value, buf, u64::from(size))?;
+        if written {
+            // Return the number of `u16`s stored, not including the null terminator.
+            Ok(u32::try_from(len).unwrap())
+        } else {
+            // The buffer is too small, so we return the required size, including the null
+            // terminator.
+            Ok(u32::try_from(len + 1).unwrap())
+        }
+    }
+
+    fn windows_set_environment_variable(
+        &mut self,
+        name_op: OpTy<'tcx, Tag>,
+        value_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let mut this = self.eval_context_mut();
+
+        let name_ptr = this.read_scalar(name_op)?.not_undef()?;
+        let value_ptr = this.read_scalar(value_op)?.not_undef()?;
+        let name = if this.is_null(name_ptr)? {
+            None
+        } else {
+            Some(this.read_os_str_from_wide_str(name_ptr)?)
+        };
+        let name = match name {
+            Some(name) if !name.is_empty() && !name.to_string_lossy().contains('=') => name,
+            _ => {
+                this.set_last_error(Scalar::from_u32(ERROR_INVALID_PARAMETER))?;
+                return Ok(0);
+            }
+        };
+
+        let old = if this.is_null(value_ptr)? {
+            // A null value deletes the variable.
+            this.machine.env_vars.map.remove(&name)
+        } else {
+            let value = this.read_os_str_from_wide_str(value_ptr)?;
+            let var_ptr = alloc_env_var_as_wide_str(&name, &value, &mut this)?;
+            this.machine.env_vars.map.insert(name, var_ptr)
+        };
+        if let Some(var) = old {
+            this.memory.deallocate(var, None, MiriMemoryKind::Env.into())?;
+        }
+        Ok(1)
+    }
+
+    /// Returns a block with all environment variables as null-terminated `"{name}={value}"`
+    /// strings, followed by another null terminator. It has to be freed with
+    /// `FreeEnvironmentStringsW`.
+    fn windows_get_environment_strings(&mut self) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        let mut block = Vec::new();
+        let vars: Vec<Pointer<Tag>> = this.machine.env_vars.map.values().copied().collect();
+        for var_ptr in vars {
+            block.extend(this.read_wide_str(var_ptr.into())?);
+            block.push(0);
+        }
+        // `write_wide_str` adds the final null terminator.
+        let size = block.len() as u64 + 1;
+        let block_type = this.tcx.mk_array(this.tcx.types.u16, size);
+        let block_place = this.allocate(this.layout_of(block_type)?, MiriMemoryKind::Env.into());
+        this.write_wide_str(&block, block_place.ptr, size)?;
+        Ok(block_place.ptr)
+    }
+
+    fn windows_free_environment_strings(
+        &mut self,
+        block_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let block = this.read_scalar(block_op)?.not_undef()?;
+        let block = this.force_ptr(block)?;
+        this.memory.deallocate(block, None, MiriMemoryKind::Env.into())?;
+        Ok(1)
+    }
+
     fn getcwd(
         &mut self,
         buf_op: OpTy<'tcx, Tag>,
@@ -1,18 +1,79 @@
 mod windows;
 mod posix;
 
-use std::{convert::TryInto, iter};
+use std::{convert::TryInto, iter, time::Duration};
 
-use rustc_hir::def_id::DefId;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{CrateNum, DefId, CRATE_DEF_INDEX, LOCAL_CRATE};
+use rustc::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use rustc::middle::exported_symbols::ExportedSymbol;
 use rustc::mir;
-use rustc::ty;
+use rustc::ty::{self, TyCtxt};
 use rustc::ty::layout::{Align, Size};
+use rand::Rng;
 use rustc_apfloat::Float;
 use rustc_span::symbol::sym;
 use syntax::attr;
 
+use crate::intptrcast;
 use crate::*;
 
+/// Returns the functions of crate `cnum` that might be exported under an unmangled name. Crates
+/// that were only checked and not compiled, like the ones `cargo miri` builds, have an empty list
+/// of exported symbols, so then their items are searched instead.
+fn exported_function_candidates(tcx: TyCtxt<'_>, cnum: CrateNum) -> Vec<DefId> {
+    let exported: Vec<DefId> = tcx
+        .exported_symbols(cnum)
+        .iter()
+        .filter_map(|&(symbol, _export_level)| match symbol {
+            // Statics can be exported as well, but cannot be called.
+            ExportedSymbol::NonGeneric(def_id) if !tcx.is_static(def_id) => Some(def_id),
+            _ => None,
+        })
+        .collect();
+    if !exported.is_empty() {
+        return exported;
+    }
+
+    let mut functions = Vec::new();
+    if cnum == LOCAL_CRATE {
+        // This includes the functions nested in other functions.
+        for item in tcx.hir().krate().items.values() {
+            if let hir::ItemKind::Fn(..) = item.kind {
+                functions.push(tcx.hir().local_def_id(item.hir_id));
+            }
+        }
+    } else {
+        let mut modules = vec![DefId { krate: cnum, index: CRATE_DEF_INDEX }];
+        while let Some(module) = modules.pop() {
+            for child in tcx.item_children(module).iter() {
+                match child.res {
+                    // Skip re-exports of other crates' items.
+                    Res::Def(_, def_id) if def_id.krate != cnum => {}
+                    Res::Def(DefKind::Mod, def_id) => modules.push(def_id),
+                    Res::Def(DefKind::Fn, def_id) if !tcx.is_foreign_item(def_id) =>
+                        functions.push(def_id),
+                    _ => {}
+                }
+            }
+        }
+    }
+    // Generic functions cannot be exported.
+    functions.retain(|&def_id| tcx.generics_of(def_id).count() == 0);
+    functions
+}
+
+/// The outcome of `emulate_foreign_item_by_name`.
+pub enum EmulateByNameResult {
+    /// The caller is expected to jump to the return block.
+    NeedsJumping,
+    /// Jumping has already been taken care of.
+    AlreadyJumped,
+    /// Miri has no shim for this item.
+    NotSupported,
+}
+
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     /// Returns the minimum alignment for the target architecture for allocations of the given size.
@@ -26,8 +87,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         };
         // Windows always aligns, even small allocations.
         // Source: <https://support.microsoft.com/en-us/help/286470/how-to-use-pageheap-exe-in-windows-xp-windows-2000-and-windows-server>
+        // So does `alloca`, which allocates on the stack.
         // But jemalloc does not, so for the C heap we only align if the allocation is sufficiently big.
-        if kind == MiriMemoryKind::WinHeap || size >= min_align {
+        if kind == MiriMemoryKind::WinHeap || kind == MiriMemoryKind::Alloca || size >= min_align {
             return Align::from_bytes(min_align).unwrap();
         }
         // We have `size < min_align`. Round `size` *down* to the next power of two and use that.
@@ -44,18 +106,53 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         Align::from_bytes(prev_power_of_two(size)).unwrap()
     }
 
-    fn malloc(&mut self, size: u64, zero_init: bool, kind: MiriMemoryKind) -> Scalar<Tag> {
+    fn malloc(
+        &mut self,
+        size: u64,
+        zero_init: bool,
+        kind: MiriMemoryKind,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
         let this = self.eval_context_mut();
         if size == 0 {
-            Scalar::from_int(0, this.pointer_size())
+            Ok(Scalar::from_int(0, this.pointer_size()))
         } else {
+            this.check_memory_limit(size)?;
             let align = this.min_align(size, kind);
             let ptr = this.memory.allocate(Size::from_bytes(size), align, kind.into());
             if zero_init {
                 // We just allocated this, the access is definitely in-bounds.
                 this.memory.write_bytes(ptr.into(), iter::repeat(0u8).take(size as usize)).unwrap();
             }
-            Scalar::Ptr(ptr)
+            Ok(Scalar::Ptr(ptr))
+        }
+    }
+
+    /// Allocates `size` bytes that live until the current frame is popped.
+    fn alloca(&mut self, size: u64) -> Pointer<Tag> {
+        let this = self.eval_context_mut();
+        let align = this.min_align(size, MiriMemoryKind::Alloca);
+        let ptr = this.memory.allocate(Size::from_bytes(size), align, MiriMemoryKind::Alloca.into());
+        // Foreign functions do not get a frame, so this is the frame of the caller.
+        this.frame_mut().extra.alloca.push(ptr);
+        ptr
+    }
+
+    /// Allocates zeroed memory for `items` objects of `len` bytes each. Like real allocators, this
+    /// fails with `ENOMEM` if the total size overflows or exceeds the largest possible object,
+    /// instead of allocating a truncated block.
+    fn calloc(&mut self, items: u64, len: u64) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+        // No object can be larger than `isize::MAX` bytes.
+        let max_size = (1u64 << (this.pointer_size().bits() - 1)) - 1;
+        match items.checked_mul(len) {
+            Some(size) if size <= max_size =>
+                // The allocation is zeroed as it is created, so all its bytes are initialized.
+                this.malloc(size, /*zero_init:*/ true, MiriMemoryKind::C),
+            _ => {
+                let enomem = this.eval_libc("ENOMEM")?;
+                this.set_last_error(enomem)?;
+                Ok(Scalar::from_int(0, this.pointer_size()))
+            }
         }
     }
 
@@ -80,6 +177,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             if new_size == 0 {
                 Ok(Scalar::from_int(0, this.pointer_size()))
             } else {
+                this.check_memory_limit(new_size)?;
                 let new_ptr =
                     this.memory.allocate(Size::from_bytes(new_size), new_align, kind.into());
                 Ok(Scalar::Ptr(new_ptr))
@@ -90,18 +188,48 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.memory.deallocate(old_ptr, None, kind.into())?;
                 Ok(Scalar::from_int(0, this.pointer_size()))
             } else {
-                let new_ptr = this.memory.reallocate(
-                    old_ptr,
-                    None,
-                    Size::from_bytes(new_size),
-                    new_align,
-                    kind.into(),
-                )?;
+                let new_ptr =
+                    this.reallocate(old_ptr, None, Size::from_bytes(new_size), new_align, kind)?;
+                if kind == MiriMemoryKind::C {
+                    this.machine.c_reallocations += 1;
+                }
                 Ok(Scalar::Ptr(new_ptr))
             }
         }
     }
 
+    /// Moves the allocation `old_ptr` points to into a new one of the given size and alignment.
+    /// Real allocators sometimes resize in place and sometimes move the data, whether the
+    /// allocation grows or shrinks, so we randomly pick either, using the seeded RNG. Either way,
+    /// the old allocation is freed and pointers to it become invalid; resizing in place just
+    /// means that the new allocation gets the same address.
+    fn reallocate(
+        &mut self,
+        old_ptr: Pointer<Tag>,
+        old_size_and_align: Option<(Size, Align)>,
+        new_size: Size,
+        new_align: Align,
+        kind: MiriMemoryKind,
+    ) -> InterpResult<'tcx, Pointer<Tag>> {
+        let this = self.eval_context_mut();
+        this.check_memory_limit(new_size.bytes())?;
+        let in_place = this.memory.extra.rngs.get_mut(RngPurpose::Addresses).gen::<bool>();
+        let new_ptr =
+            this.memory.reallocate(old_ptr, old_size_and_align, new_size, new_align, kind.into())?;
+        if in_place {
+            // Make sure the old allocation has an address the new one can take over. If the
+            // allocation grows into the addresses of later ones, it moves after all.
+            intptrcast::GlobalState::ptr_to_int(old_ptr, &this.memory)?;
+            this.memory.extra.intptrcast.get_mut().take_over_address(
+                old_ptr.alloc_id,
+                new_ptr.alloc_id,
+                new_size,
+                new_align,
+            );
+        }
+        Ok(new_ptr)
+    }
+
     /// Emulates calling a foreign item, failing if the item is not supported.
     /// This function will handle `goto_block` if needed.
     /// Returns Ok(None) if the foreign item was completely handled
@@ -126,6 +254,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let link_name = link_name.trim_end_matches("$UNIX2003");
         let tcx = &{ this.tcx.tcx };
 
+        // A `#[no_mangle]` or `#[export_name]` function in the interpreted program takes
+        // precedence over our shims, unless `-Zmiri-prefer-shims` is set.
+        let exported = this.lookup_exported_symbol(link_name)?;
+        if let Some(instance) = exported {
+            if !this.machine.prefer_shims {
+                return Ok(Some(&*this.load_mir(instance.def, None)?));
+            }
+        }
+
         // First: functions that diverge.
         let (dest, ret) = match link_name {
             // Note that this matches calls to the *foreign* item `__rust_start_panic* -
@@ -158,11 +295,43 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             => {
                 // it's really u32 for ExitProcess, but we have to put it into the `Exit` variant anyway
                 let code = this.read_scalar(args[0])?.to_i32()?;
-                throw_machine_stop!(TerminationInfo::Exit(code.into()));
+                // Like glibc, first run the destructors of the `#[thread_local]` statics of the
+                // calling thread, and then the `atexit` handlers, one at a time. When one of them
+                // returns, the caller is still at this call, so `exit` gets called again until
+                // none are left.
+                if this.call_next_thread_dtor()? || this.call_next_atexit_handler()? {
+                    return Ok(None);
+                }
+                throw_machine_stop!(TerminationInfo::Exit { code: code.into(), leak_check: true });
+            }
+            "abort" => {
+                throw_machine_stop!(TerminationInfo::Abort);
+            }
+            // Like `exit`, but skips the destructors and the `atexit` handlers.
+            | "_exit"
+            | "_Exit"
+            => {
+                let code = this.read_scalar(args[0])?.to_i32()?;
+                let leak_check = !this.machine.ignore_leaks_on_immediate_exit;
+                throw_machine_stop!(TerminationInfo::Exit { code: code.into(), leak_check });
+            }
+            // Both end the active thread. Its frames, starting with the caller, are popped in the
+            // next steps, so we do not jump anywhere.
+            "pthread_exit" => {
+                let retval = this.read_scalar(args[0])?.not_undef()?;
+                this.exit_thread(retval)?;
+                return Ok(None);
+            }
+            "ExitThread" => {
+                let code = this.read_scalar(args[0])?.to_u32()?;
+                this.exit_thread(Scalar::from_u32(code))?;
+                return Ok(None);
             }
             _ => {
                 if let Some(p) = ret {
                     p
+                } else if let Some(instance) = exported {
+                    return Ok(Some(&*this.load_mir(instance.def, None)?));
                 } else {
                     throw_unsup_format!("can't call (diverging) foreign function: {}", link_name);
                 }
@@ -170,40 +339,194 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         };
 
         // Next: functions that return.
-        if this.emulate_foreign_item_by_name(link_name, args, dest, ret)? {
-            this.dump_place(*dest);
-            this.go_to_block(ret);
+        match this.emulate_foreign_item_by_name(link_name, args, dest, ret)? {
+            EmulateByNameResult::NeedsJumping => {
+                this.dump_place(*dest);
+                this.go_to_block(ret);
+            }
+            EmulateByNameResult::AlreadyJumped => {}
+            EmulateByNameResult::NotSupported => {
+                // With `-Zmiri-prefer-shims`, the program's definition is the fallback.
+                if let Some(instance) = exported {
+                    return Ok(Some(&*this.load_mir(instance.def, None)?));
+                }
+                throw_unsup_format!("can't call foreign function: {}", link_name);
+            }
         }
 
         Ok(None)
     }
 
-    /// Emulates calling a foreign item using its name, failing if the item is not supported.
-    /// Returns `true` if the caller is expected to jump to the return block, and `false` if
-    /// jumping has already been taken care of.
+    /// Finds the function the interpreted program exports under `link_name`, using
+    /// `#[no_mangle]` or `#[export_name]`. Reports a diagnostic the first time such a function is
+    /// found, since it may shadow one of our shims or be shadowed by it.
+    fn lookup_exported_symbol(
+        &mut self,
+        link_name: &str,
+    ) -> InterpResult<'tcx, Option<ty::Instance<'tcx>>> {
+        let this = self.eval_context_mut();
+        if let Some(instance) = this.machine.exported_symbols_cache.get(link_name) {
+            return Ok(*instance);
+        }
+
+        let tcx = this.tcx.tcx;
+        let mut found: Option<DefId> = None;
+        for cnum in iter::once(LOCAL_CRATE).chain(tcx.crates().iter().copied()) {
+            for def_id in exported_function_candidates(tcx, cnum) {
+                let attrs = tcx.codegen_fn_attrs(def_id);
+                let symbol_name = if let Some(export_name) = attrs.export_name {
+                    export_name
+                } else if attrs.flags.contains(CodegenFnAttrFlags::NO_MANGLE) {
+                    tcx.item_name(def_id)
+                } else {
+                    continue;
+                };
+                if symbol_name.as_str() == link_name {
+                    if let Some(previous) = found {
+                        throw_unsup_format!(
+                            "found multiple definitions of `{}`: `{}` and `{}`",
+                            link_name,
+                            tcx.def_path_str(previous),
+                            tcx.def_path_str(def_id),
+                        );
+                    }
+                    found = Some(def_id);
+                }
+            }
+        }
+
+        let instance = found.map(|def_id| ty::Instance::mono(tcx, def_id));
+        if let Some(def_id) = found {
+            register_diagnostic(NonHaltingDiagnostic::ExportedSymbol {
+                link_name: link_name.to_owned(),
+                definition: tcx.def_path_str(def_id),
+                prefer_shims: this.machine.prefer_shims,
+            });
+        }
+        this.machine.exported_symbols_cache.insert(link_name.to_owned(), instance);
+        Ok(instance)
+    }
+
+    /// Labels the allocation that `ptr` points to the start of with `tag`, replacing any previous
+    /// label. The leak report groups leaked allocations by these labels.
+    fn tag_allocation(&mut self, ptr: Scalar<Tag>, tag: String) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let ptr = this.force_ptr(ptr)?;
+        // Make sure the allocation exists.
+        this.memory.get_size_and_align(ptr.alloc_id, AllocCheck::Live)?;
+        if ptr.offset.bytes() != 0 {
+            throw_ub_format!(
+                "`miri_tag_allocation` must be called with a pointer to the start of an allocation, but this one is at offset {}",
+                ptr.offset.bytes()
+            );
+        }
+        this.machine.allocation_tags.insert(ptr.alloc_id, tag);
+        Ok(())
+    }
+
+    /// Compares the memory range given by a pointer and a length against the same number of
+    /// expected bytes, stopping execution with a report of the first mismatching byte if they
+    /// differ. Uninitialized bytes and bytes of a pointer never match.
+    fn assert_mem_eq(
+        &mut self,
+        ptr_op: OpTy<'tcx, Tag>,
+        expected_op: OpTy<'tcx, Tag>,
+        len_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let ptr = this.read_scalar(ptr_op)?.not_undef()?;
+        let expected = this.read_scalar(expected_op)?.not_undef()?;
+        let size = Size::from_bytes(this.read_scalar(len_op)?.to_machine_usize(this)?);
+        let expected = this.memory.read_bytes(expected, size)?.to_vec();
+        let ptr = match this.memory.check_ptr_access(ptr, size, Align::from_bytes(1).unwrap())? {
+            Some(ptr) => ptr,
+            // Nothing to compare.
+            None => return Ok(()),
+        };
+
+        let ptr_size = this.pointer_size().bytes();
+        let alloc = this.memory.get_raw(ptr.alloc_id)?;
+        let start = ptr.offset.bytes();
+        let actual = alloc
+            .inspect_with_undef_and_ptr_outside_interpreter(start as usize..(start + size.bytes()) as usize);
+        let mut mismatches = expected.iter().zip(actual).enumerate().filter_map(|(idx, (&expected, &actual))| {
+            let offset = Size::from_bytes(start + idx as u64);
+            let first_ptr_byte = Size::from_bytes(offset.bytes().saturating_sub(ptr_size - 1));
+            let found = if !alloc.relocations().range(first_ptr_byte..=offset).is_empty() {
+                format!("a byte of a pointer")
+            } else if !alloc.undef_mask().get(offset) {
+                format!("an uninitialized byte")
+            } else if actual != expected {
+                format!("{:#04x}", actual)
+            } else {
+                return None;
+            };
+            Some((idx, expected, found))
+        });
+
+        if let Some((idx, expected, found)) = mismatches.next() {
+            let more = mismatches.count();
+            throw_machine_stop!(TerminationInfo::AssertMemEq(format!(
+                "byte {} of {} differs: expected {:#04x}, found {}{}",
+                idx,
+                size.bytes(),
+                expected,
+                found,
+                if more == 0 { String::new() } else { format!(" ({} more bytes differ)", more) },
+            )));
+        }
+        Ok(())
+    }
+
+    /// Emulates calling a foreign item using its name.
     fn emulate_foreign_item_by_name(
         &mut self,
         link_name: &str,
         args: &[OpTy<'tcx, Tag>],
         dest: PlaceTy<'tcx, Tag>,
         ret: mir::BasicBlock,
-    ) -> InterpResult<'tcx, bool> {
+    ) -> InterpResult<'tcx, EmulateByNameResult> {
         let this = self.eval_context_mut();
 
         // Here we dispatch all the shims for foreign functions. If you have a platform specific
         // shim, add it to the corresponding submodule.
         match link_name {
+            // Miri-specific extern functions
+            "miri_dump_threads" => {
+                register_diagnostic(NonHaltingDiagnostic::ThreadDump);
+            }
+            "miri_advance_clock" => {
+                let nanoseconds = this.read_scalar(args[0])?.to_u64()?;
+                this.machine.clock.advance(Duration::from_nanos(nanoseconds));
+            }
+            "miri_assert_mem_eq" => {
+                this.assert_mem_eq(args[0], args[1], args[2])?;
+            }
+            "miri_tag_allocation" => {
+                let ptr = this.read_scalar(args[0])?.not_undef()?;
+                let tag = this.read_immediate(args[1])?;
+                let tag = this.read_str(this.ref_to_mplace(tag)?)?.to_owned();
+                this.tag_allocation(ptr, tag)?;
+            }
+            "miri_nondet_bytes" => {
+                this.miri_nondet_bytes(args[0], args[1])?;
+            }
+
             "malloc" => {
                 let size = this.read_scalar(args[0])?.to_machine_usize(this)?;
-                let res = this.malloc(size, /*zero_init:*/ false, MiriMemoryKind::C);
+                let res = this.malloc(size, /*zero_init:*/ false, MiriMemoryKind::C)?;
                 this.write_scalar(res, dest)?;
             }
+            "alloca" | "_alloca" => {
+                let size = this.read_scalar(args[0])?.to_machine_usize(this)?;
+                let ptr = this.alloca(size);
+                this.write_scalar(Scalar::Ptr(ptr), dest)?;
+            }
             "calloc" => {
                 let items = this.read_scalar(args[0])?.to_machine_usize(this)?;
                 let len = this.read_scalar(args[1])?.to_machine_usize(this)?;
-                let size =
-                    items.checked_mul(len).ok_or_else(|| err_ub_format!("overflow during calloc size computation"))?;
-                let res = this.malloc(size, /*zero_init:*/ true, MiriMemoryKind::C);
+                let res = this.calloc(items, len)?;
                 this.write_scalar(res, dest)?;
             }
             "free" => {
@@ -226,6 +549,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 if !align.is_power_of_two() {
                     throw_unsup!(HeapAllocNonPowerOfTwoAlignment(align));
                 }
+                this.check_memory_limit(size)?;
                 let ptr = this.memory.allocate(
                     Size::from_bytes(size),
                     Align::from_bytes(align).unwrap(),
@@ -242,6 +566,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 if !align.is_power_of_two() {
                     throw_unsup!(HeapAllocNonPowerOfTwoAlignment(align));
                 }
+                this.check_memory_limit(size)?;
                 let ptr = this.memory.allocate(
                     Size::from_bytes(size),
                     Align::from_bytes(align).unwrap(),
@@ -280,19 +605,19 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 }
                 let ptr = this.force_ptr(this.read_scalar(args[0])?.not_undef()?)?;
                 let align = Align::from_bytes(align).unwrap();
-                let new_ptr = this.memory.reallocate(
+                let new_ptr = this.reallocate(
                     ptr,
                     Some((Size::from_bytes(old_size), align)),
                     Size::from_bytes(new_size),
                     align,
-                    MiriMemoryKind::Rust.into(),
+                    MiriMemoryKind::Rust,
                 )?;
                 this.write_scalar(new_ptr, dest)?;
             }
 
             "__rust_maybe_catch_panic" => {
                 this.handle_catch_panic(args, dest, ret)?;
-                return Ok(false);
+                return Ok(EmulateByNameResult::AlreadyJumped);
             }
 
             "memcmp" => {
@@ -356,6 +681,49 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_uint(n as u64, dest.layout.size), dest)?;
             }
 
+            "raise" => {
+                let signal = this.read_scalar(args[0])?.to_i32()?;
+                if signal == this.eval_libc_i32("SIGABRT")? {
+                    throw_machine_stop!(TerminationInfo::Abort);
+                }
+                throw_unsup_format!("raising signal {} is not supported", signal);
+            }
+
+            "atexit" => {
+                let func = this.read_scalar(args[0])?.not_undef()?;
+                this.register_atexit_handler(func, None)?;
+                this.write_null(dest)?;
+            }
+            "__cxa_atexit" => {
+                // The third argument identifies the shared object the handler belongs to, which
+                // only matters for unloading it.
+                let func = this.read_scalar(args[0])?.not_undef()?;
+                let arg = this.read_scalar(args[1])?.not_undef()?;
+                this.register_atexit_handler(func, Some(arg))?;
+                this.write_null(dest)?;
+            }
+
+            // What `spin_loop_hint` compiles to on x86 and aarch64.
+            "llvm.x86.sse2.pause" | "llvm.aarch64.hint" => {
+                if this.yield_active_thread()? {
+                    return Ok(EmulateByNameResult::AlreadyJumped);
+                }
+                this.spin_loop_hint();
+            }
+
+            "strtol" | "strtoll" => {
+                let result = this.strtol(args[0], args[1], args[2], /*signed:*/ true, dest.layout.size)?;
+                this.write_scalar(result, dest)?;
+            }
+            "strtoul" | "strtoull" => {
+                let result = this.strtol(args[0], args[1], args[2], /*signed:*/ false, dest.layout.size)?;
+                this.write_scalar(result, dest)?;
+            }
+            "strtod" => {
+                let result = this.strtod(args[0], args[1])?;
+                this.write_scalar(Scalar::from_u64(result.to_bits()), dest)?;
+            }
+
             // math functions
             | "cbrtf"
             | "coshf"
@@ -462,7 +830,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
         };
 
-        Ok(true)
+        Ok(EmulateByNameResult::NeedsJumping)
     }
 
     /// Evaluates the scalar at the specified path. Returns Some(val)
@@ -1,6 +1,10 @@
 mod linux;
 mod macos;
 
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::iter;
+
 use crate::*;
 use rustc::mir;
 use rustc::ty::layout::{Align, LayoutOf, Size};
@@ -13,7 +17,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         args: &[OpTy<'tcx, Tag>],
         dest: PlaceTy<'tcx, Tag>,
         ret: mir::BasicBlock,
-    ) -> InterpResult<'tcx, bool> {
+    ) -> InterpResult<'tcx, EmulateByNameResult> {
         let this = self.eval_context_mut();
         let tcx = &{ this.tcx.tcx };
 
@@ -50,11 +54,29 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
+            "dup" => {
+                let result = this.dup(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "dup2" => {
+                let result = this.dup2(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             "fcntl" => {
                 let result = this.fcntl(args[0], args[1], args.get(2).cloned())?;
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
+            "fsync" => {
+                let result = this.fsync(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "fdatasync" => {
+                let result = this.fdatasync(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             "read" => {
                 let result = this.read(args[0], args[1], args[2])?;
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
@@ -71,7 +93,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
                     let buf_cont = this.memory.read_bytes(buf, Size::from_bytes(n))?;
                     // We need to flush to make sure this actually appears on the screen
-                    let res = if fd == 1 {
+                    let res = if this.machine.discard_output {
+                        Ok(buf_cont.len())
+                    } else if fd == 1 {
                         // Stdout is buffered, flush to make sure it appears on the screen.
                         // This is the write() syscall of the interpreted program, we want it
                         // to correspond to a write() syscall on the host -- there is no good
@@ -104,6 +128,21 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
+            "symlinkat" => {
+                let result = this.symlinkat(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "readlink" => {
+                let result = this.readlink(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "readlinkat" => {
+                let result = this.readlinkat(args[0], args[1], args[2], args[3])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             "rename" => {
                 let result = this.rename(args[0], args[1])?;
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
@@ -129,7 +168,24 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
+            // Time related shims
+            "nanosleep" => {
+                let result = this.nanosleep(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "getrusage" => {
+                let result = this.getrusage(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "times" => {
+                let result = this.times(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             // Other shims
+            "getpid" => {
+                this.write_scalar(Scalar::from_int(PID, dest.layout.size), dest)?;
+            }
             "posix_memalign" => {
                 let ret = this.deref_operand(args[0])?;
                 let align = this.read_scalar(args[1])?.to_machine_usize(this)?;
@@ -193,32 +249,45 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let key_layout = this.layout_of(key_type)?;
 
                 // Create key and write it into the memory where `key_ptr` wants it.
-                let key = this.machine.tls.create_tls_key(dtor) as u128;
-                if key_layout.size.bits() < 128 && key >= (1u128 << key_layout.size.bits() as u128)
-                {
-                    throw_unsup!(OutOfTls);
-                }
-
-                this.write_scalar(Scalar::from_uint(key, key_layout.size), key_place.into())?;
+                match this.machine.tls.create_tls_key(dtor) {
+                    Some(key) => {
+                        if key_layout.size.bits() < 128
+                            && key >= (1u128 << key_layout.size.bits() as u128)
+                        {
+                            throw_unsup!(OutOfTls);
+                        }
+                        this.write_scalar(Scalar::from_uint(key, key_layout.size), key_place.into())?;
 
-                // Return success (`0`).
-                this.write_null(dest)?;
+                        // Return success (`0`).
+                        this.write_null(dest)?;
+                    }
+                    None => {
+                        let eagain = this.eval_libc("EAGAIN")?;
+                        this.write_scalar(eagain, dest)?;
+                    }
+                }
             }
             "pthread_key_delete" => {
                 let key = this.force_bits(this.read_scalar(args[0])?.not_undef()?, args[0].layout.size)?;
-                this.machine.tls.delete_tls_key(key)?;
-                // Return success (0)
-                this.write_null(dest)?;
+                if this.machine.tls.delete_tls_key(key) {
+                    // Return success (0)
+                    this.write_null(dest)?;
+                } else {
+                    let einval = this.eval_libc("EINVAL")?;
+                    this.write_scalar(einval, dest)?;
+                }
             }
             "pthread_getspecific" => {
                 let key = this.force_bits(this.read_scalar(args[0])?.not_undef()?, args[0].layout.size)?;
-                let ptr = this.machine.tls.load_tls(key, tcx)?;
+                let active_thread = this.machine.threads.active_thread();
+                let ptr = this.machine.tls.load_tls(key, active_thread, tcx)?;
                 this.write_scalar(ptr, dest)?;
             }
             "pthread_setspecific" => {
                 let key = this.force_bits(this.read_scalar(args[0])?.not_undef()?, args[0].layout.size)?;
                 let new_ptr = this.read_scalar(args[1])?.not_undef()?;
-                this.machine.tls.store_tls(key, this.test_null(new_ptr)?)?;
+                let active_thread = this.machine.threads.active_thread();
+                this.machine.tls.store_tls(key, active_thread, this.test_null(new_ptr)?)?;
 
                 // Return success (`0`).
                 this.write_null(dest)?;
@@ -227,7 +296,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // Stack size/address stuff.
             | "pthread_attr_init"
             | "pthread_attr_destroy"
-            | "pthread_self"
             | "pthread_attr_setstacksize" => {
                 this.write_null(dest)?;
             }
@@ -247,30 +315,175 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 // Return success (`0`).
                 this.write_null(dest)?;
             }
+            "pthread_attr_getguardsize" => {
+                let guard_size_place = this.deref_operand(args[1])?;
+                this.write_scalar(
+                    Scalar::from_uint(PAGE_SIZE, guard_size_place.layout.size),
+                    guard_size_place.into(),
+                )?;
+
+                // Return success (`0`).
+                this.write_null(dest)?;
+            }
 
-            // We don't support threading.
+            // Threads
             "pthread_create" => {
-                throw_unsup_format!("Miri does not support threading");
-            }
-
-            // Stub out calls for condvar, mutex and rwlock, to just return `0`.
-            | "pthread_mutexattr_init"
-            | "pthread_mutexattr_settype"
-            | "pthread_mutex_init"
-            | "pthread_mutexattr_destroy"
-            | "pthread_mutex_lock"
-            | "pthread_mutex_unlock"
-            | "pthread_mutex_destroy"
-            | "pthread_rwlock_rdlock"
-            | "pthread_rwlock_unlock"
-            | "pthread_rwlock_wrlock"
-            | "pthread_rwlock_destroy"
-            | "pthread_condattr_init"
-            | "pthread_condattr_setclock"
-            | "pthread_cond_init"
-            | "pthread_condattr_destroy"
-            | "pthread_cond_destroy"
-            => {
+                let result = this.pthread_create(args[0], args[1], args[2], args[3])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_join" => {
+                let result = match this.pthread_join(args[0], args[1])? {
+                    Some(result) => result,
+                    // The joined thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_detach" => {
+                let result = this.pthread_detach(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_self" => {
+                this.pthread_self(dest)?;
+            }
+            "pthread_getname_np" => {
+                let result = this.pthread_getname_np(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            // Synchronization primitives
+            "pthread_mutexattr_init" => {
+                let result = this.pthread_mutexattr_init(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutexattr_settype" => {
+                let result = this.pthread_mutexattr_settype(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutexattr_setrobust" => {
+                let result = this.pthread_mutexattr_setrobust(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutexattr_destroy" => {
+                let result = this.pthread_mutexattr_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutex_init" => {
+                let result = this.pthread_mutex_init(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutex_lock" => {
+                let result = this.pthread_mutex_lock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutex_trylock" => {
+                let result = this.pthread_mutex_trylock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutex_unlock" => {
+                let result = this.pthread_mutex_unlock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutex_consistent" => {
+                let result = this.pthread_mutex_consistent(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_mutex_destroy" => {
+                let result = this.pthread_mutex_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "pthread_condattr_init" => {
+                let result = this.pthread_condattr_init(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_condattr_setclock" => {
+                let result = this.pthread_condattr_setclock(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_condattr_getclock" => {
+                let result = this.pthread_condattr_getclock(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_condattr_destroy" => {
+                let result = this.pthread_condattr_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_cond_init" => {
+                let result = this.pthread_cond_init(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_cond_signal" => {
+                let result = this.pthread_cond_signal(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_cond_broadcast" => {
+                let result = this.pthread_cond_broadcast(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_cond_wait" => {
+                let result = match this.pthread_cond_wait(args[0], args[1])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_cond_timedwait" => {
+                let result = match this.pthread_cond_timedwait(args[0], args[1], args[2])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_cond_destroy" => {
+                let result = this.pthread_cond_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "pthread_rwlock_init" => {
+                let result = this.pthread_rwlock_init(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_rwlock_rdlock" => {
+                let result = this.pthread_rwlock_rdlock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_rwlock_tryrdlock" => {
+                let result = this.pthread_rwlock_tryrdlock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_rwlock_wrlock" => {
+                let result = this.pthread_rwlock_wrlock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_rwlock_trywrlock" => {
+                let result = this.pthread_rwlock_trywrlock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_rwlock_unlock" => {
+                let result = this.pthread_rwlock_unlock(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_rwlock_destroy" => {
+                let result = this.pthread_rwlock_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "pthread_once" => {
+                let result = match this.pthread_once(args[0], args[1])? {
+                    Some(result) => result,
+                    // The initialization routine runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "sched_yield" => {
+                if this.yield_active_thread()? {
+                    return Ok(EmulateByNameResult::AlreadyJumped);
+                }
                 this.write_null(dest)?;
             }
 
@@ -279,42 +492,60 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_null(dest)?;
             }
 
-            // Some things needed for `sys::thread` initialization to go through.
-            | "signal"
-            | "sigaction"
-            | "sigaltstack"
-            => {
-                this.write_scalar(Scalar::from_int(0, dest.layout.size), dest)?;
+            // Signals are never delivered, but handlers can be installed and queried.
+            "signal" => {
+                let result = this.signal(args[0], args[1])?;
+                this.write_scalar(result, dest)?;
+            }
+            "sigaction" => {
+                let result = this.sigaction(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sigaltstack" => {
+                let result = this.sigaltstack(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "getifaddrs" => {
+                let result = this.getifaddrs(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "freeifaddrs" => {
+                this.freeifaddrs(args[0])?;
+            }
+            "getaddrinfo" => {
+                let result = this.getaddrinfo(args[0], args[1], args[2], args[3])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "freeaddrinfo" => {
+                this.freeaddrinfo(args[0])?;
+            }
+            "gai_strerror" => {
+                let result = this.gai_strerror(args[0])?;
+                this.write_scalar(Scalar::Ptr(result), dest)?;
+            }
+            "socket" => {
+                let result = this.socket(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "getrlimit" | "getrlimit64" => {
+                let result = this.getrlimit(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "setrlimit" | "setrlimit64" => {
+                let result = this.setrlimit(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "uname" => {
+                let result = this.uname(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
             "sysconf" => {
-                let name = this.read_scalar(args[0])?.to_i32()?;
-
-                trace!("sysconf() called with name {}", name);
-                // TODO: Cache the sysconf integers via Miri's global cache.
-                let paths = &[
-                    (&["libc", "_SC_PAGESIZE"], Scalar::from_int(PAGE_SIZE, dest.layout.size)),
-                    (&["libc", "_SC_GETPW_R_SIZE_MAX"], Scalar::from_int(-1, dest.layout.size)),
-                    (
-                        &["libc", "_SC_NPROCESSORS_ONLN"],
-                        Scalar::from_int(NUM_CPUS, dest.layout.size),
-                    ),
-                ];
-                let mut result = None;
-                for &(path, path_value) in paths {
-                    if let Some(val) = this.eval_path_scalar(path)? {
-                        let val = val.to_i32()?;
-                        if val == name {
-                            result = Some(path_value);
-                            break;
-                        }
-                    }
-                }
-                if let Some(result) = result {
-                    this.write_scalar(result, dest)?;
-                } else {
-                    throw_unsup_format!("Unimplemented sysconf name: {}", name)
-                }
+                let result = this.sysconf(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
             "isatty" => {
@@ -345,6 +576,70 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
         };
 
-        Ok(true)
+        Ok(EmulateByNameResult::NeedsJumping)
+    }
+
+    fn sysconf(&mut self, name_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let name = this.read_scalar(name_op)?.to_i32()?;
+        trace!("sysconf() called with name {}", name);
+
+        // Not every target has every name, so a missing one is simply skipped.
+        let num_cpus = i64::try_from(this.machine.num_cpus).unwrap();
+        let table: &[(&[&str], i64)] = &[
+            (&["libc", "_SC_PAGESIZE"], PAGE_SIZE as i64),
+            (&["libc", "_SC_PAGE_SIZE"], PAGE_SIZE as i64),
+            (&["libc", "_SC_NPROCESSORS_ONLN"], num_cpus),
+            (&["libc", "_SC_NPROCESSORS_CONF"], num_cpus),
+            // There is no limit for these buffers; callers then pick a size and retry with a
+            // bigger one on `ERANGE`.
+            (&["libc", "_SC_GETPW_R_SIZE_MAX"], -1),
+            (&["libc", "_SC_GETGR_R_SIZE_MAX"], -1),
+            (&["libc", "_SC_CLK_TCK"], shims::time::CLOCK_TICKS_PER_SEC as i64),
+        ];
+        for &(path, value) in table {
+            if let Some(val) = this.eval_path_scalar(path)? {
+                if val.to_i32()? == name {
+                    return Ok(value);
+                }
+            }
+        }
+        throw_unsup_format!("Unimplemented sysconf name: {}", name)
+    }
+
+    /// Fills the `struct utsname` with fixed values for the target, so that programs sniffing
+    /// the platform at runtime see the one they were compiled for.
+    fn uname(&mut self, buf_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let buf = this.deref_operand(buf_op)?;
+        let tcx = this.tcx.tcx;
+        let target = &tcx.sess.target.target;
+        let machine = match (target.target_os.as_str(), target.arch.as_str()) {
+            ("macos", "aarch64") => "arm64",
+            (_, "x86") => "i686",
+            (_, arch) => arch,
+        };
+        // The fields in the order they are declared: `sysname`, `nodename`, `release`,
+        // `version`, `machine`, and on Linux also `domainname`.
+        let fields: &[&str] = match target.target_os.as_str() {
+            "linux" => &["Linux", "miri", "5.4.0", "#1 SMP", machine, "(none)"],
+            "macos" => &["Darwin", "miri", "19.3.0", "Darwin Kernel Version 19.3.0", machine],
+            target_os => throw_unsup_format!("`uname` is not supported on {}", target_os),
+        };
+
+        // The unused rest of each field is zeroed.
+        this.memory.write_bytes(
+            buf.ptr,
+            iter::repeat(0u8).take(buf.layout.size.bytes() as usize),
+        )?;
+        for (idx, value) in fields.iter().enumerate() {
+            let field = this.mplace_field(buf, idx as u64)?;
+            let (success, _) =
+                this.write_os_str_to_c_str(OsStr::new(value), field.ptr, field.layout.size.bytes())?;
+            assert!(success, "`uname` value {:?} does not fit into `struct utsname`", value);
+        }
+        Ok(0)
     }
 }
@@ -1,5 +1,6 @@
 use crate::*;
 use rustc::mir;
+use rustc::ty::layout::Endian;
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
@@ -9,7 +10,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         args: &[OpTy<'tcx, Tag>],
         dest: PlaceTy<'tcx, Tag>,
         _ret: mir::BasicBlock,
-    ) -> InterpResult<'tcx, bool> {
+    ) -> InterpResult<'tcx, EmulateByNameResult> {
         let this = self.eval_context_mut();
 
         match link_name {
@@ -48,6 +49,93 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.clock_gettime(args[0], args[1])?;
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
+            "clock_getres" => {
+                let result = this.clock_getres(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "timerfd_create" => {
+                let result = this.timerfd_create(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "timerfd_settime" => {
+                let result = this.timerfd_settime(args[0], args[1], args[2], args[3])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "timerfd_gettime" => {
+                let result = this.timerfd_gettime(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "clock_nanosleep" => {
+                let result = this.clock_nanosleep(args[0], args[1], args[2], args[3])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            // Threads
+            "pthread_setname_np" => {
+                let result = this.pthread_setname_np(Some(args[0]), args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "prctl" => {
+                let result = this.prctl(args)?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            // Synchronization primitives
+            // macOS only supports named semaphores, which need a file system that is shared
+            // between processes.
+            "sem_init" => {
+                let result = this.sem_init(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sem_wait" => {
+                let result = match this.sem_wait(args[0])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sem_timedwait" => {
+                let result = match this.sem_timedwait(args[0], args[1])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sem_trywait" => {
+                let result = this.sem_trywait(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sem_post" => {
+                let result = this.sem_post(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sem_getvalue" => {
+                let result = this.sem_getvalue(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "sem_destroy" => {
+                let result = this.sem_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            // macOS does not have barriers.
+            "pthread_barrier_init" => {
+                let result = this.pthread_barrier_init(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_barrier_wait" => {
+                let result = match this.pthread_barrier_wait(args[0])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "pthread_barrier_destroy" => {
+                let result = this.pthread_barrier_destroy(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
 
             // Other shims
             "pthread_getattr_np" => {
@@ -65,6 +153,16 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     .expect("Failed to get libc::SYS_statx")
                     .to_machine_usize(this)?;
 
+                let sys_gettid = this
+                    .eval_path_scalar(&["libc", "SYS_gettid"])?
+                    .expect("Failed to get libc::SYS_gettid")
+                    .to_machine_usize(this)?;
+
+                let sys_futex = this
+                    .eval_path_scalar(&["libc", "SYS_futex"])?
+                    .expect("Failed to get libc::SYS_futex")
+                    .to_machine_usize(this)?;
+
                 match this.read_scalar(args[0])?.to_machine_usize(this)? {
                     // `libc::syscall(NR_GETRANDOM, buf.as_mut_ptr(), buf.len(), GRND_NONBLOCK)`
                     // is called if a `HashMap` is created the regular way (e.g. HashMap<K, V>).
@@ -81,6 +179,21 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                         let result = this.linux_statx(args[1], args[2], args[3], args[4], args[5])?;
                         this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
                     }
+                    // glibc only got a `gettid` wrapper in version 2.30, so older code uses the
+                    // syscall directly.
+                    id if id == sys_gettid => {
+                        let result = this.current_thread_os_id();
+                        this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+                    }
+                    // `futex(addr, op, val, timeout, ...)`, which synchronization primitives
+                    // outside of libstd use to block threads.
+                    id if id == sys_futex => {
+                        let result = match futex(this, &args[1..])? {
+                            Some(result) => result,
+                            None => return Ok(EmulateByNameResult::AlreadyJumped),
+                        };
+                        this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+                    }
                     id => throw_unsup_format!("miri does not support syscall ID {}", id),
                 }
             }
@@ -89,15 +202,20 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 getrandom(this, args, dest)?;
             }
 
+            "gettid" => {
+                let result = this.current_thread_os_id();
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             "sched_getaffinity" => {
-                // Return an error; `num_cpus` then falls back to `sysconf`.
-                this.write_scalar(Scalar::from_int(-1, dest.layout.size), dest)?;
+                let result = sched_getaffinity(this, args)?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
-            _ => throw_unsup_format!("can't call foreign function: {}", link_name),
+            _ => return Ok(EmulateByNameResult::NotSupported),
         };
 
-        Ok(true)
+        Ok(EmulateByNameResult::NeedsJumping)
     }
 }
 
@@ -118,3 +236,64 @@ fn getrandom<'tcx>(
     this.write_scalar(Scalar::from_uint(len, dest.layout.size), dest)?;
     Ok(())
 }
+
+// Shims `sched_getaffinity`: the process may run on all of the `-Zmiri-num-cpus` CPUs.
+fn sched_getaffinity<'tcx>(
+    this: &mut MiriEvalContext<'_, 'tcx>,
+    args: &[OpTy<'tcx, Tag>],
+) -> InterpResult<'tcx, i32> {
+    let pid = this.read_scalar(args[0])?.to_i32()?;
+    let cpusetsize = this.read_scalar(args[1])?.to_machine_usize(this)?;
+    let mask = this.read_scalar(args[2])?.not_undef()?;
+
+    if pid != 0 && pid as u32 != PID && pid as u32 != this.current_thread_os_id() {
+        let esrch = this.eval_libc("ESRCH")?;
+        this.set_last_error(esrch)?;
+        return Ok(-1);
+    }
+    // The mask is an array of `unsigned long`, like the kernel's, so it must have room for all
+    // CPUs in whole words.
+    let word_size = this.pointer_size().bytes();
+    let num_cpus = this.machine.num_cpus;
+    if cpusetsize % word_size != 0 || cpusetsize * 8 < num_cpus {
+        let einval = this.eval_libc("EINVAL")?;
+        this.set_last_error(einval)?;
+        return Ok(-1);
+    }
+
+    let mut bytes = Vec::with_capacity(cpusetsize as usize);
+    for word in 0..cpusetsize / word_size {
+        let first_cpu = word * word_size * 8;
+        let cpus = num_cpus.saturating_sub(first_cpu).min(word_size * 8);
+        let bits = if cpus == 0 { 0 } else { u64::max_value() >> (64 - cpus) };
+        let bits = &bits.to_le_bytes()[..word_size as usize];
+        match this.tcx.data_layout.endian {
+            Endian::Little => bytes.extend(bits.iter()),
+            Endian::Big => bytes.extend(bits.iter().rev()),
+        }
+    }
+    this.memory.write_bytes(mask, bytes)?;
+    Ok(0)
+}
+
+// Shims the linux `futex` syscall. Returns `None` if the calling thread blocked and another thread
+// was started, see `futex_wait`.
+fn futex<'tcx>(
+    this: &mut MiriEvalContext<'_, 'tcx>,
+    args: &[OpTy<'tcx, Tag>],
+) -> InterpResult<'tcx, Option<i64>> {
+    // `syscall` is variadic, so callers may leave out the arguments that the operation ignores.
+    if args.len() < 3 {
+        throw_ub_format!("incorrect number of arguments for futex syscall: got {}", args.len());
+    }
+    // We do not share memory with other processes, so private futexes are all there is.
+    let private_flag = this.eval_libc_i32("FUTEX_PRIVATE_FLAG")?;
+    let futex_wait = this.eval_libc_i32("FUTEX_WAIT")?;
+    let futex_wake = this.eval_libc_i32("FUTEX_WAKE")?;
+
+    match this.read_scalar(args[1])?.to_i32()? & !private_flag {
+        op if op == futex_wait => this.futex_wait(args[0], args[2], args.get(3).copied()),
+        op if op == futex_wake => this.futex_wake(args[0], args[2]).map(Some),
+        op => throw_unsup_format!("Miri does not support futex operation {}", op),
+    }
+}
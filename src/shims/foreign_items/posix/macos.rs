@@ -9,7 +9,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         args: &[OpTy<'tcx, Tag>],
         dest: PlaceTy<'tcx, Tag>,
         _ret: mir::BasicBlock,
-    ) -> InterpResult<'tcx, bool> {
+    ) -> InterpResult<'tcx, EmulateByNameResult> {
         let this = self.eval_context_mut();
 
         match link_name {
@@ -61,6 +61,29 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.gettimeofday(args[0], args[1])?;
                 this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
+            "mach_absolute_time" => {
+                let result = this.mach_absolute_time()?;
+                this.write_scalar(Scalar::from_u64(result), dest)?;
+            }
+            "mach_timebase_info" => {
+                let result = this.mach_timebase_info(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            // Threads
+            "pthread_setname_np" => {
+                let result = this.pthread_setname_np(None, args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            // Synchronization primitives
+            "dispatch_once_f" => {
+                if !this.dispatch_once_f(args[0], args[1], args[2])? {
+                    // The initialization function runs first, and then this call is executed
+                    // again.
+                    return Ok(EmulateByNameResult::AlreadyJumped);
+                }
+            }
 
             // Other shims
             "pthread_attr_get_np" => {
@@ -78,7 +101,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
 
             "_tlv_atexit" => {
-                // FIXME: register the destructor.
+                let dtor = this.read_scalar(args[0])?.not_undef()?;
+                let dtor = this.memory.get_fn(dtor)?.as_instance()?;
+                let data = this.read_scalar(args[1])?.not_undef()?;
+                let active_thread = this.machine.threads.active_thread();
+                this.machine.tls.add_thread_dtor(active_thread, dtor, data);
             }
 
             "_NSGetArgc" => {
@@ -88,6 +115,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             "_NSGetArgv" => {
                 this.write_scalar(this.machine.argv.expect("machine must be initialized"), dest)?;
             }
+            "_NSGetEnviron" => {
+                let environ = this.machine.env_vars.environ.expect("machine must be initialized");
+                this.write_scalar(environ.ptr, dest)?;
+            }
 
             "SecRandomCopyBytes" => {
                 let len = this.read_scalar(args[1])?.to_machine_usize(this)?;
@@ -96,10 +127,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_null(dest)?;
             }
 
-            _ => throw_unsup_format!("can't call foreign function: {}", link_name),
+            _ => return Ok(EmulateByNameResult::NotSupported),
         };
 
-        Ok(true)
+        Ok(EmulateByNameResult::NeedsJumping)
     }
 }
 
@@ -1,7 +1,19 @@
 use crate::*;
 use rustc::mir;
 use rustc::ty::layout::Size;
+use shims::pipe::PipeError;
 use std::iter;
+use std::time::Duration;
+
+/// Translates the failure of a pipe operation into a Windows error code.
+fn pipe_error_code(error: PipeError) -> u32 {
+    match error {
+        PipeError::NotFound => 6, // ERROR_INVALID_HANDLE
+        PipeError::WrongEnd => 5, // ERROR_ACCESS_DENIED
+        PipeError::Closed => 232, // ERROR_NO_DATA
+        PipeError::WouldBlock => 232, // ERROR_NO_DATA
+    }
+}
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
@@ -11,7 +23,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         args: &[OpTy<'tcx, Tag>],
         dest: PlaceTy<'tcx, Tag>,
         _ret: mir::BasicBlock,
-    ) -> InterpResult<'tcx, bool> {
+    ) -> InterpResult<'tcx, EmulateByNameResult> {
         let this = self.eval_context_mut();
         let tcx = &{ this.tcx.tcx };
 
@@ -23,22 +35,20 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
             // Environment related shims
             "GetEnvironmentVariableW" => {
-                // args[0] : LPCWSTR lpName (32-bit ptr to a const string of 16-bit Unicode chars)
-                // args[1] : LPWSTR lpBuffer (32-bit pointer to a string of 16-bit Unicode chars)
-                // lpBuffer : ptr to buffer that receives contents of the env_var as a null-terminated string.
-                // Return `# of chars` stored in the buffer pointed to by lpBuffer, excluding null-terminator.
-                // Return 0 upon failure.
-
-                // This is not the env var you are looking for.
-                this.set_last_error(Scalar::from_u32(203))?; // ERROR_ENVVAR_NOT_FOUND
-                this.write_null(dest)?;
+                let result = this.windows_get_environment_variable(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
             }
-
             "SetEnvironmentVariableW" => {
-                // args[0] : LPCWSTR lpName (32-bit ptr to a const string of 16-bit Unicode chars)
-                // args[1] : LPCWSTR lpValue (32-bit ptr to a const string of 16-bit Unicode chars)
-                // Return nonzero if success, else return 0.
-                throw_unsup_format!("can't set environment variable on Windows");
+                let result = this.windows_set_environment_variable(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "GetEnvironmentStringsW" => {
+                let result = this.windows_get_environment_strings()?;
+                this.write_scalar(result, dest)?;
+            }
+            "FreeEnvironmentStringsW" => {
+                let result = this.windows_free_environment_strings(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
             }
 
             // File related shims
@@ -54,12 +64,23 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                     use std::io::{self, Write};
 
                     let buf_cont = this.memory.read_bytes(buf, Size::from_bytes(u64::from(n)))?;
-                    let res = if handle == -11 {
+                    let res = if this.machine.discard_output {
+                        Ok(buf_cont.len())
+                    } else if handle == -11 {
                         io::stdout().write(buf_cont)
                     } else {
                         io::stderr().write(buf_cont)
                     };
                     res.ok().map(|n| n as u32)
+                } else if this.machine.pipe_handler.is_pipe(handle) {
+                    let buf_cont = this.memory.read_bytes(buf, Size::from_bytes(u64::from(n)))?;
+                    match this.machine.pipe_handler.write(handle, buf_cont) {
+                        Ok(n) => Some(n as u32),
+                        Err(error) => {
+                            this.set_last_error(Scalar::from_u32(pipe_error_code(error)))?;
+                            None
+                        }
+                    }
                 } else {
                     eprintln!("Miri: Ignored output to handle {}", handle);
                     // Pretend it all went well.
@@ -76,7 +97,135 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 )?;
             }
 
+            "ReadFile" => {
+                let handle = this.read_scalar(args[0])?.to_machine_isize(this)?;
+                let buf = this.read_scalar(args[1])?.not_undef()?;
+                let n = this.read_scalar(args[2])?.to_u32()?;
+                let read_place = this.deref_operand(args[3])?;
+                if !this.is_null(this.read_scalar(args[4])?.not_undef()?)? {
+                    throw_unsup_format!("Miri does not support overlapped I/O");
+                }
+                // Spec says to always write `0` first.
+                this.write_null(read_place.into())?;
+                if !this.machine.pipe_handler.is_pipe(handle) {
+                    throw_unsup_format!("Miri only supports reading from pipes on Windows");
+                }
+                let read = match this.machine.pipe_handler.read(handle, n as usize) {
+                    // Reaching the end of a pipe whose write end was closed is reported as an
+                    // error.
+                    Ok(bytes) if bytes.is_empty() && n > 0 => {
+                        this.set_last_error(Scalar::from_u32(109))?; // ERROR_BROKEN_PIPE
+                        None
+                    }
+                    Ok(bytes) => {
+                        let len = bytes.len() as u32;
+                        this.memory.write_bytes(buf, bytes)?;
+                        Some(len)
+                    }
+                    Err(PipeError::WouldBlock) => {
+                        // Only another thread can write to the pipe. Let it run, and then this
+                        // call is executed again.
+                        match this.block_active_thread(false, None, BlockReason::Pipe)? {
+                            BlockResult::Retry => return Ok(EmulateByNameResult::AlreadyJumped),
+                            result => bug!("blocking without a timeout returned {:?}", result),
+                        }
+                    }
+                    Err(error) => {
+                        this.set_last_error(Scalar::from_u32(pipe_error_code(error)))?;
+                        None
+                    }
+                };
+                // If there was no error, write back how much was read.
+                if let Some(n) = read {
+                    this.write_scalar(Scalar::from_u32(n), read_place.into())?;
+                }
+                // Return whether this was a success.
+                this.write_scalar(
+                    Scalar::from_int(if read.is_some() { 1 } else { 0 }, dest.layout.size),
+                    dest,
+                )?;
+            }
+            "CreatePipe" => {
+                let read_place = this.deref_operand(args[0])?;
+                let write_place = this.deref_operand(args[1])?;
+                // We ignore the security attributes and the suggested buffer size; our pipes
+                // have unbounded buffers.
+                let _attributes = this.read_scalar(args[2])?.not_undef()?;
+                let _size = this.read_scalar(args[3])?.to_u32()?;
+                let (read, write) = this.machine.pipe_handler.create();
+                this.write_scalar(Scalar::from_int(read, this.pointer_size()), read_place.into())?;
+                this.write_scalar(Scalar::from_int(write, this.pointer_size()), write_place.into())?;
+                // Return success (`1`).
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+            "CloseHandle" => {
+                let handle = this.read_scalar(args[0])?.to_machine_isize(this)?;
+                let closed = this.machine.pipe_handler.close(handle)
+                    || this.windows_close_thread_handle(args[0])?;
+                if !closed {
+                    // Pipes and threads are the only kernel objects we hand out handles for.
+                    this.set_last_error(Scalar::from_u32(6))?; // ERROR_INVALID_HANDLE
+                }
+                this.write_scalar(
+                    Scalar::from_int(if closed { 1 } else { 0 }, dest.layout.size),
+                    dest,
+                )?;
+            }
+
+            "DeleteFileW" => {
+                let result = this.windows_delete_file(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "MoveFileExW" => {
+                let result = this.windows_move_file_ex(args[0], args[1], args[2])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "GetFullPathNameW" => {
+                let result = this.windows_get_full_path_name(args[0], args[1], args[2], args[3])?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+
+            "FindFirstFileW" => {
+                let result = this.windows_find_first_file(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "FindNextFileW" => {
+                let result = this.windows_find_next_file(args[0], args[1])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "FindClose" => {
+                let result = this.windows_find_close(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            // Time related shims
+            "GetSystemTimeAsFileTime" => {
+                this.windows_get_system_time_as_file_time(args[0])?;
+            }
+            "QueryPerformanceCounter" => {
+                let result = this.windows_query_performance_counter(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "QueryPerformanceFrequency" => {
+                let result = this.windows_query_performance_frequency(args[0])?;
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "Sleep" => {
+                let ms = this.read_scalar(args[0])?.to_u32()?;
+                if ms == u32::max_value() {
+                    // INFINITE
+                    throw_unsup_format!("sleeping forever is not supported");
+                }
+                this.machine.clock.sleep(Duration::from_millis(u64::from(ms)));
+            }
             // Other shims
+            "GetCurrentProcessId" => {
+                this.write_scalar(Scalar::from_u32(PID), dest)?;
+            }
+            "GetCurrentThreadId" => {
+                let result = this.current_thread_os_id();
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
             "GetProcessHeap" => {
                 // Just fake a HANDLE
                 this.write_scalar(Scalar::from_int(1, this.pointer_size()), dest)?;
@@ -86,7 +235,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let flags = this.read_scalar(args[1])?.to_u32()?;
                 let size = this.read_scalar(args[2])?.to_machine_usize(this)?;
                 let zero_init = (flags & 0x00000008) != 0; // HEAP_ZERO_MEMORY
-                let res = this.malloc(size, zero_init, MiriMemoryKind::WinHeap);
+                let res = this.malloc(size, zero_init, MiriMemoryKind::WinHeap)?;
                 this.write_scalar(res, dest)?;
             }
             "HeapFree" => {
@@ -118,17 +267,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
             }
 
-            | "InitializeCriticalSection"
-            | "EnterCriticalSection"
-            | "LeaveCriticalSection"
-            | "DeleteCriticalSection"
-            => {
-                // Nothing to do, not even a return value.
-            }
-
             | "GetModuleHandleW"
-            | "GetProcAddress"
-            | "TryEnterCriticalSection"
             | "GetConsoleScreenBufferInfo"
             | "SetConsoleTextAttribute"
             => {
@@ -146,14 +285,14 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 // Set number of processors.
                 let dword_size = Size::from_bytes(4);
                 let num_cpus = this.mplace_field(system_info, 6)?;
-                this.write_scalar(Scalar::from_int(NUM_CPUS, dword_size), num_cpus.into())?;
+                this.write_scalar(Scalar::from_uint(this.machine.num_cpus, dword_size), num_cpus.into())?;
             }
 
             "TlsAlloc" => {
                 // This just creates a key; Windows does not natively support TLS destructors.
 
-                // Create key and return it.
-                let key = this.machine.tls.create_tls_key(None) as u128;
+                // Create key and return it, or `TLS_OUT_OF_INDEXES` if there are too many.
+                let key = this.machine.tls.create_tls_key(None).unwrap_or(u32::max_value().into());
 
                 // Figure out how large a TLS key actually is. This is `c::DWORD`.
                 if dest.layout.size.bits() < 128
@@ -165,13 +304,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             }
             "TlsGetValue" => {
                 let key = this.read_scalar(args[0])?.to_u32()? as u128;
-                let ptr = this.machine.tls.load_tls(key, tcx)?;
+                let active_thread = this.machine.threads.active_thread();
+                let ptr = this.machine.tls.load_tls(key, active_thread, tcx)?;
                 this.write_scalar(ptr, dest)?;
             }
             "TlsSetValue" => {
                 let key = this.read_scalar(args[0])?.to_u32()? as u128;
                 let new_ptr = this.read_scalar(args[1])?.not_undef()?;
-                this.machine.tls.store_tls(key, this.test_null(new_ptr)?)?;
+                let active_thread = this.machine.threads.active_thread();
+                this.machine.tls.store_tls(key, active_thread, this.test_null(new_ptr)?)?;
 
                 // Return success (`1`).
                 this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
@@ -199,14 +340,126 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 this.gen_random(ptr, len as usize)?;
                 this.write_scalar(Scalar::from_bool(true), dest)?;
             }
-            // We don't support threading.
+            // Threads
             "CreateThread" => {
-                throw_unsup_format!("Miri does not support threading");
+                let handle = this.windows_create_thread(
+                    args[0], args[1], args[2], args[3], args[4], args[5],
+                )?;
+                this.write_scalar(handle, dest)?;
+            }
+            "GetProcAddress" => {
+                let _module = this.read_scalar(args[0])?;
+                let symbol = this.read_scalar(args[1])?.not_undef()?;
+                let symbol_name = this.memory.read_c_str(symbol)?;
+                // Functions we have no shim for do not exist, so that the program falls back to
+                // something else.
+                match std::str::from_utf8(symbol_name).ok().and_then(Dlsym::from_windows_name) {
+                    Some(dlsym) => {
+                        let ptr = this.memory.create_fn_alloc(FnVal::Other(dlsym));
+                        this.write_scalar(Scalar::from(ptr), dest)?;
+                    }
+                    None => this.write_null(dest)?,
+                }
+            }
+
+            // Synchronization primitives
+            "InitializeCriticalSection" => {
+                this.initialize_critical_section(args[0])?;
+            }
+            "EnterCriticalSection" => {
+                this.enter_critical_section(args[0])?;
+            }
+            "TryEnterCriticalSection" => {
+                let entered = this.try_enter_critical_section(args[0])?;
+                this.write_scalar(Scalar::from_int(entered as i32, dest.layout.size), dest)?;
+            }
+            "LeaveCriticalSection" => {
+                this.leave_critical_section(args[0])?;
+            }
+            "DeleteCriticalSection" => {
+                this.delete_critical_section(args[0])?;
+            }
+            "AcquireSRWLockExclusive" => {
+                this.acquire_srwlock_exclusive(args[0])?;
+            }
+            "AcquireSRWLockShared" => {
+                this.acquire_srwlock_shared(args[0])?;
+            }
+            "TryAcquireSRWLockExclusive" => {
+                let acquired = this.try_acquire_srwlock_exclusive(args[0])?;
+                this.write_scalar(Scalar::from_int(acquired as i32, dest.layout.size), dest)?;
+            }
+            "TryAcquireSRWLockShared" => {
+                let acquired = this.try_acquire_srwlock_shared(args[0])?;
+                this.write_scalar(Scalar::from_int(acquired as i32, dest.layout.size), dest)?;
+            }
+            "ReleaseSRWLockExclusive" => {
+                this.release_srwlock_exclusive(args[0])?;
+            }
+            "ReleaseSRWLockShared" => {
+                this.release_srwlock_shared(args[0])?;
+            }
+            "InitializeConditionVariable" => {
+                // Waiters are tracked by the address of the condition variable; there is no state
+                // in its memory.
+            }
+            "SleepConditionVariableSRW" => {
+                let result = this.sleep_condition_variable_srw(args[0], args[1], args[2], args[3])?;
+                let result = match result {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "SleepConditionVariableCS" => {
+                let result = match this.sleep_condition_variable_cs(args[0], args[1], args[2])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "WakeConditionVariable" => {
+                this.wake_condition_variable(args[0])?;
+            }
+            "WakeAllConditionVariable" => {
+                this.wake_all_condition_variable(args[0])?;
+            }
+            "WaitOnAddress" => {
+                let result = match this.wait_on_address(args[0], args[1], args[2], args[3])? {
+                    Some(result) => result,
+                    // Another thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "WakeByAddressSingle" => {
+                this.wake_by_address(args[0], false)?;
+            }
+            "WakeByAddressAll" => {
+                this.wake_by_address(args[0], true)?;
+            }
+
+            "SwitchToThread" => {
+                if this.yield_active_thread()? {
+                    return Ok(EmulateByNameResult::AlreadyJumped);
+                }
+                // There is no other thread to switch to anymore.
+                this.write_null(dest)?;
+            }
+            "WaitForSingleObject" => {
+                let result = match this.windows_wait_for_single_object(args[0], args[1])? {
+                    Some(result) => result,
+                    // The thread runs first, and then this call is executed again.
+                    None => return Ok(EmulateByNameResult::AlreadyJumped),
+                };
+                this.write_scalar(Scalar::from_u32(result), dest)?;
             }
-            _ => throw_unsup_format!("can't call foreign function: {}", link_name),
+            _ => return Ok(EmulateByNameResult::NotSupported),
         }
 
-        Ok(true)
+        Ok(EmulateByNameResult::NeedsJumping)
     }
 }
 
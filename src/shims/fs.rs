@@ -1,22 +1,158 @@
+use std::cell::Cell;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
-use std::fs::{read_dir, remove_dir, remove_file, rename, DirBuilder, File, FileType, OpenOptions, ReadDir};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
-use std::time::SystemTime;
-
-use rustc::ty::layout::{Align, LayoutOf, Size};
+use std::ffi::OsStr;
+use std::fs::{
+    read_dir, remove_dir, remove_file, rename, DirBuilder, DirEntry, File, FileType, Metadata,
+    OpenOptions, ReadDir,
+};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+use rustc::ty::layout::{Align, Endian, LayoutOf, Size};
 
 use crate::stacked_borrows::Tag;
 use crate::*;
 use helpers::immty_from_uint_checked;
-use shims::time::system_time_to_duration;
+use shims::time::{system_time_to_duration, system_time_to_filetime};
+
+/// The file system operations whose failures `-Zmiri-io-error` can script.
+pub const SCRIPTABLE_IO_OPS: &[&str] = &["open", "read", "write", "close", "fsync", "fdatasync"];
+
+/// A failure scripted with `-Zmiri-io-error`: the `call`th call, counting from 1, of the file
+/// system operation `op` fails with the `libc` error constant `error`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptedIoError {
+    pub op: String,
+    pub call: u64,
+    pub error: String,
+}
+
+/// The object behind a file descriptor.
+#[derive(Debug)]
+enum FileObject {
+    /// A file on the host.
+    Host(File),
+    /// A read-only file whose contents are synthesized by Miri, like `/proc/self/maps`.
+    Virtual(VirtualFile),
+    /// A timer created by `timerfd_create`. Reading it is handled by the `read` shim, since it
+    /// needs access to the interpreter's clock.
+    Timer(Timer),
+}
+
+/// The contents of a file synthesized by Miri, and the offset of the descriptor. Like for host
+/// files, duplicated descriptors share the offset.
+#[derive(Debug, Clone)]
+struct VirtualFile {
+    contents: Rc<[u8]>,
+    offset: Rc<Cell<u64>>,
+}
+
+impl VirtualFile {
+    fn new(contents: Vec<u8>) -> Self {
+        VirtualFile { contents: contents.into(), offset: Rc::new(Cell::new(0)) }
+    }
+
+    /// Runs `f` on a cursor at the shared offset, and stores where it leaves the cursor.
+    fn with_cursor<T>(&self, f: impl FnOnce(&mut Cursor<&[u8]>) -> T) -> T {
+        let mut cursor = Cursor::new(&self.contents[..]);
+        cursor.set_position(self.offset.get());
+        let result = f(&mut cursor);
+        self.offset.set(cursor.position());
+        result
+    }
+}
+
+/// The state of a timer created by `timerfd_create`.
+#[derive(Debug, Clone)]
+struct Timer {
+    /// The clock that deadlines are measured with.
+    clock_id: i32,
+    /// Whether `read` fails with `EAGAIN` instead of waiting for the next expiration.
+    nonblocking: bool,
+    /// The next expiration, on the timer's clock, or `None` if the timer is disarmed.
+    deadline: Option<Duration>,
+    /// The period of the timer; zero for a one-shot timer.
+    interval: Duration,
+}
+
+impl FileObject {
+    fn try_clone(&self) -> std::io::Result<FileObject> {
+        match self {
+            FileObject::Host(file) => file.try_clone().map(FileObject::Host),
+            FileObject::Virtual(file) => Ok(FileObject::Virtual(file.clone())),
+            // FIXME: the duplicate should share the timer with the original.
+            FileObject::Timer(timer) => Ok(FileObject::Timer(timer.clone())),
+        }
+    }
+
+    fn sync_all(&self) -> std::io::Result<()> {
+        match self {
+            FileObject::Host(file) => file.sync_all(),
+            FileObject::Virtual(_) | FileObject::Timer(_) => Ok(()),
+        }
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        match self {
+            FileObject::Host(file) => file.sync_data(),
+            FileObject::Virtual(_) | FileObject::Timer(_) => Ok(()),
+        }
+    }
+}
+
+impl Read for FileObject {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            FileObject::Host(file) => file.read(buf),
+            FileObject::Virtual(file) => file.with_cursor(|cursor| cursor.read(buf)),
+            FileObject::Timer(_) => Err(std::io::ErrorKind::InvalidInput.into()),
+        }
+    }
+}
+
+impl Write for FileObject {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            FileObject::Host(file) => file.write(buf),
+            FileObject::Virtual(_) => Err(std::io::ErrorKind::PermissionDenied.into()),
+            FileObject::Timer(_) => Err(std::io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            FileObject::Host(file) => file.flush(),
+            FileObject::Virtual(_) | FileObject::Timer(_) => Ok(()),
+        }
+    }
+}
+
+impl Seek for FileObject {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            FileObject::Host(file) => file.seek(pos),
+            FileObject::Virtual(file) => file.with_cursor(|cursor| cursor.seek(pos)),
+            FileObject::Timer(_) => Err(std::io::ErrorKind::InvalidInput.into()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct FileHandle {
-    file: File,
+    file: FileObject,
     writable: bool,
+    /// The absolute path this file was opened with, used to answer `F_GETPATH` and
+    /// `/proc/self/fd` queries.
+    path: PathBuf,
+    /// Whether the `FD_CLOEXEC` flag is set. This belongs to the descriptor and not to the file,
+    /// so it is not shared with duplicates. Miri does not support `exec`, so nothing acts on it;
+    /// we only keep it to report it back consistently.
+    cloexec: bool,
 }
 
 #[derive(Debug, Default)]
@@ -64,6 +200,48 @@ impl FileHandler {
     }
 }
 
+/// Converts a path coming from a Windows program into a host path.
+fn windows_path_to_host(wide: &[u16]) -> Option<PathBuf> {
+    #[cfg(windows)]
+    return Some(std::os::windows::ffi::OsStringExt::from_wide(wide).into());
+    #[cfg(not(windows))]
+    return String::from_utf16(wide).ok().map(|s| s.replace('\\', "/").into());
+}
+
+/// Converts a host path into the representation a Windows program expects.
+fn host_path_to_windows(path: &Path) -> Option<Vec<u16>> {
+    #[cfg(windows)]
+    return Some(std::os::windows::ffi::OsStrExt::encode_wide(path.as_os_str()).collect());
+    #[cfg(not(windows))]
+    return path.to_str().map(|s| s.replace('/', "\\").encode_utf16().collect());
+}
+
+/// Resolves `.` and `..` components without touching the file system, like `GetFullPathNameW`.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Resolves a relative `path` against the host's current working directory.
+fn absolute_path(path: PathBuf) -> std::io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
 impl<'mir, 'tcx> EvalContextExtPrivate<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     /// Emulate `stat` or `lstat` on the `macos` platform. This function is not intended to be
@@ -151,6 +329,452 @@ trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, '
         Ok(0)
     }
 
+    /// Reads the path argument of one of the `*at` functions and resolves it with respect to
+    /// `dirfd`. We only support absolute paths and paths relative to `AT_FDCWD`, because we do not
+    /// keep track of the paths of directory file descriptors.
+    fn read_path_at(
+        &mut self,
+        dirfd_op: OpTy<'tcx, Tag>,
+        path_op: OpTy<'tcx, Tag>,
+        name: &str,
+    ) -> InterpResult<'tcx, PathBuf> {
+        let this = self.eval_context_mut();
+
+        let dirfd = this.read_scalar(dirfd_op)?.to_i32()?;
+        let path: PathBuf =
+            this.read_os_str_from_c_str(this.read_scalar(path_op)?.not_undef()?)?.into();
+        this.check_path_at(dirfd, &path, name)?;
+
+        Ok(path)
+    }
+
+    /// Checks that `path` can be resolved with respect to `dirfd`, see `read_path_at`.
+    fn check_path_at(&mut self, dirfd: i32, path: &Path, name: &str) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if !(path.is_absolute() || dirfd == this.eval_libc_i32("AT_FDCWD")?) {
+            throw_unsup_format!(
+                "Using `{}` is only supported with absolute paths and relative paths with the \
+                file descriptor `AT_FDCWD`",
+                name,
+            )
+        }
+
+        Ok(())
+    }
+
+    /// Reads the target of the symbolic link at `path` into the buffer described by `buf_op` and
+    /// `bufsize_op`. This is shared by `readlink` and `readlinkat`.
+    fn read_link_to_buf(
+        &mut self,
+        path: PathBuf,
+        buf_op: OpTy<'tcx, Tag>,
+        bufsize_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let buf = this.read_scalar(buf_op)?.not_undef()?;
+        let bufsize = this.read_scalar(bufsize_op)?.to_machine_usize(&*this.tcx)?;
+
+        // Check that the *entire* buffer is actually valid memory.
+        this.memory.check_ptr_access(
+            buf,
+            Size::from_bytes(bufsize),
+            Align::from_bytes(1).unwrap(),
+        )?;
+
+        // On Linux, `/proc/self/fd/N` refers to the file behind our virtual descriptor `N`, not
+        // to whatever the host has open under that number.
+        let result = match this.proc_self_fd_target(&path) {
+            Some(target) => target,
+            None => std::fs::read_link(path),
+        };
+
+        match result {
+            Ok(target) => {
+                let target = target.into_os_string();
+                let bytes = target.to_str().map(str::as_bytes).ok_or_else(|| {
+                    err_unsup_format!("{:?} is not a valid utf-8 string", target)
+                })?;
+                // `readlink` silently truncates the contents if the buffer is too small, and it
+                // never appends a null terminator.
+                let len = u64::try_from(bytes.len()).unwrap().min(bufsize);
+                this.memory.write_bytes(buf, bytes.iter().copied().take(len as usize))?;
+                Ok(i64::try_from(len).unwrap())
+            }
+            Err(e) => {
+                this.set_last_error_from_io_error(e)?;
+                Ok(-1)
+            }
+        }
+    }
+
+    /// If `path` has the shape `/proc/self/fd/N` and we are emulating Linux, returns the path
+    /// of the file behind descriptor `N`. The standard streams are forwarded to the host.
+    fn proc_self_fd_target(&mut self, path: &Path) -> Option<std::io::Result<PathBuf>> {
+        let this = self.eval_context_mut();
+
+        if this.tcx.sess.target.target.target_os != "linux" {
+            return None;
+        }
+        let fd: i32 = path.strip_prefix("/proc/self/fd").ok()?.to_str()?.parse().ok()?;
+        if fd < MIN_NORMAL_FILE_FD {
+            return None;
+        }
+        Some(match this.machine.file_handler.handles.get(&fd) {
+            Some(FileHandle { path, .. }) => Ok(path.clone()),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        })
+    }
+
+    /// Returns the synthesized contents of `path` if it is one of the `/proc/self` files that Miri
+    /// emulates on Linux, and `None` otherwise. The contents only need to be good enough for
+    /// the usual parsers of these files.
+    fn proc_self_file_contents(&mut self, path: &Path) -> InterpResult<'tcx, Option<Vec<u8>>> {
+        let this = self.eval_context_mut();
+
+        if this.tcx.sess.target.target.target_os != "linux" {
+            return Ok(None);
+        }
+        let contents = match path.to_str() {
+            Some("/proc/self/cmdline") => this.proc_self_cmdline()?,
+            Some("/proc/self/maps") => {
+                // Everything we hand out integer addresses for lives above `STACK_ADDR`, so we
+                // present that as one big heap mapping, preceded by the stack.
+                let heap_end = this.memory.extra.intptrcast.borrow().next_base_addr;
+                let heap_end = heap_end.max(STACK_ADDR + PAGE_SIZE);
+                let heap_end = (heap_end + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+                format!(
+                    "{:08x}-{:08x} rw-p 00000000 00:00 0                          [stack]\n\
+                     {:08x}-{:08x} rw-p 00000000 00:00 0                          [heap]\n",
+                    STACK_ADDR - STACK_SIZE,
+                    STACK_ADDR,
+                    STACK_ADDR,
+                    heap_end,
+                )
+                .into_bytes()
+            }
+            Some("/proc/self/status") => {
+                // The kernel truncates the name to 15 bytes.
+                let cmdline = this.proc_self_cmdline()?;
+                let argv0 = cmdline.split(|&b| b == 0).next().unwrap_or(&[]);
+                let name = argv0.rsplit(|&b| b == b'/').next().unwrap_or(&[]);
+                let name = String::from_utf8_lossy(&name[..name.len().min(15)]).into_owned();
+                // Threads that did not start yet already exist for the kernel.
+                let threads =
+                    this.machine.threads.iter().filter(|(_, thread)| !thread.is_terminated()).count();
+                format!("Name:\t{}\nState:\tR (running)\nThreads:\t{}\n", name, threads)
+                    .into_bytes()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(contents))
+    }
+
+    /// Returns the contents of `/proc/self/cmdline`: every program argument followed by a null
+    /// terminator. The arguments are read back from the `argv` array the program was started
+    /// with.
+    fn proc_self_cmdline(&mut self) -> InterpResult<'tcx, Vec<u8>> {
+        let this = self.eval_context_mut();
+
+        let isize_layout = this.layout_of(this.tcx.types.isize)?;
+        let ptr_layout = this.layout_of(this.tcx.mk_imm_ptr(this.tcx.types.u8))?;
+
+        let argc_ptr = this.machine.argc.expect("machine must be initialized").assert_ptr();
+        let argc = this.read_scalar(MPlaceTy::from_aligned_ptr(argc_ptr, isize_layout).into())?;
+        let argc = argc.to_machine_usize(this)?;
+        let argv_ptr = this.machine.argv.expect("machine must be initialized").assert_ptr();
+        let argv = this.read_scalar(MPlaceTy::from_aligned_ptr(argv_ptr, ptr_layout).into())?;
+        let argv = argv.not_undef()?;
+
+        let mut cmdline = Vec::new();
+        for idx in 0..argc {
+            let arg_ptr = argv.ptr_offset(ptr_layout.size * idx, this)?.assert_ptr();
+            let arg = this.read_scalar(MPlaceTy::from_aligned_ptr(arg_ptr, ptr_layout).into())?;
+            cmdline.extend_from_slice(this.memory.read_c_str(arg.not_undef()?)?);
+            cmdline.push(0);
+        }
+        Ok(cmdline)
+    }
+
+    /// Emulates an `O_TMPFILE` open on the host: creates a file with a fresh name inside `dir`
+    /// and unlinks it right away, so that it can only be reached through the returned `File`.
+    /// Like Linux, we report the path of such a file with a ` (deleted)` suffix.
+    fn open_tmpfile(
+        &mut self,
+        dir: PathBuf,
+        mut options: OpenOptions,
+    ) -> std::io::Result<(File, PathBuf)> {
+        let this = self.eval_context_mut();
+
+        // Make sure we get a proper error (e.g. `ENOTDIR`) if `dir` is not a directory.
+        read_dir(&dir)?;
+        options.create_new(true);
+        loop {
+            let suffix: u64 = this.memory.extra.rngs.get_mut(RngPurpose::TempFiles).gen();
+            let path = dir.join(format!(".miri_tmpfile_{:016x}", suffix));
+            match options.open(&path) {
+                Ok(file) => {
+                    remove_file(&path)?;
+                    let mut deleted = path.into_os_string();
+                    deleted.push(" (deleted)");
+                    return Ok((file, deleted.into()));
+                }
+                // Somebody else is using this name, try another one.
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Decides whether the current call of the file system operation `op`, one of
+    /// `SCRIPTABLE_IO_OPS`, should fail. If `-Zmiri-io-error` scripted a failure for this call,
+    /// its error is used. Otherwise, under `-Zmiri-io-error-rate`, one of the `errors` (names of
+    /// `libc` error constants) may be picked at random. The error is stored as the last OS error,
+    /// and `true` is returned.
+    fn inject_io_error(&mut self, op: &'static str, errors: &[&str]) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        let call = this.machine.io_calls.entry(op).or_insert(0);
+        *call += 1;
+        let call = *call;
+        let scripted = this.machine.io_error_script.iter().find(|e| e.op == op && e.call == call);
+        let name = match scripted {
+            Some(scripted) => scripted.error.clone(),
+            None => {
+                let rate = this.machine.io_error_rate;
+                if rate <= 0.0 {
+                    return Ok(false);
+                }
+                let rng = this.memory.extra.rngs.get_mut(RngPurpose::FaultInjection);
+                if !rng.gen_bool(rate) {
+                    return Ok(false);
+                }
+                errors[rng.gen_range(0, errors.len())].to_owned()
+            }
+        };
+        let errno = this.eval_libc(&name)?;
+        this.set_last_error(errno)?;
+        Ok(true)
+    }
+
+    /// Flushes the file behind `fd` to the disk with `sync`, for the operation `op`, which is
+    /// `fsync` or `fdatasync`.
+    fn sync_file(
+        &mut self,
+        fd: i32,
+        op: &'static str,
+        sync: fn(&FileObject) -> std::io::Result<()>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if !this.machine.file_handler.handles.contains_key(&fd) {
+            return this.handle_not_found();
+        }
+        if this.inject_io_error(op, &["EIO", "ENOSPC"])? {
+            return Ok(-1);
+        }
+        let result = sync(&this.machine.file_handler.handles[&fd].file).map(|_| 0i32);
+        this.try_unwrap_io_result(result)
+    }
+
+    /// Reads a null-terminated UTF-16 path, as passed to the Windows file system functions.
+    fn read_windows_path(&self, path_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, PathBuf> {
+        let this = self.eval_context_ref();
+
+        let wide = this.read_wide_str(this.read_scalar(path_op)?.not_undef()?)?;
+        windows_path_to_host(&wide).ok_or_else(|| {
+            err_unsup_format!("{:?} is not a valid utf-16 string", String::from_utf16_lossy(&wide))
+                .into()
+        })
+    }
+
+    /// Under `-Zmiri-short-io`, randomly picks how many of the `count` requested bytes a `read`
+    /// or `write` actually transfers. Both transferring fewer bytes and failing with `EINTR`
+    /// before transferring anything are legal, so programs have to cope with either. Returns
+    /// `None` in the latter case, after setting the last OS error.
+    fn shorten_io(&mut self, count: u64) -> InterpResult<'tcx, Option<u64>> {
+        let this = self.eval_context_mut();
+
+        if !this.machine.short_io || count == 0 {
+            return Ok(Some(count));
+        }
+        let rng = this.memory.extra.rngs.get_mut(RngPurpose::FaultInjection);
+        if rng.gen_bool(0.1) {
+            let eintr = this.eval_libc("EINTR")?;
+            this.set_last_error(eintr)?;
+            return Ok(None);
+        }
+        Ok(Some(rng.gen_range(1, count + 1)))
+    }
+
+    /// Fills a `WIN32_FIND_DATAW` struct with the information about one directory entry.
+    fn windows_write_find_data(
+        &mut self,
+        name: &OsStr,
+        metadata: std::io::Result<Metadata>,
+        data_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+        const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+        const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+        const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000000C;
+        // The offset of `cFileName` and the number of `u16`s it can hold.
+        const FILE_NAME_OFFSET: usize = 44;
+        const MAX_PATH: u64 = 260;
+
+        let data = this.deref_operand(data_op)?;
+        let mut bytes = vec![0u8; data.layout.size.bytes() as usize];
+        if bytes.len() != 592 {
+            throw_unsup_format!("unexpected size {} of `WIN32_FIND_DATAW`", bytes.len());
+        }
+        let mut write_u32 = |offset: usize, value: u32| {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+
+        if let Ok(metadata) = metadata {
+            let file_type = metadata.file_type();
+            let mut attributes = 0;
+            if file_type.is_dir() {
+                attributes |= FILE_ATTRIBUTE_DIRECTORY;
+            }
+            if file_type.is_symlink() {
+                attributes |= FILE_ATTRIBUTE_REPARSE_POINT;
+                // `dwReserved0` holds the kind of reparse point.
+                write_u32(36, IO_REPARSE_TAG_SYMLINK);
+            }
+            if metadata.permissions().readonly() {
+                attributes |= FILE_ATTRIBUTE_READONLY;
+            }
+            write_u32(0, if attributes == 0 { FILE_ATTRIBUTE_NORMAL } else { attributes });
+            // `ftCreationTime`, `ftLastAccessTime` and `ftLastWriteTime`.
+            let times = [metadata.created(), metadata.accessed(), metadata.modified()];
+            for (idx, time) in times.iter().enumerate() {
+                let filetime = match time {
+                    Ok(time) => system_time_to_filetime(time)?,
+                    Err(_) => 0,
+                };
+                write_u32(4 + idx * 8, filetime as u32);
+                write_u32(8 + idx * 8, (filetime >> 32) as u32);
+            }
+            // `nFileSizeHigh` and `nFileSizeLow`.
+            write_u32(28, (metadata.len() >> 32) as u32);
+            write_u32(32, metadata.len() as u32);
+        } else {
+            write_u32(0, FILE_ATTRIBUTE_NORMAL);
+        }
+        this.memory.write_bytes(data.ptr, bytes)?;
+
+        let name = host_path_to_windows(Path::new(name)).ok_or_else(|| {
+            err_unsup_format!("{:?} is not a valid utf-8 string", name)
+        })?;
+        let name_ptr = data.ptr.ptr_offset(Size::from_bytes(FILE_NAME_OFFSET as u64), this)?;
+        let (written, _) = this.write_wide_str(&name, name_ptr, MAX_PATH)?;
+        if !written {
+            throw_unsup_format!("file names longer than `MAX_PATH` are not supported");
+        }
+        Ok(())
+    }
+
+    /// Duplicates `fd` into the lowest free descriptor that is at least `min_fd`. The new
+    /// descriptor shares the file and its offset with `fd`, but not the `FD_CLOEXEC` flag, which
+    /// is set to `cloexec` instead.
+    fn dup_fd(&mut self, fd: i32, min_fd: i32, cloexec: bool) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if fd < MIN_NORMAL_FILE_FD {
+            throw_unsup_format!("Duplicating file descriptors for stdin, stdout, or stderr is not supported")
+        }
+        let fh = &mut this.machine.file_handler;
+        let (file_result, writable, path) = match fh.handles.get(&fd) {
+            Some(FileHandle { file, writable, path, .. }) =>
+                (file.try_clone(), *writable, path.clone()),
+            None => return this.handle_not_found(),
+        };
+        let fd_result = file_result.map(|file| {
+            fh.insert_fd_with_min_fd(FileHandle { file, writable, path, cloexec }, min_fd)
+        });
+        this.try_unwrap_io_result(fd_result)
+    }
+
+    fn is_timer(&self, fd: i32) -> bool {
+        let this = self.eval_context_ref();
+        match this.machine.file_handler.handles.get(&fd) {
+            Some(FileHandle { file: FileObject::Timer(_), .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether `fd` is served by Miri itself without touching the host, like timers and the
+    /// `/proc/self` files. Such descriptors can be used with isolation.
+    fn is_virtual(&self, fd: i32) -> bool {
+        let this = self.eval_context_ref();
+        match this.machine.file_handler.handles.get(&fd) {
+            Some(FileHandle { file: FileObject::Timer(_), .. })
+            | Some(FileHandle { file: FileObject::Virtual(_), .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Reads the number of expirations of the timer `fd` since the last read into `buf`, waiting
+    /// for the next expiration if there was none.
+    fn read_timer(&mut self, fd: i32, buf: Scalar<Tag>, count: u64) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        // The expirations are returned as a `u64`, so smaller buffers are rejected.
+        if count < 8 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+
+        let timer = match this.machine.file_handler.handles.get(&fd) {
+            Some(FileHandle { file: FileObject::Timer(timer), .. }) => timer.clone(),
+            _ => bug!("`read_timer` called on something that is not a timer"),
+        };
+        let mut now = this.read_clock(timer.clock_id)?.unwrap();
+        let deadline = match timer.deadline {
+            Some(deadline) if deadline <= now => deadline,
+            _ if timer.nonblocking => {
+                let eagain = this.eval_libc("EAGAIN")?;
+                this.set_last_error(eagain)?;
+                return Ok(-1);
+            }
+            // There is only one thread, so nobody can ever arm the timer.
+            None => return this.deadlock(BlockReason::Timer),
+            Some(deadline) => {
+                this.machine.clock.sleep(deadline - now);
+                now = deadline;
+                deadline
+            }
+        };
+
+        // Count the expirations up to now, and compute the next one.
+        let (expirations, next_deadline) = if timer.interval == Duration::from_secs(0) {
+            (1, None)
+        } else {
+            let periods = (now - deadline).as_nanos() / timer.interval.as_nanos();
+            let periods = u32::try_from(periods).map_err(|_| {
+                err_unsup_format!("timer expired more than 2^32 times since it was last read")
+            })?;
+            (u64::from(periods) + 1, Some(deadline + timer.interval * (periods + 1)))
+        };
+        match this.machine.file_handler.handles.get_mut(&fd) {
+            Some(FileHandle { file: FileObject::Timer(timer), .. }) => timer.deadline = next_deadline,
+            _ => bug!("timer disappeared while reading it"),
+        }
+
+        let bytes = match this.tcx.data_layout.endian {
+            Endian::Little => expirations.to_le_bytes(),
+            Endian::Big => expirations.to_be_bytes(),
+        };
+        this.memory.write_bytes(buf, bytes.iter().copied())?;
+        Ok(8)
+    }
+
     /// Function used when a handle is not found inside `FileHandler`. It returns `Ok(-1)`and sets
     /// the last OS error to `libc::EBADF` (invalid file descriptor). This function uses
     /// `T: From<i32>` instead of `i32` directly because some fs functions return different integer
@@ -204,6 +828,43 @@ trait EvalContextExtPrivate<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, '
     }
 }
 
+/// A directory stream, either read lazily from the host or collected and sorted upfront.
+#[derive(Debug)]
+enum DirStream {
+    Host(ReadDir),
+    Sorted(std::vec::IntoIter<std::io::Result<DirEntry>>),
+}
+
+impl DirStream {
+    fn new(read_dir: ReadDir, deterministic: bool) -> DirStream {
+        if deterministic {
+            let mut entries: Vec<_> = read_dir.collect();
+            // Sort by name so that the order does not depend on the host file system. Errors go
+            // last, which is where a lazy iterator could have reported them as well.
+            entries.sort_by(|a, b| match (a, b) {
+                (Ok(a), Ok(b)) => a.file_name().cmp(&b.file_name()),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+            });
+            DirStream::Sorted(entries.into_iter())
+        } else {
+            DirStream::Host(read_dir)
+        }
+    }
+}
+
+impl Iterator for DirStream {
+    type Item = std::io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            DirStream::Host(read_dir) => read_dir.next(),
+            DirStream::Sorted(entries) => entries.next(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DirHandler {
     /// Directory iterators used to emulate libc "directory streams", as used in opendir, readdir,
@@ -215,28 +876,30 @@ pub struct DirHandler {
     /// the corresponding ReadDir iterator from this HashMap, and information from the next
     /// directory entry is returned. When closedir is called, the ReadDir iterator is removed from
     /// this HashMap.
-    streams: HashMap<u64, ReadDir>,
+    streams: HashMap<u64, DirStream>,
     /// ID number to be used by the next call to opendir
     next_id: u64,
+    /// Whether to sort the entries of every directory stream by name, instead of using the order
+    /// in which the host returns them.
+    deterministic: bool,
 }
 
 impl DirHandler {
-    fn insert_new(&mut self, read_dir: ReadDir) -> u64 {
-        let id = self.next_id;
-        self.next_id += 1;
-        self.streams.insert(id, read_dir).unwrap_none();
-        id
-    }
-}
-
-impl Default for DirHandler {
-    fn default() -> DirHandler {
+    pub(crate) fn new(deterministic: bool) -> DirHandler {
         DirHandler {
             streams: HashMap::new(),
             // Skip 0 as an ID, because it looks like a null pointer to libc
             next_id: 1,
+            deterministic,
         }
     }
+
+    fn insert_new(&mut self, read_dir: ReadDir) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.streams.insert(id, DirStream::new(read_dir, self.deterministic)).unwrap_none();
+        id
+    }
 }
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
@@ -248,8 +911,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("open")?;
-
         let flag = this.read_scalar(flag_op)?.to_i32()?;
 
         let mut options = OpenOptions::new();
@@ -299,22 +960,68 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             mirror |= o_creat;
         }
         let o_cloexec = this.eval_libc_i32("O_CLOEXEC")?;
-        if flag & o_cloexec != 0 {
-            // We do not need to do anything for this flag because `std` already sets it.
-            // (Technically we do not support *not* setting this flag, but we ignore that.)
+        let cloexec = flag & o_cloexec != 0;
+        if cloexec {
+            // We do not need to do anything on the host for this flag, we only have to remember it
+            // for `F_GETFD`.
             mirror |= o_cloexec;
         }
+        // `O_TMPFILE` only exists on Linux, where it also includes the bits of `O_DIRECTORY`.
+        let mut tmpfile = false;
+        if this.tcx.sess.target.target.target_os == "linux" {
+            let o_tmpfile = this.eval_libc_i32("O_TMPFILE")?;
+            if flag & o_tmpfile == o_tmpfile {
+                // An unnamed temporary file must be opened with write access.
+                if access_mode == o_rdonly {
+                    let einval = this.eval_libc("EINVAL")?;
+                    this.set_last_error(einval)?;
+                    return Ok(-1);
+                }
+                tmpfile = true;
+                mirror |= o_tmpfile;
+                // `O_EXCL` only prevents the file from being linked into the filesystem later,
+                // which we do not support anyway.
+                let o_excl = this.eval_libc_i32("O_EXCL")?;
+                mirror |= flag & o_excl;
+            }
+        }
         // If `flag` is not equal to `mirror`, there is an unsupported option enabled in `flag`,
         // then we throw an error.
         if flag != mirror {
             throw_unsup_format!("unsupported flags {:#x}", flag & !mirror);
         }
 
-        let path = this.read_os_str_from_c_str(this.read_scalar(path_op)?.not_undef()?)?;
+        let path: PathBuf =
+            this.read_os_str_from_c_str(this.read_scalar(path_op)?.not_undef()?)?.into();
 
-        let fd = options.open(&path).map(|file| {
+        // Some files in `/proc/self` are served by Miri itself, since the host's versions would
+        // describe the interpreter and not the interpreted program.
+        if let Some(contents) = this.proc_self_file_contents(&path)? {
+            if writable {
+                let eacces = this.eval_libc("EACCES")?;
+                this.set_last_error(eacces)?;
+                return Ok(-1);
+            }
+            let file = FileObject::Virtual(VirtualFile::new(contents));
             let fh = &mut this.machine.file_handler;
-            fh.insert_fd(FileHandle { file, writable })
+            return Ok(fh.insert_fd(FileHandle { file, writable, path, cloexec }));
+        }
+
+        this.check_no_isolation("open")?;
+
+        if this.inject_io_error("open", &["EINTR", "EMFILE"])? {
+            return Ok(-1);
+        }
+
+        let result = match absolute_path(path) {
+            Ok(path) if tmpfile => this.open_tmpfile(path, options),
+            Ok(path) => options.open(&path).map(|file| (file, path)),
+            Err(e) => Err(e),
+        };
+        let fd = result.map(|(file, path)| {
+            let file = FileObject::Host(file);
+            let fh = &mut this.machine.file_handler;
+            fh.insert_fd(FileHandle { file, writable, path, cloexec })
         });
 
         this.try_unwrap_io_result(fd)
@@ -328,59 +1035,145 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("fcntl")?;
-
         let fd = this.read_scalar(fd_op)?.to_i32()?;
+        if !this.is_virtual(fd) {
+            this.check_no_isolation("fcntl")?;
+        }
         let cmd = this.read_scalar(cmd_op)?.to_i32()?;
-        // We only support getting the flags for a descriptor.
+        // `FD_CLOEXEC` is the only descriptor flag there is.
         if cmd == this.eval_libc_i32("F_GETFD")? {
-            // Currently this is the only flag that `F_GETFD` returns. It is OK to just return the
-            // `FD_CLOEXEC` value without checking if the flag is set for the file because `std`
-            // always sets this flag when opening a file. However we still need to check that the
-            // file itself is open.
-            if this.machine.file_handler.handles.contains_key(&fd) {
-                Ok(this.eval_libc_i32("FD_CLOEXEC")?)
-            } else {
-                this.handle_not_found()
+            match this.machine.file_handler.handles.get(&fd) {
+                Some(FileHandle { cloexec: true, .. }) => Ok(this.eval_libc_i32("FD_CLOEXEC")?),
+                Some(FileHandle { cloexec: false, .. }) => Ok(0),
+                None => this.handle_not_found(),
+            }
+        } else if cmd == this.eval_libc_i32("F_SETFD")? {
+            let flags_op = start_op.ok_or_else(|| {
+                err_unsup_format!("fcntl with command F_SETFD requires a third argument")
+            })?;
+            let flags = this.read_scalar(flags_op)?.to_i32()?;
+            let fd_cloexec = this.eval_libc_i32("FD_CLOEXEC")?;
+            match this.machine.file_handler.handles.get_mut(&fd) {
+                Some(FileHandle { cloexec, .. }) => {
+                    *cloexec = flags & fd_cloexec != 0;
+                    Ok(0)
+                }
+                None => this.handle_not_found(),
             }
         } else if cmd == this.eval_libc_i32("F_DUPFD")?
             || cmd == this.eval_libc_i32("F_DUPFD_CLOEXEC")?
         {
-            // Note that we always assume the FD_CLOEXEC flag is set for every open file, in part
-            // because exec() isn't supported. The F_DUPFD and F_DUPFD_CLOEXEC commands only
-            // differ in whether the FD_CLOEXEC flag is pre-set on the new file descriptor,
-            // thus they can share the same implementation here.
-            if fd < MIN_NORMAL_FILE_FD {
-                throw_unsup_format!("Duplicating file descriptors for stdin, stdout, or stderr is not supported")
-            }
+            // The F_DUPFD and F_DUPFD_CLOEXEC commands only differ in whether the FD_CLOEXEC flag
+            // is set on the new file descriptor, thus they can share the same implementation here.
             let start_op = start_op.ok_or_else(|| {
                 err_unsup_format!(
                     "fcntl with command F_DUPFD or F_DUPFD_CLOEXEC requires a third argument"
                 )
             })?;
             let start = this.read_scalar(start_op)?.to_i32()?;
-            let fh = &mut this.machine.file_handler;
-            let (file_result, writable) = match fh.handles.get(&fd) {
-                Some(FileHandle { file, writable }) => (file.try_clone(), *writable),
+            let cloexec = cmd == this.eval_libc_i32("F_DUPFD_CLOEXEC")?;
+            this.dup_fd(fd, start, cloexec)
+        } else if this.tcx.sess.target.target.target_os == "macos"
+            && cmd == this.eval_libc_i32("F_GETPATH")?
+        {
+            // The buffer passed to `F_GETPATH` must be at least `PATH_MAX` bytes long.
+            let buf_op = start_op.ok_or_else(|| {
+                err_unsup_format!("fcntl with command F_GETPATH requires a third argument")
+            })?;
+            let buf = this.read_scalar(buf_op)?.not_undef()?;
+            let path = match this.machine.file_handler.handles.get(&fd) {
+                Some(FileHandle { path, .. }) => path.clone(),
                 None => return this.handle_not_found(),
             };
-            let fd_result = file_result.map(|duplicated| {
-                fh.insert_fd_with_min_fd(FileHandle { file: duplicated, writable }, start)
-            });
-            this.try_unwrap_io_result(fd_result)
+            let path_max = u64::try_from(this.eval_libc_i32("PATH_MAX")?).unwrap();
+            let (written, _) = this.write_os_str_to_c_str(path.as_os_str(), buf, path_max)?;
+            if written {
+                Ok(0)
+            } else {
+                let enametoolong = this.eval_libc("ENAMETOOLONG")?;
+                this.set_last_error(enametoolong)?;
+                Ok(-1)
+            }
+        } else if this.tcx.sess.target.target.target_os == "macos"
+            && cmd == this.eval_libc_i32("F_FULLFSYNC")?
+        {
+            // This is how `File::sync_all` flushes a file on macOS.
+            this.sync_file(fd, "fsync", FileObject::sync_all)
         } else {
             throw_unsup_format!("The {:#x} command is not supported for `fcntl`)", cmd);
         }
     }
 
-    fn close(&mut self, fd_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+    fn fsync(&mut self, fd_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        if !this.is_virtual(fd) {
+            this.check_no_isolation("fsync")?;
+        }
+        this.sync_file(fd, "fsync", FileObject::sync_all)
+    }
+
+    fn fdatasync(&mut self, fd_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        if !this.is_virtual(fd) {
+            this.check_no_isolation("fdatasync")?;
+        }
+        this.sync_file(fd, "fdatasync", FileObject::sync_data)
+    }
+
+    fn dup(&mut self, fd_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("close")?;
+        this.check_no_isolation("dup")?;
 
         let fd = this.read_scalar(fd_op)?.to_i32()?;
+        // The new descriptor never has `FD_CLOEXEC` set.
+        this.dup_fd(fd, 0, false)
+    }
+
+    fn dup2(
+        &mut self,
+        oldfd_op: OpTy<'tcx, Tag>,
+        newfd_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("dup2")?;
+
+        let oldfd = this.read_scalar(oldfd_op)?.to_i32()?;
+        let newfd = this.read_scalar(newfd_op)?.to_i32()?;
+
+        if !this.machine.file_handler.handles.contains_key(&oldfd) {
+            return this.handle_not_found();
+        }
+        // Duplicating a descriptor onto itself does nothing, not even clearing `FD_CLOEXEC`.
+        if oldfd == newfd {
+            return Ok(newfd);
+        }
+        if newfd < MIN_NORMAL_FILE_FD {
+            throw_unsup_format!("Replacing stdin, stdout, or stderr is not supported")
+        }
+        // `newfd` is silently closed first, if it is open.
+        this.machine.file_handler.handles.remove(&newfd);
+        let result = this.dup_fd(oldfd, newfd, false)?;
+        // The lowest free descriptor starting from `newfd` is `newfd` itself now.
+        assert!(result == -1 || result == newfd);
+        Ok(result)
+    }
+
+    fn close(&mut self, fd_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
 
-        if let Some(FileHandle { file, writable }) = this.machine.file_handler.handles.remove(&fd) {
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+
+        if !this.is_virtual(fd) {
+            this.check_no_isolation("close")?;
+        }
+
+        if let Some(FileHandle { file, writable, .. }) = this.machine.file_handler.handles.remove(&fd) {
             // We sync the file if it was opened in a mode different than read-only.
             if writable {
                 // `File::sync_all` does the checks that are done when closing a file. We do this to
@@ -388,6 +1181,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 let result = this.try_unwrap_io_result(file.sync_all().map(|_| 0i32));
                 // Now we actually close the file.
                 drop(file);
+                // The descriptor is gone either way, but the program may still be told that the
+                // final flush failed.
+                if this.inject_io_error("close", &["EIO", "ENOSPC"])? {
+                    return Ok(-1);
+                }
                 // And return the result.
                 result
             } else {
@@ -412,12 +1210,17 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i64> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("read")?;
-
         let fd = this.read_scalar(fd_op)?.to_i32()?;
         let buf = this.read_scalar(buf_op)?.not_undef()?;
         let count = this.read_scalar(count_op)?.to_machine_usize(&*this.tcx)?;
 
+        if this.is_timer(fd) {
+            return this.read_timer(fd, buf, count);
+        }
+        if !this.is_virtual(fd) {
+            this.check_no_isolation("read")?;
+        }
+
         // Check that the *entire* buffer is actually valid memory.
         this.memory.check_ptr_access(
             buf,
@@ -429,7 +1232,21 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         // host's and target's `isize`. This saves us from having to handle overflows later.
         let count = count.min(this.isize_max() as u64).min(isize::max_value() as u64);
 
-        if let Some(FileHandle { file, writable: _ }) = this.machine.file_handler.handles.get_mut(&fd) {
+        // Before touching the file, give `-Zmiri-io-error-rate` and `-Zmiri-short-io` a chance to
+        // interfere.
+        let count = if this.machine.file_handler.handles.contains_key(&fd) {
+            if this.inject_io_error("read", &["EINTR", "EIO"])? {
+                return Ok(-1);
+            }
+            match this.shorten_io(count)? {
+                Some(count) => count,
+                None => return Ok(-1),
+            }
+        } else {
+            count
+        };
+
+        if let Some(FileHandle { file, .. }) = this.machine.file_handler.handles.get_mut(&fd) {
             // This can never fail because `count` was capped to be smaller than
             // `isize::max_value()`.
             let count = isize::try_from(count).unwrap();
@@ -483,7 +1300,21 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         // host's and target's `isize`. This saves us from having to handle overflows later.
         let count = count.min(this.isize_max() as u64).min(isize::max_value() as u64);
 
-        if let Some(FileHandle { file, writable: _ }) = this.machine.file_handler.handles.get_mut(&fd) {
+        // Before touching the file, give `-Zmiri-io-error-rate` and `-Zmiri-short-io` a chance to
+        // interfere.
+        let count = if this.machine.file_handler.handles.contains_key(&fd) {
+            if this.inject_io_error("write", &["EINTR", "EIO", "ENOSPC"])? {
+                return Ok(-1);
+            }
+            match this.shorten_io(count)? {
+                Some(count) => count,
+                None => return Ok(-1),
+            }
+        } else {
+            count
+        };
+
+        if let Some(FileHandle { file, .. }) = this.machine.file_handler.handles.get_mut(&fd) {
             let bytes = this.memory.read_bytes(buf, Size::from_bytes(count))?;
             let result = file.write(&bytes).map(|c| i64::try_from(c).unwrap());
             this.try_unwrap_io_result(result)
@@ -500,9 +1331,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i64> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("lseek64")?;
-
         let fd = this.read_scalar(fd_op)?.to_i32()?;
+        if !this.is_virtual(fd) {
+            this.check_no_isolation("lseek64")?;
+        }
         let offset = this.read_scalar(offset_op)?.to_i64()?;
         let whence = this.read_scalar(whence_op)?.to_i32()?;
 
@@ -518,7 +1350,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return Ok(-1);
         };
 
-        if let Some(FileHandle { file, writable: _ }) = this.machine.file_handler.handles.get_mut(&fd) {
+        if let Some(FileHandle { file, .. }) = this.machine.file_handler.handles.get_mut(&fd) {
             let result = file.seek(seek_from).map(|offset| offset as i64);
             this.try_unwrap_io_result(result)
         } else {
@@ -526,6 +1358,130 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
+    fn timerfd_create(
+        &mut self,
+        clock_id_op: OpTy<'tcx, Tag>,
+        flags_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.assert_platform("linux", "timerfd_create");
+
+        let clock_id = this.read_scalar(clock_id_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+
+        let tfd_nonblock = this.eval_libc_i32("TFD_NONBLOCK")?;
+        let tfd_cloexec = this.eval_libc_i32("TFD_CLOEXEC")?;
+        if this.read_clock(clock_id)?.is_none() || flags & !(tfd_nonblock | tfd_cloexec) != 0 {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+
+        let timer = Timer {
+            clock_id,
+            nonblocking: flags & tfd_nonblock != 0,
+            deadline: None,
+            interval: Duration::from_secs(0),
+        };
+        let fh = &mut this.machine.file_handler;
+        let fd = fh.insert_fd(FileHandle {
+            file: FileObject::Timer(timer),
+            writable: false,
+            // This is what Linux shows in `/proc/self/fd`.
+            path: PathBuf::from("anon_inode:[timerfd]"),
+            cloexec: flags & tfd_cloexec != 0,
+        });
+        Ok(fd)
+    }
+
+    fn timerfd_settime(
+        &mut self,
+        fd_op: OpTy<'tcx, Tag>,
+        flags_op: OpTy<'tcx, Tag>,
+        new_value_op: OpTy<'tcx, Tag>,
+        old_value_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.assert_platform("linux", "timerfd_settime");
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+        if !this.is_timer(fd) {
+            return this.handle_not_found();
+        }
+
+        let new_value = this.deref_operand(new_value_op)?;
+        let interval = this.read_timespec(this.mplace_field(new_value, 0)?)?;
+        let value = this.read_timespec(this.mplace_field(new_value, 1)?)?;
+        let tfd_timer_abstime = this.eval_libc_i32("TFD_TIMER_ABSTIME")?;
+        let (interval, value) = match (interval, value) {
+            (Some(interval), Some(value)) if flags & !tfd_timer_abstime == 0 => (interval, value),
+            _ => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
+
+        if !this.is_null(this.read_scalar(old_value_op)?.not_undef()?)? {
+            this.timerfd_gettime(fd_op, old_value_op)?;
+        }
+
+        let clock_id = match this.machine.file_handler.handles.get(&fd) {
+            Some(FileHandle { file: FileObject::Timer(timer), .. }) => timer.clock_id,
+            _ => bug!("timer disappeared while setting it"),
+        };
+        let deadline = if value == Duration::from_secs(0) {
+            // A zero value disarms the timer.
+            None
+        } else if flags & tfd_timer_abstime != 0 {
+            Some(value)
+        } else {
+            Some(this.read_clock(clock_id)?.unwrap() + value)
+        };
+        match this.machine.file_handler.handles.get_mut(&fd) {
+            Some(FileHandle { file: FileObject::Timer(timer), .. }) => {
+                timer.deadline = deadline;
+                timer.interval = interval;
+            }
+            _ => bug!("timer disappeared while setting it"),
+        }
+
+        Ok(0)
+    }
+
+    fn timerfd_gettime(
+        &mut self,
+        fd_op: OpTy<'tcx, Tag>,
+        curr_value_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.assert_platform("linux", "timerfd_gettime");
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let timer = match this.machine.file_handler.handles.get(&fd) {
+            Some(FileHandle { file: FileObject::Timer(timer), .. }) => timer.clone(),
+            _ => return this.handle_not_found(),
+        };
+
+        // The value is the time until the next expiration, or zero if the timer is disarmed.
+        let now = this.read_clock(timer.clock_id)?.unwrap();
+        let value = timer
+            .deadline
+            .map_or(Duration::from_secs(0), |deadline| {
+                deadline.checked_sub(now).unwrap_or(Duration::from_secs(0))
+            });
+
+        let curr_value = this.deref_operand(curr_value_op)?;
+        this.write_timespec(this.mplace_field(curr_value, 0)?, timer.interval)?;
+        this.write_timespec(this.mplace_field(curr_value, 1)?, value)?;
+
+        Ok(0)
+    }
+
     fn unlink(&mut self, path_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
@@ -568,6 +1524,66 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         this.try_unwrap_io_result(create_link(target, linkpath).map(|_| 0))
     }
 
+    fn symlinkat(
+        &mut self,
+        target_op: OpTy<'tcx, Tag>,
+        newdirfd_op: OpTy<'tcx, Tag>,
+        linkpath_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("symlinkat")?;
+
+        // The target is stored verbatim in the link, so only `linkpath` is resolved using
+        // `newdirfd`.
+        let target: PathBuf =
+            this.read_os_str_from_c_str(this.read_scalar(target_op)?.not_undef()?)?.into();
+        let linkpath = this.read_path_at(newdirfd_op, linkpath_op, "symlinkat")?;
+
+        #[cfg(target_family = "unix")]
+        let result = std::os::unix::fs::symlink(target, linkpath);
+        #[cfg(target_family = "windows")]
+        let result = if target.is_dir() {
+            std::os::windows::fs::symlink_dir(target, linkpath)
+        } else {
+            std::os::windows::fs::symlink_file(target, linkpath)
+        };
+
+        this.try_unwrap_io_result(result.map(|_| 0))
+    }
+
+    fn readlink(
+        &mut self,
+        pathname_op: OpTy<'tcx, Tag>,
+        buf_op: OpTy<'tcx, Tag>,
+        bufsize_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("readlink")?;
+
+        let pathname =
+            this.read_os_str_from_c_str(this.read_scalar(pathname_op)?.not_undef()?)?.into();
+
+        this.read_link_to_buf(pathname, buf_op, bufsize_op)
+    }
+
+    fn readlinkat(
+        &mut self,
+        dirfd_op: OpTy<'tcx, Tag>,
+        pathname_op: OpTy<'tcx, Tag>,
+        buf_op: OpTy<'tcx, Tag>,
+        bufsize_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("readlinkat")?;
+
+        let pathname = this.read_path_at(dirfd_op, pathname_op, "readlinkat")?;
+
+        this.read_link_to_buf(pathname, buf_op, bufsize_op)
+    }
+
     fn macos_stat(
         &mut self,
         path_op: OpTy<'tcx, Tag>,
@@ -621,7 +1637,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("statx")?;
         this.assert_platform("linux", "statx");
 
         let statxbuf_scalar = this.read_scalar(statxbuf_op)?.not_undef()?;
@@ -663,23 +1678,12 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             this.read_scalar(dirfd_op)?.to_machine_isize(&*this.tcx)?.try_into().map_err(|e| {
                 err_unsup_format!("Failed to convert pointer sized operand to integer: {}", e)
             })?;
-        // We only support:
-        // * interpreting `path` as an absolute directory,
-        // * interpreting `path` as a path relative to `dirfd` when the latter is `AT_FDCWD`, or
-        // * interpreting `dirfd` as any file descriptor when `path` is empty and AT_EMPTY_PATH is
-        // set.
+        // Besides the paths that `read_path_at` supports, we interpret `dirfd` as any file
+        // descriptor when `path` is empty and AT_EMPTY_PATH is set.
         // Other behaviors cannot be tested from `libstd` and thus are not implemented. If you
         // found this error, please open an issue reporting it.
-        if !(
-            path.is_absolute() ||
-            dirfd == this.eval_libc_i32("AT_FDCWD")? ||
-            (path.as_os_str().is_empty() && empty_path_flag)
-        ) {
-            throw_unsup_format!(
-                "Using statx is only supported with absolute paths, relative paths with the file \
-                descriptor `AT_FDCWD`, and empty paths with the `AT_EMPTY_PATH` flag set and any \
-                file descriptor"
-            )
+        if !(path.as_os_str().is_empty() && empty_path_flag) {
+            this.check_path_at(dirfd, &path, "statx")?;
         }
 
         // the `_mask_op` paramter specifies the file information that the caller requested.
@@ -696,8 +1700,12 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         // If the path is empty, and the AT_EMPTY_PATH flag is set, we query the open file
         // represented by dirfd, whether it's a directory or otherwise.
         let metadata = if path.as_os_str().is_empty() && empty_path_flag {
+            if !this.is_virtual(dirfd) {
+                this.check_no_isolation("statx")?;
+            }
             FileMetadata::from_fd(this, dirfd)?
         } else {
+            this.check_no_isolation("statx")?;
             FileMetadata::from_path(this, path, follow_symlink)?
         };
         let metadata = match metadata {
@@ -800,6 +1808,101 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         this.try_unwrap_io_result(result)
     }
 
+    fn windows_delete_file(&mut self, path_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("DeleteFileW")?;
+        this.assert_platform("windows", "DeleteFileW");
+
+        let path = this.read_windows_path(path_op)?;
+
+        // Windows functions return a `BOOL`, so we turn `-1` into `0` (`FALSE`).
+        let result = remove_file(path).map(|_| 1);
+        Ok(this.try_unwrap_io_result(result)?.max(0))
+    }
+
+    fn windows_move_file_ex(
+        &mut self,
+        existing_op: OpTy<'tcx, Tag>,
+        new_op: OpTy<'tcx, Tag>,
+        flags_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("MoveFileExW")?;
+        this.assert_platform("windows", "MoveFileExW");
+
+        const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+        const MOVEFILE_WRITE_THROUGH: u32 = 0x8;
+
+        let existing = this.read_windows_path(existing_op)?;
+        let new = this.read_windows_path(new_op)?;
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+
+        // Our moves are always synchronous, so `MOVEFILE_WRITE_THROUGH` does not need any work.
+        if flags & !(MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH) != 0 {
+            throw_unsup_format!("unsupported flags {:#x} for `MoveFileExW`", flags);
+        }
+
+        // The host's `rename` always replaces the destination, so we have to check ourselves.
+        let result = if flags & MOVEFILE_REPLACE_EXISTING == 0 && new.symlink_metadata().is_ok() {
+            Err(std::io::ErrorKind::AlreadyExists.into())
+        } else {
+            rename(existing, new).map(|_| 1)
+        };
+        Ok(this.try_unwrap_io_result(result)?.max(0))
+    }
+
+    fn windows_get_full_path_name(
+        &mut self,
+        path_op: OpTy<'tcx, Tag>,
+        size_op: OpTy<'tcx, Tag>,
+        buf_op: OpTy<'tcx, Tag>,
+        file_part_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("GetFullPathNameW")?;
+        this.assert_platform("windows", "GetFullPathNameW");
+
+        let path = this.read_windows_path(path_op)?;
+        let size = this.read_scalar(size_op)?.to_u32()?;
+        let buf = this.read_scalar(buf_op)?.not_undef()?;
+        let file_part = this.read_scalar(file_part_op)?.not_undef()?;
+
+        let full_path = match absolute_path(path) {
+            Ok(path) => normalize_lexically(&path),
+            Err(e) => {
+                this.set_last_error_from_io_error(e)?;
+                return Ok(0);
+            }
+        };
+        let wide = host_path_to_windows(&full_path).ok_or_else(|| {
+            err_unsup_format!("{:?} is not a valid utf-8 string", full_path)
+        })?;
+
+        let (written, len) = this.write_wide_str(&wide, buf, u64::from(size))?;
+        if !written {
+            // The buffer is too small, so we return the required size, including the null
+            // terminator.
+            return Ok(u32::try_from(len + 1).unwrap());
+        }
+        if !this.is_null(file_part)? {
+            // Point to the last component of the path, unless it is a directory name.
+            let file_part_place = this.deref_operand(file_part_op)?;
+            let file_part_ptr = match full_path.file_name() {
+                Some(name) if !wide.ends_with(&[u16::from(b'\\')]) => {
+                    let name_len = host_path_to_windows(Path::new(name)).unwrap().len() as u64;
+                    let offset = Size::from_bytes((len - name_len) * 2);
+                    buf.ptr_offset(offset, this)?
+                }
+                _ => Scalar::from_int(0, this.pointer_size()),
+            };
+            this.write_scalar(file_part_ptr, file_part_place.into())?;
+        }
+        Ok(u32::try_from(len).unwrap())
+    }
+
     fn mkdir(
         &mut self,
         path_op: OpTy<'tcx, Tag>,
@@ -1048,6 +2151,88 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         }
     }
 
+    fn windows_find_first_file(
+        &mut self,
+        pattern_op: OpTy<'tcx, Tag>,
+        data_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("FindFirstFileW")?;
+        this.assert_platform("windows", "FindFirstFileW");
+
+        // We only support listing whole directories, which is what `std` does.
+        let pattern = this.read_windows_path(pattern_op)?;
+        let dir = match pattern.file_name() {
+            Some(name) if name == "*" => pattern.parent().unwrap().to_owned(),
+            _ => throw_unsup_format!(
+                "`FindFirstFileW` only supports patterns of the form `dir\\*`"
+            ),
+        };
+
+        match read_dir(&dir) {
+            Ok(dir_iter) => {
+                // The host does not report `.`, but Windows does, and it always comes first.
+                // This also means that the first call never runs out of entries.
+                this.windows_write_find_data(OsStr::new("."), dir.metadata(), data_op)?;
+                let id = this.machine.dir_handler.insert_new(dir_iter);
+                Ok(i64::try_from(id).unwrap())
+            }
+            Err(e) => {
+                this.set_last_error_from_io_error(e)?;
+                Ok(-1) // INVALID_HANDLE_VALUE
+            }
+        }
+    }
+
+    fn windows_find_next_file(
+        &mut self,
+        handle_op: OpTy<'tcx, Tag>,
+        data_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("FindNextFileW")?;
+        this.assert_platform("windows", "FindNextFileW");
+
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+
+        let dir_iter = this.machine.dir_handler.streams.get_mut(&handle).ok_or_else(|| {
+            err_unsup_format!("The handle passed to FindNextFileW did not come from FindFirstFileW")
+        })?;
+        match dir_iter.next() {
+            Some(Ok(dir_entry)) => {
+                this.windows_write_find_data(&dir_entry.file_name(), dir_entry.metadata(), data_op)?;
+                Ok(1)
+            }
+            None => {
+                this.set_last_error(Scalar::from_u32(18))?; // ERROR_NO_MORE_FILES
+                Ok(0)
+            }
+            Some(Err(e)) => {
+                this.set_last_error_from_io_error(e)?;
+                Ok(0)
+            }
+        }
+    }
+
+    fn windows_find_close(&mut self, handle_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("FindClose")?;
+        this.assert_platform("windows", "FindClose");
+
+        let handle = this.read_scalar(handle_op)?.to_machine_usize(this)?;
+
+        if let Some(dir_iter) = this.machine.dir_handler.streams.remove(&handle) {
+            drop(dir_iter);
+            Ok(1)
+        } else {
+            this.set_last_error(Scalar::from_u32(6))?; // ERROR_INVALID_HANDLE
+            Ok(0)
+        }
+    }
+
     fn closedir(&mut self, dirp_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
@@ -1107,7 +2292,19 @@ impl FileMetadata {
     ) -> InterpResult<'tcx, Option<FileMetadata>> {
         let option = ecx.machine.file_handler.handles.get(&fd);
         let file = match option {
-            Some(FileHandle { file, writable: _ }) => file,
+            Some(FileHandle { file: FileObject::Host(file), .. }) => file,
+            Some(FileHandle { file: FileObject::Virtual(_), .. })
+            | Some(FileHandle { file: FileObject::Timer(_), .. }) => {
+                // Like procfs, report an empty regular file without timestamps.
+                let mode = ecx.eval_libc("S_IFREG")?;
+                return Ok(Some(FileMetadata {
+                    mode,
+                    size: 0,
+                    created: None,
+                    accessed: None,
+                    modified: None,
+                }));
+            }
             None => return ecx.handle_not_found().map(|_: i32| None),
         };
         let metadata = file.metadata();
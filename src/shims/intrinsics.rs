@@ -20,6 +20,13 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         unwind: Option<mir::BasicBlock>,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
+        // The generic implementation of `transmute` merely asserts that the sizes match, so we
+        // check this ourselves to be able to report a proper error.
+        if this.tcx.item_name(instance.def_id()).as_str() == "transmute" {
+            if let Some((dest, _)) = ret {
+                this.check_transmute_size(args[0].layout, dest.layout)?;
+            }
+        }
         if this.emulate_intrinsic(span, instance, args, ret)? {
             return Ok(());
         }
@@ -80,15 +87,22 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             | "atomic_load_acq"
             => {
                 let place = this.deref_operand(args[0])?;
-                let val = this.read_scalar(place.into())?; // make sure it fits into a scalar; otherwise it cannot be atomic
+                let ordering = atomic_ordering(intrinsic_name, "atomic_load");
+                // make sure it fits into a scalar; otherwise it cannot be atomic
+                let val = this.allow_data_races_ref(|this| this.read_scalar(place.into()))?;
 
                 // Check alignment requirements. Atomics must always be aligned to their size,
                 // even if the type they wrap would be less aligned (e.g. AtomicU64 on 32bit must
                 // be 8-aligned).
                 let align = Align::from_bytes(place.layout.size.bytes()).unwrap();
                 this.memory.check_ptr_access(place.ptr, place.layout.size, align)?;
+                this.record_atomic_op(place, AtomicOp::Load)?;
+                let val = this.buffered_atomic_load(place, ordering, val)?;
 
-                this.write_scalar(val, dest)?;
+                if this.write_atomic_load_result(place, val, dest)? {
+                    // Load again once the thread that was started finished.
+                    return Ok(());
+                }
             }
 
             #[rustfmt::skip]
@@ -97,6 +111,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             | "atomic_store_rel"
             => {
                 let place = this.deref_operand(args[0])?;
+                let ordering = atomic_ordering(intrinsic_name, "atomic_store");
                 let val = this.read_scalar(args[1])?; // make sure it fits into a scalar; otherwise it cannot be atomic
 
                 // Check alignment requirements. Atomics must always be aligned to their size,
@@ -104,8 +119,10 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                 // be 8-aligned).
                 let align = Align::from_bytes(place.layout.size.bytes()).unwrap();
                 this.memory.check_ptr_access(place.ptr, place.layout.size, align)?;
+                this.record_atomic_op(place, AtomicOp::Store)?;
+                this.buffered_atomic_write(place, ordering, val, false)?;
 
-                this.write_scalar(val, place.into())?;
+                this.allow_data_races_mut(|this| this.write_scalar(val, place.into()))?;
             }
 
             #[rustfmt::skip]
@@ -113,34 +130,44 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             | "atomic_fence_rel"
             | "atomic_fence_acqrel"
             | "atomic_fence"
+            => {
+                this.validate_atomic_fence(atomic_ordering(intrinsic_name, "atomic_fence"));
+            }
+
+            #[rustfmt::skip]
             | "atomic_singlethreadfence_acq"
             | "atomic_singlethreadfence_rel"
             | "atomic_singlethreadfence_acqrel"
             | "atomic_singlethreadfence"
             => {
-                // we are inherently singlethreaded and singlecored, this is a nop
+                // Compiler fences only order accesses against signal handlers of the same thread,
+                // which run in program order anyway. Between threads, they order nothing.
             }
 
             _ if intrinsic_name.starts_with("atomic_xchg") => {
                 let place = this.deref_operand(args[0])?;
+                let ordering = atomic_ordering(intrinsic_name, "atomic_xchg");
                 let new = this.read_scalar(args[1])?;
-                let old = this.read_scalar(place.into())?;
+                let old = this.allow_data_races_ref(|this| this.read_scalar(place.into()))?;
 
                 // Check alignment requirements. Atomics must always be aligned to their size,
                 // even if the type they wrap would be less aligned (e.g. AtomicU64 on 32bit must
                 // be 8-aligned).
                 let align = Align::from_bytes(place.layout.size.bytes()).unwrap();
                 this.memory.check_ptr_access(place.ptr, place.layout.size, align)?;
+                this.record_atomic_op(place, AtomicOp::ReadModifyWrite)?;
+                this.buffered_atomic_write(place, ordering, new, true)?;
 
                 this.write_scalar(old, dest)?; // old value is returned
-                this.write_scalar(new, place.into())?;
+                this.allow_data_races_mut(|this| this.write_scalar(new, place.into()))?;
             }
 
             _ if intrinsic_name.starts_with("atomic_cxchg") => {
                 let place = this.deref_operand(args[0])?;
                 let expect_old = this.read_immediate(args[1])?; // read as immediate for the sake of `binary_op()`
                 let new = this.read_scalar(args[2])?;
-                let old = this.read_immediate(place.into())?; // read as immediate for the sake of `binary_op()`
+                // read as immediate for the sake of `binary_op()`
+                let old = this.allow_data_races_ref(|this| this.read_immediate(place.into()))?;
 
                 // Check alignment requirements. Atomics must always be aligned to their size,
                 // even if the type they wrap would be less aligned (e.g. AtomicU64 on 32bit must
@@ -150,12 +177,20 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
                 // `binary_op` will bail if either of them is not a scalar.
                 let eq = this.overflowing_binary_op(mir::BinOp::Eq, old, expect_old)?.0;
+                this.record_atomic_op(place, AtomicOp::CompareExchange(eq.to_bool()?))?;
+                let (success, failure) = cxchg_orderings(intrinsic_name);
+                if eq.to_bool()? {
+                    this.buffered_atomic_write(place, success, new, true)?;
+                } else {
+                    // Like read-modify-writes, compare-exchanges always read the latest store.
+                    this.validate_atomic_load(place, failure)?;
+                }
                 let res = Immediate::ScalarPair(old.to_scalar_or_undef(), eq.into());
                 // Return old value.
                 this.write_immediate(res, dest)?;
                 // Update ptr depending on comparison.
                 if eq.to_bool()? {
-                    this.write_scalar(new, place.into())?;
+                    this.allow_data_races_mut(|this| this.write_scalar(new, place.into()))?;
                 }
             }
 
@@ -190,34 +225,69 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             | "atomic_xsub_rel"
             | "atomic_xsub_acqrel"
             | "atomic_xsub_relaxed"
+            | "atomic_max"
+            | "atomic_max_acq"
+            | "atomic_max_rel"
+            | "atomic_max_acqrel"
+            | "atomic_max_relaxed"
+            | "atomic_min"
+            | "atomic_min_acq"
+            | "atomic_min_rel"
+            | "atomic_min_acqrel"
+            | "atomic_min_relaxed"
+            | "atomic_umax"
+            | "atomic_umax_acq"
+            | "atomic_umax_rel"
+            | "atomic_umax_acqrel"
+            | "atomic_umax_relaxed"
+            | "atomic_umin"
+            | "atomic_umin_acq"
+            | "atomic_umin_rel"
+            | "atomic_umin_acqrel"
+            | "atomic_umin_relaxed"
             => {
                 let place = this.deref_operand(args[0])?;
                 if !place.layout.ty.is_integral() {
                     bug!("Atomic arithmetic operations only work on integer types");
                 }
                 let rhs = this.read_immediate(args[1])?;
-                let old = this.read_immediate(place.into())?;
+                let old = this.allow_data_races_ref(|this| this.read_immediate(place.into()))?;
 
                 // Check alignment requirements. Atomics must always be aligned to their size,
                 // even if the type they wrap would be less aligned (e.g. AtomicU64 on 32bit must
                 // be 8-aligned).
                 let align = Align::from_bytes(place.layout.size.bytes()).unwrap();
                 this.memory.check_ptr_access(place.ptr, place.layout.size, align)?;
+                this.record_atomic_op(place, AtomicOp::ReadModifyWrite)?;
+                let ordering = intrinsic_name.split('_').nth(2).unwrap_or("");
+                let ordering = AtomicOrdering::from_intrinsic_suffix(ordering).unwrap();
 
                 this.write_immediate(*old, dest)?; // old value is returned
-                let (op, neg) = match intrinsic_name.split('_').nth(1).unwrap() {
-                    "or" => (mir::BinOp::BitOr, false),
-                    "xor" => (mir::BinOp::BitXor, false),
-                    "and" => (mir::BinOp::BitAnd, false),
-                    "xadd" => (mir::BinOp::Add, false),
-                    "xsub" => (mir::BinOp::Sub, false),
-                    "nand" => (mir::BinOp::BitAnd, true),
-                    _ => bug!(),
+                let val = match intrinsic_name.split('_').nth(1).unwrap() {
+                    // The signed and unsigned variants are called with signed and unsigned
+                    // integers, respectively, so comparing them does the right thing.
+                    op @ "max" | op @ "umax" | op @ "min" | op @ "umin" => {
+                        let lt = this.overflowing_binary_op(mir::BinOp::Lt, old, rhs)?.0;
+                        let keep_old = lt.to_bool()? == op.ends_with("min");
+                        if keep_old { *old } else { *rhs }
+                    }
+                    op => {
+                        let (op, neg) = match op {
+                            "or" => (mir::BinOp::BitOr, false),
+                            "xor" => (mir::BinOp::BitXor, false),
+                            "and" => (mir::BinOp::BitAnd, false),
+                            "xadd" => (mir::BinOp::Add, false),
+                            "xsub" => (mir::BinOp::Sub, false),
+                            "nand" => (mir::BinOp::BitAnd, true),
+                            _ => bug!(),
+                        };
+                        // Atomics wrap around on overflow.
+                        let val = this.binary_op(op, old, rhs)?;
+                        *if neg { this.unary_op(mir::UnOp::Not, val)? } else { val }
+                    }
                 };
-                // Atomics wrap around on overflow.
-                let val = this.binary_op(op, old, rhs)?;
-                let val = if neg { this.unary_op(mir::UnOp::Not, val)? } else { val };
-                this.write_immediate(*val, place.into())?;
+                this.buffered_atomic_write(place, ordering, val.to_scalar_or_undef(), true)?;
+                this.allow_data_races_mut(|this| this.write_immediate(val, place.into()))?;
             }
 
             "breakpoint" => unimplemented!(), // halt miri
@@ -499,6 +569,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
             "size_of_val" => {
                 let mplace = this.deref_operand(args[0])?;
+                if let ty::Dynamic(..) = mplace.layout.ty.kind {
+                    this.check_vtable(mplace.meta.unwrap_meta(), mplace.layout.ty)?;
+                }
                 let (size, _) = this
                     .size_and_align_of_mplace(mplace)?
                     .expect("size_of_val called on extern type");
@@ -511,6 +584,9 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             | "align_of_val"
             => {
                 let mplace = this.deref_operand(args[0])?;
+                if let ty::Dynamic(..) = mplace.layout.ty.kind {
+                    this.check_vtable(mplace.meta.unwrap_meta(), mplace.layout.ty)?;
+                }
                 let (_, align) = this
                     .size_and_align_of_mplace(mplace)?
                     .expect("size_of_val called on extern type");
@@ -570,3 +646,28 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         Ok(())
     }
 }
+
+/// The ordering of the atomic intrinsic `intrinsic_name`, which is `prefix` optionally followed by
+/// `_` and the ordering.
+fn atomic_ordering(intrinsic_name: &str, prefix: &str) -> AtomicOrdering {
+    let suffix = intrinsic_name[prefix.len()..].trim_start_matches('_');
+    AtomicOrdering::from_intrinsic_suffix(suffix)
+        .unwrap_or_else(|| bug!("unknown ordering of atomic intrinsic {}", intrinsic_name))
+}
+
+/// The orderings of a compare-exchange intrinsic on success and on failure, e.g. `acq` and
+/// `failrelaxed` in `atomic_cxchg_acq_failrelaxed`.
+fn cxchg_orderings(intrinsic_name: &str) -> (AtomicOrdering, AtomicOrdering) {
+    let mut success = AtomicOrdering::SeqCst;
+    let mut failure = None;
+    for part in intrinsic_name.split('_').skip(2) {
+        let ordering = AtomicOrdering::from_intrinsic_suffix(part.trim_start_matches("fail"))
+            .unwrap_or_else(|| bug!("unknown ordering of atomic intrinsic {}", intrinsic_name));
+        if part.starts_with("fail") {
+            failure = Some(ordering);
+        } else {
+            success = ordering;
+        }
+    }
+    (success, failure.unwrap_or_else(|| success.failure_ordering()))
+}
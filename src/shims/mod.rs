@@ -1,12 +1,22 @@
+pub mod atexit;
 pub mod dlsym;
 pub mod env;
 pub mod foreign_items;
 pub mod fs;
 pub mod intrinsics;
+pub mod net;
+pub mod nondet;
 pub mod panic;
+pub mod pipe;
+pub mod rlimit;
+pub mod signal;
+pub mod strconv;
+pub mod sync;
+pub mod thread;
 pub mod time;
 pub mod tls;
 
+use crate::progress;
 use crate::*;
 use rustc::{mir, ty};
 
@@ -44,6 +54,15 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             return this.emulate_foreign_item(instance.def_id(), args, ret, unwind);
         }
 
+        // Let tools watching the progress know when a test starts. The frame we are about to
+        // push picks up the name, to report when the test finishes.
+        if let Some(events) = &mut this.machine.progress_events {
+            if let Some(name) = progress::test_name(this.tcx.tcx, instance.def_id()) {
+                events.test_started(&name);
+                events.entering_test = Some(name);
+            }
+        }
+
         // Otherwise, load the MIR.
         Ok(Some(&*this.load_mir(instance.def, None)?))
     }
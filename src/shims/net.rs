@@ -0,0 +1,314 @@
+//! Implement the network-related shims. What they do is controlled by `-Zmiri-net`: in the default
+//! virtual mode, the interpreted program sees a machine whose only network interface is the loopback
+//! interface, and names are resolved without DNS.
+
+use std::ffi::OsStr;
+use std::iter;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use rustc::ty::layout::{Align, Endian, LayoutOf, Size};
+
+use crate::*;
+
+/// How the interpreted program's attempts to use the network are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NetMode {
+    /// A virtual network that only has the loopback interface and only knows the name `localhost`.
+    /// It behaves the same on every host.
+    Virtual,
+    /// Network operations fail with `EACCES`, like in a sandbox without network access.
+    Deny,
+    /// Network operations stop the program with an error.
+    Error,
+}
+
+/// The addresses of the loopback interface, with their netmasks, as reported by `getifaddrs`.
+fn loopback_addresses() -> [(IpAddr, IpAddr); 2] {
+    [
+        (IpAddr::V4(Ipv4Addr::LOCALHOST), IpAddr::V4(Ipv4Addr::new(255, 0, 0, 0))),
+        (IpAddr::V6(Ipv6Addr::LOCALHOST), IpAddr::V6(Ipv6Addr::from(u128::max_value()))),
+    ]
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Builds the list of interface addresses in memory that `freeifaddrs` frees: one node per
+    /// address of the loopback interface.
+    fn getifaddrs(&mut self, ifap_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if !this.check_net_access("getifaddrs")? {
+            return Ok(-1);
+        }
+
+        let ifap = this.deref_operand(ifap_op)?;
+        let ifaddrs_layout = this.layout_of(ifap.layout.ty.builtin_deref(true).unwrap().ty)?;
+        let name = if this.tcx.sess.target.target.target_os == "macos" { "lo0" } else { "lo" };
+        let flags =
+            this.eval_libc_i32("IFF_UP")? | this.eval_libc_i32("IFF_LOOPBACK")? | this.eval_libc_i32("IFF_RUNNING")?;
+
+        // Build the list back to front, so that every node can point to the next one.
+        let mut next = Scalar::ptr_null(this);
+        for &(addr, netmask) in loopback_addresses().iter().rev() {
+            let node = this.allocate(ifaddrs_layout, MiriMemoryKind::C.into());
+            this.memory.write_bytes(
+                node.ptr,
+                iter::repeat(0u8).take(ifaddrs_layout.size.bytes() as usize),
+            )?;
+            // The fields are `ifa_next`, `ifa_name`, `ifa_flags`, `ifa_addr` and `ifa_netmask`,
+            // followed by the broadcast or destination address and `ifa_data`, which stay NULL.
+            let name = this.alloc_os_str_as_c_str(OsStr::new(name), MiriMemoryKind::C.into());
+            let (addr, _) = this.alloc_sockaddr(addr, 0)?;
+            let (netmask, _) = this.alloc_sockaddr(netmask, 0)?;
+            this.write_scalar(next, this.mplace_field(node, 0)?.into())?;
+            this.write_scalar(Scalar::Ptr(name), this.mplace_field(node, 1)?.into())?;
+            let flags_place = this.mplace_field(node, 2)?;
+            this.write_scalar(Scalar::from_int(flags, flags_place.layout.size), flags_place.into())?;
+            this.write_scalar(Scalar::Ptr(addr), this.mplace_field(node, 3)?.into())?;
+            this.write_scalar(Scalar::Ptr(netmask), this.mplace_field(node, 4)?.into())?;
+            next = node.ptr;
+        }
+        this.write_scalar(next, ifap.into())?;
+        Ok(0)
+    }
+
+    fn freeifaddrs(&mut self, ifa_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let mut node = this.read_immediate(ifa_op)?;
+        while !this.is_null(node.to_scalar()?)? {
+            let place = this.deref_operand(node.into())?;
+            let next = this.read_immediate(this.mplace_field(place, 0)?.into())?;
+            // Free the name, the address and the netmask.
+            for &idx in &[1, 3, 4] {
+                let ptr = this.read_scalar(this.mplace_field(place, idx)?.into())?.not_undef()?;
+                this.free(ptr, MiriMemoryKind::C)?;
+            }
+            this.free(place.ptr, MiriMemoryKind::C)?;
+            node = next;
+        }
+        Ok(())
+    }
+
+    /// Resolves `node` and `service` on the virtual network. Only numeric addresses and
+    /// `localhost` can be resolved, and only numeric services.
+    fn getaddrinfo(
+        &mut self,
+        node_op: OpTy<'tcx, Tag>,
+        service_op: OpTy<'tcx, Tag>,
+        hints_op: OpTy<'tcx, Tag>,
+        res_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if !this.check_net_access("getaddrinfo")? {
+            return this.eval_libc_i32("EAI_SYSTEM");
+        }
+
+        let node = this.read_scalar(node_op)?.not_undef()?;
+        let service = this.read_scalar(service_op)?.not_undef()?;
+        let hints = this.read_scalar(hints_op)?.not_undef()?;
+        let res = this.deref_operand(res_op)?;
+        let addrinfo_layout = this.layout_of(res.layout.ty.builtin_deref(true).unwrap().ty)?;
+
+        let (flags, family, socktype, protocol) = if this.is_null(hints)? {
+            (0, this.eval_libc_i32("AF_UNSPEC")?, 0, 0)
+        } else {
+            let hints = this.deref_operand(hints_op)?;
+            let mut fields = Vec::new();
+            for &name in &["ai_flags", "ai_family", "ai_socktype", "ai_protocol"] {
+                let field = this.mplace_field_named(hints, name)?;
+                fields.push(this.read_scalar(field.into())?.to_i32()?);
+            }
+            (fields[0], fields[1], fields[2], fields[3])
+        };
+
+        if this.is_null(node)? && this.is_null(service)? {
+            return this.eval_libc_i32("EAI_NONAME");
+        }
+        let port = if this.is_null(service)? {
+            0
+        } else {
+            let service = this.read_os_str_from_c_str(service)?.to_str();
+            match service.and_then(|service| service.parse::<u16>().ok()) {
+                Some(port) => port,
+                // There is no services database to look up names like `http` in.
+                None => return this.eval_libc_i32("EAI_SERVICE"),
+            }
+        };
+        let mut addrs: Vec<IpAddr> = if this.is_null(node)? {
+            if flags & this.eval_libc_i32("AI_PASSIVE")? != 0 {
+                vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V6(Ipv6Addr::UNSPECIFIED)]
+            } else {
+                loopback_addresses().iter().map(|&(addr, _)| addr).collect()
+            }
+        } else {
+            let node = this.read_os_str_from_c_str(node)?.to_str().map(str::to_owned);
+            match node.as_ref().and_then(|node| node.parse::<IpAddr>().ok()) {
+                Some(addr) => vec![addr],
+                None if node.as_deref() == Some("localhost")
+                    && flags & this.eval_libc_i32("AI_NUMERICHOST")? == 0 =>
+                    loopback_addresses().iter().map(|&(addr, _)| addr).collect(),
+                None => return this.eval_libc_i32("EAI_NONAME"),
+            }
+        };
+        if family == this.eval_libc_i32("AF_INET")? {
+            addrs.retain(|addr| addr.is_ipv4());
+        } else if family == this.eval_libc_i32("AF_INET6")? {
+            addrs.retain(|addr| addr.is_ipv6());
+        } else if family != this.eval_libc_i32("AF_UNSPEC")? {
+            return this.eval_libc_i32("EAI_FAMILY");
+        }
+        if addrs.is_empty() {
+            return this.eval_libc_i32("EAI_NONAME");
+        }
+        let canonname = if flags & this.eval_libc_i32("AI_CANONNAME")? != 0 && !this.is_null(node)? {
+            Some(this.read_os_str_from_c_str(node)?.to_owned())
+        } else {
+            None
+        };
+
+        // Build the list back to front, so that every node can point to the next one.
+        let mut next = Scalar::ptr_null(this);
+        for (idx, &addr) in addrs.iter().enumerate().rev() {
+            let entry = this.allocate(addrinfo_layout, MiriMemoryKind::C.into());
+            this.memory.write_bytes(
+                entry.ptr,
+                iter::repeat(0u8).take(addrinfo_layout.size.bytes() as usize),
+            )?;
+            for &(name, value) in
+                &[("ai_flags", flags), ("ai_socktype", socktype), ("ai_protocol", protocol)]
+            {
+                let field = this.mplace_field_named(entry, name)?;
+                this.write_scalar(Scalar::from_int(value, field.layout.size), field.into())?;
+            }
+            let family = this.eval_libc_i32(if addr.is_ipv4() { "AF_INET" } else { "AF_INET6" })?;
+            let family_place = this.mplace_field_named(entry, "ai_family")?;
+            this.write_scalar(Scalar::from_int(family, family_place.layout.size), family_place.into())?;
+            let (sockaddr, len) = this.alloc_sockaddr(addr, port)?;
+            let len_place = this.mplace_field_named(entry, "ai_addrlen")?;
+            this.write_scalar(Scalar::from_uint(len, len_place.layout.size), len_place.into())?;
+            this.write_scalar(Scalar::Ptr(sockaddr), this.mplace_field_named(entry, "ai_addr")?.into())?;
+            // Only the first entry has the canonical name.
+            if let (0, Some(canonname)) = (idx, &canonname) {
+                let canonname = this.alloc_os_str_as_c_str(canonname, MiriMemoryKind::C.into());
+                this.write_scalar(Scalar::Ptr(canonname), this.mplace_field_named(entry, "ai_canonname")?.into())?;
+            }
+            this.write_scalar(next, this.mplace_field_named(entry, "ai_next")?.into())?;
+            next = entry.ptr;
+        }
+        this.write_scalar(next, res.into())?;
+        Ok(0)
+    }
+
+    fn freeaddrinfo(&mut self, res_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let mut entry = this.read_immediate(res_op)?;
+        while !this.is_null(entry.to_scalar()?)? {
+            let place = this.deref_operand(entry.into())?;
+            let next = this.read_immediate(this.mplace_field_named(place, "ai_next")?.into())?;
+            let addr = this.read_scalar(this.mplace_field_named(place, "ai_addr")?.into())?.not_undef()?;
+            this.free(addr, MiriMemoryKind::C)?;
+            let canonname =
+                this.read_scalar(this.mplace_field_named(place, "ai_canonname")?.into())?.not_undef()?;
+            this.free(canonname, MiriMemoryKind::C)?;
+            this.free(place.ptr, MiriMemoryKind::C)?;
+            entry = next;
+        }
+        Ok(())
+    }
+
+    /// Returns the message for an error code of `getaddrinfo`.
+    fn gai_strerror(&mut self, errcode_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, Pointer<Tag>> {
+        let this = self.eval_context_mut();
+
+        let errcode = this.read_scalar(errcode_op)?.to_i32()?;
+        let messages = &[
+            ("EAI_NONAME", "Name or service not known"),
+            ("EAI_SERVICE", "Servname not supported for ai_socktype"),
+            ("EAI_FAMILY", "ai_family not supported"),
+            ("EAI_SYSTEM", "System error"),
+        ];
+        let mut message = "Unknown error";
+        for &(name, msg) in messages {
+            if errcode == this.eval_libc_i32(name)? {
+                message = msg;
+            }
+        }
+        Ok(this.alloc_os_str_as_c_str(OsStr::new(message), MiriMemoryKind::Env.into()))
+    }
+
+    fn socket(
+        &mut self,
+        _domain_op: OpTy<'tcx, Tag>,
+        _type_op: OpTy<'tcx, Tag>,
+        _protocol_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if !this.check_net_access("socket")? {
+            return Ok(-1);
+        }
+        throw_unsup_format!("sockets are not supported on the virtual network yet")
+    }
+
+    /// Checks whether `-Zmiri-net` allows the network operation `name`. Sets the last error to
+    /// `EACCES` and returns `false` if it is denied, and stops the program if that is requested.
+    fn check_net_access(&mut self, name: &str) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        match this.machine.net {
+            NetMode::Virtual => Ok(true),
+            NetMode::Deny => {
+                let eacces = this.eval_libc("EACCES")?;
+                this.set_last_error(eacces)?;
+                Ok(false)
+            }
+            NetMode::Error => throw_unsup_format!(
+                "`{}` not available when network access is an error. Pass the flag `-Zmiri-net=virtual` or `-Zmiri-net=deny` to allow it.",
+                name,
+            ),
+        }
+    }
+
+    /// Allocates a `sockaddr_in` or `sockaddr_in6` with the given address and port, and returns
+    /// it along with its size.
+    fn alloc_sockaddr(&mut self, addr: IpAddr, port: u16) -> InterpResult<'tcx, (Pointer<Tag>, u64)> {
+        let this = self.eval_context_mut();
+
+        let (family, len, octets) = match addr {
+            IpAddr::V4(addr) => (this.eval_libc_i32("AF_INET")?, 16, addr.octets().to_vec()),
+            IpAddr::V6(addr) => (this.eval_libc_i32("AF_INET6")?, 28, addr.octets().to_vec()),
+        };
+        let mut bytes = Vec::with_capacity(len);
+        if this.tcx.sess.target.target.target_os == "macos" {
+            // BSD sockets start with the length and have a one-byte family.
+            bytes.push(len as u8);
+            bytes.push(family as u8);
+        } else {
+            let family = family as u16;
+            bytes.extend_from_slice(&match this.tcx.data_layout.endian {
+                Endian::Little => family.to_le_bytes(),
+                Endian::Big => family.to_be_bytes(),
+            });
+        }
+        // The port, in network byte order.
+        bytes.extend_from_slice(&port.to_be_bytes());
+        if let IpAddr::V6(_) = addr {
+            // The flow information.
+            bytes.extend_from_slice(&[0; 4]);
+        }
+        bytes.extend_from_slice(&octets);
+        // The padding of `sockaddr_in`, or the scope ID of `sockaddr_in6`.
+        bytes.resize(len, 0);
+
+        let ptr = this.memory.allocate(
+            Size::from_bytes(len as u64),
+            Align::from_bytes(4).unwrap(),
+            MiriMemoryKind::C.into(),
+        );
+        this.memory.write_bytes(ptr.into(), bytes)?;
+        Ok((ptr, len as u64))
+    }
+}
@@ -0,0 +1,47 @@
+//! Implement `miri_nondet_bytes`, which hands the program nondeterministic input. The bytes are
+//! random unless `-Zmiri-nondet-input` replays them from a file. All bytes handed out are
+//! recorded, so that a failing run can be reproduced and its input minimized.
+
+use rand::RngCore;
+
+use crate::*;
+
+#[derive(Debug, Default)]
+pub struct NondetBytes {
+    /// The bytes to hand out instead of random ones. Bytes past its end are zero.
+    pub(crate) input: Option<Vec<u8>>,
+    /// All bytes handed out so far.
+    pub(crate) recorded: Vec<u8>,
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn miri_nondet_bytes(
+        &mut self,
+        ptr_op: OpTy<'tcx, Tag>,
+        len_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let ptr = this.read_scalar(ptr_op)?.not_undef()?;
+        let len = this.read_scalar(len_op)?.to_machine_usize(this)? as usize;
+        if len == 0 {
+            return Ok(());
+        }
+
+        let nondet = &mut this.machine.nondet;
+        let bytes = match &nondet.input {
+            Some(input) => {
+                let start = nondet.recorded.len();
+                (start..start + len).map(|idx| input.get(idx).copied().unwrap_or(0)).collect()
+            }
+            None => {
+                let mut bytes = vec![0; len];
+                this.memory.extra.rngs.get_mut(RngPurpose::Nondet).fill_bytes(&mut bytes);
+                bytes
+            }
+        };
+        nondet.recorded.extend_from_slice(&bytes);
+        this.memory.write_bytes(ptr, bytes.iter().copied())
+    }
+}
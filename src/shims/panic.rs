@@ -10,6 +10,12 @@
 //! - A hook executed each time a frame is popped, such that if the frame pushed by `__rust_maybe_catch_panic`
 //!   gets popped *during unwinding*, we take the panic payload and store it according to the extra
 //!   metadata we remembered when pushing said frame.
+//!
+//! Miri itself calls TLS destructors and `atexit` handlers when a thread or the program exits.
+//! There is nothing to unwind into from those, so like the platforms we emulate, a panic that
+//! unwinds out of them aborts the program.
+
+use std::fmt;
 
 use rustc::mir;
 use rustc::ty::{self, layout::LayoutOf};
@@ -33,6 +39,30 @@ pub struct CatchUnwindData<'tcx> {
     pub dest: PlaceTy<'tcx, Tag>,
 }
 
+/// The functions that Miri calls itself when a thread or the program exits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExitHandlerKind {
+    TlsDtor,
+    AtExit,
+}
+
+impl fmt::Display for ExitHandlerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitHandlerKind::TlsDtor => write!(f, "TLS destructor"),
+            ExitHandlerKind::AtExit => write!(f, "`atexit` handler"),
+        }
+    }
+}
+
+impl<'tcx> VisitTags for CatchUnwindData<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        self.data_place.visit_tags(visit);
+        self.vtable_place.visit_tags(visit);
+        self.dest.visit_tags(visit);
+    }
+}
+
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
     /// Handles the special "miri_start_panic" intrinsic, which is called
@@ -54,6 +84,18 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         );
         this.machine.panic_payload = Some(scalar);
 
+        // Remember where the panic started, to point there if it ends up aborting the program.
+        // That is the innermost frame of the local crate; the frames above it belong to the
+        // panic machinery of the standard library.
+        let site = this
+            .stack()
+            .iter()
+            .rev()
+            .find(|frame| frame.instance.def_id().is_local())
+            .unwrap_or_else(|| this.frame());
+        this.machine.panic_site =
+            Some(site.current_source_info().map_or(site.body.span, |info| info.span));
+
         // Jump to the unwind block to begin unwinding.
         this.unwind_to_block(unwind);
         return Ok(());
@@ -118,6 +160,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         // We only care about `catch_panic` if we're unwinding - if we're doing a normal
         // return, then we don't need to do anything special.
+        if let (true, Some(handler)) = (unwinding, extra.exit_handler) {
+            // Do not run any more destructors or handlers: the program is aborting.
+            throw_machine_stop!(TerminationInfo::UnwoundOutOfExitHandler(handler));
+        }
+
         let res = if let (true, Some(unwind_data)) = (unwinding, extra.catch_panic.take()) {
             // We've just popped a frame that was pushed by `__rust_maybe_catch_panic`,
             // and we are unwinding, so we should catch that.
@@ -130,6 +177,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // `unwind_data`, i.e., we store them where `__rust_maybe_catch_panic`
             // was told to put them.
             let payload = this.machine.panic_payload.take().unwrap();
+            this.machine.panic_site = None;
             let payload = this.ref_to_mplace(payload)?;
             let payload_data_place = payload.ptr;
             let payload_vtable_place = payload.meta.unwrap_meta();
@@ -146,6 +194,11 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         } else {
             StackPopInfo::Normal
         };
+        // Free what `alloca` allocated in this frame. Pointers to it that escaped the frame are
+        // dangling from now on.
+        for ptr in extra.alloca.drain(..) {
+            this.memory.deallocate(ptr, None, MiriMemoryKind::Alloca.into())?;
+        }
         if let Some(stacked_borrows) = this.memory.extra.stacked_borrows.as_ref() {
             stacked_borrows.borrow_mut().end_call(extra.call_id);
         }
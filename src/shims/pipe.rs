@@ -0,0 +1,127 @@
+//! In-memory anonymous pipes. The platform-specific shims only translate between their handles or
+//! file descriptors and the handles used here. Named pipes are not supported.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// The data in flight between the two ends of a pipe.
+#[derive(Debug, Default)]
+struct PipeBuffer {
+    data: VecDeque<u8>,
+    /// Whether the read end is still open.
+    reader_open: bool,
+    /// Whether the write end is still open.
+    writer_open: bool,
+}
+
+#[derive(Debug)]
+enum PipeEnd {
+    Read(Rc<RefCell<PipeBuffer>>),
+    Write(Rc<RefCell<PipeBuffer>>),
+}
+
+/// The reasons why reading from or writing to a pipe end can fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PipeError {
+    /// The handle does not refer to a pipe end.
+    NotFound,
+    /// The handle refers to the other end of the pipe.
+    WrongEnd,
+    /// The other end of the pipe was closed.
+    Closed,
+    /// There is nothing to read yet, but the write end is still open.
+    WouldBlock,
+}
+
+#[derive(Debug)]
+pub struct PipeHandler {
+    ends: HashMap<i64, PipeEnd>,
+    /// Handle to be used by the next pipe end that gets created.
+    next_handle: i64,
+}
+
+impl Default for PipeHandler {
+    fn default() -> Self {
+        PipeHandler {
+            ends: HashMap::new(),
+            // Stay clear of the small values used for other fake handles, like the ones for the
+            // standard streams and the process heap. Like real Windows handles, ours are
+            // multiples of 4.
+            next_handle: 0x1000,
+        }
+    }
+}
+
+impl PipeHandler {
+    fn fresh_handle(&mut self) -> i64 {
+        let handle = self.next_handle;
+        self.next_handle += 4;
+        handle
+    }
+
+    /// Creates a new pipe and returns the handles of its read and write ends.
+    pub fn create(&mut self) -> (i64, i64) {
+        let buffer = Rc::new(RefCell::new(PipeBuffer {
+            data: VecDeque::new(),
+            reader_open: true,
+            writer_open: true,
+        }));
+        let read = self.fresh_handle();
+        let write = self.fresh_handle();
+        self.ends.insert(read, PipeEnd::Read(buffer.clone())).unwrap_none();
+        self.ends.insert(write, PipeEnd::Write(buffer)).unwrap_none();
+        (read, write)
+    }
+
+    pub fn is_pipe(&self, handle: i64) -> bool {
+        self.ends.contains_key(&handle)
+    }
+
+    /// Takes at most `len` bytes out of the pipe. Returns an empty buffer once all data has been
+    /// read and the write end is closed.
+    pub fn read(&mut self, handle: i64, len: usize) -> Result<Vec<u8>, PipeError> {
+        let mut buffer = match self.ends.get(&handle) {
+            Some(PipeEnd::Read(buffer)) => buffer.borrow_mut(),
+            Some(PipeEnd::Write(_)) => return Err(PipeError::WrongEnd),
+            None => return Err(PipeError::NotFound),
+        };
+        if buffer.data.is_empty() && buffer.writer_open && len > 0 {
+            return Err(PipeError::WouldBlock);
+        }
+        let len = len.min(buffer.data.len());
+        Ok(buffer.data.drain(..len).collect())
+    }
+
+    /// Appends `bytes` to the pipe. The buffer is unbounded, so this never blocks.
+    pub fn write(&mut self, handle: i64, bytes: &[u8]) -> Result<usize, PipeError> {
+        let mut buffer = match self.ends.get(&handle) {
+            Some(PipeEnd::Write(buffer)) => buffer.borrow_mut(),
+            Some(PipeEnd::Read(_)) => return Err(PipeError::WrongEnd),
+            None => return Err(PipeError::NotFound),
+        };
+        if !buffer.reader_open {
+            return Err(PipeError::Closed);
+        }
+        buffer.data.extend(bytes);
+        Ok(bytes.len())
+    }
+
+    /// Closes one end of a pipe. Returns `false` if `handle` does not refer to a pipe end.
+    pub fn close(&mut self, handle: i64) -> bool {
+        match self.ends.remove(&handle) {
+            Some(PipeEnd::Read(buffer)) => {
+                let mut buffer = buffer.borrow_mut();
+                buffer.reader_open = false;
+                // Nobody will ever read this data.
+                buffer.data.clear();
+                true
+            }
+            Some(PipeEnd::Write(buffer)) => {
+                buffer.borrow_mut().writer_open = false;
+                true
+            }
+            None => false,
+        }
+    }
+}
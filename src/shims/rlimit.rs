@@ -0,0 +1,126 @@
+//! Implement `getrlimit` and `setrlimit` on top of synthetic resource limits, which can be
+//! configured with `-Zmiri-rlimit`.
+
+use std::collections::HashMap;
+
+use crate::*;
+
+/// A soft and a hard limit on the use of a resource. `None` means unlimited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResourceLimit {
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+/// The resources the program can query the limits of, named like the `RLIMIT_*` constants
+/// without the prefix, with the limits a typical Linux system sets.
+pub const DEFAULT_RESOURCE_LIMITS: &[(&str, ResourceLimit)] = &[
+    ("AS", ResourceLimit { soft: None, hard: None }),
+    ("CORE", ResourceLimit { soft: Some(0), hard: None }),
+    ("CPU", ResourceLimit { soft: None, hard: None }),
+    ("DATA", ResourceLimit { soft: None, hard: None }),
+    ("FSIZE", ResourceLimit { soft: None, hard: None }),
+    ("MEMLOCK", ResourceLimit { soft: Some(64 * 1024), hard: Some(64 * 1024) }),
+    ("NOFILE", ResourceLimit { soft: Some(1024), hard: Some(4096) }),
+    ("NPROC", ResourceLimit { soft: None, hard: None }),
+    ("RSS", ResourceLimit { soft: None, hard: None }),
+    ("STACK", ResourceLimit { soft: Some(8 * 1024 * 1024), hard: None }),
+];
+
+/// Returns the default limits, with the given ones replacing them.
+pub fn resource_limits(overrides: Vec<(String, ResourceLimit)>) -> HashMap<String, ResourceLimit> {
+    let mut limits: HashMap<String, ResourceLimit> = DEFAULT_RESOURCE_LIMITS
+        .iter()
+        .map(|&(name, limit)| (name.to_owned(), limit))
+        .collect();
+    limits.extend(overrides);
+    limits
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn getrlimit(
+        &mut self,
+        resource_op: OpTy<'tcx, Tag>,
+        rlim_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let name = match this.resource_name(resource_op)? {
+            Some(name) => name,
+            None => return Ok(-1),
+        };
+        let limit = this.machine.resource_limits[&name];
+        let rlim = this.deref_operand(rlim_op)?;
+        let infinity = this.eval_libc("RLIM_INFINITY")?;
+        for &(field, value) in &[("rlim_cur", limit.soft), ("rlim_max", limit.hard)] {
+            let place = this.mplace_field_named(rlim, field)?;
+            let value = match value {
+                Some(value) => Scalar::from_uint(value, place.layout.size),
+                None => infinity,
+            };
+            this.write_scalar(value, place.into())?;
+        }
+        Ok(0)
+    }
+
+    /// Changes the limits for the following `getrlimit` calls. Like an unprivileged process, the
+    /// program can lower the hard limit but not raise it.
+    fn setrlimit(
+        &mut self,
+        resource_op: OpTy<'tcx, Tag>,
+        rlim_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let name = match this.resource_name(resource_op)? {
+            Some(name) => name,
+            None => return Ok(-1),
+        };
+        let rlim = this.deref_operand(rlim_op)?;
+        let infinity = this.eval_libc("RLIM_INFINITY")?;
+        let mut values = Vec::new();
+        for &field in &["rlim_cur", "rlim_max"] {
+            let place = this.mplace_field_named(rlim, field)?;
+            let infinity = infinity.to_bits(place.layout.size)?;
+            let value = this.read_scalar(place.into())?.to_bits(place.layout.size)?;
+            values.push(if value >= infinity { None } else { Some(value as u64) });
+        }
+        let new = ResourceLimit { soft: values[0], hard: values[1] };
+
+        // `None` is unlimited, so it has to be compared as the largest value.
+        let as_bound = |limit: Option<u64>| limit.unwrap_or(u64::max_value());
+        let old = this.machine.resource_limits[&name];
+        if as_bound(new.soft) > as_bound(new.hard) {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        if as_bound(new.hard) > as_bound(old.hard) {
+            let eperm = this.eval_libc("EPERM")?;
+            this.set_last_error(eperm)?;
+            return Ok(-1);
+        }
+        this.machine.resource_limits.insert(name, new);
+        Ok(0)
+    }
+
+    /// Returns the name of the resource identified by `resource_op`, or sets the last error to
+    /// `EINVAL` and returns `None` if it is not a known resource.
+    fn resource_name(&mut self, resource_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, Option<String>> {
+        let this = self.eval_context_mut();
+
+        let resource = this.read_scalar(resource_op)?.to_i32()?;
+        for &(name, _) in DEFAULT_RESOURCE_LIMITS {
+            let path = format!("RLIMIT_{}", name);
+            if let Some(val) = this.eval_path_scalar(&["libc", &path])? {
+                if val.to_i32()? == resource {
+                    return Ok(Some(name.to_owned()));
+                }
+            }
+        }
+        let einval = this.eval_libc("EINVAL")?;
+        this.set_last_error(einval)?;
+        Ok(None)
+    }
+}
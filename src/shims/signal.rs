@@ -0,0 +1,189 @@
+//! Implement installing signal handlers. Miri never delivers signals, so the handlers never run,
+//! but they are remembered so that the program can query them again.
+
+use std::iter;
+
+use crate::*;
+
+/// How the program asked for a signal to be handled.
+#[derive(Debug)]
+pub struct SignalAction<'tcx> {
+    /// `SIG_DFL`, `SIG_IGN` or a function pointer.
+    handler: Scalar<Tag>,
+    /// A copy of the whole `struct sigaction`, if the handler was installed with `sigaction`
+    /// rather than `signal`.
+    action: Option<MPlaceTy<'tcx, Tag>>,
+}
+
+impl<'tcx> VisitTags for SignalAction<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        self.handler.visit_tags(visit);
+        self.action.visit_tags(visit);
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn signal(
+        &mut self,
+        signum_op: OpTy<'tcx, Tag>,
+        handler_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        let signum = this.read_scalar(signum_op)?.to_i32()?;
+        let handler = this.read_scalar(handler_op)?.not_undef()?;
+        if !this.check_signal(signum, true)? {
+            let sig_err = this.eval_libc("SIG_ERR")?;
+            return Ok(sig_err);
+        }
+        this.check_signal_handler(handler)?;
+
+        let old = this.replace_signal_action(signum, SignalAction { handler, action: None })?;
+        Ok(old.map_or_else(|| Scalar::ptr_null(this), |old| old.handler))
+    }
+
+    fn sigaction(
+        &mut self,
+        signum_op: OpTy<'tcx, Tag>,
+        act_op: OpTy<'tcx, Tag>,
+        oldact_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let signum = this.read_scalar(signum_op)?.to_i32()?;
+        let act = this.read_scalar(act_op)?.not_undef()?;
+        let oldact = this.read_scalar(oldact_op)?.not_undef()?;
+        if !this.check_signal(signum, !this.is_null(act)?)? {
+            return Ok(-1);
+        }
+
+        // `act` and `oldact` may point to the same struct, so the new action has to be copied
+        // before the old one gets written.
+        let new = if this.is_null(act)? {
+            None
+        } else {
+            let act = this.deref_operand(act_op)?;
+            let handler = this.read_scalar(this.mplace_field(act, 0)?.into())?.not_undef()?;
+            this.check_signal_handler(handler)?;
+            let copy = this.allocate(act.layout, MiriMemoryKind::Env.into());
+            this.copy_op(act.into(), copy.into())?;
+            Some(SignalAction { handler, action: Some(copy) })
+        };
+
+        if !this.is_null(oldact)? {
+            let oldact = this.deref_operand(oldact_op)?;
+            this.memory.write_bytes(
+                oldact.ptr,
+                iter::repeat(0u8).take(oldact.layout.size.bytes() as usize),
+            )?;
+            match this.machine.signal_actions.get(&signum) {
+                Some(SignalAction { action: Some(action), .. }) => {
+                    let action = *action;
+                    this.copy_op(action.into(), oldact.into())?;
+                }
+                Some(SignalAction { handler, action: None }) => {
+                    // Installed with `signal`, so the mask and the flags are empty.
+                    let handler = *handler;
+                    this.write_scalar(handler, this.mplace_field(oldact, 0)?.into())?;
+                }
+                // Everything is zero for `SIG_DFL`.
+                None => {}
+            }
+        }
+
+        if let Some(new) = new {
+            this.replace_signal_action(signum, new)?;
+        }
+        Ok(0)
+    }
+
+    fn sigaltstack(
+        &mut self,
+        ss_op: OpTy<'tcx, Tag>,
+        old_ss_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let ss = this.read_scalar(ss_op)?.not_undef()?;
+        let old_ss = this.read_scalar(old_ss_op)?.not_undef()?;
+
+        let new = if this.is_null(ss)? {
+            None
+        } else {
+            let ss = this.deref_operand(ss_op)?;
+            let copy = this.allocate(ss.layout, MiriMemoryKind::Env.into());
+            this.copy_op(ss.into(), copy.into())?;
+            Some(copy)
+        };
+
+        if !this.is_null(old_ss)? {
+            let old_ss = this.deref_operand(old_ss_op)?;
+            if let Some(stack) = this.machine.signal_stack {
+                this.copy_op(stack.into(), old_ss.into())?;
+            } else {
+                // Report an empty stack that is not disabled. Signals are never delivered, so no
+                // alternate stack is needed, and reporting `SS_DISABLE` would make the standard
+                // library allocate one with `mmap`.
+                this.memory.write_bytes(
+                    old_ss.ptr,
+                    iter::repeat(0u8).take(old_ss.layout.size.bytes() as usize),
+                )?;
+            }
+        }
+
+        if let Some(new) = new {
+            if let Some(old) = this.machine.signal_stack.replace(new) {
+                this.memory.deallocate(old.ptr.assert_ptr(), None, MiriMemoryKind::Env.into())?;
+            }
+        }
+        Ok(0)
+    }
+
+    /// Checks whether `signum` is a signal whose action can be queried or, if `installing` is
+    /// set, changed. Sets the last error to `EINVAL` if not.
+    fn check_signal(&mut self, signum: i32, installing: bool) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        // The highest signal number, which is `SIGRTMAX` on Linux and `NSIG - 1` on macOS.
+        let max = match this.tcx.sess.target.target.target_os.as_str() {
+            "linux" => 64,
+            "macos" => 31,
+            target_os => throw_unsup_format!("signals are not supported on {}", target_os),
+        };
+        let uncatchable = signum == this.eval_libc_i32("SIGKILL")?
+            || signum == this.eval_libc_i32("SIGSTOP")?;
+        if signum < 1 || signum > max || (installing && uncatchable) {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn check_signal_handler(&mut self, handler: Scalar<Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if handler != this.eval_libc("SIG_DFL")? && handler != this.eval_libc("SIG_IGN")? {
+            // Make sure this is a function pointer, even though it will never be called.
+            this.memory.get_fn(handler)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the action for `signum` and returns the previous one.
+    fn replace_signal_action(
+        &mut self,
+        signum: i32,
+        new: SignalAction<'tcx>,
+    ) -> InterpResult<'tcx, Option<SignalAction<'tcx>>> {
+        let this = self.eval_context_mut();
+
+        trace!("Setting the action for signal {} to {:?}", signum, new);
+        let old = this.machine.signal_actions.insert(signum, new);
+        if let Some(SignalAction { action: Some(action), .. }) = &old {
+            this.memory.deallocate(action.ptr.assert_ptr(), None, MiriMemoryKind::Env.into())?;
+        }
+        Ok(old)
+    }
+}
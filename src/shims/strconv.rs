@@ -0,0 +1,295 @@
+//! Implement the C string-to-number conversions (`strtol` and friends). Parsing always behaves
+//! like in the "C" locale, whatever the host's locale is.
+
+use std::convert::TryFrom;
+
+use rustc::ty::layout::Size;
+
+use crate::*;
+
+/// Whether `c` is whitespace in the "C" locale, as checked by `isspace`.
+fn is_c_space(c: u8) -> bool {
+    match c {
+        b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r' => true,
+        _ => false,
+    }
+}
+
+/// The result of parsing an integer like `strtol` does.
+#[derive(Debug, PartialEq)]
+pub struct ParsedInt {
+    pub negative: bool,
+    /// The absolute value, or `None` if it does not even fit into a `u128`.
+    pub magnitude: Option<u128>,
+    /// The number of bytes that make up the number, or 0 if there is no number at all.
+    pub len: usize,
+}
+
+/// Parses the integer at the start of `s` in the given `base` (0 to pick the base from the
+/// prefix, or 2 to 36), like `strtol`. Returns `None` for an invalid base.
+pub fn parse_int(s: &[u8], base: u32) -> Option<ParsedInt> {
+    if base == 1 || base > 36 {
+        return None;
+    }
+    let mut pos = s.iter().take_while(|&&c| is_c_space(c)).count();
+    let negative = match s.get(pos) {
+        Some(b'-') => {
+            pos += 1;
+            true
+        }
+        Some(b'+') => {
+            pos += 1;
+            false
+        }
+        _ => false,
+    };
+    let digit = |pos: usize, base: u32| s.get(pos).and_then(|&c| (c as char).to_digit(base));
+    let has_hex_prefix = s.get(pos) == Some(&b'0')
+        && s.get(pos + 1).map(u8::to_ascii_lowercase) == Some(b'x')
+        && digit(pos + 2, 16).is_some();
+    let base = match base {
+        0 if has_hex_prefix => 16,
+        0 if s.get(pos) == Some(&b'0') => 8,
+        0 => 10,
+        base => base,
+    };
+    if base == 16 && has_hex_prefix {
+        pos += 2;
+    }
+
+    let start = pos;
+    let mut magnitude = Some(0u128);
+    while let Some(d) = digit(pos, base) {
+        magnitude = magnitude
+            .and_then(|m| m.checked_mul(u128::from(base)))
+            .and_then(|m| m.checked_add(u128::from(d)));
+        pos += 1;
+    }
+    let len = if pos == start { 0 } else { pos };
+    Some(ParsedInt { negative, magnitude, len })
+}
+
+/// Returns the number of bytes of the floating-point number at the start of `s`, like `strtod`
+/// parses it, and whether it is written in hexadecimal. Returns 0 if there is no number.
+fn float_len(s: &[u8]) -> (usize, bool) {
+    let mut pos = s.iter().take_while(|&&c| is_c_space(c)).count();
+    if s.get(pos) == Some(&b'+') || s.get(pos) == Some(&b'-') {
+        pos += 1;
+    }
+    let starts_with = |pos: usize, word: &[u8]| {
+        s.len() >= pos + word.len() && s[pos..pos + word.len()].eq_ignore_ascii_case(word)
+    };
+    if starts_with(pos, b"infinity") {
+        return (pos + 8, false);
+    }
+    if starts_with(pos, b"inf") {
+        return (pos + 3, false);
+    }
+    if starts_with(pos, b"nan") {
+        pos += 3;
+        // `nan(n-char-sequence)` is also a NaN, with a payload we ignore.
+        let chars = s[pos..].iter().skip(1).take_while(|c| c.is_ascii_alphanumeric() || **c == b'_');
+        let chars = chars.count();
+        if s.get(pos) == Some(&b'(') && s.get(pos + 1 + chars) == Some(&b')') {
+            pos += chars + 2;
+        }
+        return (pos, false);
+    }
+    let hex = starts_with(pos, b"0x");
+    let (digits_start, is_digit, exponent): (usize, fn(&u8) -> bool, u8) = if hex {
+        (pos + 2, u8::is_ascii_hexdigit, b'p')
+    } else {
+        (pos, u8::is_ascii_digit, b'e')
+    };
+    let mut end = digits_start;
+    let count_digits = |pos: usize| s[pos..].iter().take_while(|c| is_digit(*c)).count();
+    let int_digits = count_digits(end);
+    end += int_digits;
+    let mut frac_digits = 0;
+    if s.get(end) == Some(&b'.') {
+        frac_digits = count_digits(end + 1);
+        if int_digits + frac_digits > 0 {
+            end += 1 + frac_digits;
+        }
+    }
+    if int_digits + frac_digits == 0 {
+        // For "0x" without hex digits, the number is the "0".
+        return if hex { (pos + 1, false) } else { (0, false) };
+    }
+    if s.get(end).map(u8::to_ascii_lowercase) == Some(exponent) {
+        let mut exp_end = end + 1;
+        if s.get(exp_end) == Some(&b'+') || s.get(exp_end) == Some(&b'-') {
+            exp_end += 1;
+        }
+        let exp_digits = s[exp_end..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if exp_digits > 0 {
+            end = exp_end + exp_digits;
+        }
+    }
+    (end, hex)
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Implements `strtol`, `strtoul` and their `long long` versions. The size of the result
+    /// comes from `dest_size`. Sets `errno` and returns the limit of the result type if the
+    /// number does not fit.
+    fn strtol(
+        &mut self,
+        nptr_op: OpTy<'tcx, Tag>,
+        endptr_op: OpTy<'tcx, Tag>,
+        base_op: OpTy<'tcx, Tag>,
+        signed: bool,
+        dest_size: Size,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        let nptr = this.read_scalar(nptr_op)?.not_undef()?;
+        let base = this.read_scalar(base_op)?.to_i32()?;
+        // Check the whole string, even the part after the number.
+        let s = this.memory.read_c_str(nptr)?;
+        let parsed = match u32::try_from(base).ok().and_then(|base| parse_int(s, base)) {
+            Some(parsed) => parsed,
+            None => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                this.write_endptr(endptr_op, nptr, 0)?;
+                return Ok(Scalar::from_int(0, dest_size));
+            }
+        };
+        this.write_endptr(endptr_op, nptr, parsed.len)?;
+
+        let bits = dest_size.bits();
+        let result = if signed {
+            let limit = if parsed.negative { 1u128 << (bits - 1) } else { (1u128 << (bits - 1)) - 1 };
+            match parsed.magnitude.filter(|&m| m <= limit) {
+                Some(m) if parsed.negative => Scalar::from_int(-(m as i128), dest_size),
+                Some(m) => Scalar::from_uint(m, dest_size),
+                None => {
+                    let erange = this.eval_libc("ERANGE")?;
+                    this.set_last_error(erange)?;
+                    if parsed.negative {
+                        Scalar::from_int(-(limit as i128), dest_size)
+                    } else {
+                        Scalar::from_uint(limit, dest_size)
+                    }
+                }
+            }
+        } else {
+            let max = u128::max_value() >> (128 - bits);
+            match parsed.magnitude.filter(|&m| m <= max) {
+                // The result of negating is converted to the unsigned type.
+                Some(m) if parsed.negative => Scalar::from_uint(m.wrapping_neg() & max, dest_size),
+                Some(m) => Scalar::from_uint(m, dest_size),
+                None => {
+                    let erange = this.eval_libc("ERANGE")?;
+                    this.set_last_error(erange)?;
+                    Scalar::from_uint(max, dest_size)
+                }
+            }
+        };
+        Ok(result)
+    }
+
+    /// Implements `strtod`. Sets `errno` if the number is too large or too small to be
+    /// represented.
+    fn strtod(
+        &mut self,
+        nptr_op: OpTy<'tcx, Tag>,
+        endptr_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, f64> {
+        let this = self.eval_context_mut();
+
+        let nptr = this.read_scalar(nptr_op)?.not_undef()?;
+        let s = this.memory.read_c_str(nptr)?;
+        let (len, hex) = float_len(s);
+        if hex {
+            throw_unsup_format!("`strtod` does not support hexadecimal floating-point numbers");
+        }
+        // `float_len` only accepts ASCII.
+        let number = std::str::from_utf8(&s[..len]).unwrap().trim_start_matches(|c| is_c_space(c as u8));
+        let unsigned = number.trim_start_matches(|c| c == '+' || c == '-');
+        let result = if len == 0 {
+            0.0
+        } else if unsigned.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            // Rust spells infinities and NaNs differently than C.
+            let value = if unsigned.to_ascii_lowercase().starts_with("inf") {
+                std::f64::INFINITY
+            } else {
+                std::f64::NAN
+            };
+            if number.starts_with('-') { -value } else { value }
+        } else {
+            let value: f64 = number.parse().unwrap();
+            let nonzero = unsigned
+                .bytes()
+                .take_while(|&c| c != b'e' && c != b'E')
+                .any(|c| c != b'0' && c != b'.');
+            // Overflow gives an infinity, underflow loses all nonzero digits.
+            if value.is_infinite() || (value == 0.0 && nonzero) {
+                let erange = this.eval_libc("ERANGE")?;
+                this.set_last_error(erange)?;
+            }
+            value
+        };
+        this.write_endptr(endptr_op, nptr, len)?;
+        Ok(result)
+    }
+
+    /// Stores `nptr + len` into `*endptr`, unless `endptr` is NULL.
+    fn write_endptr(
+        &mut self,
+        endptr_op: OpTy<'tcx, Tag>,
+        nptr: Scalar<Tag>,
+        len: usize,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let endptr = this.read_scalar(endptr_op)?.not_undef()?;
+        if !this.is_null(endptr)? {
+            let end = nptr.ptr_offset(Size::from_bytes(len as u64), this)?;
+            this.write_scalar(end, this.deref_operand(endptr_op)?.into())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(negative: bool, magnitude: u128, len: usize) -> Option<ParsedInt> {
+        Some(ParsedInt { negative, magnitude: Some(magnitude), len })
+    }
+
+    #[test]
+    fn test_parse_int() {
+        assert_eq!(parse_int(b"  -42abc", 10), int(true, 42, 5));
+        assert_eq!(parse_int(b"+0x1F", 0), int(false, 31, 5));
+        assert_eq!(parse_int(b"0x1F", 16), int(false, 31, 4));
+        assert_eq!(parse_int(b"0755", 0), int(false, 0o755, 4));
+        assert_eq!(parse_int(b"zz", 36), int(false, 35 * 36 + 35, 2));
+        // The "0" is the number, the "x" is not part of it.
+        assert_eq!(parse_int(b"0xg", 0), int(false, 0, 1));
+        assert_eq!(parse_int(b"  -", 10), int(true, 0, 0));
+        assert_eq!(parse_int(b"1", 1), None);
+        assert_eq!(parse_int(b"1", 37), None);
+        let huge = parse_int(&[b'9'; 40], 10).unwrap();
+        assert_eq!((huge.magnitude, huge.len), (None, 40));
+    }
+
+    #[test]
+    fn test_float_len() {
+        assert_eq!(float_len(b" 1.5e3x"), (6, false));
+        assert_eq!(float_len(b"-.5"), (3, false));
+        assert_eq!(float_len(b"1.e"), (2, false));
+        assert_eq!(float_len(b"1e+"), (1, false));
+        assert_eq!(float_len(b"."), (0, false));
+        assert_eq!(float_len(b"-Infinity"), (9, false));
+        assert_eq!(float_len(b"infinit"), (3, false));
+        assert_eq!(float_len(b"nan(123)"), (8, false));
+        assert_eq!(float_len(b"nan(12"), (3, false));
+        assert_eq!(float_len(b"0x1p3"), (5, true));
+        assert_eq!(float_len(b"0xg"), (1, false));
+    }
+}
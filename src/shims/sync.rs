@@ -0,0 +1,2064 @@
+//! Emulation of the pthread synchronization primitives, and of the SRW locks, critical sections
+//! and condition variables of Windows. Their state is stored directly in the memory of the
+//! objects, at offsets that leave the static initializers of the supported targets intact.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use rustc::ty::layout::{LayoutOf, Size, TyLayout};
+use rustc::ty;
+
+use crate::stacked_borrows::Tag;
+use crate::*;
+
+/// glibc keeps the robustness of a mutex in the same word as its kind; so do we.
+const MUTEX_ROBUST_FLAG: i32 = 0x4000_0000;
+
+/// Makes sure that `op` points to an object of at least `min_size` bytes, so that we can store our
+/// state in it.
+fn assert_ptr_target_min_size<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    op: OpTy<'tcx, Tag>,
+    min_size: u64,
+) -> InterpResult<'tcx, ()> {
+    let target_ty = match op.layout.ty.kind {
+        ty::RawPtr(ty::TypeAndMut { ty, mutbl: _ }) => ty,
+        _ => panic!("Argument to pthread function was not a raw pointer"),
+    };
+    let target_layout = ecx.layout_of(target_ty)?;
+    assert!(target_layout.size.bytes() >= min_size);
+    Ok(())
+}
+
+fn get_at_offset<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    op: OpTy<'tcx, Tag>,
+    offset: u64,
+    layout: TyLayout<'tcx>,
+    min_size: u64,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    // Ensure that the following read at an offset to the pointer is within bounds.
+    assert_ptr_target_min_size(ecx, op, min_size)?;
+    let op_place = ecx.deref_operand(op)?;
+    let value_place = op_place.offset(Size::from_bytes(offset), MemPlaceMeta::None, layout, ecx)?;
+    // Our state is only ever accessed by the shims, which synchronize the threads themselves.
+    ecx.allow_data_races_ref(|ecx| ecx.read_scalar(value_place.into()))
+}
+
+fn set_at_offset<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    op: OpTy<'tcx, Tag>,
+    offset: u64,
+    value: impl Into<ScalarMaybeUndef<Tag>>,
+    layout: TyLayout<'tcx>,
+    min_size: u64,
+) -> InterpResult<'tcx, ()> {
+    // Ensure that the following write at an offset to the pointer is within bounds.
+    assert_ptr_target_min_size(ecx, op, min_size)?;
+    let op_place = ecx.deref_operand(op)?;
+    let value_place = op_place.offset(Size::from_bytes(offset), MemPlaceMeta::None, layout, ecx)?;
+    ecx.allow_data_races_mut(|ecx| ecx.write_scalar(value.into(), value_place.into()))
+}
+
+/// The location of the synchronization object `op` points to, which identifies it in the state
+/// that is kept outside of its memory.
+fn object_location<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, (AllocId, Size)> {
+    let ptr = ecx.force_ptr(ecx.read_scalar(op)?.not_undef()?)?;
+    Ok((ptr.alloc_id, ptr.offset))
+}
+
+// pthread_mutexattr_t is either 4 or 16 bytes, depending on the platform.
+
+// Our chosen memory layout for the attributes: store the kind and the robustness flag in the first
+// four bytes (the kind field of glibc's `pthread_mutexattr_t` works the same way).
+
+const PTHREAD_MUTEXATTR_T_MIN_SIZE: u64 = 4;
+
+fn mutexattr_get_kind<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    attr_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    get_at_offset(ecx, attr_op, 0, ecx.layout_of(ecx.tcx.types.i32)?, PTHREAD_MUTEXATTR_T_MIN_SIZE)
+}
+
+fn mutexattr_set_kind<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    attr_op: OpTy<'tcx, Tag>,
+    kind: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.i32)?;
+    set_at_offset(ecx, attr_op, 0, kind, layout, PTHREAD_MUTEXATTR_T_MIN_SIZE)
+}
+
+// pthread_mutex_t is between 24 and 64 bytes, depending on the platform.
+
+// Our chosen memory layout for the mutex:
+// bytes 0-3: reserved for signature on macOS
+// (need to avoid this because it is set by static initializer macros)
+// bytes 4-7: count of how many times this mutex has been locked, as a u32
+// bytes 8-11: the id of the thread that holds the lock, as a u32
+// (only meaningful while the mutex is locked)
+// bytes 12-15 or 16-19 (depending on platform): mutex kind and robustness flag, as an i32
+// (the kind has to be at its offset for compatibility with static initializer macros)
+// bytes 20-23: whether a robust mutex is consistent, as a u32 (see `MUTEX_CONSISTENT` and friends)
+// (the static initializers leave it zero, which is consistent)
+
+const PTHREAD_MUTEX_T_MIN_SIZE: u64 = 24;
+
+fn mutex_get_locked_count<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    get_at_offset(ecx, mutex_op, 4, ecx.layout_of(ecx.tcx.types.u32)?, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+fn mutex_set_locked_count<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+    locked_count: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, mutex_op, 4, locked_count, layout, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+fn mutex_get_owner<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    get_at_offset(ecx, mutex_op, 8, ecx.layout_of(ecx.tcx.types.u32)?, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+fn mutex_set_owner<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+    owner: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, mutex_op, 8, owner, layout, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+fn mutex_kind_offset<'mir, 'tcx: 'mir>(ecx: &MiriEvalContext<'mir, 'tcx>) -> u64 {
+    if ecx.pointer_size().bytes() == 8 { 16 } else { 12 }
+}
+
+fn mutex_get_kind<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    let offset = mutex_kind_offset(ecx);
+    get_at_offset(ecx, mutex_op, offset, ecx.layout_of(ecx.tcx.types.i32)?, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+fn mutex_set_kind<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+    kind: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let offset = mutex_kind_offset(ecx);
+    let layout = ecx.layout_of(ecx.tcx.types.i32)?;
+    set_at_offset(ecx, mutex_op, offset, kind, layout, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+/// The robust mutex can be locked normally.
+const MUTEX_CONSISTENT: u32 = 0;
+/// The owner of the robust mutex terminated while holding it. The new owner has to call
+/// `pthread_mutex_consistent` before unlocking it.
+const MUTEX_INCONSISTENT: u32 = 1;
+/// The robust mutex was unlocked while it was inconsistent, and cannot be locked anymore.
+const MUTEX_NOT_RECOVERABLE: u32 = 2;
+
+fn mutex_get_robust_state<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    get_at_offset(ecx, mutex_op, 20, ecx.layout_of(ecx.tcx.types.u32)?, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+fn mutex_set_robust_state<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+    state: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, mutex_op, 20, state, layout, PTHREAD_MUTEX_T_MIN_SIZE)
+}
+
+/// The pthread mutexes and critical sections that are locked, by their location, with the thread
+/// that holds them and what kind of lock they are. The lock state itself is kept in the memory of
+/// the locks; this is only used to list the locks of each thread in thread dumps.
+#[derive(Debug, Default)]
+pub struct HeldLocks(BTreeMap<(AllocId, Size), (ThreadId, &'static str)>);
+
+impl HeldLocks {
+    fn hold(&mut self, location: (AllocId, Size), thread: ThreadId, kind: &'static str) {
+        self.0.insert(location, (thread, kind));
+    }
+
+    fn release(&mut self, location: (AllocId, Size)) {
+        self.0.remove(&location);
+    }
+
+    /// Describes the locks that `thread` holds, e.g. "pthread mutex at alloc12+0x8".
+    pub fn held_by(&self, thread: ThreadId) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(_, (holder, _))| *holder == thread)
+            .map(|((alloc_id, offset), (_, kind))| {
+                format!("{} at {}+{:#x}", kind, alloc_id, offset.bytes())
+            })
+            .collect()
+    }
+}
+
+/// Tries to lock a mutex that the active thread does not hold. Returns the error code to report
+/// if it got the lock (or cannot ever get it), and `None` if another thread holds it.
+fn mutex_acquire<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+    robust: bool,
+) -> InterpResult<'tcx, Option<i32>> {
+    let active = ecx.machine.threads.active_thread();
+    if robust && mutex_get_robust_state(ecx, mutex_op)?.to_u32()? == MUTEX_NOT_RECOVERABLE {
+        return Ok(Some(ecx.eval_libc_i32("ENOTRECOVERABLE")?));
+    }
+    let locked_count = mutex_get_locked_count(ecx, mutex_op)?.to_u32()?;
+    if locked_count == 0 {
+        mutex_set_locked_count(ecx, mutex_op, Scalar::from_u32(1))?;
+        mutex_set_owner(ecx, mutex_op, Scalar::from_u32(active.to_u32()))?;
+        let location = object_location(ecx, mutex_op)?;
+        ecx.acquire_sync_clock(location);
+        ecx.machine.held_locks.hold(location, active, "pthread mutex");
+        return Ok(Some(0));
+    }
+    let owner = ThreadId::from(mutex_get_owner(ecx, mutex_op)?.to_u32()?);
+    let owner_terminated = ecx.machine.threads.get(owner).map_or(false, Thread::is_terminated);
+    if robust && owner_terminated {
+        // The owner can never unlock the mutex, so the active thread takes it over. Whatever the
+        // mutex protects may have been left in an inconsistent state.
+        mutex_set_locked_count(ecx, mutex_op, Scalar::from_u32(1))?;
+        mutex_set_owner(ecx, mutex_op, Scalar::from_u32(active.to_u32()))?;
+        mutex_set_robust_state(ecx, mutex_op, Scalar::from_u32(MUTEX_INCONSISTENT))?;
+        let location = object_location(ecx, mutex_op)?;
+        ecx.acquire_sync_clock(location);
+        ecx.machine.held_locks.hold(location, active, "pthread mutex");
+        return Ok(Some(ecx.eval_libc_i32("EOWNERDEAD")?));
+    }
+    Ok(None)
+}
+
+/// Whether the active thread holds the lock of the mutex.
+fn mutex_held_by_active_thread<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    mutex_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, bool> {
+    if mutex_get_locked_count(ecx, mutex_op)?.to_u32()? == 0 {
+        return Ok(false);
+    }
+    let owner = mutex_get_owner(ecx, mutex_op)?.to_u32()?;
+    Ok(owner == ecx.machine.threads.active_thread().to_u32())
+}
+
+// pthread_condattr_t is either 4 or 16 bytes, depending on the platform.
+
+// Our chosen memory layout for the attributes: store the clock id in the first four bytes.
+
+const PTHREAD_CONDATTR_T_MIN_SIZE: u64 = 4;
+
+fn condattr_get_clock_id<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    attr_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    get_at_offset(ecx, attr_op, 0, ecx.layout_of(ecx.tcx.types.i32)?, PTHREAD_CONDATTR_T_MIN_SIZE)
+}
+
+fn condattr_set_clock_id<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    attr_op: OpTy<'tcx, Tag>,
+    clock_id: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.i32)?;
+    set_at_offset(ecx, attr_op, 0, clock_id, layout, PTHREAD_CONDATTR_T_MIN_SIZE)
+}
+
+// pthread_cond_t is either 48 or 64 bytes, depending on the platform.
+
+// Our chosen memory layout for the condition variable:
+// bytes 0-3: reserved for signature on macOS
+// bytes 4-7: the clock id used by `pthread_cond_timedwait`, as an i32
+// (the static initializers leave it zero, which is `CLOCK_REALTIME` on all supported targets)
+
+const PTHREAD_COND_T_MIN_SIZE: u64 = 8;
+
+fn cond_get_clock_id<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    cond_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    get_at_offset(ecx, cond_op, 4, ecx.layout_of(ecx.tcx.types.i32)?, PTHREAD_COND_T_MIN_SIZE)
+}
+
+fn cond_set_clock_id<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    cond_op: OpTy<'tcx, Tag>,
+    clock_id: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.i32)?;
+    set_at_offset(ecx, cond_op, 4, clock_id, layout, PTHREAD_COND_T_MIN_SIZE)
+}
+
+/// A thread that waits for a condition variable to be signaled.
+#[derive(Debug)]
+pub struct CondWaiter {
+    thread: ThreadId,
+    /// The location of the `pthread_cond_t`.
+    cond: (AllocId, Size),
+    /// How many times the thread had locked the mutex, which is restored when it wakes up.
+    mutex_locked_count: u32,
+    /// The deadline on the monotonic clock, if the wait has a relative timeout (on Windows).
+    deadline: Option<Duration>,
+    signaled: bool,
+}
+
+/// A thread that waits in `futex(FUTEX_WAIT)`.
+#[derive(Debug)]
+pub struct FutexWaiter {
+    thread: ThreadId,
+    /// The location of the futex word.
+    addr: (AllocId, Size),
+    /// The deadline on the monotonic clock, if the wait has a timeout.
+    deadline: Option<Duration>,
+    woken: bool,
+}
+
+/// Wakes up at most `count` of the threads that wait for the futex word at `addr`, in the order
+/// they started waiting, and returns how many were woken up.
+fn futex_wake_waiters<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    addr: (AllocId, Size),
+    count: usize,
+) -> usize {
+    let mut woken = 0;
+    for waiter in ecx.machine.futex_waiters.iter_mut() {
+        if woken == count {
+            break;
+        }
+        if waiter.addr == addr && !waiter.woken {
+            waiter.woken = true;
+            woken += 1;
+        }
+    }
+    ecx.release_sync_clock(addr);
+    woken
+}
+
+/// Waits for a condition variable, with a deadline given as a clock id and an absolute time. This
+/// is called again whenever a thread that was started to signal the condition variable finished.
+/// Returns `None` if such a thread was started.
+fn cond_wait<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    cond_op: OpTy<'tcx, Tag>,
+    mutex_op: OpTy<'tcx, Tag>,
+    deadline: Option<(i32, Duration)>,
+) -> InterpResult<'tcx, Option<i32>> {
+    let active = ecx.machine.threads.active_thread();
+    let idx = match ecx.machine.cond_waiters.iter().position(|waiter| waiter.thread == active) {
+        Some(idx) => idx,
+        None => {
+            // The thread starts waiting, and releases the mutex for the thread that signals it.
+            if !mutex_held_by_active_thread(ecx, mutex_op)? {
+                throw_ub_format!(
+                    "waited for a condition variable with a mutex that the thread does not hold"
+                );
+            }
+            let mutex_locked_count = mutex_get_locked_count(ecx, mutex_op)?.to_u32()?;
+            mutex_set_locked_count(ecx, mutex_op, Scalar::from_u32(0))?;
+            let location = object_location(ecx, mutex_op)?;
+            ecx.release_sync_clock(location);
+            ecx.machine.held_locks.release(location);
+            let cond = object_location(ecx, cond_op)?;
+            let waiter = CondWaiter {
+                thread: active,
+                cond,
+                mutex_locked_count,
+                deadline: None,
+                signaled: false,
+            };
+            ecx.machine.cond_waiters.push(waiter);
+            ecx.machine.cond_waiters.len() - 1
+        }
+    };
+
+    let remaining = match deadline {
+        Some((clock_id, deadline)) => {
+            let now = ecx.read_clock(clock_id)?.expect("the clock id was checked");
+            Some(deadline.checked_sub(now).unwrap_or_default())
+        }
+        None => None,
+    };
+    let signaled = ecx.machine.cond_waiters[idx].signaled;
+    let result = match ecx.block_active_thread(signaled, remaining, BlockReason::CondVar)? {
+        BlockResult::Woken => 0,
+        BlockResult::TimedOut => ecx.eval_libc_i32("ETIMEDOUT")?,
+        BlockResult::Retry => return Ok(None),
+    };
+
+    // Lock the mutex again, as often as before.
+    let waiter = ecx.machine.cond_waiters.remove(idx);
+    let robust = mutex_get_kind(ecx, mutex_op)?.not_undef()?.to_i32()? & MUTEX_ROBUST_FLAG != 0;
+    match mutex_acquire(ecx, mutex_op, robust)? {
+        Some(0) => {
+            let count = Scalar::from_u32(waiter.mutex_locked_count);
+            mutex_set_locked_count(ecx, mutex_op, count)?;
+            Ok(Some(result))
+        }
+        Some(error) => Ok(Some(error)),
+        // The thread that holds the mutex cannot run anymore.
+        None => ecx.deadlock(BlockReason::Mutex),
+    }
+}
+
+/// Wakes up the thread that waits the longest for the condition variable at `cond`.
+fn cond_signal<'mir, 'tcx: 'mir>(ecx: &mut MiriEvalContext<'mir, 'tcx>, cond: (AllocId, Size)) {
+    if let Some(waiter) = ecx
+        .machine
+        .cond_waiters
+        .iter_mut()
+        .find(|waiter| waiter.cond == cond && !waiter.signaled)
+    {
+        waiter.signaled = true;
+    }
+}
+
+/// Wakes up all threads that wait for the condition variable at `cond`.
+fn cond_broadcast<'mir, 'tcx: 'mir>(ecx: &mut MiriEvalContext<'mir, 'tcx>, cond: (AllocId, Size)) {
+    for waiter in ecx.machine.cond_waiters.iter_mut().filter(|waiter| waiter.cond == cond) {
+        waiter.signaled = true;
+    }
+}
+
+// pthread_rwlock_t is between 32 and 200 bytes, depending on the platform.
+
+// Our chosen memory layout for the rwlock:
+// bytes 0-3: reserved for signature on macOS
+// (need to avoid this because it is set by static initializer macros)
+// bytes 4-7: the id of the thread that holds the write lock plus one, or zero, as a u32
+// bytes 8-11: how many read locks are held, as a u32
+// Which thread holds how many read locks does not fit in there; see `RwLockReaders`.
+
+const PTHREAD_RWLOCK_T_MIN_SIZE: u64 = 12;
+
+fn rwlock_get_writer<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    rwlock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, Option<ThreadId>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    let writer = get_at_offset(ecx, rwlock_op, 4, layout, PTHREAD_RWLOCK_T_MIN_SIZE)?.to_u32()?;
+    Ok(writer.checked_sub(1).map(ThreadId::from))
+}
+
+fn rwlock_set_writer<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    rwlock_op: OpTy<'tcx, Tag>,
+    writer: Option<ThreadId>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    let writer = Scalar::from_u32(writer.map_or(0, |id| id.to_u32() + 1));
+    set_at_offset(ecx, rwlock_op, 4, writer, layout, PTHREAD_RWLOCK_T_MIN_SIZE)
+}
+
+fn rwlock_get_readers<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    rwlock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    get_at_offset(ecx, rwlock_op, 8, layout, PTHREAD_RWLOCK_T_MIN_SIZE)
+}
+
+fn rwlock_set_readers<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    rwlock_op: OpTy<'tcx, Tag>,
+    readers: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, rwlock_op, 8, readers, layout, PTHREAD_RWLOCK_T_MIN_SIZE)
+}
+
+/// The read locks that each thread holds, for every `pthread_rwlock_t` that is read-locked, by
+/// the location of the `pthread_rwlock_t`.
+#[derive(Debug, Default)]
+pub struct RwLockReaders(HashMap<(AllocId, Size), BTreeMap<ThreadId, u32>>);
+
+/// Takes a read lock for the active thread, if no thread holds the write lock.
+fn rwlock_acquire_read<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    rwlock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, i32> {
+    let readers = rwlock_get_readers(ecx, rwlock_op)?.to_u32()?;
+    let readers = match readers.checked_add(1) {
+        Some(readers) => readers,
+        None => return ecx.eval_libc_i32("EAGAIN"),
+    };
+    rwlock_set_readers(ecx, rwlock_op, Scalar::from_u32(readers))?;
+    let location = object_location(ecx, rwlock_op)?;
+    ecx.acquire_sync_clock(location);
+    let active = ecx.machine.threads.active_thread();
+    *ecx.machine.rwlock_readers.0.entry(location).or_default().entry(active).or_insert(0) += 1;
+    Ok(0)
+}
+
+// sem_t is either 16 or 32 bytes, depending on the platform.
+
+// Our chosen memory layout for the semaphore:
+// bytes 0-3: the value of the semaphore, as a u32
+// The threads waiting for it are kept in `Evaluator::sem_waiters`.
+
+const SEM_T_MIN_SIZE: u64 = 4;
+
+/// The largest value a semaphore can have, like `SEM_VALUE_MAX` in glibc.
+const SEM_VALUE_MAX: u32 = i32::max_value() as u32;
+
+fn sem_get_value<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    sem_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    get_at_offset(ecx, sem_op, 0, layout, SEM_T_MIN_SIZE)
+}
+
+fn sem_set_value<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    sem_op: OpTy<'tcx, Tag>,
+    value: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, sem_op, 0, value, layout, SEM_T_MIN_SIZE)
+}
+
+/// A thread that waits in `sem_wait` or `sem_timedwait`.
+#[derive(Debug)]
+pub struct SemWaiter {
+    thread: ThreadId,
+    /// The location of the `sem_t`.
+    sem: (AllocId, Size),
+    /// Set by `sem_post`, which hands its unit directly to the waiter instead of incrementing the
+    /// value of the semaphore.
+    woken: bool,
+}
+
+/// Decrements the semaphore, waiting until that is possible or `deadline` passes. This is called
+/// again whenever a thread that was started to post the semaphore finished. Returns `None` if such
+/// a thread was started.
+fn sem_wait_deadline<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    sem_op: OpTy<'tcx, Tag>,
+    deadline: Option<Duration>,
+) -> InterpResult<'tcx, Option<i32>> {
+    let active = ecx.machine.threads.active_thread();
+    let sem = object_location(ecx, sem_op)?;
+    let idx = match ecx.machine.sem_waiters.iter().position(|waiter| waiter.thread == active) {
+        Some(idx) => idx,
+        None => {
+            let value = sem_get_value(ecx, sem_op)?.to_u32()?;
+            if value > 0 {
+                sem_set_value(ecx, sem_op, Scalar::from_u32(value - 1))?;
+                ecx.acquire_sync_clock(sem);
+                return Ok(Some(0));
+            }
+            let waiter = SemWaiter { thread: active, sem, woken: false };
+            ecx.machine.sem_waiters.push(waiter);
+            ecx.machine.sem_waiters.len() - 1
+        }
+    };
+
+    let remaining = match deadline {
+        Some(deadline) => {
+            let now = ecx.machine.clock.realtime()?;
+            Some(deadline.checked_sub(now).unwrap_or_default())
+        }
+        None => None,
+    };
+    let woken = ecx.machine.sem_waiters[idx].woken;
+    let result = match ecx.block_active_thread(woken, remaining, BlockReason::Semaphore)? {
+        BlockResult::Woken => {
+            // The thread that posted the semaphore happens before us.
+            ecx.acquire_sync_clock(sem);
+            0
+        }
+        BlockResult::TimedOut => {
+            let etimedout = ecx.eval_libc("ETIMEDOUT")?;
+            ecx.set_last_error(etimedout)?;
+            -1
+        }
+        BlockResult::Retry => return Ok(None),
+    };
+    ecx.machine.sem_waiters.remove(idx);
+    Ok(Some(result))
+}
+
+// pthread_once_t is 4 bytes on Linux; on macOS, it is 16 bytes and starts with a signature that
+// is set by the static initializer. dispatch_once_t is 8 bytes.
+
+// Our chosen memory layout for a once-only initialization: store whether the initialization has
+// not started, runs or is done (see `ONCE_INIT` and friends) in the first four bytes after the
+// signature, as a u32 (the static initializers leave it zero).
+
+const ONCE_INIT: u32 = 0;
+const ONCE_RUNNING: u32 = 1;
+const ONCE_DONE: u32 = 2;
+
+/// An initialization routine that a thread runs for a `pthread_once_t` or a `dispatch_once_t`.
+#[derive(Debug)]
+pub struct OnceRun {
+    thread: ThreadId,
+    /// The location of the `pthread_once_t` or `dispatch_once_t`.
+    once: (AllocId, Size),
+    /// The height of the stack when the initialization routine was called; the routine returned
+    /// once the stack is this low again.
+    stack_height: usize,
+}
+
+/// Calls `f` with the argument `arg` unless another call for `once_op` did that already, and
+/// waits until it returned. The state is stored at `offset` in the object `once_op` points to.
+/// Returns `false` if the frame of `f` was pushed; the call has to be repeated once that frame
+/// returns, which finishes the initialization.
+fn run_once<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    once_op: OpTy<'tcx, Tag>,
+    offset: u64,
+    f: Scalar<Tag>,
+    arg: Option<Scalar<Tag>>,
+) -> InterpResult<'tcx, bool> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    let once = object_location(ecx, once_op)?;
+    let state = get_at_offset(ecx, once_op, offset, layout, offset + 4)?.to_u32()?;
+    match state {
+        ONCE_INIT => {
+            set_at_offset(ecx, once_op, offset, Scalar::from_u32(ONCE_RUNNING), layout, offset + 4)?;
+            let f = ecx.memory.get_fn(f)?.as_instance()?;
+            let args: Vec<Immediate<Tag>> = arg.into_iter().map(|arg| arg.into()).collect();
+            let run = OnceRun {
+                thread: ecx.machine.threads.active_thread(),
+                once,
+                stack_height: ecx.stack().len(),
+            };
+            ecx.machine.once_runs.push(run);
+            let ret_place = MPlaceTy::dangling(ecx.layout_of(ecx.tcx.mk_unit())?, ecx).into();
+            ecx.call_function(f, &args, Some(ret_place), StackPopCleanup::None { cleanup: true })?;
+            Ok(false)
+        }
+        ONCE_RUNNING => {
+            let active = ecx.machine.threads.active_thread();
+            let idx = ecx
+                .machine
+                .once_runs
+                .iter()
+                .position(|run| run.once == once)
+                .expect("the initialization runs, but nobody runs it");
+            let run = &ecx.machine.once_runs[idx];
+            if run.thread != active || ecx.stack().len() != run.stack_height {
+                // Either the initialization routine called us, or the thread that runs it is
+                // suspended further down, waiting for us. Either way, it cannot return anymore.
+                return ecx.deadlock(BlockReason::Once);
+            }
+            // The initialization routine returned.
+            ecx.machine.once_runs.remove(idx);
+            set_at_offset(ecx, once_op, offset, Scalar::from_u32(ONCE_DONE), layout, offset + 4)?;
+            ecx.release_sync_clock(once);
+            Ok(true)
+        }
+        ONCE_DONE => {
+            // The initialization happens before everything after the calls that wait for it.
+            ecx.acquire_sync_clock(once);
+            Ok(true)
+        }
+        _ => throw_ub_format!("called a once-only initialization on an object in an invalid state"),
+    }
+}
+
+// pthread_barrier_t is either 20 or 32 bytes, depending on the platform.
+
+// Our chosen memory layout for the barrier:
+// bytes 0-3: how many threads have to arrive at the barrier before they can continue, as a u32
+// bytes 4-7: how many threads have arrived at the barrier since it last let them continue, as a u32
+// The threads waiting at it are kept in `Evaluator::barrier_waiters`.
+
+const PTHREAD_BARRIER_T_MIN_SIZE: u64 = 8;
+
+fn barrier_get_count<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    barrier_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    get_at_offset(ecx, barrier_op, 0, layout, PTHREAD_BARRIER_T_MIN_SIZE)
+}
+
+fn barrier_set_count<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    barrier_op: OpTy<'tcx, Tag>,
+    count: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, barrier_op, 0, count, layout, PTHREAD_BARRIER_T_MIN_SIZE)
+}
+
+fn barrier_get_arrived<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    barrier_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    get_at_offset(ecx, barrier_op, 4, layout, PTHREAD_BARRIER_T_MIN_SIZE)
+}
+
+fn barrier_set_arrived<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    barrier_op: OpTy<'tcx, Tag>,
+    arrived: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, barrier_op, 4, arrived, layout, PTHREAD_BARRIER_T_MIN_SIZE)
+}
+
+/// A thread that waits in `pthread_barrier_wait` for the other threads to arrive.
+#[derive(Debug)]
+pub struct BarrierWaiter {
+    thread: ThreadId,
+    /// The location of the `pthread_barrier_t`.
+    barrier: (AllocId, Size),
+    /// Set by the last thread that arrives at the barrier.
+    woken: bool,
+}
+
+// SRWLOCK is a single pointer, which is zero when it is unlocked, so SRWLOCK_INIT is zero.
+
+// Our chosen memory layout for the SRW lock:
+// bytes 0-3: the id of the thread that holds the exclusive lock plus one, or zero, as a u32
+// The shared locks are kept in `RwLockReaders`, like the read locks of a `pthread_rwlock_t`.
+
+const SRWLOCK_MIN_SIZE: u64 = 4;
+
+fn srwlock_get_writer<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    lock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, Option<ThreadId>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    let writer = get_at_offset(ecx, lock_op, 0, layout, SRWLOCK_MIN_SIZE)?.to_u32()?;
+    Ok(writer.checked_sub(1).map(ThreadId::from))
+}
+
+fn srwlock_set_writer<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    lock_op: OpTy<'tcx, Tag>,
+    writer: Option<ThreadId>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    let writer = Scalar::from_u32(writer.map_or(0, |id| id.to_u32() + 1));
+    set_at_offset(ecx, lock_op, 0, writer, layout, SRWLOCK_MIN_SIZE)
+}
+
+/// Takes the exclusive lock for the active thread, if no thread holds the SRW lock.
+fn srwlock_try_acquire_exclusive<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    lock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, bool> {
+    let location = object_location(ecx, lock_op)?;
+    if srwlock_get_writer(ecx, lock_op)?.is_some()
+        || ecx.machine.rwlock_readers.0.contains_key(&location)
+    {
+        return Ok(false);
+    }
+    let active = ecx.machine.threads.active_thread();
+    srwlock_set_writer(ecx, lock_op, Some(active))?;
+    ecx.acquire_sync_clock(location);
+    Ok(true)
+}
+
+/// Takes a shared lock for the active thread, if no thread holds the exclusive lock.
+fn srwlock_try_acquire_shared<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    lock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, bool> {
+    if srwlock_get_writer(ecx, lock_op)?.is_some() {
+        return Ok(false);
+    }
+    let location = object_location(ecx, lock_op)?;
+    ecx.acquire_sync_clock(location);
+    let active = ecx.machine.threads.active_thread();
+    *ecx.machine.rwlock_readers.0.entry(location).or_default().entry(active).or_insert(0) += 1;
+    Ok(true)
+}
+
+fn srwlock_release_exclusive<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    lock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ()> {
+    if srwlock_get_writer(ecx, lock_op)? != Some(ecx.machine.threads.active_thread()) {
+        throw_ub_format!("released an SRW lock that the thread does not hold exclusively");
+    }
+    srwlock_set_writer(ecx, lock_op, None)?;
+    let location = object_location(ecx, lock_op)?;
+    ecx.release_sync_clock(location);
+    Ok(())
+}
+
+fn srwlock_release_shared<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    lock_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ()> {
+    let active = ecx.machine.threads.active_thread();
+    let location = object_location(ecx, lock_op)?;
+    let msg = "released an SRW lock that the thread does not hold shared";
+    let readers = match ecx.machine.rwlock_readers.0.get_mut(&location) {
+        Some(readers) => readers,
+        None => throw_ub_format!("{}", msg),
+    };
+    match readers.get_mut(&active) {
+        Some(count) if *count > 1 => *count -= 1,
+        Some(_) => {
+            readers.remove(&active);
+            if readers.is_empty() {
+                ecx.machine.rwlock_readers.0.remove(&location);
+            }
+        }
+        None => throw_ub_format!("{}", msg),
+    }
+    ecx.release_sync_clock(location);
+    Ok(())
+}
+
+// CRITICAL_SECTION is either 24 or 40 bytes, depending on the platform, and it has to be
+// initialized with `InitializeCriticalSection`.
+
+// Our chosen memory layout for the critical section:
+// bytes 0-3: the id of the thread that entered it plus one, or zero, as a u32
+// bytes 4-7: how often that thread entered it, as a u32
+
+const CRITICAL_SECTION_MIN_SIZE: u64 = 8;
+
+fn critical_section_get_owner<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    section_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, Option<ThreadId>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    let owner = get_at_offset(ecx, section_op, 0, layout, CRITICAL_SECTION_MIN_SIZE)?.to_u32()?;
+    Ok(owner.checked_sub(1).map(ThreadId::from))
+}
+
+fn critical_section_set_owner<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    section_op: OpTy<'tcx, Tag>,
+    owner: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, section_op, 0, owner, layout, CRITICAL_SECTION_MIN_SIZE)
+}
+
+fn critical_section_get_count<'mir, 'tcx: 'mir>(
+    ecx: &MiriEvalContext<'mir, 'tcx>,
+    section_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    get_at_offset(ecx, section_op, 4, layout, CRITICAL_SECTION_MIN_SIZE)
+}
+
+fn critical_section_set_count<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    section_op: OpTy<'tcx, Tag>,
+    count: impl Into<ScalarMaybeUndef<Tag>>,
+) -> InterpResult<'tcx, ()> {
+    let layout = ecx.layout_of(ecx.tcx.types.u32)?;
+    set_at_offset(ecx, section_op, 4, count, layout, CRITICAL_SECTION_MIN_SIZE)
+}
+
+/// Enters the critical section, if no other thread is in it. Critical sections are recursive.
+fn critical_section_try_enter<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    section_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, bool> {
+    let active = ecx.machine.threads.active_thread();
+    match critical_section_get_owner(ecx, section_op)? {
+        None => {
+            critical_section_set_owner(ecx, section_op, Scalar::from_u32(active.to_u32() + 1))?;
+            critical_section_set_count(ecx, section_op, Scalar::from_u32(1))?;
+            let location = object_location(ecx, section_op)?;
+            ecx.acquire_sync_clock(location);
+            ecx.machine.held_locks.hold(location, active, "critical section");
+            Ok(true)
+        }
+        Some(owner) if owner == active => {
+            let count = critical_section_get_count(ecx, section_op)?.to_u32()?;
+            let count = match count.checked_add(1) {
+                Some(count) => count,
+                None => throw_unsup_format!("entered a critical section too often"),
+            };
+            critical_section_set_count(ecx, section_op, Scalar::from_u32(count))?;
+            Ok(true)
+        }
+        Some(_) => Ok(false),
+    }
+}
+
+/// The lock that a thread releases while it waits for a `CONDITION_VARIABLE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WindowsLock {
+    SrwExclusive,
+    SrwShared,
+    CriticalSection,
+}
+
+// CONDITION_VARIABLE is a single pointer, which CONDITION_VARIABLE_INIT sets to zero. The threads
+// waiting for it are kept in `Evaluator::cond_waiters`, like those of a `pthread_cond_t`.
+
+/// Waits for a condition variable, releasing `lock` meanwhile, for at most the number of
+/// milliseconds given by `timeout_op`. This is called again whenever a thread that was started to
+/// wake the caller up finished. Returns `None` if such a thread was started, and otherwise
+/// whether the thread was woken up before the timeout expired.
+fn windows_cond_wait<'mir, 'tcx: 'mir>(
+    ecx: &mut MiriEvalContext<'mir, 'tcx>,
+    cond_op: OpTy<'tcx, Tag>,
+    lock_op: OpTy<'tcx, Tag>,
+    lock: WindowsLock,
+    timeout_op: OpTy<'tcx, Tag>,
+) -> InterpResult<'tcx, Option<bool>> {
+    let active = ecx.machine.threads.active_thread();
+    let idx = match ecx.machine.cond_waiters.iter().position(|waiter| waiter.thread == active) {
+        Some(idx) => idx,
+        None => {
+            // The thread starts waiting, and releases the lock for the thread that wakes it up.
+            let locked_count = match lock {
+                WindowsLock::SrwExclusive => {
+                    srwlock_release_exclusive(ecx, lock_op)?;
+                    1
+                }
+                WindowsLock::SrwShared => {
+                    srwlock_release_shared(ecx, lock_op)?;
+                    1
+                }
+                WindowsLock::CriticalSection => {
+                    if critical_section_get_owner(ecx, lock_op)? != Some(active) {
+                        throw_ub_format!(
+                            "waited for a condition variable with a critical section that the thread did not enter"
+                        );
+                    }
+                    let count = critical_section_get_count(ecx, lock_op)?.to_u32()?;
+                    critical_section_set_owner(ecx, lock_op, Scalar::from_u32(0))?;
+                    critical_section_set_count(ecx, lock_op, Scalar::from_u32(0))?;
+                    let location = object_location(ecx, lock_op)?;
+                    ecx.release_sync_clock(location);
+                    ecx.machine.held_locks.release(location);
+                    count
+                }
+            };
+            let timeout_ms = ecx.read_scalar(timeout_op)?.to_u32()?;
+            // INFINITE
+            let deadline = if timeout_ms == u32::max_value() {
+                None
+            } else {
+                Some(ecx.machine.clock.monotonic() + Duration::from_millis(timeout_ms.into()))
+            };
+            let cond = object_location(ecx, cond_op)?;
+            let waiter = CondWaiter {
+                thread: active,
+                cond,
+                mutex_locked_count: locked_count,
+                deadline,
+                signaled: false,
+            };
+            ecx.machine.cond_waiters.push(waiter);
+            ecx.machine.cond_waiters.len() - 1
+        }
+    };
+
+    let now = ecx.machine.clock.monotonic();
+    let waiter = &ecx.machine.cond_waiters[idx];
+    let signaled = waiter.signaled;
+    let remaining = waiter.deadline.map(|deadline| deadline.checked_sub(now).unwrap_or_default());
+    let woken = match ecx.block_active_thread(signaled, remaining, BlockReason::CondVar)? {
+        BlockResult::Woken => true,
+        BlockResult::TimedOut => false,
+        BlockResult::Retry => return Ok(None),
+    };
+
+    // Take the lock again, as often as before.
+    let waiter = ecx.machine.cond_waiters.remove(idx);
+    let (acquired, reason) = match lock {
+        WindowsLock::SrwExclusive => {
+            (srwlock_try_acquire_exclusive(ecx, lock_op)?, BlockReason::RwLock)
+        }
+        WindowsLock::SrwShared => (srwlock_try_acquire_shared(ecx, lock_op)?, BlockReason::RwLock),
+        WindowsLock::CriticalSection => {
+            let acquired = critical_section_try_enter(ecx, lock_op)?;
+            if acquired {
+                let count = Scalar::from_u32(waiter.mutex_locked_count);
+                critical_section_set_count(ecx, lock_op, count)?;
+            }
+            (acquired, BlockReason::Mutex)
+        }
+    };
+    if !acquired {
+        // The thread that holds the lock cannot run anymore.
+        return ecx.deadlock(reason);
+    }
+    Ok(Some(woken))
+}
+
+/// `ERROR_TIMEOUT`, reported when a wait for a condition variable or an address times out.
+const ERROR_TIMEOUT: u32 = 1460;
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn pthread_mutexattr_init(&mut self, attr_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let default_kind = this.eval_libc("PTHREAD_MUTEX_DEFAULT")?;
+        mutexattr_set_kind(this, attr_op, default_kind)?;
+
+        Ok(0)
+    }
+
+    fn pthread_mutexattr_settype(
+        &mut self,
+        attr_op: OpTy<'tcx, Tag>,
+        kind_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let kind = this.read_scalar(kind_op)?.to_i32()?;
+        if kind == this.eval_libc_i32("PTHREAD_MUTEX_NORMAL")?
+            || kind == this.eval_libc_i32("PTHREAD_MUTEX_ERRORCHECK")?
+            || kind == this.eval_libc_i32("PTHREAD_MUTEX_RECURSIVE")?
+        {
+            let robust = mutexattr_get_kind(this, attr_op)?.not_undef()?.to_i32()? & MUTEX_ROBUST_FLAG;
+            mutexattr_set_kind(this, attr_op, Scalar::from_i32(kind | robust))?;
+        } else {
+            let einval = this.eval_libc_i32("EINVAL")?;
+            return Ok(einval);
+        }
+
+        Ok(0)
+    }
+
+    fn pthread_mutexattr_setrobust(
+        &mut self,
+        attr_op: OpTy<'tcx, Tag>,
+        robust_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let robust = this.read_scalar(robust_op)?.to_i32()?;
+        let kind = mutexattr_get_kind(this, attr_op)?.not_undef()?.to_i32()? & !MUTEX_ROBUST_FLAG;
+        if robust == this.eval_libc_i32("PTHREAD_MUTEX_ROBUST")? {
+            mutexattr_set_kind(this, attr_op, Scalar::from_i32(kind | MUTEX_ROBUST_FLAG))?;
+        } else if robust == this.eval_libc_i32("PTHREAD_MUTEX_STALLED")? {
+            mutexattr_set_kind(this, attr_op, Scalar::from_i32(kind))?;
+        } else {
+            let einval = this.eval_libc_i32("EINVAL")?;
+            return Ok(einval);
+        }
+
+        Ok(0)
+    }
+
+    fn pthread_mutexattr_destroy(&mut self, attr_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        mutexattr_set_kind(this, attr_op, ScalarMaybeUndef::Undef)?;
+
+        Ok(0)
+    }
+
+    fn pthread_mutex_init(
+        &mut self,
+        mutex_op: OpTy<'tcx, Tag>,
+        attr_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let attr = this.read_scalar(attr_op)?.not_undef()?;
+        let kind = if this.is_null(attr)? {
+            this.eval_libc("PTHREAD_MUTEX_DEFAULT")?
+        } else {
+            mutexattr_get_kind(this, attr_op)?.not_undef()?
+        };
+
+        mutex_set_locked_count(this, mutex_op, Scalar::from_u32(0))?;
+        mutex_set_kind(this, mutex_op, kind)?;
+        mutex_set_robust_state(this, mutex_op, Scalar::from_u32(MUTEX_CONSISTENT))?;
+
+        Ok(0)
+    }
+
+    fn pthread_mutex_lock(&mut self, mutex_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let kind = mutex_get_kind(this, mutex_op)?.not_undef()?.to_i32()?;
+        let robust = kind & MUTEX_ROBUST_FLAG != 0;
+        let kind = kind & !MUTEX_ROBUST_FLAG;
+
+        if !mutex_held_by_active_thread(this, mutex_op)? {
+            match mutex_acquire(this, mutex_op, robust)? {
+                Some(result) => Ok(result),
+                // The owner is suspended further down the stack, waiting for the active thread,
+                // or it terminated. Either way, it cannot unlock the mutex anymore.
+                None => this.deadlock(BlockReason::Mutex),
+            }
+        } else if kind == this.eval_libc_i32("PTHREAD_MUTEX_NORMAL")? {
+            this.deadlock(BlockReason::Mutex)
+        } else if kind == this.eval_libc_i32("PTHREAD_MUTEX_ERRORCHECK")? {
+            this.eval_libc_i32("EDEADLK")
+        } else if kind == this.eval_libc_i32("PTHREAD_MUTEX_RECURSIVE")? {
+            let locked_count = mutex_get_locked_count(this, mutex_op)?.to_u32()?;
+            match locked_count.checked_add(1) {
+                Some(new_count) => {
+                    mutex_set_locked_count(this, mutex_op, Scalar::from_u32(new_count))?;
+                    Ok(0)
+                }
+                None => this.eval_libc_i32("EAGAIN"),
+            }
+        } else {
+            throw_ub_format!("called pthread_mutex_lock on an unsupported type of mutex");
+        }
+    }
+
+    fn pthread_mutex_trylock(&mut self, mutex_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let kind = mutex_get_kind(this, mutex_op)?.not_undef()?.to_i32()?;
+        let robust = kind & MUTEX_ROBUST_FLAG != 0;
+        let kind = kind & !MUTEX_ROBUST_FLAG;
+
+        if !mutex_held_by_active_thread(this, mutex_op)? {
+            match mutex_acquire(this, mutex_op, robust)? {
+                Some(result) => Ok(result),
+                None => this.eval_libc_i32("EBUSY"),
+            }
+        } else if kind == this.eval_libc_i32("PTHREAD_MUTEX_NORMAL")?
+            || kind == this.eval_libc_i32("PTHREAD_MUTEX_ERRORCHECK")?
+        {
+            this.eval_libc_i32("EBUSY")
+        } else if kind == this.eval_libc_i32("PTHREAD_MUTEX_RECURSIVE")? {
+            let locked_count = mutex_get_locked_count(this, mutex_op)?.to_u32()?;
+            match locked_count.checked_add(1) {
+                Some(new_count) => {
+                    mutex_set_locked_count(this, mutex_op, Scalar::from_u32(new_count))?;
+                    Ok(0)
+                }
+                None => this.eval_libc_i32("EAGAIN"),
+            }
+        } else {
+            throw_ub_format!("called pthread_mutex_trylock on an unsupported type of mutex");
+        }
+    }
+
+    fn pthread_mutex_unlock(&mut self, mutex_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let kind = mutex_get_kind(this, mutex_op)?.not_undef()?.to_i32()?;
+        let robust = kind & MUTEX_ROBUST_FLAG != 0;
+        let kind = kind & !MUTEX_ROBUST_FLAG;
+        let locked_count = mutex_get_locked_count(this, mutex_op)?.to_u32()?;
+        let held = mutex_held_by_active_thread(this, mutex_op)?;
+
+        let new_count = if kind == this.eval_libc_i32("PTHREAD_MUTEX_NORMAL")? {
+            if locked_count == 0 {
+                throw_ub_format!("unlocked a PTHREAD_MUTEX_NORMAL mutex that was not locked");
+            } else if !held {
+                throw_ub_format!(
+                    "unlocked a PTHREAD_MUTEX_NORMAL mutex that was locked by another thread"
+                );
+            }
+            0
+        } else if kind == this.eval_libc_i32("PTHREAD_MUTEX_ERRORCHECK")?
+            || kind == this.eval_libc_i32("PTHREAD_MUTEX_RECURSIVE")?
+        {
+            if !held {
+                return this.eval_libc_i32("EPERM");
+            }
+            locked_count - 1
+        } else {
+            throw_ub_format!("called pthread_mutex_unlock on an unsupported type of mutex");
+        };
+
+        mutex_set_locked_count(this, mutex_op, Scalar::from_u32(new_count))?;
+        if new_count == 0 {
+            let location = object_location(this, mutex_op)?;
+            this.release_sync_clock(location);
+            this.machine.held_locks.release(location);
+        }
+        if new_count == 0
+            && robust
+            && mutex_get_robust_state(this, mutex_op)?.to_u32()? == MUTEX_INCONSISTENT
+        {
+            // The new owner did not mark the mutex as consistent before releasing it.
+            mutex_set_robust_state(this, mutex_op, Scalar::from_u32(MUTEX_NOT_RECOVERABLE))?;
+        }
+        Ok(0)
+    }
+
+    fn pthread_mutex_consistent(&mut self, mutex_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let kind = mutex_get_kind(this, mutex_op)?.not_undef()?.to_i32()?;
+        if kind & MUTEX_ROBUST_FLAG == 0 {
+            throw_ub_format!("called pthread_mutex_consistent on a mutex that is not robust");
+        }
+        // Only the thread that took the mutex over from its terminated owner can repair it.
+        if mutex_get_robust_state(this, mutex_op)?.to_u32()? != MUTEX_INCONSISTENT
+            || !mutex_held_by_active_thread(this, mutex_op)?
+        {
+            return this.eval_libc_i32("EINVAL");
+        }
+        mutex_set_robust_state(this, mutex_op, Scalar::from_u32(MUTEX_CONSISTENT))?;
+        Ok(0)
+    }
+
+    fn pthread_mutex_destroy(&mut self, mutex_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if mutex_get_locked_count(this, mutex_op)?.to_u32()? != 0 {
+            throw_ub_format!("destroyed a locked mutex");
+        }
+
+        mutex_set_kind(this, mutex_op, ScalarMaybeUndef::Undef)?;
+        mutex_set_locked_count(this, mutex_op, ScalarMaybeUndef::Undef)?;
+        mutex_set_owner(this, mutex_op, ScalarMaybeUndef::Undef)?;
+        mutex_set_robust_state(this, mutex_op, ScalarMaybeUndef::Undef)?;
+
+        Ok(0)
+    }
+
+    fn pthread_condattr_init(&mut self, attr_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let default_clock_id = this.eval_libc("CLOCK_REALTIME")?;
+        condattr_set_clock_id(this, attr_op, default_clock_id)?;
+
+        Ok(0)
+    }
+
+    fn pthread_condattr_setclock(
+        &mut self,
+        attr_op: OpTy<'tcx, Tag>,
+        clock_id_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let clock_id = this.read_scalar(clock_id_op)?.not_undef()?;
+        if clock_id == this.eval_libc("CLOCK_REALTIME")?
+            || clock_id == this.eval_libc("CLOCK_MONOTONIC")?
+        {
+            condattr_set_clock_id(this, attr_op, clock_id)?;
+        } else {
+            let einval = this.eval_libc_i32("EINVAL")?;
+            return Ok(einval);
+        }
+
+        Ok(0)
+    }
+
+    fn pthread_condattr_getclock(
+        &mut self,
+        attr_op: OpTy<'tcx, Tag>,
+        clk_id_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let clock_id = condattr_get_clock_id(this, attr_op)?;
+        this.write_scalar(clock_id, this.deref_operand(clk_id_op)?.into())?;
+
+        Ok(0)
+    }
+
+    fn pthread_condattr_destroy(&mut self, attr_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        condattr_set_clock_id(this, attr_op, ScalarMaybeUndef::Undef)?;
+
+        Ok(0)
+    }
+
+    fn pthread_cond_init(
+        &mut self,
+        cond_op: OpTy<'tcx, Tag>,
+        attr_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let attr = this.read_scalar(attr_op)?.not_undef()?;
+        let clock_id = if this.is_null(attr)? {
+            this.eval_libc("CLOCK_REALTIME")?
+        } else {
+            condattr_get_clock_id(this, attr_op)?.not_undef()?
+        };
+
+        cond_set_clock_id(this, cond_op, clock_id)?;
+
+        Ok(0)
+    }
+
+    fn pthread_cond_signal(&mut self, cond_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let cond = object_location(this, cond_op)?;
+        cond_signal(this, cond);
+
+        Ok(0)
+    }
+
+    fn pthread_cond_broadcast(&mut self, cond_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let cond = object_location(this, cond_op)?;
+        cond_broadcast(this, cond);
+
+        Ok(0)
+    }
+
+    /// Returns `None` if a thread that may signal the condition variable was started. The call
+    /// has to be repeated once that thread finished.
+    fn pthread_cond_wait(
+        &mut self,
+        cond_op: OpTy<'tcx, Tag>,
+        mutex_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        cond_wait(this, cond_op, mutex_op, None)
+    }
+
+    /// Returns `None` if a thread that may signal the condition variable was started. The call
+    /// has to be repeated once that thread finished.
+    fn pthread_cond_timedwait(
+        &mut self,
+        cond_op: OpTy<'tcx, Tag>,
+        mutex_op: OpTy<'tcx, Tag>,
+        abstime_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        // The deadline is measured by the clock the condition variable was created with.
+        let clock_id = cond_get_clock_id(this, cond_op)?.not_undef()?.to_i32()?;
+        if this.read_clock(clock_id)?.is_none() {
+            throw_ub_format!(
+                "called pthread_cond_timedwait on a condition variable with an invalid clock"
+            );
+        }
+        let deadline = match this.read_timespec(this.deref_operand(abstime_op)?)? {
+            Some(deadline) => deadline,
+            None => return Ok(Some(this.eval_libc_i32("EINVAL")?)),
+        };
+
+        cond_wait(this, cond_op, mutex_op, Some((clock_id, deadline)))
+    }
+
+    fn pthread_cond_destroy(&mut self, cond_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let cond = object_location(this, cond_op)?;
+        if this.machine.cond_waiters.iter().any(|waiter| waiter.cond == cond) {
+            throw_ub_format!("destroyed a condition variable that a thread is waiting for");
+        }
+
+        cond_set_clock_id(this, cond_op, ScalarMaybeUndef::Undef)?;
+
+        Ok(0)
+    }
+
+    fn pthread_rwlock_init(
+        &mut self,
+        rwlock_op: OpTy<'tcx, Tag>,
+        _attr_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        rwlock_set_writer(this, rwlock_op, None)?;
+        rwlock_set_readers(this, rwlock_op, Scalar::from_u32(0))?;
+        let location = object_location(this, rwlock_op)?;
+        this.machine.rwlock_readers.0.remove(&location);
+
+        Ok(0)
+    }
+
+    fn pthread_rwlock_rdlock(&mut self, rwlock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        // Writers are preferred over readers: a reader has to wait while a writer waits for the
+        // lock. However, a thread that has to wait deadlocks right away, because the threads that
+        // could release the lock are suspended further down the stack or terminated. So no writer
+        // is ever waiting here.
+        let active = this.machine.threads.active_thread();
+        match rwlock_get_writer(this, rwlock_op)? {
+            None => rwlock_acquire_read(this, rwlock_op),
+            Some(writer) if writer == active => this.eval_libc_i32("EDEADLK"),
+            Some(_) => this.deadlock(BlockReason::RwLock),
+        }
+    }
+
+    fn pthread_rwlock_tryrdlock(&mut self, rwlock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        match rwlock_get_writer(this, rwlock_op)? {
+            None => rwlock_acquire_read(this, rwlock_op),
+            Some(_) => this.eval_libc_i32("EBUSY"),
+        }
+    }
+
+    fn pthread_rwlock_wrlock(&mut self, rwlock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread();
+        match rwlock_get_writer(this, rwlock_op)? {
+            Some(writer) if writer == active => this.eval_libc_i32("EDEADLK"),
+            Some(_) => this.deadlock(BlockReason::RwLock),
+            // Even if the active thread is the only reader, it would wait for itself.
+            None if rwlock_get_readers(this, rwlock_op)?.to_u32()? != 0 => {
+                this.deadlock(BlockReason::RwLock)
+            }
+            None => {
+                rwlock_set_writer(this, rwlock_op, Some(active))?;
+                let location = object_location(this, rwlock_op)?;
+                this.acquire_sync_clock(location);
+                Ok(0)
+            }
+        }
+    }
+
+    fn pthread_rwlock_trywrlock(&mut self, rwlock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if rwlock_get_writer(this, rwlock_op)?.is_some()
+            || rwlock_get_readers(this, rwlock_op)?.to_u32()? != 0
+        {
+            return this.eval_libc_i32("EBUSY");
+        }
+        let active = this.machine.threads.active_thread();
+        rwlock_set_writer(this, rwlock_op, Some(active))?;
+        let location = object_location(this, rwlock_op)?;
+        this.acquire_sync_clock(location);
+        Ok(0)
+    }
+
+    fn pthread_rwlock_unlock(&mut self, rwlock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread();
+        let location = object_location(this, rwlock_op)?;
+        if rwlock_get_writer(this, rwlock_op)? == Some(active) {
+            rwlock_set_writer(this, rwlock_op, None)?;
+            this.release_sync_clock(location);
+            return Ok(0);
+        }
+        let readers = match this.machine.rwlock_readers.0.get_mut(&location) {
+            Some(readers) => readers,
+            None => return this.eval_libc_i32("EPERM"),
+        };
+        match readers.get_mut(&active) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                readers.remove(&active);
+                if readers.is_empty() {
+                    this.machine.rwlock_readers.0.remove(&location);
+                }
+            }
+            None => return this.eval_libc_i32("EPERM"),
+        }
+        let readers = rwlock_get_readers(this, rwlock_op)?.to_u32()?;
+        rwlock_set_readers(this, rwlock_op, Scalar::from_u32(readers - 1))?;
+        this.release_sync_clock(location);
+        Ok(0)
+    }
+
+    fn pthread_rwlock_destroy(&mut self, rwlock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if rwlock_get_writer(this, rwlock_op)?.is_some()
+            || rwlock_get_readers(this, rwlock_op)?.to_u32()? != 0
+        {
+            throw_ub_format!("destroyed a locked rwlock");
+        }
+
+        let layout = this.layout_of(this.tcx.types.u32)?;
+        let undef = ScalarMaybeUndef::Undef;
+        set_at_offset(this, rwlock_op, 4, undef, layout, PTHREAD_RWLOCK_T_MIN_SIZE)?;
+        rwlock_set_readers(this, rwlock_op, undef)?;
+
+        Ok(0)
+    }
+
+    /// `futex(addr, FUTEX_WAIT, val, timeout)`. This is called again whenever a thread that was
+    /// started to wake the caller up finished. Returns `None` if such a thread was started.
+    fn futex_wait(
+        &mut self,
+        addr_op: OpTy<'tcx, Tag>,
+        val_op: OpTy<'tcx, Tag>,
+        timeout_op: Option<OpTy<'tcx, Tag>>,
+    ) -> InterpResult<'tcx, Option<i64>> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread();
+        let idx = match this.machine.futex_waiters.iter().position(|w| w.thread == active) {
+            Some(idx) => idx,
+            None => {
+                // The thread only starts waiting if the futex word still has the expected value.
+                let addr = this.deref_operand(addr_op)?;
+                let i32_layout = this.layout_of(this.tcx.types.i32)?;
+                let word = addr.offset(Size::ZERO, MemPlaceMeta::None, i32_layout, this)?;
+                let val = this.read_scalar(val_op)?.to_i32()?;
+                // The kernel reads the futex word atomically.
+                let current = this.allow_data_races_ref(|this| this.read_scalar(word.into()))?;
+                this.validate_atomic_load(word, AtomicOrdering::Relaxed)?;
+                if current.to_i32()? != val {
+                    let eagain = this.eval_libc("EAGAIN")?;
+                    this.set_last_error(eagain)?;
+                    return Ok(Some(-1));
+                }
+                let timeout_op = match timeout_op {
+                    Some(op) if !this.is_null(this.read_scalar(op)?.not_undef()?)? => Some(op),
+                    _ => None,
+                };
+                let timeout = match timeout_op {
+                    Some(timeout_op) => {
+                        let timeout = this.deref_operand(timeout_op)?;
+                        match this.read_timespec(timeout)? {
+                            Some(timeout) => Some(timeout),
+                            None => {
+                                let einval = this.eval_libc("EINVAL")?;
+                                this.set_last_error(einval)?;
+                                return Ok(Some(-1));
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                // The timeout is relative, and measured on the monotonic clock.
+                let deadline = timeout.map(|timeout| this.machine.clock.monotonic() + timeout);
+                let addr = object_location(this, addr_op)?;
+                let waiter = FutexWaiter { thread: active, addr, deadline, woken: false };
+                this.machine.futex_waiters.push(waiter);
+                this.machine.futex_waiters.len() - 1
+            }
+        };
+
+        let now = this.machine.clock.monotonic();
+        let waiter = &this.machine.futex_waiters[idx];
+        let woken = waiter.woken;
+        let remaining = waiter.deadline.map(|deadline| {
+            deadline.checked_sub(now).unwrap_or_default()
+        });
+        let result = match this.block_active_thread(woken, remaining, BlockReason::Futex)? {
+            BlockResult::Woken => {
+                // Whoever woke us up happens before us.
+                let addr = this.machine.futex_waiters[idx].addr;
+                this.acquire_sync_clock(addr);
+                0
+            }
+            BlockResult::TimedOut => {
+                let etimedout = this.eval_libc("ETIMEDOUT")?;
+                this.set_last_error(etimedout)?;
+                -1
+            }
+            BlockResult::Retry => return Ok(None),
+        };
+        this.machine.futex_waiters.remove(idx);
+        Ok(Some(result))
+    }
+
+    /// `futex(addr, FUTEX_WAKE, count)`: wakes up at most `count` of the threads that wait for the
+    /// futex, in the order they started waiting, and returns how many were woken up.
+    fn futex_wake(
+        &mut self,
+        addr_op: OpTy<'tcx, Tag>,
+        count_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let addr = object_location(this, addr_op)?;
+        // A negative count wakes up nobody, like on Linux.
+        let count = usize::try_from(this.read_scalar(count_op)?.to_i32()?).unwrap_or(0);
+        Ok(futex_wake_waiters(this, addr, count) as i64)
+    }
+
+    fn sem_init(
+        &mut self,
+        sem_op: OpTy<'tcx, Tag>,
+        _pshared_op: OpTy<'tcx, Tag>,
+        value_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        // There are no other processes to share the semaphore with, so `pshared` does not matter.
+        let value = this.read_scalar(value_op)?.to_u32()?;
+        if value > SEM_VALUE_MAX {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+        sem_set_value(this, sem_op, Scalar::from_u32(value))?;
+
+        Ok(0)
+    }
+
+    /// Returns `None` if a thread that may post the semaphore was started. The call has to be
+    /// repeated once that thread finished.
+    fn sem_wait(&mut self, sem_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        sem_wait_deadline(this, sem_op, None)
+    }
+
+    /// Returns `None` if a thread that may post the semaphore was started. The call has to be
+    /// repeated once that thread finished.
+    fn sem_timedwait(
+        &mut self,
+        sem_op: OpTy<'tcx, Tag>,
+        abstime_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        // The deadline is measured on the realtime clock.
+        let deadline = match this.read_timespec(this.deref_operand(abstime_op)?)? {
+            Some(deadline) => deadline,
+            None => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(Some(-1));
+            }
+        };
+
+        sem_wait_deadline(this, sem_op, Some(deadline))
+    }
+
+    fn sem_trywait(&mut self, sem_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let value = sem_get_value(this, sem_op)?.to_u32()?;
+        if value == 0 {
+            let eagain = this.eval_libc("EAGAIN")?;
+            this.set_last_error(eagain)?;
+            return Ok(-1);
+        }
+        sem_set_value(this, sem_op, Scalar::from_u32(value - 1))?;
+        let sem = object_location(this, sem_op)?;
+        this.acquire_sync_clock(sem);
+        Ok(0)
+    }
+
+    /// Wakes up the thread that has waited for the semaphore the longest, if there is one, and
+    /// increments the semaphore otherwise.
+    fn sem_post(&mut self, sem_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let sem = object_location(this, sem_op)?;
+        let waiter = this
+            .machine
+            .sem_waiters
+            .iter_mut()
+            .find(|waiter| waiter.sem == sem && !waiter.woken);
+        match waiter {
+            Some(waiter) => waiter.woken = true,
+            None => {
+                let value = sem_get_value(this, sem_op)?.to_u32()?;
+                if value == SEM_VALUE_MAX {
+                    let eoverflow = this.eval_libc("EOVERFLOW")?;
+                    this.set_last_error(eoverflow)?;
+                    return Ok(-1);
+                }
+                sem_set_value(this, sem_op, Scalar::from_u32(value + 1))?;
+            }
+        }
+        this.release_sync_clock(sem);
+        Ok(0)
+    }
+
+    fn sem_getvalue(
+        &mut self,
+        sem_op: OpTy<'tcx, Tag>,
+        value_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let value = sem_get_value(this, sem_op)?.to_u32()?;
+        let value_place = this.deref_operand(value_op)?;
+        this.write_scalar(Scalar::from_u32(value), value_place.into())?;
+        Ok(0)
+    }
+
+    fn sem_destroy(&mut self, sem_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let sem = object_location(this, sem_op)?;
+        if this.machine.sem_waiters.iter().any(|waiter| waiter.sem == sem) {
+            throw_ub_format!("destroyed a semaphore that a thread is waiting for");
+        }
+
+        sem_set_value(this, sem_op, ScalarMaybeUndef::Undef)?;
+
+        Ok(0)
+    }
+
+    fn pthread_barrier_init(
+        &mut self,
+        barrier_op: OpTy<'tcx, Tag>,
+        _attr_op: OpTy<'tcx, Tag>,
+        count_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        // The only attribute is whether the barrier is shared between processes, which does not
+        // matter to us.
+        let count = this.read_scalar(count_op)?.to_u32()?;
+        if count == 0 {
+            return this.eval_libc_i32("EINVAL");
+        }
+        barrier_set_count(this, barrier_op, Scalar::from_u32(count))?;
+        barrier_set_arrived(this, barrier_op, Scalar::from_u32(0))?;
+
+        Ok(0)
+    }
+
+    /// Returns `None` if a thread that may arrive at the barrier was started. The call has to be
+    /// repeated once that thread finished.
+    fn pthread_barrier_wait(
+        &mut self,
+        barrier_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread();
+        let barrier = object_location(this, barrier_op)?;
+        let idx = match this.machine.barrier_waiters.iter().position(|w| w.thread == active) {
+            Some(idx) => idx,
+            None => {
+                // Everything that happens before a thread arrives at the barrier happens before
+                // all the threads continue.
+                this.release_sync_clock(barrier);
+                let count = barrier_get_count(this, barrier_op)?.to_u32()?;
+                let arrived = barrier_get_arrived(this, barrier_op)?.to_u32()? + 1;
+                if arrived == count {
+                    // The last thread to arrive lets the others continue, and is the one that
+                    // gets `PTHREAD_BARRIER_SERIAL_THREAD`.
+                    barrier_set_arrived(this, barrier_op, Scalar::from_u32(0))?;
+                    for waiter in this.machine.barrier_waiters.iter_mut() {
+                        if waiter.barrier == barrier {
+                            waiter.woken = true;
+                        }
+                    }
+                    this.acquire_sync_clock(barrier);
+                    return Ok(Some(this.eval_libc_i32("PTHREAD_BARRIER_SERIAL_THREAD")?));
+                }
+                barrier_set_arrived(this, barrier_op, Scalar::from_u32(arrived))?;
+                let waiter = BarrierWaiter { thread: active, barrier, woken: false };
+                this.machine.barrier_waiters.push(waiter);
+                this.machine.barrier_waiters.len() - 1
+            }
+        };
+
+        let woken = this.machine.barrier_waiters[idx].woken;
+        match this.block_active_thread(woken, None, BlockReason::Barrier)? {
+            BlockResult::Woken => {}
+            BlockResult::Retry => return Ok(None),
+            BlockResult::TimedOut => unreachable!("waiting at a barrier has no timeout"),
+        }
+        this.machine.barrier_waiters.remove(idx);
+        this.acquire_sync_clock(barrier);
+        Ok(Some(0))
+    }
+
+    fn pthread_barrier_destroy(&mut self, barrier_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let barrier = object_location(this, barrier_op)?;
+        if this.machine.barrier_waiters.iter().any(|waiter| waiter.barrier == barrier) {
+            throw_ub_format!("destroyed a barrier that a thread is waiting at");
+        }
+
+        barrier_set_count(this, barrier_op, ScalarMaybeUndef::Undef)?;
+        barrier_set_arrived(this, barrier_op, ScalarMaybeUndef::Undef)?;
+
+        Ok(0)
+    }
+
+    /// Returns `None` if the frame of the initialization routine was pushed. The call has to be
+    /// repeated once that frame returns.
+    fn pthread_once(
+        &mut self,
+        once_op: OpTy<'tcx, Tag>,
+        init_routine_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        // On macOS, the state comes after the signature.
+        let offset = if this.tcx.sess.target.target.target_os == "macos" { 8 } else { 0 };
+        let init_routine = this.read_scalar(init_routine_op)?.not_undef()?;
+        if run_once(this, once_op, offset, init_routine, None)? { Ok(Some(0)) } else { Ok(None) }
+    }
+
+    /// `dispatch_once_f(predicate, context, function)`. Returns `false` if the frame of `function`
+    /// was pushed; the call has to be repeated once that frame returns.
+    fn dispatch_once_f(
+        &mut self,
+        predicate_op: OpTy<'tcx, Tag>,
+        context_op: OpTy<'tcx, Tag>,
+        function_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        let context = this.read_scalar(context_op)?.not_undef()?;
+        let function = this.read_scalar(function_op)?.not_undef()?;
+        run_once(this, predicate_op, 0, function, Some(context))
+    }
+
+    fn initialize_critical_section(&mut self, section_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        critical_section_set_owner(this, section_op, Scalar::from_u32(0))?;
+        critical_section_set_count(this, section_op, Scalar::from_u32(0))
+    }
+
+    fn enter_critical_section(&mut self, section_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if critical_section_try_enter(this, section_op)? {
+            Ok(())
+        } else {
+            // The owner is suspended further down the stack, waiting for the active thread, or
+            // it terminated. Either way, it cannot leave the critical section anymore.
+            this.deadlock(BlockReason::Mutex)
+        }
+    }
+
+    fn try_enter_critical_section(&mut self, section_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        critical_section_try_enter(this, section_op)
+    }
+
+    fn leave_critical_section(&mut self, section_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if critical_section_get_owner(this, section_op)? != Some(this.machine.threads.active_thread()) {
+            throw_ub_format!("left a critical section that the thread did not enter");
+        }
+        let count = critical_section_get_count(this, section_op)?.to_u32()? - 1;
+        critical_section_set_count(this, section_op, Scalar::from_u32(count))?;
+        if count == 0 {
+            critical_section_set_owner(this, section_op, Scalar::from_u32(0))?;
+            let location = object_location(this, section_op)?;
+            this.release_sync_clock(location);
+            this.machine.held_locks.release(location);
+        }
+        Ok(())
+    }
+
+    fn delete_critical_section(&mut self, section_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if critical_section_get_owner(this, section_op)?.is_some() {
+            throw_ub_format!("deleted a critical section that a thread is in");
+        }
+
+        critical_section_set_owner(this, section_op, ScalarMaybeUndef::Undef)?;
+        critical_section_set_count(this, section_op, ScalarMaybeUndef::Undef)
+    }
+
+    fn acquire_srwlock_exclusive(&mut self, lock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // SRW locks are not recursive, so a thread that already holds the lock waits for itself.
+        if srwlock_try_acquire_exclusive(this, lock_op)? {
+            Ok(())
+        } else {
+            this.deadlock(BlockReason::RwLock)
+        }
+    }
+
+    fn acquire_srwlock_shared(&mut self, lock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        if srwlock_try_acquire_shared(this, lock_op)? {
+            Ok(())
+        } else {
+            this.deadlock(BlockReason::RwLock)
+        }
+    }
+
+    fn try_acquire_srwlock_exclusive(&mut self, lock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        srwlock_try_acquire_exclusive(this, lock_op)
+    }
+
+    fn try_acquire_srwlock_shared(&mut self, lock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        srwlock_try_acquire_shared(this, lock_op)
+    }
+
+    fn release_srwlock_exclusive(&mut self, lock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        srwlock_release_exclusive(this, lock_op)
+    }
+
+    fn release_srwlock_shared(&mut self, lock_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        srwlock_release_shared(this, lock_op)
+    }
+
+    /// `SleepConditionVariableSRW(cond, lock, milliseconds, flags)`. Returns `None` if a thread
+    /// that may wake the caller up was started; the call has to be repeated once that thread
+    /// finished.
+    fn sleep_condition_variable_srw(
+        &mut self,
+        cond_op: OpTy<'tcx, Tag>,
+        lock_op: OpTy<'tcx, Tag>,
+        timeout_op: OpTy<'tcx, Tag>,
+        flags_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        // CONDITION_VARIABLE_LOCKMODE_SHARED
+        let lock = if this.read_scalar(flags_op)?.to_u32()? & 1 != 0 {
+            WindowsLock::SrwShared
+        } else {
+            WindowsLock::SrwExclusive
+        };
+        match windows_cond_wait(this, cond_op, lock_op, lock, timeout_op)? {
+            Some(true) => Ok(Some(1)),
+            Some(false) => {
+                this.set_last_error(Scalar::from_u32(ERROR_TIMEOUT))?;
+                Ok(Some(0))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// `SleepConditionVariableCS(cond, section, milliseconds)`. Returns `None` if a thread that
+    /// may wake the caller up was started; the call has to be repeated once that thread finished.
+    fn sleep_condition_variable_cs(
+        &mut self,
+        cond_op: OpTy<'tcx, Tag>,
+        section_op: OpTy<'tcx, Tag>,
+        timeout_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        let lock = WindowsLock::CriticalSection;
+        match windows_cond_wait(this, cond_op, section_op, lock, timeout_op)? {
+            Some(true) => Ok(Some(1)),
+            Some(false) => {
+                this.set_last_error(Scalar::from_u32(ERROR_TIMEOUT))?;
+                Ok(Some(0))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn wake_condition_variable(&mut self, cond_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let cond = object_location(this, cond_op)?;
+        cond_signal(this, cond);
+        Ok(())
+    }
+
+    fn wake_all_condition_variable(&mut self, cond_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let cond = object_location(this, cond_op)?;
+        cond_broadcast(this, cond);
+        Ok(())
+    }
+
+    /// `WaitOnAddress(address, compare_address, size, milliseconds)`, the Windows counterpart of
+    /// `FUTEX_WAIT`: the threads waiting for an address are kept with those waiting for a futex.
+    /// This is called again whenever a thread that was started to wake the caller up finished.
+    /// Returns `None` if such a thread was started.
+    fn wait_on_address(
+        &mut self,
+        addr_op: OpTy<'tcx, Tag>,
+        compare_op: OpTy<'tcx, Tag>,
+        size_op: OpTy<'tcx, Tag>,
+        timeout_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread();
+        let idx = match this.machine.futex_waiters.iter().position(|w| w.thread == active) {
+            Some(idx) => idx,
+            None => {
+                let ty = match this.read_scalar(size_op)?.to_machine_usize(this)? {
+                    1 => this.tcx.types.u8,
+                    2 => this.tcx.types.u16,
+                    4 => this.tcx.types.u32,
+                    8 => this.tcx.types.u64,
+                    _ => {
+                        // ERROR_INVALID_PARAMETER
+                        this.set_last_error(Scalar::from_u32(87))?;
+                        return Ok(Some(0));
+                    }
+                };
+                let layout = this.layout_of(ty)?;
+                // The thread only starts waiting if the value at the address is still the one
+                // that was passed in.
+                let addr = this.deref_operand(addr_op)?;
+                let value = addr.offset(Size::ZERO, MemPlaceMeta::None, layout, this)?;
+                let current = this.allow_data_races_ref(|this| this.read_scalar(value.into()))?;
+                this.validate_atomic_load(value, AtomicOrdering::Relaxed)?;
+                let compare = this.deref_operand(compare_op)?;
+                let compare = compare.offset(Size::ZERO, MemPlaceMeta::None, layout, this)?;
+                let compare = this.read_scalar(compare.into())?;
+                if this.force_bits(current.not_undef()?, layout.size)?
+                    != this.force_bits(compare.not_undef()?, layout.size)?
+                {
+                    return Ok(Some(1));
+                }
+                let timeout_ms = this.read_scalar(timeout_op)?.to_u32()?;
+                // INFINITE
+                let deadline = if timeout_ms == u32::max_value() {
+                    None
+                } else {
+                    Some(this.machine.clock.monotonic() + Duration::from_millis(timeout_ms.into()))
+                };
+                let addr = object_location(this, addr_op)?;
+                let waiter = FutexWaiter { thread: active, addr, deadline, woken: false };
+                this.machine.futex_waiters.push(waiter);
+                this.machine.futex_waiters.len() - 1
+            }
+        };
+
+        let now = this.machine.clock.monotonic();
+        let waiter = &this.machine.futex_waiters[idx];
+        let woken = waiter.woken;
+        let remaining = waiter.deadline.map(|deadline| {
+            deadline.checked_sub(now).unwrap_or_default()
+        });
+        let result = match this.block_active_thread(woken, remaining, BlockReason::Futex)? {
+            BlockResult::Woken => {
+                // Whoever woke us up happens before us.
+                let addr = this.machine.futex_waiters[idx].addr;
+                this.acquire_sync_clock(addr);
+                1
+            }
+            BlockResult::TimedOut => {
+                this.set_last_error(Scalar::from_u32(ERROR_TIMEOUT))?;
+                0
+            }
+            BlockResult::Retry => return Ok(None),
+        };
+        this.machine.futex_waiters.remove(idx);
+        Ok(Some(result))
+    }
+
+    /// `WakeByAddressSingle(address)` and `WakeByAddressAll(address)`.
+    fn wake_by_address(&mut self, addr_op: OpTy<'tcx, Tag>, all: bool) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let addr = object_location(this, addr_op)?;
+        futex_wake_waiters(this, addr, if all { usize::max_value() } else { 1 });
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+//! Implement the pthread and Windows functions that create threads and wait for them on top of
+//! the threads of the machine, see `crate::thread`.
+
+use std::convert::TryFrom;
+use std::iter;
+use std::time::Duration;
+
+use rustc::ty::layout::LayoutOf;
+
+use crate::*;
+
+/// Thread handles on Windows are the thread id plus this value. Like the handles of pipes, they
+/// stay clear of the small values used for other fake handles.
+const THREAD_HANDLE_BASE: i64 = 0x4000_0000;
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    fn pthread_create(
+        &mut self,
+        thread_op: OpTy<'tcx, Tag>,
+        _attr_op: OpTy<'tcx, Tag>,
+        start_routine_op: OpTy<'tcx, Tag>,
+        arg_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let thread = this.deref_operand(thread_op)?;
+        let start_routine = this.read_scalar(start_routine_op)?.not_undef()?;
+        let arg = this.read_scalar(arg_op)?.not_undef()?;
+        let ret_layout = this.layout_of(this.tcx.mk_mut_ptr(this.tcx.mk_unit()))?;
+        let id = this.create_thread(start_routine, arg, ret_layout)?;
+        this.write_scalar(Scalar::from_uint(id.to_u32(), thread.layout.size), thread.into())?;
+        Ok(0)
+    }
+
+    /// Returns `None` if a frame of the joined thread was pushed because it did not terminate
+    /// yet. The call has to be repeated once that frame returns.
+    fn pthread_join(
+        &mut self,
+        thread_op: OpTy<'tcx, Tag>,
+        retval_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        let id = match this.read_thread_id(thread_op)? {
+            Some(id) => id,
+            None => return Ok(Some(this.eval_libc_i32("ESRCH")?)),
+        };
+        if let Some(error) = this.check_joinable(id)? {
+            return Ok(Some(error));
+        }
+        if this.wait_for_thread(id)? {
+            return Ok(None);
+        }
+
+        let ret = this.join_thread(id)?;
+        let retval = this.read_scalar(retval_op)?.not_undef()?;
+        if !this.is_null(retval)? {
+            let retval = this.deref_operand(retval_op)?;
+            this.write_scalar(ret, retval.into())?;
+        }
+        Ok(Some(0))
+    }
+
+    fn pthread_detach(&mut self, thread_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let id = match this.read_thread_id(thread_op)? {
+            Some(id) => id,
+            None => return this.eval_libc_i32("ESRCH"),
+        };
+        match this.machine.threads.get(id) {
+            None => this.eval_libc_i32("ESRCH"),
+            Some(thread) if thread.is_detached() => this.eval_libc_i32("EINVAL"),
+            Some(_) => {
+                this.detach_thread(id)?;
+                Ok(0)
+            }
+        }
+    }
+
+    fn pthread_self(&mut self, dest: PlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let id = this.machine.threads.active_thread();
+        this.write_scalar(Scalar::from_uint(id.to_u32(), dest.layout.size), dest)
+    }
+
+    /// Sets the name of the thread `thread_op`, or of the active thread if there is none like on
+    /// macOS.
+    fn pthread_setname_np(
+        &mut self,
+        thread_op: Option<OpTy<'tcx, Tag>>,
+        name_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let id = match thread_op {
+            Some(thread_op) => match this.read_thread_id(thread_op)? {
+                Some(id) if this.machine.threads.get(id).is_some() => id,
+                _ => return this.eval_libc_i32("ESRCH"),
+            },
+            None => this.machine.threads.active_thread(),
+        };
+        let name = this.read_scalar(name_op)?.not_undef()?;
+        let name = this.memory.read_c_str(name)?.to_owned();
+        // The limits include the null terminator.
+        let (max_len, error) = match this.tcx.sess.target.target.target_os.as_str() {
+            "macos" => (64, "ENAMETOOLONG"),
+            _ => (16, "ERANGE"),
+        };
+        if name.len() >= max_len {
+            return this.eval_libc_i32(error);
+        }
+        this.set_thread_name(id, name);
+        Ok(0)
+    }
+
+    fn pthread_getname_np(
+        &mut self,
+        thread_op: OpTy<'tcx, Tag>,
+        buf_op: OpTy<'tcx, Tag>,
+        len_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let thread = match this.read_thread_id(thread_op)? {
+            Some(id) => this.machine.threads.get(id),
+            None => None,
+        };
+        let name = match thread {
+            Some(thread) => thread.name().unwrap_or_default().to_owned(),
+            None => return this.eval_libc_i32("ESRCH"),
+        };
+        let buf = this.read_scalar(buf_op)?.not_undef()?;
+        let len = this.read_scalar(len_op)?.to_machine_usize(this)?;
+        if len <= name.len() as u64 {
+            return this.eval_libc_i32("ERANGE");
+        }
+        this.memory.write_bytes(buf, name.into_iter().chain(iter::once(0)))?;
+        Ok(0)
+    }
+
+    /// Implements the options of `prctl` that get and set the name of the active thread, which
+    /// std uses to name threads on Linux.
+    fn prctl(&mut self, args: &[OpTy<'tcx, Tag>]) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if args.len() < 2 {
+            throw_ub_format!("incorrect number of arguments for prctl: got {}", args.len());
+        }
+        let option = this.read_scalar(args[0])?.to_i32()?;
+        let active = this.machine.threads.active_thread();
+        if option == this.eval_libc_i32("PR_SET_NAME")? {
+            let name = this.read_scalar(args[1])?.not_undef()?;
+            // Longer names are cut off to fit 16 bytes with the null terminator.
+            let mut name = this.memory.read_c_str(name)?.to_owned();
+            name.truncate(15);
+            this.set_thread_name(active, name);
+        } else if option == this.eval_libc_i32("PR_GET_NAME")? {
+            let buf = this.read_scalar(args[1])?.not_undef()?;
+            let thread = this.machine.threads.get(active).unwrap();
+            let name = thread.name().unwrap_or_default().to_owned();
+            this.memory.write_bytes(buf, name.into_iter().chain(iter::once(0)))?;
+        } else {
+            throw_unsup_format!("Miri does not support prctl option {}", option);
+        }
+        Ok(0)
+    }
+
+    /// Reads a `pthread_t`. Returns `None` if it cannot be the id of any thread.
+    fn read_thread_id(
+        &mut self,
+        thread_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<ThreadId>> {
+        let this = self.eval_context_mut();
+
+        let id = this.read_scalar(thread_op)?.to_machine_usize(this)?;
+        Ok(u32::try_from(id).ok().map(ThreadId::from))
+    }
+
+    fn windows_create_thread(
+        &mut self,
+        _security_op: OpTy<'tcx, Tag>,
+        _stack_size_op: OpTy<'tcx, Tag>,
+        start_op: OpTy<'tcx, Tag>,
+        arg_op: OpTy<'tcx, Tag>,
+        flags_op: OpTy<'tcx, Tag>,
+        thread_id_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        let start = this.read_scalar(start_op)?.not_undef()?;
+        let arg = this.read_scalar(arg_op)?.not_undef()?;
+        // The only flag std passes is `STACK_SIZE_PARAM_IS_A_RESERVATION`, which we can ignore.
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+        if flags & 0x4 != 0 {
+            throw_unsup_format!("Miri does not support creating suspended threads");
+        }
+        let ret_layout = this.layout_of(this.tcx.types.u32)?;
+        let id = this.create_thread(start, arg, ret_layout)?;
+        let thread_id = this.read_scalar(thread_id_op)?.not_undef()?;
+        if !this.is_null(thread_id)? {
+            let thread_id = this.deref_operand(thread_id_op)?;
+            this.write_scalar(Scalar::from_u32(PID + id.to_u32()), thread_id.into())?;
+        }
+        let handle = THREAD_HANDLE_BASE + i64::from(id.to_u32());
+        Ok(Scalar::from_int(handle, this.pointer_size()))
+    }
+
+    /// Returns `None` if a frame of some thread was pushed because the thread did not terminate
+    /// yet. The call has to be repeated once that frame returns.
+    fn windows_wait_for_single_object(
+        &mut self,
+        handle_op: OpTy<'tcx, Tag>,
+        timeout_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<u32>> {
+        let this = self.eval_context_mut();
+
+        let id = match this.windows_read_thread_handle(handle_op)? {
+            Some(id) => id,
+            None => throw_unsup_format!("Miri only supports waiting for thread handles on Windows"),
+        };
+        let timeout_ms = this.read_scalar(timeout_op)?.to_u32()?;
+        // INFINITE
+        let terminated = if timeout_ms == u32::max_value() {
+            if this.wait_for_thread(id)? {
+                return Ok(None);
+            }
+            true
+        } else {
+            match this.wait_for_thread_until(id, Duration::from_millis(timeout_ms.into()))? {
+                Some(terminated) => terminated,
+                None => return Ok(None),
+            }
+        };
+        if terminated {
+            this.thread_joined(id);
+            // WAIT_OBJECT_0
+            Ok(Some(0))
+        } else {
+            // WAIT_TIMEOUT
+            Ok(Some(0x102))
+        }
+    }
+
+    /// Closes the handle if it is a thread handle, and returns whether it was.
+    fn windows_close_thread_handle(
+        &mut self,
+        handle_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        match this.windows_read_thread_handle(handle_op)? {
+            Some(id) => {
+                this.detach_thread(id)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Reads a Windows `HANDLE`. Returns the thread it refers to, if it is an open thread handle.
+    fn windows_read_thread_handle(
+        &mut self,
+        handle_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, Option<ThreadId>> {
+        let this = self.eval_context_mut();
+
+        let handle = this.read_scalar(handle_op)?.to_machine_isize(this)?;
+        let id = handle.checked_sub(THREAD_HANDLE_BASE).and_then(|id| u32::try_from(id).ok());
+        Ok(id.map(ThreadId::from).filter(|&id| match this.machine.threads.get(id) {
+            Some(thread) => !thread.is_detached() && id != ThreadId::MAIN,
+            None => false,
+        }))
+    }
+}
@@ -1,22 +1,70 @@
+use std::convert::TryFrom;
+use std::iter;
 use std::time::{Duration, SystemTime};
 
 use crate::stacked_borrows::Tag;
 use crate::*;
 use helpers::immty_from_int_checked;
 
-// Returns the time elapsed between now and the unix epoch as a `Duration`.
-fn get_time<'tcx>() -> InterpResult<'tcx, Duration> {
-    system_time_to_duration(&SystemTime::now())
-}
-
 /// Returns the time elapsed between the provided time and the unix epoch as a `Duration`.
 pub fn system_time_to_duration<'tcx>(time: &SystemTime) -> InterpResult<'tcx, Duration> {
     time.duration_since(SystemTime::UNIX_EPOCH)
         .map_err(|_| err_unsup_format!("Times before the Unix epoch are not supported").into())
 }
 
+/// The unit of `clock_t` values returned by `times`, as reported by `sysconf(_SC_CLK_TCK)`.
+pub const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+/// The number of 100ns intervals between 1601-01-01, where Windows `FILETIME`s start counting,
+/// and the unix epoch.
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+/// The number of 100ns intervals in a second.
+const FILETIME_INTERVALS_PER_SEC: u64 = 10_000_000;
+
+/// Converts the time elapsed since the unix epoch into a Windows `FILETIME`. Returns `None` if
+/// the result does not fit into 64 bits.
+pub fn duration_to_filetime(duration: Duration) -> Option<u64> {
+    duration
+        .as_secs()
+        .checked_mul(FILETIME_INTERVALS_PER_SEC)?
+        .checked_add(u64::from(duration.subsec_nanos()) / 100)?
+        .checked_add(FILETIME_UNIX_EPOCH)
+}
+
+/// Converts a Windows `FILETIME` into the time elapsed since the unix epoch. Returns `None` if
+/// the `FILETIME` lies before the unix epoch.
+pub fn filetime_to_duration(filetime: u64) -> Option<Duration> {
+    let intervals = filetime.checked_sub(FILETIME_UNIX_EPOCH)?;
+    let secs = intervals / FILETIME_INTERVALS_PER_SEC;
+    let nanos = (intervals % FILETIME_INTERVALS_PER_SEC) * 100;
+    Some(Duration::new(secs, nanos as u32))
+}
+
+/// Converts `time` into a Windows `FILETIME`. Fails if `time` happens before the unix epoch or
+/// too far in the future.
+pub fn system_time_to_filetime<'tcx>(time: &SystemTime) -> InterpResult<'tcx, u64> {
+    let duration = system_time_to_duration(time)?;
+    duration_to_filetime(duration).ok_or_else(|| {
+        err_unsup_format!("{:?} cannot be represented as a `FILETIME`", time).into()
+    })
+}
+
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Reads the clock identified by `clk_id`. Returns `None` if there is no such clock.
+    fn read_clock(&mut self, clk_id: i32) -> InterpResult<'tcx, Option<Duration>> {
+        let this = self.eval_context_mut();
+
+        // With isolation enabled, both clocks are served by the virtual clock.
+        if clk_id == this.eval_libc_i32("CLOCK_REALTIME")? {
+            Ok(Some(this.machine.clock.realtime()?))
+        } else if clk_id == this.eval_libc_i32("CLOCK_MONOTONIC")? {
+            Ok(Some(this.machine.clock.monotonic()))
+        } else {
+            Ok(None)
+        }
+    }
+
     // Foreign function used by linux
     fn clock_gettime(
         &mut self,
@@ -25,18 +73,18 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("clock_gettime")?;
-
         let clk_id = this.read_scalar(clk_id_op)?.to_i32()?;
-        if clk_id != this.eval_libc_i32("CLOCK_REALTIME")? {
-            let einval = this.eval_libc("EINVAL")?;
-            this.set_last_error(einval)?;
-            return Ok(-1);
-        }
+        let duration = match this.read_clock(clk_id)? {
+            Some(duration) => duration,
+            None => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
 
         let tp = this.deref_operand(tp_op)?;
 
-        let duration = get_time()?;
         let tv_sec = duration.as_secs() as i128;
         let tv_nsec = duration.subsec_nanos() as i128;
 
@@ -49,6 +97,132 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         Ok(0)
     }
+    // Foreign function used by linux
+    fn clock_getres(
+        &mut self,
+        clk_id_op: OpTy<'tcx, Tag>,
+        res_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let clk_id = this.read_scalar(clk_id_op)?.to_i32()?;
+        if this.read_clock(clk_id)?.is_none() {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        }
+
+        // A null `res` is allowed; the caller then only checks whether the clock exists.
+        let res = this.read_scalar(res_op)?.not_undef()?;
+        if !this.is_null(res)? {
+            // Both the host and the virtual clock are reported with nanosecond resolution.
+            let imms = [
+                immty_from_int_checked(0, this.libc_ty_layout("time_t")?)?,
+                immty_from_int_checked(1, this.libc_ty_layout("c_long")?)?,
+            ];
+            let res = this.deref_operand(res_op)?;
+            this.write_packed_immediates(res, &imms)?;
+        }
+
+        Ok(0)
+    }
+
+    // Foreign function used by linux
+    fn clock_nanosleep(
+        &mut self,
+        clk_id_op: OpTy<'tcx, Tag>,
+        flags_op: OpTy<'tcx, Tag>,
+        req_op: OpTy<'tcx, Tag>,
+        _rem_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        // Unlike most functions, this one returns the error number instead of setting `errno`.
+        let clk_id = this.read_scalar(clk_id_op)?.to_i32()?;
+        let now = match this.read_clock(clk_id)? {
+            Some(now) => now,
+            None => return this.eval_libc_i32("EINVAL"),
+        };
+        let flags = this.read_scalar(flags_op)?.to_i32()?;
+        let req = match this.read_timespec(this.deref_operand(req_op)?)? {
+            Some(req) => req,
+            None => return this.eval_libc_i32("EINVAL"),
+        };
+
+        let duration = if flags & this.eval_libc_i32("TIMER_ABSTIME")? != 0 {
+            // `req` is a deadline; if it already passed, we return immediately.
+            req.checked_sub(now).unwrap_or(Duration::from_secs(0))
+        } else {
+            req
+        };
+
+        // Nothing can interrupt the sleep, so the remaining time (`rem`) is never written.
+        this.machine.clock.sleep(duration);
+
+        Ok(0)
+    }
+
+    fn getrusage(
+        &mut self,
+        who_op: OpTy<'tcx, Tag>,
+        usage_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let who = this.read_scalar(who_op)?.to_i32()?;
+        let user_time = if who == this.eval_libc_i32("RUSAGE_SELF")?
+            || (this.tcx.sess.target.target.target_os == "linux"
+                && who == this.eval_libc_i32("RUSAGE_THREAD")?)
+        {
+            cpu_time(this.machine.basic_block_count)
+        } else if who == this.eval_libc_i32("RUSAGE_CHILDREN")? {
+            // There are no child processes.
+            Duration::from_secs(0)
+        } else {
+            let einval = this.eval_libc("EINVAL")?;
+            this.set_last_error(einval)?;
+            return Ok(-1);
+        };
+
+        // All the counters (page faults, context switches, ...) and the system time are zero.
+        let usage = this.deref_operand(usage_op)?;
+        let zeros = iter::repeat(0u8).take(usage.layout.size.bytes() as usize);
+        this.memory.write_bytes(usage.ptr, zeros)?;
+
+        let ru_utime = this.mplace_field(usage, 0)?;
+        let imms = [
+            immty_from_int_checked(user_time.as_secs(), this.libc_ty_layout("time_t")?)?,
+            immty_from_int_checked(user_time.subsec_micros(), this.libc_ty_layout("suseconds_t")?)?,
+        ];
+        this.write_packed_immediates(ru_utime, &imms)?;
+
+        Ok(0)
+    }
+
+    fn times(&mut self, buf_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+
+        let to_ticks = |duration: Duration| {
+            duration.as_secs() * CLOCK_TICKS_PER_SEC
+                + u64::from(duration.subsec_nanos()) * CLOCK_TICKS_PER_SEC / 1_000_000_000
+        };
+        let user_ticks = to_ticks(cpu_time(this.machine.basic_block_count));
+
+        let clock_t = this.libc_ty_layout("clock_t")?;
+        let imms = [
+            immty_from_int_checked(user_ticks, clock_t)?, // tms_utime
+            immty_from_int_checked(0, clock_t)?, // tms_stime
+            immty_from_int_checked(0, clock_t)?, // tms_cutime
+            immty_from_int_checked(0, clock_t)?, // tms_cstime
+        ];
+        let buf = this.deref_operand(buf_op)?;
+        this.write_packed_immediates(buf, &imms)?;
+
+        // The return value is the elapsed real time since an arbitrary point in the past.
+        let elapsed = to_ticks(this.machine.clock.monotonic());
+        i64::try_from(elapsed).map_err(|_| err_unsup_format!("the elapsed time overflowed `clock_t`").into())
+    }
+
     // Foreign function used by generic unix (in particular macOS)
     fn gettimeofday(
         &mut self,
@@ -57,7 +231,6 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
     ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        this.check_no_isolation("gettimeofday")?;
         // Using tz is obsolete and should always be null
         let tz = this.read_scalar(tz_op)?.not_undef()?;
         if !this.is_null(tz)? {
@@ -68,7 +241,7 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         let tv = this.deref_operand(tv_op)?;
 
-        let duration = get_time()?;
+        let duration = this.machine.clock.realtime()?;
         let tv_sec = duration.as_secs() as i128;
         let tv_usec = duration.subsec_micros() as i128;
 
@@ -81,4 +254,147 @@ pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
         Ok(0)
     }
+
+    // Foreign function used by macOS
+    fn mach_absolute_time(&self) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+        this.assert_platform("macos", "mach_absolute_time");
+
+        // We report the time in nanoseconds, see `mach_timebase_info`.
+        let duration = this.machine.clock.monotonic();
+        u64::try_from(duration.as_nanos()).map_err(|_| {
+            err_unsup_format!("programs running longer than 2^64 nanoseconds are not supported")
+                .into()
+        })
+    }
+
+    // Foreign function used by macOS
+    fn mach_timebase_info(&mut self, info_op: OpTy<'tcx, Tag>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        this.assert_platform("macos", "mach_timebase_info");
+
+        let info = this.deref_operand(info_op)?;
+
+        // `mach_absolute_time` counts nanoseconds, so the ratio is 1/1.
+        let imms = [
+            immty_from_int_checked(1u32, this.libc_ty_layout("c_uint")?)?,
+            immty_from_int_checked(1u32, this.libc_ty_layout("c_uint")?)?,
+        ];
+        this.write_packed_immediates(info, &imms)?;
+
+        Ok(0) // KERN_SUCCESS
+    }
+
+    // Foreign function used by Windows
+    fn windows_get_system_time_as_file_time(
+        &mut self,
+        filetime_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.assert_platform("windows", "GetSystemTimeAsFileTime");
+
+        let duration = this.machine.clock.realtime()?;
+        let filetime = duration_to_filetime(duration).ok_or_else(|| {
+            err_unsup_format!("{:?} after the unix epoch cannot be represented as a `FILETIME`", duration)
+        })?;
+
+        let dword = this.layout_of(this.tcx.types.u32)?;
+        let imms = [
+            immty_from_int_checked(filetime & 0xFFFF_FFFF, dword)?, // dwLowDateTime
+            immty_from_int_checked(filetime >> 32, dword)?, // dwHighDateTime
+        ];
+        let filetime_place = this.deref_operand(filetime_op)?;
+        this.write_packed_immediates(filetime_place, &imms)?;
+
+        Ok(())
+    }
+
+    // Foreign function used by Windows
+    fn windows_query_performance_counter(
+        &mut self,
+        count_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        this.assert_platform("windows", "QueryPerformanceCounter");
+
+        // We count nanoseconds, see `windows_query_performance_frequency`.
+        let duration = this.machine.clock.monotonic();
+        let count = i64::try_from(duration.as_nanos()).map_err(|_| {
+            err_unsup_format!("programs running longer than 2^63 nanoseconds are not supported")
+        })?;
+        let count_place = this.deref_operand(count_op)?;
+        this.write_scalar(Scalar::from_i64(count), count_place.into())?;
+
+        Ok(1) // TRUE
+    }
+
+    // Foreign function used by Windows
+    fn windows_query_performance_frequency(
+        &mut self,
+        frequency_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        this.assert_platform("windows", "QueryPerformanceFrequency");
+
+        // The performance counter ticks once per nanosecond.
+        let frequency_place = this.deref_operand(frequency_op)?;
+        this.write_scalar(Scalar::from_i64(1_000_000_000), frequency_place.into())?;
+
+        Ok(1) // TRUE
+    }
+
+    fn nanosleep(
+        &mut self,
+        req_op: OpTy<'tcx, Tag>,
+        _rem_op: OpTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let duration = match this.read_timespec(this.deref_operand(req_op)?)? {
+            Some(duration) => duration,
+            None => {
+                let einval = this.eval_libc("EINVAL")?;
+                this.set_last_error(einval)?;
+                return Ok(-1);
+            }
+        };
+
+        // Nothing can interrupt the sleep, so the remaining time (`rem`) is never written.
+        this.machine.clock.sleep(duration);
+
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filetime_unix_epoch() {
+        assert_eq!(duration_to_filetime(Duration::from_secs(0)), Some(FILETIME_UNIX_EPOCH));
+        assert_eq!(filetime_to_duration(FILETIME_UNIX_EPOCH), Some(Duration::from_secs(0)));
+        // Times before the unix epoch cannot be represented as a `Duration`.
+        assert_eq!(filetime_to_duration(FILETIME_UNIX_EPOCH - 1), None);
+    }
+
+    #[test]
+    fn test_filetime_roundtrip() {
+        // 2020-03-01 12:34:56.789012300 UTC.
+        let duration = Duration::new(1_583_066_096, 789_012_300);
+        let filetime = duration_to_filetime(duration).unwrap();
+        assert_eq!(filetime, 132_275_396_967_890_123);
+        assert_eq!(filetime_to_duration(filetime), Some(duration));
+        // Precision below 100ns is lost.
+        let filetime = duration_to_filetime(Duration::new(0, 199)).unwrap();
+        assert_eq!(filetime_to_duration(filetime), Some(Duration::new(0, 100)));
+    }
+
+    #[test]
+    fn test_filetime_overflow() {
+        assert_eq!(duration_to_filetime(Duration::from_secs(u64::max_value())), None);
+        let max_secs = (u64::max_value() - FILETIME_UNIX_EPOCH) / FILETIME_INTERVALS_PER_SEC;
+        assert!(duration_to_filetime(Duration::from_secs(max_secs)).is_some());
+        assert_eq!(duration_to_filetime(Duration::from_secs(max_secs + 1)), None);
+    }
 }
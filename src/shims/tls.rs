@@ -1,86 +1,142 @@
 //! Implement thread-local storage.
 
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 
 use rustc::{ty, ty::layout::HasDataLayout};
 use rustc_target::abi::LayoutOf;
 
-use crate::{HelpersEvalContextExt, InterpResult, MPlaceTy, Scalar, StackPopCleanup, Tag};
+use crate::{
+    ExitHandlerKind, HelpersEvalContextExt, InterpResult, MPlaceTy, Scalar, StackPopCleanup, Tag,
+    ThreadId, VisitTags,
+};
 
 pub type TlsKey = u128;
 
-#[derive(Copy, Clone, Debug)]
+/// How often all pending TLS destructors are run before giving up on keys whose destructors keep
+/// setting them again, like `PTHREAD_DESTRUCTOR_ITERATIONS` on Linux and macOS.
+const TLS_DTOR_ITERATIONS: u32 = 4;
+
+/// How many keys can exist at the same time, like `PTHREAD_KEYS_MAX` on Linux.
+const TLS_KEYS_MAX: usize = 1024;
+
+#[derive(Clone, Debug)]
 pub struct TlsEntry<'tcx> {
-    /// The data for this key. None is used to represent NULL.
+    /// The data for this key, for each thread that set it. A missing entry represents NULL.
     /// (We normalize this early to avoid having to do a NULL-ptr-test each time we access the data.)
-    /// Will eventually become a map from thread IDs to `Scalar`s, if we ever support more than one thread.
-    data: Option<Scalar<Tag>>,
+    data: BTreeMap<ThreadId, Scalar<Tag>>,
     dtor: Option<ty::Instance<'tcx>>,
 }
 
 #[derive(Debug)]
 pub struct TlsData<'tcx> {
-    /// The Key to use for the next thread-local allocation.
-    next_key: TlsKey,
-
     /// pthreads-style thread-local storage.
     keys: BTreeMap<TlsKey, TlsEntry<'tcx>>,
+
+    /// For the threads whose TLS destructors are running, the key of the last destructor that
+    /// ran and how often all destructors were run.
+    dtors_running: HashMap<ThreadId, (Option<TlsKey>, u32)>,
+
+    /// The destructors of `#[thread_local]` statics that each thread registered with
+    /// `_tlv_atexit` (on macOS), with their argument, in the order they were registered.
+    thread_dtors: BTreeMap<ThreadId, Vec<(ty::Instance<'tcx>, Scalar<Tag>)>>,
 }
 
 impl<'tcx> Default for TlsData<'tcx> {
     fn default() -> Self {
         TlsData {
-            next_key: 1, // start with 1 as we must not use 0 on Windows
             keys: Default::default(),
+            dtors_running: Default::default(),
+            thread_dtors: Default::default(),
         }
     }
 }
 
 impl<'tcx> TlsData<'tcx> {
-    pub fn create_tls_key(&mut self, dtor: Option<ty::Instance<'tcx>>) -> TlsKey {
-        let new_key = self.next_key;
-        self.next_key += 1;
-        self.keys.insert(new_key, TlsEntry { data: None, dtor }).unwrap_none();
+    /// Creates a key whose value is NULL for every thread, or returns `None` if there are
+    /// `TLS_KEYS_MAX` keys already. Like the platforms we emulate, the smallest key that is not in
+    /// use is handed out, so the keys of deleted keys are reused.
+    pub fn create_tls_key(&mut self, dtor: Option<ty::Instance<'tcx>>) -> Option<TlsKey> {
+        if self.keys.len() >= TLS_KEYS_MAX {
+            return None;
+        }
+        // Start with 1 as we must not use 0 on Windows.
+        let new_key = (1..).find(|key| !self.keys.contains_key(key)).unwrap();
+        self.keys.insert(new_key, TlsEntry { data: Default::default(), dtor }).unwrap_none();
         trace!("New TLS key allocated: {} with dtor {:?}", new_key, dtor);
-        new_key
+        Some(new_key)
     }
 
-    pub fn delete_tls_key(&mut self, key: TlsKey) -> InterpResult<'tcx> {
-        match self.keys.remove(&key) {
-            Some(_) => {
-                trace!("TLS key {} removed", key);
-                Ok(())
-            }
-            None => throw_unsup!(TlsOutOfBounds),
+    /// Deletes `key`, without calling its destructor for any value. Returns `false` if there is
+    /// no such key.
+    pub fn delete_tls_key(&mut self, key: TlsKey) -> bool {
+        let deleted = self.keys.remove(&key).is_some();
+        if deleted {
+            trace!("TLS key {} removed", key);
         }
+        deleted
     }
 
     pub fn load_tls(
         &mut self,
         key: TlsKey,
+        thread: ThreadId,
         cx: &impl HasDataLayout,
     ) -> InterpResult<'tcx, Scalar<Tag>> {
         match self.keys.get(&key) {
-            Some(&TlsEntry { data, .. }) => {
-                trace!("TLS key {} loaded: {:?}", key, data);
-                Ok(data.unwrap_or_else(|| Scalar::ptr_null(cx).into()))
+            Some(TlsEntry { data, .. }) => {
+                let value = data.get(&thread).copied();
+                trace!("TLS key {} for thread {} loaded: {:?}", key, thread, value);
+                Ok(value.unwrap_or_else(|| Scalar::ptr_null(cx).into()))
             }
             None => throw_unsup!(TlsOutOfBounds),
         }
     }
 
-    pub fn store_tls(&mut self, key: TlsKey, new_data: Option<Scalar<Tag>>) -> InterpResult<'tcx> {
+    pub fn store_tls(
+        &mut self,
+        key: TlsKey,
+        thread: ThreadId,
+        new_data: Option<Scalar<Tag>>,
+    ) -> InterpResult<'tcx> {
         match self.keys.get_mut(&key) {
-            Some(&mut TlsEntry { ref mut data, .. }) => {
-                trace!("TLS key {} stored: {:?}", key, new_data);
-                *data = new_data;
+            Some(TlsEntry { data, .. }) => {
+                trace!("TLS key {} for thread {} stored: {:?}", key, thread, new_data);
+                match new_data {
+                    Some(new_data) => data.insert(thread, new_data),
+                    None => data.remove(&thread),
+                };
                 Ok(())
             }
             None => throw_unsup!(TlsOutOfBounds),
         }
     }
 
-    /// Returns a dtor, its argument and its index, if one is supposed to run
+    /// Registers a destructor of a `#[thread_local]` static that runs with `data` when `thread`
+    /// terminates.
+    pub fn add_thread_dtor(
+        &mut self,
+        thread: ThreadId,
+        dtor: ty::Instance<'tcx>,
+        data: Scalar<Tag>,
+    ) {
+        trace!("Thread {} registered thread-local dtor {:?} on {:?}", thread, dtor, data);
+        self.thread_dtors.entry(thread).or_default().push((dtor, data));
+    }
+
+    /// Returns the `#[thread_local]` destructor that has to run next for `thread`, with its
+    /// argument. They run in the opposite order of their registration, and destructors that are
+    /// registered while they run are picked up as well.
+    fn fetch_thread_dtor(&mut self, thread: ThreadId) -> Option<(ty::Instance<'tcx>, Scalar<Tag>)> {
+        let dtors = self.thread_dtors.get_mut(&thread)?;
+        let dtor = dtors.pop();
+        if dtors.is_empty() {
+            self.thread_dtors.remove(&thread);
+        }
+        dtor
+    }
+
+    /// Returns a dtor, its argument and its index, if one is supposed to run for `thread`
     ///
     /// An optional destructor function may be associated with each key value.
     /// At thread exit, if a key value has a non-NULL destructor pointer,
@@ -101,6 +157,7 @@ impl<'tcx> TlsData<'tcx> {
     fn fetch_tls_dtor(
         &mut self,
         key: Option<TlsKey>,
+        thread: ThreadId,
     ) -> Option<(ty::Instance<'tcx>, Scalar<Tag>, TlsKey)> {
         use std::collections::Bound::*;
 
@@ -109,48 +166,137 @@ impl<'tcx> TlsData<'tcx> {
             Some(key) => Excluded(key),
             None => Unbounded,
         };
-        for (&key, &mut TlsEntry { ref mut data, dtor }) in
-            thread_local.range_mut((start, Unbounded))
-        {
-            if let Some(data_scalar) = *data {
-                if let Some(dtor) = dtor {
-                    let ret = Some((dtor, data_scalar, key));
-                    *data = None;
-                    return ret;
+        for (&key, TlsEntry { data, dtor }) in thread_local.range_mut((start, Unbounded)) {
+            if let Some(dtor) = *dtor {
+                if let Some(data_scalar) = data.remove(&thread) {
+                    return Some((dtor, data_scalar, key));
                 }
             }
         }
         None
     }
+
+    /// Returns the keys that still have a non-NULL value for `thread` and a destructor, with
+    /// that destructor.
+    fn pending_tls_dtors(&self, thread: ThreadId) -> Vec<(TlsKey, ty::Instance<'tcx>)> {
+        self.keys
+            .iter()
+            .filter_map(|(&key, entry)| match entry.dtor {
+                Some(dtor) if entry.data.contains_key(&thread) => Some((key, dtor)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl<'tcx> VisitTags for TlsData<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        for entry in self.keys.values() {
+            for data in entry.data.values() {
+                data.visit_tags(visit);
+            }
+        }
+        for dtors in self.thread_dtors.values() {
+            for (_, arg) in dtors {
+                arg.visit_tags(visit);
+            }
+        }
+    }
 }
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Runs the TLS destructors of the active thread. Must be called when the stack is empty.
     fn run_tls_dtors(&mut self) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
-        let mut dtor = this.machine.tls.fetch_tls_dtor(None);
-        // FIXME: replace loop by some structure that works with stepping
-        while let Some((instance, ptr, key)) = dtor {
-            trace!("Running TLS dtor {:?} on {:?}", instance, ptr);
-            assert!(!this.is_null(ptr).unwrap(), "Data can't be NULL when dtor is called!");
-
-            let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
-            this.call_function(
-                instance,
-                &[ptr.into()],
-                Some(ret_place),
-                StackPopCleanup::None { cleanup: true },
-            )?;
-
-            // step until out of stackframes
-            this.run()?;
-
-            dtor = match this.machine.tls.fetch_tls_dtor(Some(key)) {
-                dtor @ Some(_) => dtor,
-                None => this.machine.tls.fetch_tls_dtor(None),
-            };
+        while this.call_next_tls_dtor()? {
+            // Step until out of stackframes.
+            while this.step_with_hooks()? {}
         }
+        this.warn_pending_tls_dtors();
         // FIXME: On a windows target, call `unsafe extern "system" fn on_tls_callback`.
         Ok(())
     }
+
+    /// Pushes the frame of the next TLS destructor of the active thread that has to run, and
+    /// returns whether there was one. The destructor returns to whatever was executing before,
+    /// without moving on to the next statement. The destructors of `#[thread_local]` statics run
+    /// before the ones of pthread keys.
+    fn call_next_tls_dtor(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        if this.call_next_thread_dtor()? {
+            return Ok(true);
+        }
+        let thread = this.machine.threads.active_thread();
+        let tls = &mut this.machine.tls;
+        let (key, mut iteration) = tls.dtors_running.get(&thread).copied().unwrap_or((None, 1));
+        let dtor = match tls.fetch_tls_dtor(key, thread) {
+            dtor @ Some(_) => dtor,
+            None if iteration < TLS_DTOR_ITERATIONS => {
+                iteration += 1;
+                tls.fetch_tls_dtor(None, thread)
+            }
+            None => None,
+        };
+        let (instance, ptr, key) = match dtor {
+            Some(dtor) => dtor,
+            None => {
+                tls.dtors_running.remove(&thread);
+                return Ok(false);
+            }
+        };
+        tls.dtors_running.insert(thread, (Some(key), iteration));
+        this.call_tls_dtor(instance, ptr)?;
+        Ok(true)
+    }
+
+    /// Like `call_next_tls_dtor`, but only for the destructors of `#[thread_local]` statics. These
+    /// are also the only ones that `exit` runs.
+    fn call_next_thread_dtor(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let thread = this.machine.threads.active_thread();
+        match this.machine.tls.fetch_thread_dtor(thread) {
+            Some((instance, ptr)) => {
+                this.call_tls_dtor(instance, ptr)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Pushes the frame of the TLS destructor `instance`, to be called with `ptr`.
+    fn call_tls_dtor(
+        &mut self,
+        instance: ty::Instance<'tcx>,
+        ptr: Scalar<Tag>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let thread = this.machine.threads.active_thread();
+        trace!("Running TLS dtor {:?} on {:?} for thread {}", instance, ptr, thread);
+        assert!(!this.is_null(ptr).unwrap(), "Data can't be NULL when dtor is called!");
+        let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
+        this.call_function(
+            instance,
+            &[ptr.into()],
+            Some(ret_place),
+            StackPopCleanup::None { cleanup: true },
+        )?;
+        this.frame_mut().extra.exit_handler = Some(ExitHandlerKind::TlsDtor);
+        Ok(())
+    }
+
+    /// Like the platforms we emulate, we stop calling destructors that keep setting their key,
+    /// instead of looping forever. Tell the user which ones were skipped.
+    fn warn_pending_tls_dtors(&self) {
+        let this = self.eval_context_ref();
+        let thread = this.machine.threads.active_thread();
+        for (key, dtor) in this.machine.tls.pending_tls_dtors(thread) {
+            this.tcx.sess.warn(&format!(
+                "TLS key {} still has a value after {} iterations of TLS destructors; its destructor `{}` keeps setting it and is not called anymore",
+                key,
+                TLS_DTOR_ITERATIONS,
+                this.tcx.def_path_str(dtor.def_id()),
+            ));
+        }
+    }
 }
@@ -0,0 +1,214 @@
+//! Minimizing the nondeterministic input of a failing program. With `-Zmiri-shrink-nondet=<dir>`,
+//! the bytes that `miri_nondet_bytes` produced during a failing run are shrunk with delta
+//! debugging (ddmin), re-running the program for every candidate, and the smallest input that
+//! still fails the same way is written to `<dir>` along with instructions to replay it.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rustc::ty::TyCtxt;
+use rustc_hir::def_id::DefId;
+
+use crate::*;
+
+/// How a run failed. A candidate input only counts as reproducing the failure if it fails the
+/// same way, so that the shrinker does not wander off to an unrelated bug.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureClass {
+    /// The program exited with a nonzero exit code.
+    ExitCode(i64),
+    UndefinedBehavior,
+    Unsupported,
+    ResourceExhaustion,
+    Abort,
+    Deadlock,
+    Other,
+}
+
+impl FailureClass {
+    pub fn of_error(e: &InterpErrorInfo<'_>) -> Self {
+        match e.kind {
+            InterpError::UndefinedBehavior(_) => FailureClass::UndefinedBehavior,
+            InterpError::Unsupported(_) => FailureClass::Unsupported,
+            InterpError::ResourceExhaustion(_) => FailureClass::ResourceExhaustion,
+            InterpError::MachineStop(ref info) => match info.downcast_ref::<TerminationInfo>() {
+                Some(TerminationInfo::Exit { code, .. }) => FailureClass::ExitCode(*code),
+                Some(TerminationInfo::Abort)
+                | Some(TerminationInfo::UnwoundOutOfExitHandler(_)) => FailureClass::Abort,
+                Some(TerminationInfo::Deadlock { .. }) => FailureClass::Deadlock,
+                Some(TerminationInfo::MemoryLimitExceeded { .. }) =>
+                    FailureClass::ResourceExhaustion,
+                _ => FailureClass::Other,
+            },
+            _ => FailureClass::Other,
+        }
+    }
+}
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureClass::ExitCode(code) => write!(f, "exit code {}", code),
+            FailureClass::UndefinedBehavior => write!(f, "undefined behavior"),
+            FailureClass::Unsupported => write!(f, "unsupported operation"),
+            FailureClass::ResourceExhaustion => write!(f, "resource exhaustion"),
+            FailureClass::Abort => write!(f, "abort"),
+            FailureClass::Deadlock => write!(f, "deadlock"),
+            FailureClass::Other => write!(f, "other error"),
+        }
+    }
+}
+
+/// Minimizes `input`, the nondeterministic input of a run that failed with `failure`, and writes
+/// the result to the directory given by `config.shrink_nondet`.
+pub fn shrink_nondet_input<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    main_id: DefId,
+    config: MiriConfig,
+    input: Vec<u8>,
+    failure: FailureClass,
+) {
+    let dir = config.shrink_nondet.clone().expect("shrinking was not requested");
+    if input.is_empty() {
+        tcx.sess.note_without_error(
+            "the program did not use `miri_nondet_bytes`, so there is no input to minimize",
+        );
+        return;
+    }
+    let mut runs = 0;
+    let minimized = ddmin(&input, |candidate| {
+        runs += 1;
+        let result = crate::eval::rerun_with_nondet_input(tcx, main_id, config.clone(), candidate);
+        result == Some(failure)
+    });
+    let seed = config.seed;
+    match write_bundle(&dir, &minimized, input.len(), runs, failure, seed) {
+        Ok(()) => tcx.sess.note_without_error(&format!(
+            "minimized the nondeterministic input from {} to {} bytes in {} runs; \
+             the reproducer was written to {}",
+            input.len(),
+            minimized.len(),
+            runs,
+            dir.display(),
+        )),
+        Err(err) => tcx.sess.warn(&format!(
+            "cannot write the minimized input to {}: {}",
+            dir.display(),
+            err
+        )),
+    }
+}
+
+/// Writes the minimized input and a description of how to replay it to `dir`.
+fn write_bundle(
+    dir: &Path,
+    minimized: &[u8],
+    original_len: usize,
+    runs: usize,
+    failure: FailureClass,
+    seed: Option<u64>,
+) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let input_path = dir.join("nondet-input.bin");
+    fs::write(&input_path, minimized)?;
+    let mut flags = format!("-Zmiri-nondet-input={}", input_path.display());
+    if let Some(seed) = seed {
+        flags.push_str(&format!(" -Zmiri-seed={:016x}", seed));
+    }
+    let repro = format!(
+        "failure: {}\ninput: {} bytes (minimized from {} bytes in {} runs)\nflags: {}\n",
+        failure,
+        minimized.len(),
+        original_len,
+        runs,
+        flags,
+    );
+    fs::write(dir.join("repro.txt"), repro)
+}
+
+/// Delta debugging: returns a subsequence of `input` for which `fails` holds and from which no
+/// single chunk at the final granularity can be removed. `fails(input)` is assumed to hold.
+pub fn ddmin(input: &[u8], mut fails: impl FnMut(&[u8]) -> bool) -> Vec<u8> {
+    let mut input = input.to_vec();
+    if input.is_empty() || fails(&[]) {
+        return Vec::new();
+    }
+    let mut granularity = 2;
+    while input.len() >= 2 {
+        let chunks = chunk_bounds(input.len(), granularity);
+        let mut reduced = false;
+        // Try every chunk on its own.
+        for &(start, end) in &chunks {
+            if fails(&input[start..end]) {
+                input = input[start..end].to_vec();
+                granularity = 2;
+                reduced = true;
+                break;
+            }
+        }
+        // Try leaving out every chunk. With two chunks, that was already done above.
+        if !reduced && granularity > 2 {
+            for &(start, end) in &chunks {
+                let complement: Vec<u8> =
+                    input[..start].iter().chain(&input[end..]).copied().collect();
+                if fails(&complement) {
+                    input = complement;
+                    granularity = (granularity - 1).max(2);
+                    reduced = true;
+                    break;
+                }
+            }
+        }
+        if !reduced {
+            if granularity >= input.len() {
+                break;
+            }
+            granularity = (granularity * 2).min(input.len());
+        }
+    }
+    input
+}
+
+/// Splits `0..len` into `n` ranges whose lengths differ by at most one.
+fn chunk_bounds(len: usize, n: usize) -> Vec<(usize, usize)> {
+    (0..n).map(|i| (i * len / n, (i + 1) * len / n)).filter(|(start, end)| start < end).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddmin_finds_single_byte() {
+        let input: Vec<u8> = (0..100).collect();
+        let result = ddmin(&input, |c| c.contains(&42));
+        assert_eq!(result, vec![42]);
+    }
+
+    #[test]
+    fn ddmin_keeps_required_bytes_in_order() {
+        let input: Vec<u8> = (0..64).collect();
+        let result = ddmin(&input, |c| c.contains(&3) && c.contains(&50));
+        assert_eq!(result, vec![3, 50]);
+    }
+
+    #[test]
+    fn ddmin_empty_input_fails() {
+        let input = vec![1, 2, 3];
+        assert_eq!(ddmin(&input, |_| true), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn ddmin_length_threshold() {
+        let input = vec![0xff; 37];
+        let result = ddmin(&input, |c| c.len() >= 5);
+        assert_eq!(result.len(), 5);
+    }
+
+    #[test]
+    fn chunk_bounds_cover_input() {
+        assert_eq!(chunk_bounds(5, 2), vec![(0, 2), (2, 5)]);
+        assert_eq!(chunk_bounds(3, 4), vec![(0, 1), (1, 2), (2, 3)]);
+    }
+}
@@ -0,0 +1,91 @@
+//! Recognizes spin loops that wait for another thread without a spin loop hint or a yield. A
+//! thread that keeps loading the same value from the same location with atomic loads, and writes
+//! no memory in between, is taken to be spinning. Threads run one after the other, so only a
+//! thread that did not start yet can still change that value: like at a spin loop hint, it gets
+//! to run, and the remaining iterations of the loop are skipped.
+//!
+//! Locals that are not in memory change without any machine hook seeing it, so a loop that polls
+//! a flag while counting in a local may be mistaken for a spin loop. That only makes the waiting
+//! threads start earlier, which is a schedule the program has to cope with anyway.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rustc::ty::layout::Size;
+
+use crate::*;
+
+/// How many times in a row a thread loads the same value without writing memory before it is
+/// taken to be spinning.
+pub const SPIN_LOOP_LOADS: u64 = 100;
+
+pub type MemoryExtra = Rc<RefCell<GlobalState>>;
+
+/// The last atomic load, and the memory writes since then.
+#[derive(Debug, Default)]
+pub struct GlobalState {
+    /// The number of memory writes so far. Every allocation counts its writes here.
+    writes: u64,
+    /// The thread that did the last atomic load, and the location and the value it loaded.
+    last_load: Option<(ThreadId, (AllocId, Size), Scalar<Tag>)>,
+    /// The number of memory writes right after the last atomic load wrote its result.
+    writes_after_load: u64,
+    /// How many times in a row the last atomic load was repeated without memory writes.
+    repeated: u64,
+}
+
+impl GlobalState {
+    pub(crate) fn memory_written(&mut self) {
+        self.writes += 1;
+    }
+
+    /// Records an atomic load by `thread`, and returns whether `thread` is spinning.
+    fn load(&mut self, thread: ThreadId, location: (AllocId, Size), val: Scalar<Tag>) -> bool {
+        let load = Some((thread, location, val));
+        if self.last_load == load && self.writes == self.writes_after_load {
+            self.repeated += 1;
+        } else {
+            self.last_load = load;
+            self.repeated = 0;
+        }
+        self.repeated >= SPIN_LOOP_LOADS
+    }
+
+    /// Records that the last atomic load wrote its result.
+    fn load_written(&mut self) {
+        self.writes_after_load = self.writes;
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Writes `val`, which the active thread loaded from `place` with an atomic load, to `dest`,
+    /// and lets the threads that did not start yet run if the active thread is spinning. Returns
+    /// `true` if a frame of such a thread was pushed; the load has to be repeated once that frame
+    /// returns.
+    fn write_atomic_load_result(
+        &mut self,
+        place: MPlaceTy<'tcx, Tag>,
+        val: Scalar<Tag>,
+        dest: PlaceTy<'tcx, Tag>,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let ptr = this.force_ptr(place.ptr)?;
+        let thread = this.machine.threads.active_thread();
+        let spinning =
+            this.memory.extra.spin_loop.borrow_mut().load(thread, (ptr.alloc_id, ptr.offset), val);
+        // If the result goes to memory, writing it is part of every iteration.
+        this.write_scalar(val, dest)?;
+        this.memory.extra.spin_loop.borrow_mut().load_written();
+        if !spinning {
+            return Ok(false);
+        }
+        let yielded = this.yield_active_thread()?;
+        if yielded {
+            if let Some(stats) = &mut this.machine.atomic_stats {
+                stats.record_spin_loop();
+            }
+        }
+        Ok(yielded)
+    }
+}
@@ -7,9 +7,11 @@ use std::fmt;
 use std::num::NonZeroU64;
 use std::rc::Rc;
 
+use rustc_hir::def_id::DefId;
 use rustc_hir::Mutability;
 use rustc::mir::RetagKind;
-use rustc::ty::{self, layout::Size};
+use rustc::ty::{self, layout::Size, TyCtxt};
+use rustc_span::{Span, DUMMY_SP};
 
 use crate::*;
 
@@ -22,6 +24,9 @@ pub type AllocExtra = Stacks;
 pub enum Tag {
     Tagged(PtrId),
     Untagged,
+    /// The tag of pointers cast from integers with `-Zmiri-permissive-provenance`. It is granted
+    /// access by the items of all exposed tags.
+    Wildcard,
 }
 
 impl fmt::Debug for Tag {
@@ -29,6 +34,7 @@ impl fmt::Debug for Tag {
         match self {
             Tag::Tagged(id) => write!(f, "<{}>", id),
             Tag::Untagged => write!(f, "<untagged>"),
+            Tag::Wildcard => write!(f, "<wildcard>"),
         }
     }
 }
@@ -42,6 +48,9 @@ pub enum Permission {
     SharedReadWrite,
     /// Grants shared read-only access.
     SharedReadOnly,
+    /// The reservation of a two-phase borrow: grants shared mutable access, but the first write
+    /// activates it, turning it into `Unique` as if it was only now reborrowed from its parent.
+    Reserved,
     /// Grants no access, but separates two groups of SharedReadWrite so they are not
     /// all considered mutually compatible.
     Disabled,
@@ -101,14 +110,75 @@ pub struct GlobalState {
     next_call_id: CallId,
     /// Those call IDs corresponding to functions that are still running.
     active_calls: HashSet<CallId>,
-    /// The id to trace in this execution run
-    tracked_pointer_tag: Option<PtrId>,
+    /// The ids to trace in this execution run
+    tracked_pointer_tags: HashSet<PtrId>,
+    /// The allocation whose borrow stack events are printed, see `-Zmiri-dump-borrow-stacks`.
+    dumped_alloc: Option<AllocId>,
+    /// Where the current step of the interpreter happens, see `step_with_hooks`.
+    pub(crate) current_span: Span,
+    /// What happened to the most recent `TAG_HISTORY_LIMIT` tags, and to the tracked tags.
+    history: HashMap<PtrId, TagHistory>,
+    /// The tag that had no appropriate item in a borrow stack when the last error was raised.
+    failed_tag: Option<Tag>,
+    /// Crates and item paths whose code is not checked, see `-Zmiri-disable-stacked-borrows-for`.
+    exempt_paths: Vec<String>,
+    /// Whether the function of every frame seen so far is exempt.
+    exempt_functions: HashMap<DefId, bool>,
+    /// Whether the current step of the interpreter is in an exempt function.
+    exempt: bool,
+    /// Whether raw pointers get their own tags instead of being untagged.
+    raw_pointer_tagging: bool,
+    /// Whether references passed to functions are protected for the duration of the call.
+    protectors: bool,
+    /// The parent of every two-phase borrow, which it is reborrowed from on activation.
+    reservations: HashMap<PtrId, Tag>,
+    /// The allocations that have borrow stacks, which the tag garbage collector looks at.
+    allocs: HashSet<AllocId>,
+    /// The tags of the pointers that were cast to integers, whose items grant access to
+    /// wildcard pointers.
+    exposed: HashSet<PtrId>,
 }
+
+/// What happened to a tag so far. Used to explain errors about tags that are not in a borrow
+/// stack.
+#[derive(Clone, Debug)]
+pub struct TagHistory {
+    /// Where the tag was created, and by which kind of retag (`None` for base tags).
+    pub created: (Span, Option<RefKind>),
+    /// Where an item for the tag was last popped from (or disabled in) a borrow stack, and by
+    /// which kind of access (`None` for deallocations).
+    pub invalidated: Option<(Span, Option<AccessKind>)>,
+}
+/// How many of the most recent tags have their history remembered. Errors about older tags
+/// are reported without the notes on where the tag was created and invalidated.
+const TAG_HISTORY_LIMIT: u64 = 100_000;
+
 /// Memory extra state gives us interior mutable access to the global state.
 pub type MemoryExtra = Rc<RefCell<GlobalState>>;
 
+/// The allocation and offset range a borrow stack operation applies to.
+/// Used to tell the user where events for tracked tags happened.
+#[derive(Copy, Clone, Debug)]
+pub struct TagRange {
+    pub alloc_id: AllocId,
+    pub offset: Size,
+    pub size: Size,
+}
+
+impl fmt::Display for TagRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}[{:#x}..{:#x}]",
+            self.alloc_id,
+            self.offset.bytes(),
+            self.offset.bytes() + self.size.bytes()
+        )
+    }
+}
+
 /// Indicates which kind of access is being performed.
-#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum AccessKind {
     Read,
     Write,
@@ -126,7 +196,7 @@ impl fmt::Display for AccessKind {
 /// Indicates which kind of reference is being created.
 /// Used by high-level `reborrow` to compute which permissions to grant to the
 /// new pointer.
-#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum RefKind {
     /// `&mut` and `Box`.
     Unique { two_phase: bool },
@@ -150,22 +220,173 @@ impl fmt::Display for RefKind {
 
 /// Utilities for initialization and ID generation
 impl GlobalState {
-    pub fn new(tracked_pointer_tag: Option<PtrId>) -> Self {
+    pub fn new(
+        tracked_pointer_tags: HashSet<PtrId>,
+        dumped_alloc: Option<AllocId>,
+        exempt_paths: Vec<String>,
+        raw_pointer_tagging: bool,
+        protectors: bool,
+    ) -> Self {
         GlobalState {
             next_ptr_id: NonZeroU64::new(1).unwrap(),
             base_ptr_ids: HashMap::default(),
             next_call_id: NonZeroU64::new(1).unwrap(),
             active_calls: HashSet::default(),
-            tracked_pointer_tag,
+            tracked_pointer_tags,
+            dumped_alloc,
+            current_span: DUMMY_SP,
+            history: HashMap::default(),
+            failed_tag: None,
+            exempt_paths,
+            exempt_functions: HashMap::default(),
+            exempt: false,
+            raw_pointer_tagging,
+            protectors,
+            reservations: HashMap::default(),
+            allocs: HashSet::default(),
+            exposed: HashSet::default(),
+        }
+    }
+
+    /// Describes the variant of the aliasing model that is checked, for error reports.
+    pub fn describe_mode(&self, tree_borrows: bool) -> String {
+        let on_off = |on| if on { "on" } else { "off" };
+        if tree_borrows {
+            format!("Tree Borrows with protectors {}", on_off(self.protectors))
+        } else {
+            format!(
+                "Stacked Borrows with raw pointer tagging {} and protectors {}",
+                on_off(self.raw_pointer_tagging),
+                on_off(self.protectors),
+            )
+        }
+    }
+
+    /// Whether accesses of the current step of the interpreter are not checked, because they
+    /// happen in an exempt function.
+    pub(crate) fn is_exempt(&self) -> bool {
+        self.exempt
+    }
+
+    /// Whether `def_id` belongs to one of the exempt crates or paths.
+    fn is_exempt_function(&self, tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+        crate::helpers::is_in_paths(tcx, def_id, &self.exempt_paths)
+    }
+
+    /// Whether events for this tag should be reported to the user.
+    pub(crate) fn is_tracked(&self, tag: Tag) -> bool {
+        match tag {
+            Tag::Tagged(id) => self.tracked_pointer_tags.contains(&id),
+            Tag::Untagged | Tag::Wildcard => false,
+        }
+    }
+
+    /// Reports `event` and the borrow stacks of `range` after it, if they are dumped.
+    fn dump(&self, stacks: &RangeMap<Stack>, range: TagRange, event: fmt::Arguments<'_>) {
+        if self.dumped_alloc != Some(range.alloc_id) {
+            return;
         }
+        let mut dump = format!("{} at {}", event, range);
+        for (offsets, stack) in stacks.iter_with_ranges(range.offset, range.size) {
+            dump.push_str(&format!(
+                "\n    {:#x}..{:#x}: {:?}",
+                offsets.start, offsets.end, stack.borrows
+            ));
+        }
+        register_diagnostic(NonHaltingDiagnostic::BorrowStacksDump(dump));
     }
 
-    fn new_ptr(&mut self) -> PtrId {
+    /// Creates a new tag, by a retag of the given kind or (for `None`) as a base tag.
+    pub(crate) fn new_ptr(&mut self, retag: Option<RefKind>) -> PtrId {
         let id = self.next_ptr_id;
         self.next_ptr_id = NonZeroU64::new(id.get() + 1).unwrap();
+        let created = (self.current_span, retag);
+        self.history.insert(id, TagHistory { created, invalidated: None });
+        // Forget the oldest tag, so that the histories do not grow with every retag.
+        if let Some(old) = id.get().checked_sub(TAG_HISTORY_LIMIT).and_then(NonZeroU64::new) {
+            if !self.tracked_pointer_tags.contains(&old) {
+                self.history.remove(&old);
+            }
+        }
         id
     }
 
+    /// Remembers that the tag lost its permission through the given access (`None` for
+    /// deallocations).
+    pub(crate) fn note_invalidated(&mut self, tag: Tag, access: Option<AccessKind>) {
+        if let Tag::Tagged(id) = tag {
+            let span = self.current_span;
+            if let Some(history) = self.history.get_mut(&id) {
+                history.invalidated = Some((span, access));
+            }
+        }
+    }
+
+    /// Remembers that an access or reborrow was rejected because of `tag`, to explain the error.
+    pub(crate) fn note_failed(&mut self, tag: Tag) {
+        self.failed_tag = Some(tag);
+    }
+
+    /// Takes the tag that caused the last error, together with its history.
+    pub fn take_failed_tag_history(&mut self) -> Option<(PtrId, TagHistory)> {
+        match self.failed_tag.take()? {
+            Tag::Tagged(id) => self.history.get(&id).map(|history| (id, history.clone())),
+            Tag::Untagged | Tag::Wildcard => None,
+        }
+    }
+
+    /// Remembers that a pointer with `tag` was cast to an integer, so wildcard pointers may use
+    /// its items.
+    pub(crate) fn expose(&mut self, tag: Tag) {
+        if let Tag::Tagged(id) = tag {
+            self.exposed.insert(id);
+        }
+    }
+
+    /// Whether the items of `tag` grant access to pointers with `tag` itself, or to wildcard
+    /// pointers.
+    fn item_matches(&self, item: &Item, tag: Tag) -> bool {
+        match (tag, item.tag) {
+            (Tag::Wildcard, Tag::Tagged(id)) => self.exposed.contains(&id),
+            // Untagged pointers may have been cast to integers without us noticing.
+            (Tag::Wildcard, Tag::Untagged) => true,
+            _ => tag == item.tag,
+        }
+    }
+
+    /// The allocations that have borrow stacks.
+    pub(crate) fn live_allocs(&self) -> Vec<AllocId> {
+        self.allocs.iter().copied().collect()
+    }
+
+    /// Adds the tags that must stay usable even if no pointer carries them to the `live` tags
+    /// the garbage collector found, and forgets everything about the other tags.
+    pub(crate) fn retain_live_tags(&mut self, live: &mut HashSet<PtrId>) {
+        // Global pointers in `tcx` carry the base tags of statics.
+        for tag in self.base_ptr_ids.values() {
+            if let Tag::Tagged(id) = tag {
+                live.insert(*id);
+            }
+        }
+        // Tracked tags keep being reported until they are really popped.
+        live.extend(self.tracked_pointer_tags.iter().copied());
+        // Wildcard pointers may use the items of exposed tags.
+        live.extend(self.exposed.iter().copied());
+        // A two-phase borrow is reborrowed from its parent when it gets activated. The parent
+        // may itself be a reservation.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, parent) in &self.reservations {
+                if let (true, Tag::Tagged(parent)) = (live.contains(id), parent) {
+                    changed |= live.insert(*parent);
+                }
+            }
+        }
+        self.reservations.retain(|id, _| live.contains(id));
+        self.history.retain(|id, _| live.contains(id));
+    }
+
     pub fn new_call(&mut self) -> CallId {
         let id = self.next_call_id;
         trace!("new_call: Assigning ID {}", id);
@@ -178,13 +399,13 @@ impl GlobalState {
         assert!(self.active_calls.remove(&id));
     }
 
-    fn is_active(&self, id: CallId) -> bool {
+    pub(crate) fn is_active(&self, id: CallId) -> bool {
         self.active_calls.contains(&id)
     }
 
     pub fn static_base_ptr(&mut self, id: AllocId) -> Tag {
         self.base_ptr_ids.get(&id).copied().unwrap_or_else(|| {
-            let tag = Tag::Tagged(self.new_ptr());
+            let tag = Tag::Tagged(self.new_ptr(None));
             trace!("New allocation {:?} has base tag {:?}", id, tag);
             self.base_ptr_ids.insert(id, tag).unwrap_none();
             tag
@@ -222,7 +443,8 @@ impl Permission {
 impl<'tcx> Stack {
     /// Find the item granting the given kind of access to the given tag, and return where
     /// it is on the stack.
-    fn find_granting(&self, access: AccessKind, tag: Tag) -> Option<usize> {
+    /// Wildcard pointers use the topmost item of an exposed tag, which is the most permissive choice.
+    fn find_granting(&self, access: AccessKind, tag: Tag, global: &GlobalState) -> Option<usize> {
         self.borrows
             .iter()
             .enumerate() // we also need to know *where* in the stack
@@ -231,7 +453,11 @@ impl<'tcx> Stack {
             // We require a permission with the right tag, ensuring U3 and F3.
             .find_map(
                 |(idx, item)| {
-                    if tag == item.tag && item.perm.grants(access) { Some(idx) } else { None }
+                    if global.item_matches(item, tag) && item.perm.grants(access) {
+                        Some(idx)
+                    } else {
+                        None
+                    }
                 },
             )
     }
@@ -243,13 +469,16 @@ impl<'tcx> Stack {
         match perm {
             Permission::SharedReadOnly => bug!("Cannot use SharedReadOnly for writing"),
             Permission::Disabled => bug!("Cannot use Disabled for anything"),
+            Permission::Reserved => bug!("Reserved items get activated before writing"),
             // On a write, everything above us is incompatible.
             Permission::Unique => granting + 1,
             Permission::SharedReadWrite => {
                 // The SharedReadWrite *just* above us are compatible, to skip those.
+                // Reservations tolerate writes by the SharedReadWrite next to them, like with
+                // interior mutability; writes through a `Unique` parent still remove them.
                 let mut idx = granting + 1;
                 while let Some(item) = self.borrows.get(idx) {
-                    if item.perm == Permission::SharedReadWrite {
+                    if item.perm == Permission::SharedReadWrite || item.perm == Permission::Reserved {
                         // Go on.
                         idx += 1;
                     } else {
@@ -262,13 +491,19 @@ impl<'tcx> Stack {
         }
     }
 
-    /// Check if the given item is protected.
-    fn check_protector(item: &Item, tag: Option<Tag>, global: &GlobalState) -> InterpResult<'tcx> {
-        if let Tag::Tagged(id) = item.tag {
-            if Some(id) == global.tracked_pointer_tag {
-                register_diagnostic(NonHaltingDiagnostic::PoppedTrackedPointerTag(item.clone()));
-            }
+    /// Check if the given item is protected, and remember that it gets invalidated by the
+    /// given access (`None` for deallocations).
+    fn check_protector(
+        item: &Item,
+        provoking_access: Option<(Tag, AccessKind)>,
+        range: TagRange,
+        global: &mut GlobalState,
+    ) -> InterpResult<'tcx> {
+        if global.is_tracked(item.tag) {
+            register_diagnostic(NonHaltingDiagnostic::PoppedTrackedPointerTag(*item, range));
         }
+        global.note_invalidated(item.tag, provoking_access.map(|(_, access)| access));
+        let tag = provoking_access.map(|(tag, _)| tag);
         if let Some(call) = item.protector {
             if global.is_active(call) {
                 if let Some(tag) = tag {
@@ -289,13 +524,33 @@ impl<'tcx> Stack {
 
     /// Test if a memory `access` using pointer tagged `tag` is granted.
     /// If yes, return the index of the item that granted it.
-    fn access(&mut self, access: AccessKind, tag: Tag, global: &GlobalState) -> InterpResult<'tcx> {
+    fn access(
+        &mut self,
+        access: AccessKind,
+        tag: Tag,
+        range: TagRange,
+        global: &mut GlobalState,
+    ) -> InterpResult<'tcx> {
         // Two main steps: Find granting item, remove incompatible items above.
 
         // Step 1: Find granting item.
-        let granting_idx = self.find_granting(access, tag).ok_or_else(|| err_ub!(UbExperimental(
-            format!("no item granting {} to tag {:?} found in borrow stack.", access, tag),
-        )))?;
+        let mut granting_idx = self.find_granting(access, tag, global).ok_or_else(|| {
+            global.note_failed(tag);
+            if tag == Tag::Wildcard {
+                err_ub!(UbExperimental(format!(
+                    "no item granting {} to a pointer cast from an integer found in borrow stack: no pointer to this location whose address was exposed is still valid",
+                    access
+                )))
+            } else {
+                err_ub!(UbExperimental(format!(
+                    "no item granting {} to tag {:?} found in borrow stack.",
+                    access, tag
+                )))
+            }
+        })?;
+        if access == AccessKind::Write && self.borrows[granting_idx].perm == Permission::Reserved {
+            granting_idx = self.activate(granting_idx, range, global)?;
+        }
 
         // Step 2: Remove incompatible items above them.  Make sure we do not remove protected
         // items.  Behavior differs for reads and writes.
@@ -305,7 +560,7 @@ impl<'tcx> Stack {
             let first_incompatible_idx = self.find_first_write_incompatible(granting_idx);
             for item in self.borrows.drain(first_incompatible_idx..).rev() {
                 trace!("access: popping item {:?}", item);
-                Stack::check_protector(&item, Some(tag), global)?;
+                Stack::check_protector(&item, Some((tag, access)), range, global)?;
             }
         } else {
             // On a read, *disable* all `Unique` above the granting item.  This ensures U2 for read accesses.
@@ -320,7 +575,7 @@ impl<'tcx> Stack {
                 let item = &mut self.borrows[idx];
                 if item.perm == Permission::Unique {
                     trace!("access: disabling item {:?}", item);
-                    Stack::check_protector(item, Some(tag), global)?;
+                    Stack::check_protector(item, Some((tag, access)), range, global)?;
                     item.perm = Permission::Disabled;
                 }
             }
@@ -330,18 +585,53 @@ impl<'tcx> Stack {
         Ok(())
     }
 
+    /// Activates the two-phase borrow whose reservation is at `idx`: it gets reborrowed from its
+    /// parent as `Unique`, which invalidates everything that was derived from the parent during
+    /// the reservation. Returns where the `Unique` item ends up.
+    fn activate(
+        &mut self,
+        idx: usize,
+        range: TagRange,
+        global: &mut GlobalState,
+    ) -> InterpResult<'tcx, usize> {
+        let reserved = self.borrows.remove(idx);
+        let parent = match reserved.tag {
+            Tag::Tagged(id) => global.reservations[&id],
+            Tag::Untagged | Tag::Wildcard => bug!("two-phase borrows are always tagged"),
+        };
+        trace!("access: activating two-phase borrow {:?} derived from {:?}", reserved, parent);
+        // The reservation tolerated reads of the parent, but not writes.
+        if self.find_granting(AccessKind::Write, parent, global).is_none() {
+            global.note_failed(reserved.tag);
+            throw_ub!(UbExperimental(format!(
+                "activating two-phase borrow with tag {:?}, but its parent tag {:?} was invalidated during the reservation",
+                reserved.tag, parent,
+            )));
+        }
+        self.grant(parent, Item { perm: Permission::Unique, ..reserved }, range, global)?;
+        Ok(self.borrows.len() - 1)
+    }
+
     /// Deallocate a location: Like a write access, but also there must be no
     /// active protectors at all because we will remove all items.
-    fn dealloc(&mut self, tag: Tag, global: &GlobalState) -> InterpResult<'tcx> {
+    fn dealloc(
+        &mut self,
+        tag: Tag,
+        range: TagRange,
+        global: &mut GlobalState,
+    ) -> InterpResult<'tcx> {
         // Step 1: Find granting item.
-        self.find_granting(AccessKind::Write, tag).ok_or_else(|| err_ub!(UbExperimental(format!(
-            "no item granting write access for deallocation to tag {:?} found in borrow stack",
-            tag,
-        ))))?;
+        self.find_granting(AccessKind::Write, tag, global).ok_or_else(|| {
+            global.note_failed(tag);
+            err_ub!(UbExperimental(format!(
+                "no item granting write access for deallocation to tag {:?} found in borrow stack",
+                tag,
+            )))
+        })?;
 
         // Step 2: Remove all items.  Also checks for protectors.
         for item in self.borrows.drain(..).rev() {
-            Stack::check_protector(&item, None, global)?;
+            Stack::check_protector(&item, None, range, global)?;
         }
 
         Ok(())
@@ -351,27 +641,37 @@ impl<'tcx> Stack {
     /// `weak` controls whether this operation is weak or strong: weak granting does not act as
     /// an access, and they add the new item directly on top of the one it is derived
     /// from instead of all the way at the top of the stack.
-    fn grant(&mut self, derived_from: Tag, new: Item, global: &GlobalState) -> InterpResult<'tcx> {
+    fn grant(
+        &mut self,
+        derived_from: Tag,
+        new: Item,
+        range: TagRange,
+        global: &mut GlobalState,
+    ) -> InterpResult<'tcx> {
         // Figure out which access `perm` corresponds to.
         let access =
             if new.perm.grants(AccessKind::Write) { AccessKind::Write } else { AccessKind::Read };
         // Now we figure out which item grants our parent (`derived_from`) this kind of access.
         // We use that to determine where to put the new item.
-        let granting_idx = self.find_granting(access, derived_from)
-            .ok_or_else(|| err_ub!(UbExperimental(format!(
+        let granting_idx = self.find_granting(access, derived_from, global).ok_or_else(|| {
+            global.note_failed(derived_from);
+            err_ub!(UbExperimental(format!(
                 "trying to reborrow for {:?}, but parent tag {:?} does not have an appropriate item in the borrow stack",
                 new.perm, derived_from,
-            ))))?;
+            )))
+        })?;
 
         // Compute where to put the new item.
         // Either way, we ensure that we insert the new item in a way such that between
         // `derived_from` and the new one, there are only items *compatible with* `derived_from`.
-        let new_idx = if new.perm == Permission::SharedReadWrite {
+        let new_idx = if new.perm == Permission::SharedReadWrite
+            || new.perm == Permission::Reserved
+        {
             assert!(
                 access == AccessKind::Write,
                 "this case only makes sense for stack-like accesses"
             );
-            // SharedReadWrite can coexist with "existing loans", meaning they don't act like a write
+            // SharedReadWrite and Reserved can coexist with "existing loans", meaning they don't act like a write
             // access.  Instead of popping the stack, we insert the item at the place the stack would
             // be popped to (i.e., we insert it above all the write-compatible items).
             // This ensures F2b by adding the new item below any potentially existing `SharedReadOnly`.
@@ -380,7 +680,7 @@ impl<'tcx> Stack {
             // A "safe" reborrow for a pointer that actually expects some aliasing guarantees.
             // Here, creating a reference actually counts as an access.
             // This ensures F2b for `Unique`, by removing offending `SharedReadOnly`.
-            self.access(access, derived_from, global)?;
+            self.access(access, derived_from, range, global)?;
 
             // We insert "as far up as possible": We know only compatible items are remaining
             // on top of `derived_from`, and we want the new item at the top so that we
@@ -400,6 +700,38 @@ impl<'tcx> Stack {
 
         Ok(())
     }
+
+    /// Removes the items of tags that are not `live`, unless that would change which accesses
+    /// the remaining items grant. The bottom item, untagged items and protected items are kept.
+    fn retain(&mut self, live: &HashSet<PtrId>, global: &GlobalState) {
+        let is_live = |item: &Item| {
+            let tag_live = match item.tag {
+                Tag::Tagged(id) => live.contains(&id),
+                Tag::Untagged | Tag::Wildcard => true,
+            };
+            tag_live || item.protector.map_or(false, |call| global.is_active(call))
+        };
+        let mut retained = Vec::with_capacity(self.borrows.len());
+        retained.push(self.borrows[0]);
+        for window in self.borrows.windows(2) {
+            let (below, item) = (window[0], window[1]);
+            // Items that are not `SharedReadWrite` end a block of `SharedReadWrite` items, and
+            // removing them would merge that block with the one above.
+            let ends_block = below.perm == Permission::SharedReadWrite
+                || below.perm == Permission::Reserved;
+            let keep = match item.perm {
+                Permission::SharedReadWrite | Permission::Reserved => is_live(&item),
+                Permission::Unique | Permission::SharedReadOnly | Permission::Disabled =>
+                    ends_block || is_live(&item),
+            };
+            if keep {
+                retained.push(item);
+            } else {
+                trace!("gc: removing item {:?}", item);
+            }
+        }
+        self.borrows = retained;
+    }
 }
 // # Stacked Borrows Core End
 
@@ -413,19 +745,43 @@ impl<'tcx> Stacks {
         Stacks { stacks: RefCell::new(RangeMap::new(size, stack)), global: extra }
     }
 
-    /// Call `f` on every stack in the range.
+    /// Call `f` on every stack in the range. `event` describes what `f` does, for
+    /// `-Zmiri-dump-borrow-stacks`.
     fn for_each(
         &self,
         ptr: Pointer<Tag>,
         size: Size,
-        f: impl Fn(&mut Stack, &GlobalState) -> InterpResult<'tcx>,
+        event: fmt::Arguments<'_>,
+        f: impl Fn(&mut Stack, TagRange, &mut GlobalState) -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx> {
-        let global = self.global.borrow();
+        let mut global = self.global.borrow_mut();
         let mut stacks = self.stacks.borrow_mut();
-        for stack in stacks.iter_mut(ptr.offset, size) {
-            f(stack, &*global)?;
+        let range = TagRange { alloc_id: ptr.alloc_id, offset: ptr.offset, size };
+        let result =
+            stacks.iter_mut(ptr.offset, size).try_for_each(|stack| f(stack, range, &mut *global));
+        if result.is_err() {
+            global.dump(&stacks, range, format_args!("{} (failed)", event));
+        } else {
+            global.dump(&stacks, range, event);
         }
-        Ok(())
+        result
+    }
+
+    /// Removes the items of tags that are not `live` from all stacks of the allocation
+    /// `alloc_id` of the given size.
+    pub(crate) fn retain(
+        &self,
+        alloc_id: AllocId,
+        size: Size,
+        live: &HashSet<PtrId>,
+        global: &GlobalState,
+    ) {
+        let mut stacks = self.stacks.borrow_mut();
+        for stack in stacks.iter_mut_all() {
+            stack.retain(live, global);
+        }
+        let range = TagRange { alloc_id, offset: Size::ZERO, size };
+        global.dump(&stacks, range, format_args!("garbage collection"));
     }
 }
 
@@ -443,7 +799,7 @@ impl Stacks {
             // not through a pointer). That is, whenever we directly write to a local, this will pop
             // everything else off the stack, invalidating all previous pointers,
             // and in particular, *all* raw pointers.
-            MemoryKind::Stack => (Tag::Tagged(extra.borrow_mut().new_ptr()), Permission::Unique),
+            MemoryKind::Stack => (Tag::Tagged(extra.borrow_mut().new_ptr(None)), Permission::Unique),
             // Static memory can be referenced by "global" pointers from `tcx`.
             // Thus we call `static_base_ptr` such that the global pointers get the same tag
             // as what we use here.
@@ -454,25 +810,47 @@ impl Stacks {
             // FIXME: experiment with more precise tracking.
             _ => (Tag::Untagged, Permission::SharedReadWrite),
         };
-        (Stacks::new(size, perm, tag, extra), tag)
+        extra.borrow_mut().allocs.insert(id);
+        if extra.borrow().is_tracked(tag) {
+            let range = TagRange { alloc_id: id, offset: Size::ZERO, size };
+            register_diagnostic(NonHaltingDiagnostic::CreatedPointerTag(tag, range));
+        }
+        let stacks = Stacks::new(size, perm, tag, extra);
+        let range = TagRange { alloc_id: id, offset: Size::ZERO, size };
+        stacks.global.borrow().dump(
+            &stacks.stacks.borrow(),
+            range,
+            format_args!("allocation with base tag {:?}", tag),
+        );
+        (stacks, tag)
     }
 
     #[inline(always)]
     pub fn memory_read<'tcx>(&self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
         trace!("read access with tag {:?}: {:?}, size {}", ptr.tag, ptr.erase_tag(), size.bytes());
-        self.for_each(ptr, size, |stack, global| {
-            stack.access(AccessKind::Read, ptr.tag, global)?;
-            Ok(())
-        })
+        if self.global.borrow().is_exempt() {
+            return Ok(());
+        }
+        self.for_each(
+            ptr,
+            size,
+            format_args!("read access with tag {:?}", ptr.tag),
+            |stack, range, global| stack.access(AccessKind::Read, ptr.tag, range, global),
+        )
     }
 
     #[inline(always)]
     pub fn memory_written<'tcx>(&mut self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
         trace!("write access with tag {:?}: {:?}, size {}", ptr.tag, ptr.erase_tag(), size.bytes());
-        self.for_each(ptr, size, |stack, global| {
-            stack.access(AccessKind::Write, ptr.tag, global)?;
-            Ok(())
-        })
+        if self.global.borrow().is_exempt() {
+            return Ok(());
+        }
+        self.for_each(
+            ptr,
+            size,
+            format_args!("write access with tag {:?}", ptr.tag),
+            |stack, range, global| stack.access(AccessKind::Write, ptr.tag, range, global),
+        )
     }
 
     #[inline(always)]
@@ -482,7 +860,16 @@ impl Stacks {
         size: Size,
     ) -> InterpResult<'tcx> {
         trace!("deallocation with tag {:?}: {:?}, size {}", ptr.tag, ptr.erase_tag(), size.bytes());
-        self.for_each(ptr, size, |stack, global| stack.dealloc(ptr.tag, global))
+        self.global.borrow_mut().allocs.remove(&ptr.alloc_id);
+        if self.global.borrow().is_exempt() {
+            return Ok(());
+        }
+        self.for_each(
+            ptr,
+            size,
+            format_args!("deallocation with tag {:?}", ptr.tag),
+            |stack, range, global| stack.dealloc(ptr.tag, range, global),
+        )
     }
 }
 
@@ -515,12 +902,29 @@ trait EvalContextPrivExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
         let extra = &this.memory.get_raw(ptr.alloc_id)?.extra;
         let stacked_borrows =
             extra.stacked_borrows.as_ref().expect("we should have Stacked Borrows data");
+        {
+            let mut global = stacked_borrows.global.borrow_mut();
+            // Two-phase borrows get reborrowed from their parent again when they are activated.
+            if let (RefKind::Unique { two_phase: true }, Tag::Tagged(id)) = (kind, new_tag) {
+                global.reservations.insert(id, ptr.tag);
+            }
+            // Tell the user about retags involving tracked tags, in either direction.
+            if global.is_tracked(new_tag) || global.is_tracked(ptr.tag) {
+                let range = TagRange { alloc_id: ptr.alloc_id, offset: ptr.offset, size };
+                register_diagnostic(NonHaltingDiagnostic::RetaggedPointerTag {
+                    new_tag,
+                    derived_from: ptr.tag,
+                    kind,
+                    range,
+                });
+            }
+        }
         // Update the stacks.
         // Make sure that raw pointers and mutable shared references are reborrowed "weak":
         // There could be existing unique pointers reborrowed from them that should remain valid!
         let perm = match kind {
             RefKind::Unique { two_phase: false } => Permission::Unique,
-            RefKind::Unique { two_phase: true } => Permission::SharedReadWrite,
+            RefKind::Unique { two_phase: true } => Permission::Reserved,
             RefKind::Raw { mutable: true } => Permission::SharedReadWrite,
             RefKind::Shared | RefKind::Raw { mutable: false } => {
                 // Shared references and *const are a whole different kind of game, the
@@ -534,14 +938,22 @@ trait EvalContextPrivExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
                         Permission::SharedReadWrite
                     };
                     let item = Item { perm, tag: new_tag, protector };
-                    stacked_borrows.for_each(cur_ptr, size, |stack, global| {
-                        stack.grant(cur_ptr.tag, item, global)
-                    })
+                    stacked_borrows.for_each(
+                        cur_ptr,
+                        size,
+                        format_args!("{} retag {:?} from {:?}", kind, item, cur_ptr.tag),
+                        |stack, range, global| stack.grant(cur_ptr.tag, item, range, global),
+                    )
                 });
             }
         };
         let item = Item { perm, tag: new_tag, protector };
-        stacked_borrows.for_each(ptr, size, |stack, global| stack.grant(ptr.tag, item, global))
+        stacked_borrows.for_each(
+            ptr,
+            size,
+            format_args!("{} retag {:?} from {:?}", kind, item, ptr.tag),
+            |stack, range, global| stack.grant(ptr.tag, item, range, global),
+        )
     }
 
     /// Retags an indidual pointer, returning the retagged version.
@@ -566,16 +978,26 @@ trait EvalContextPrivExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
             // Nothing to do for ZSTs.
             return Ok(*val);
         }
+        let global = this.memory.extra.stacked_borrows.as_ref().unwrap();
+        let (raw_pointer_tagging, protectors) = {
+            let global = global.borrow();
+            (global.raw_pointer_tagging, global.protectors)
+        };
+        let protect = protect && protectors;
+        if this.memory.extra.tree_borrows {
+            // Tree Borrows retags the same pointers, but tracks them differently.
+            return this.tb_retag_reference(place, size, kind, protect);
+        }
 
         // Compute new borrow.
         let new_tag = match kind {
-            // Give up tracking for raw pointers.
+            // Give up tracking for raw pointers, unless `-Zmiri-raw-pointer-tagging=on`.
             // FIXME: Experiment with more precise tracking. Blocked on `&raw`
             // because `Rc::into_raw` currently creates intermediate references,
             // breaking `Rc::from_raw`.
-            RefKind::Raw { .. } => Tag::Untagged,
+            RefKind::Raw { .. } if !raw_pointer_tagging => Tag::Untagged,
             // All other pointesr are properly tracked.
-            _ => Tag::Tagged(this.memory.extra.stacked_borrows.as_ref().unwrap().borrow_mut().new_ptr()),
+            _ => Tag::Tagged(global.borrow_mut().new_ptr(Some(kind))),
         };
 
         // Reborrow.
@@ -589,8 +1011,41 @@ trait EvalContextPrivExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx
 
 impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
 pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Notes where the next step of the interpreter happens, and whether it is exempt from
+    /// checking.
+    fn sb_note_step(&mut self) {
+        let this = self.eval_context_mut();
+        let stacked_borrows = match &this.memory.extra.stacked_borrows {
+            Some(stacked_borrows) => stacked_borrows,
+            None => return,
+        };
+        let mut global = stacked_borrows.borrow_mut();
+        let frame = this.stack().last();
+        global.current_span = frame.map_or(DUMMY_SP, |frame| {
+            frame.current_source_info().map_or(frame.span, |info| info.span)
+        });
+        global.exempt = match frame {
+            Some(frame) if !global.exempt_paths.is_empty() => {
+                let def_id = frame.instance.def_id();
+                match global.exempt_functions.get(&def_id) {
+                    Some(&exempt) => exempt,
+                    None => {
+                        let exempt = global.is_exempt_function(*this.tcx, def_id);
+                        global.exempt_functions.insert(def_id, exempt);
+                        exempt
+                    }
+                }
+            }
+            _ => false,
+        };
+    }
+
     fn retag(&mut self, kind: RetagKind, place: PlaceTy<'tcx, Tag>) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
+        if this.memory.extra.stacked_borrows.as_ref().unwrap().borrow().is_exempt() {
+            // Pointers created by exempt code keep their tags.
+            return Ok(());
+        }
         // Determine mutability and whether to add a protector.
         // Cannot use `builtin_deref` because that reports *immutable* for `Box`,
         // making it useless.
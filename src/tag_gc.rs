@@ -0,0 +1,169 @@
+//! Removes the items of tags that can never be used again from the borrow stacks. A tag can only
+//! be used through a pointer that carries it, so every tag that does not occur in memory, in the
+//! locals of a frame or in a value Miri keeps around for a shim is dead.
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::*;
+
+/// How many basic blocks are executed between two garbage collections, unless
+/// `-Zmiri-tag-gc` says otherwise.
+pub const DEFAULT_TAG_GC_INTERVAL: u64 = 10_000;
+
+/// Values that may contain tagged pointers.
+pub trait VisitTags {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag));
+}
+
+impl<T: VisitTags> VisitTags for Option<T> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        if let Some(x) = self {
+            x.visit_tags(visit);
+        }
+    }
+}
+
+impl<T: VisitTags> VisitTags for Vec<T> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        for x in self {
+            x.visit_tags(visit);
+        }
+    }
+}
+
+impl VisitTags for Tag {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        visit(*self);
+    }
+}
+
+impl VisitTags for Pointer<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        visit(self.tag);
+    }
+}
+
+impl VisitTags for Scalar<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        if let Scalar::Ptr(ptr) = self {
+            ptr.visit_tags(visit);
+        }
+    }
+}
+
+impl VisitTags for ScalarMaybeUndef<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        if let ScalarMaybeUndef::Scalar(scalar) = self {
+            scalar.visit_tags(visit);
+        }
+    }
+}
+
+impl VisitTags for Immediate<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        match self {
+            Immediate::Scalar(a) => a.visit_tags(visit),
+            Immediate::ScalarPair(a, b) => {
+                a.visit_tags(visit);
+                b.visit_tags(visit);
+            }
+        }
+    }
+}
+
+impl VisitTags for MemPlace<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        self.ptr.visit_tags(visit);
+        if let MemPlaceMeta::Meta(meta) = self.meta {
+            meta.visit_tags(visit);
+        }
+    }
+}
+
+impl VisitTags for Place<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        match self {
+            Place::Ptr(mplace) => mplace.visit_tags(visit),
+            // The local is visited with its frame.
+            Place::Local { .. } => {}
+        }
+    }
+}
+
+impl VisitTags for Operand<Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        match self {
+            Operand::Immediate(imm) => imm.visit_tags(visit),
+            Operand::Indirect(mplace) => mplace.visit_tags(visit),
+        }
+    }
+}
+
+impl<'tcx> VisitTags for ImmTy<'tcx, Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        (**self).visit_tags(visit);
+    }
+}
+
+impl<'tcx> VisitTags for MPlaceTy<'tcx, Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        (**self).visit_tags(visit);
+    }
+}
+
+impl<'tcx> VisitTags for PlaceTy<'tcx, Tag> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        (**self).visit_tags(visit);
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Removes the items of dead tags from all borrow stacks, and forgets what Stacked Borrows
+    /// remembers about those tags.
+    fn garbage_collect_tags(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let stacked_borrows = match &this.memory.extra.stacked_borrows {
+            Some(stacked_borrows) if !this.memory.extra.tree_borrows => Rc::clone(stacked_borrows),
+            _ => return Ok(()),
+        };
+        let allocs = stacked_borrows.borrow().live_allocs();
+
+        let mut live = HashSet::new();
+        let mut visit = |tag| {
+            if let Tag::Tagged(id) = tag {
+                live.insert(id);
+            }
+        };
+        for frame in this.stack() {
+            for local in frame.locals.iter() {
+                if let LocalValue::Live(op) = &local.value {
+                    op.visit_tags(&mut visit);
+                }
+            }
+            frame.return_place.visit_tags(&mut visit);
+            frame.extra.visit_tags(&mut visit);
+        }
+        this.machine.visit_tags(&mut visit);
+        for &id in &allocs {
+            let alloc = this.memory.get_raw(id)?;
+            for &(_, (tag, _)) in alloc.relocations().iter() {
+                visit(tag);
+            }
+            if let Some(race) = &alloc.extra.data_race {
+                race.weak_memory.visit_tags(&mut visit);
+            }
+        }
+
+        stacked_borrows.borrow_mut().retain_live_tags(&mut live);
+        let global = stacked_borrows.borrow();
+        for &id in &allocs {
+            let alloc = this.memory.get_raw(id)?;
+            if let Some(stacks) = &alloc.extra.stacked_borrows {
+                stacks.retain(id, alloc.size, &live, &global);
+            }
+        }
+        Ok(())
+    }
+}
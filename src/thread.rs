@@ -0,0 +1,651 @@
+//! The threads of the interpreted program.
+//!
+//! Miri has a single interpreter stack, so threads cannot be interleaved arbitrarily. Instead,
+//! a new thread is pending until some thread waits for it: then the frames of the new thread are
+//! pushed on top of the frames of the waiting thread, and the waiting thread continues once the
+//! new thread has finished. A thread therefore only ever waits for threads that run on top of it,
+//! which is why waiting for a thread that is suspended further down the stack is a deadlock.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+
+use rustc::ty::{self, layout::{LayoutOf, TyLayout}};
+
+use crate::*;
+
+/// The id of a thread, which is its index in the list of all threads ever created. The main
+/// thread has id 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ThreadId(u32);
+
+impl ThreadId {
+    pub const MAIN: ThreadId = ThreadId(0);
+
+    pub fn to_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for ThreadId {
+    fn from(id: u32) -> Self {
+        ThreadId(id)
+    }
+}
+
+impl Default for ThreadId {
+    fn default() -> Self {
+        ThreadId::MAIN
+    }
+}
+
+impl fmt::Display for ThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum ThreadState<'tcx> {
+    /// The thread was created, but its start routine was not called yet.
+    Pending { start: ty::Instance<'tcx>, arg: Scalar<Tag> },
+    /// The thread is executing, or it is suspended below the frames of another thread.
+    Running,
+    /// The start routine returned, and the thread-local destructors of the thread are running.
+    Exiting,
+    /// The thread has finished.
+    Terminated,
+}
+
+/// How a thread that blocks continues, see `block_active_thread`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockResult {
+    /// The thread was woken up.
+    Woken,
+    /// The timeout expired before the thread was woken up.
+    TimedOut,
+    /// Another thread was started, which may wake the blocked thread up. The blocking operation
+    /// has to be repeated once that thread finished.
+    Retry,
+}
+
+/// What a blocked thread waits for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockReason {
+    /// Waiting for the thread to terminate, e.g. in `pthread_join`.
+    Join(ThreadId),
+    Mutex,
+    RwLock,
+    CondVar,
+    Futex,
+    Semaphore,
+    Barrier,
+    /// Waiting for the initialization routine of `pthread_once` or `dispatch_once_f` to return.
+    Once,
+    /// Waiting for a timer file descriptor to expire.
+    Timer,
+    /// Waiting for data in an empty pipe whose write end is still open.
+    Pipe,
+    /// Letting other threads run at a yield point, see `yield_active_thread`.
+    Yield,
+}
+
+impl fmt::Display for BlockReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockReason::Join(id) => write!(f, "waiting for thread {} to terminate", id),
+            BlockReason::Mutex => write!(f, "waiting to lock a mutex"),
+            BlockReason::RwLock => write!(f, "waiting to lock a read-write lock"),
+            BlockReason::CondVar => write!(f, "waiting for a condition variable"),
+            BlockReason::Futex => write!(f, "waiting on a futex"),
+            BlockReason::Semaphore => write!(f, "waiting for a semaphore"),
+            BlockReason::Barrier => write!(f, "waiting at a barrier"),
+            BlockReason::Once => write!(f, "waiting for a once-only initialization"),
+            BlockReason::Timer => write!(f, "waiting for a timer to expire"),
+            BlockReason::Pipe => write!(f, "waiting to read from a pipe"),
+            BlockReason::Yield => write!(f, "yielding to other threads"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Thread<'tcx> {
+    state: ThreadState<'tcx>,
+    /// Where the start routine of the thread stores its return value. The main thread has none.
+    ret_place: Option<MPlaceTy<'tcx, Tag>>,
+    /// Whether the thread was detached or joined, so it cannot be joined anymore.
+    detached: bool,
+    /// The `errno` of the thread while it is not the active thread, whose `errno` is the
+    /// `last_error` of the machine.
+    last_error: Option<MPlaceTy<'tcx, Tag>>,
+    /// What the thread waits for while it is suspended below the frames of another thread, or
+    /// when it deadlocked.
+    blocked_on: Option<BlockReason>,
+    /// The name set with `pthread_setname_np` or `prctl`. The main thread is called `main`.
+    name: Option<Vec<u8>>,
+    /// Whether the thread called `ExitThread` or `pthread_exit`, and its frames are being popped,
+    /// see `exit_thread`.
+    exits_early: bool,
+    /// When the wait of the thread in `wait_for_thread_until` times out. The wait is repeated
+    /// whenever a thread that ran on top of it returns, so the deadline is kept here meanwhile.
+    wait_deadline: Option<Duration>,
+}
+
+impl<'tcx> Thread<'tcx> {
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        match self.state {
+            ThreadState::Terminated => true,
+            _ => false,
+        }
+    }
+
+    pub fn blocked_on(&self) -> Option<BlockReason> {
+        self.blocked_on
+    }
+
+    pub fn name(&self) -> Option<&[u8]> {
+        self.name.as_deref()
+    }
+
+    /// A human-readable description of what the thread is doing.
+    pub fn state_description(&self) -> String {
+        if let Some(reason) = self.blocked_on {
+            return format!("blocked {}", reason);
+        }
+        match self.state {
+            ThreadState::Pending { .. } => "not started yet",
+            ThreadState::Running => "running",
+            ThreadState::Exiting => "running thread-local destructors",
+            ThreadState::Terminated => "terminated",
+        }
+        .to_owned()
+    }
+}
+
+#[derive(Debug)]
+pub struct ThreadManager<'tcx> {
+    /// The thread whose frames are on top of the stack.
+    active_thread: ThreadId,
+    /// All threads that were ever created, indexed by their id.
+    threads: Vec<Thread<'tcx>>,
+}
+
+impl<'tcx> Default for ThreadManager<'tcx> {
+    fn default() -> Self {
+        let main = Thread {
+            state: ThreadState::Running,
+            ret_place: None,
+            detached: false,
+            last_error: None,
+            blocked_on: None,
+            name: Some(b"main".to_vec()),
+            exits_early: false,
+            wait_deadline: None,
+        };
+        ThreadManager { active_thread: ThreadId::MAIN, threads: vec![main] }
+    }
+}
+
+impl<'tcx> ThreadManager<'tcx> {
+    pub fn active_thread(&self) -> ThreadId {
+        self.active_thread
+    }
+
+    /// Returns the thread with the given id, if it was ever created.
+    pub fn get(&self, id: ThreadId) -> Option<&Thread<'tcx>> {
+        self.threads.get(id.0 as usize)
+    }
+
+    /// Describes the thread `id` for diagnostics.
+    pub fn describe(&self, id: ThreadId) -> String {
+        describe_thread(id, self.get(id).and_then(Thread::name))
+    }
+
+    /// Whether the frames of the active thread are being popped because it exits early.
+    pub fn active_thread_exits_early(&self) -> bool {
+        self.threads[self.active_thread.0 as usize].exits_early
+    }
+
+    /// Iterates over all threads that were ever created.
+    pub fn iter(&self) -> impl Iterator<Item = (ThreadId, &Thread<'tcx>)> {
+        self.threads.iter().enumerate().map(|(idx, thread)| (ThreadId(idx as u32), thread))
+    }
+
+    /// Returns the threads that were created but did not start running yet, oldest first.
+    pub fn pending_threads(&self) -> Vec<ThreadId> {
+        self.iter()
+            .filter(|(_, thread)| match thread.state {
+                ThreadState::Pending { .. } => true,
+                _ => false,
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn get_mut(&mut self, id: ThreadId) -> &mut Thread<'tcx> {
+        &mut self.threads[id.0 as usize]
+    }
+
+    /// Returns which frames of the interpreter stack belong to which thread, starting with the
+    /// active thread at the top of the stack.
+    pub fn thread_frames<'mir>(
+        &self,
+        stack: &[Frame<'mir, 'tcx, Tag, FrameData<'tcx>>],
+    ) -> Vec<(ThreadId, Range<usize>)> {
+        let mut frames = Vec::new();
+        let mut thread = self.active_thread;
+        let mut end = stack.len();
+        for (idx, frame) in stack.iter().enumerate().rev() {
+            if let Some(waiting) = frame.extra.resumes_thread {
+                frames.push((thread, idx..end));
+                thread = waiting;
+                end = idx;
+            }
+        }
+        frames.push((thread, 0..end));
+        frames
+    }
+}
+
+/// Describes the thread `id` called `name` for diagnostics.
+pub fn describe_thread(id: ThreadId, name: Option<&[u8]>) -> String {
+    match name {
+        Some(name) => format!("thread {} `{}`", id, String::from_utf8_lossy(name)),
+        None => format!("thread {}", id),
+    }
+}
+
+impl<'tcx> VisitTags for ThreadManager<'tcx> {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        for thread in &self.threads {
+            if let ThreadState::Pending { arg, .. } = &thread.state {
+                arg.visit_tags(visit);
+            }
+            thread.ret_place.visit_tags(visit);
+            thread.last_error.visit_tags(visit);
+        }
+    }
+}
+
+/// The copies of the `#[thread_local]` statics for all threads but the main thread, which uses
+/// the statics themselves.
+#[derive(Clone, Debug, Default)]
+pub struct ThreadLocalStatics {
+    /// A copy of the active thread of the `ThreadManager`, because allocation ids are
+    /// redirected with access to the memory only.
+    pub(crate) active_thread: ThreadId,
+    /// The allocation used by each thread for each static that it accessed.
+    allocations: RefCell<HashMap<(AllocId, ThreadId), AllocId>>,
+}
+
+impl ThreadLocalStatics {
+    /// Returns the allocation that the active thread uses for the `#[thread_local]` static with
+    /// the given definition and allocation. The first access of a thread creates a new allocation
+    /// with the initial value of the static.
+    pub fn allocation_for_active_thread(
+        &self,
+        tcx: ty::TyCtxt<'_>,
+        def_id: rustc_hir::def_id::DefId,
+        id: AllocId,
+    ) -> AllocId {
+        if self.active_thread == ThreadId::MAIN {
+            return id;
+        }
+        *self.allocations.borrow_mut().entry((id, self.active_thread)).or_insert_with(|| {
+            let gid = GlobalId { instance: ty::Instance::mono(tcx, def_id), promoted: None };
+            let initial = tcx
+                .const_eval_raw(ty::ParamEnv::reveal_all().and(gid))
+                .expect("the static was evaluated when the main thread accessed it");
+            let mut alloc_map = tcx.alloc_map.lock();
+            let initial = alloc_map.unwrap_memory(initial.alloc_id);
+            let new_id = alloc_map.reserve();
+            alloc_map.set_alloc_id_memory(new_id, initial);
+            new_id
+        })
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Registers a new thread that calls `start` with `arg` once some thread waits for it. The
+    /// start routine returns a value of layout `ret_layout`.
+    fn create_thread(
+        &mut self,
+        start: Scalar<Tag>,
+        arg: Scalar<Tag>,
+        ret_layout: TyLayout<'tcx>,
+    ) -> InterpResult<'tcx, ThreadId> {
+        let this = self.eval_context_mut();
+
+        let start = this.memory.get_fn(start)?.as_instance()?;
+        let ret_place = this.allocate(ret_layout, MiriMemoryKind::Env.into());
+        let errno_layout = this.layout_of(this.tcx.types.u32)?;
+        let errno_place = this.allocate(errno_layout, MiriMemoryKind::Env.into());
+        this.write_scalar(Scalar::from_u32(0), errno_place.into())?;
+        let threads = &mut this.machine.threads.threads;
+        let id = ThreadId(threads.len() as u32);
+        threads.push(Thread {
+            state: ThreadState::Pending { start, arg },
+            ret_place: Some(ret_place),
+            detached: false,
+            last_error: Some(errno_place),
+            blocked_on: None,
+            name: None,
+            exits_early: false,
+            wait_deadline: None,
+        });
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().thread_created(id);
+        }
+        trace!("Created thread {} running {:?} with argument {:?}", id, start, arg);
+        Ok(id)
+    }
+
+    /// Makes the thread with the given id the active thread.
+    fn set_active_thread(&mut self, id: ThreadId) {
+        let this = self.eval_context_mut();
+        trace!("Switching from thread {} to thread {}", this.machine.threads.active_thread, id);
+        let previous = this.machine.threads.active_thread;
+        let last_error = this.machine.last_error.take();
+        this.machine.threads.get_mut(previous).last_error = last_error;
+        this.machine.last_error = this.machine.threads.get_mut(id).last_error.take();
+        this.machine.threads.active_thread = id;
+        this.memory.extra.thread_local_statics.active_thread = id;
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().set_active_thread(id);
+        }
+    }
+
+    /// Called when a frame that was pushed to run a different thread is popped, to continue with
+    /// the thread `resumed` that was waiting for it.
+    fn resume_thread(&mut self, resumed: ThreadId, unwinding: bool) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let finished = this.machine.threads.active_thread;
+        let thread = this.machine.threads.get_mut(finished);
+        if unwinding && !thread.exits_early {
+            throw_ub_format!("unwinding past the topmost frame of a thread");
+        }
+        thread.exits_early = false;
+        if let ThreadState::Running = thread.state {
+            // This was the frame of the start routine.
+            thread.state = ThreadState::Exiting;
+        }
+        this.machine.threads.get_mut(resumed).blocked_on = None;
+        this.set_active_thread(resumed);
+        Ok(())
+    }
+
+    /// Lets the thread `id` make progress so that the active thread, blocked for `reason`, can
+    /// wait for it. Returns `true` if a frame of that thread was pushed; the active thread is
+    /// resumed when it is popped, and should check again. Returns `false` if the thread has
+    /// terminated.
+    fn run_thread_for_wait(
+        &mut self,
+        id: ThreadId,
+        reason: BlockReason,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        let waiting = this.machine.threads.active_thread;
+        let thread = this.machine.threads.get_mut(id);
+        match thread.state {
+            ThreadState::Pending { start, arg } => {
+                thread.state = ThreadState::Running;
+                let ret_place = thread.ret_place.expect("only the main thread has no return place");
+                trace!("Starting thread {} on top of thread {}", id, waiting);
+                this.set_active_thread(id);
+                this.call_function(
+                    start,
+                    &[arg.into()],
+                    Some(ret_place.into()),
+                    StackPopCleanup::None { cleanup: true },
+                )?;
+            }
+            ThreadState::Running => {
+                // The thread is suspended further down, waiting for us. Unless it waits for us to
+                // terminate, it would continue if threads were interleaved arbitrarily.
+                if thread.blocked_on == Some(BlockReason::Join(waiting)) {
+                    return this.deadlock(reason);
+                }
+                this.machine.threads.get_mut(waiting).blocked_on = Some(reason);
+                throw_machine_stop!(TerminationInfo::Deadlock { suspended_below: Some(id) })
+            }
+            ThreadState::Exiting => {
+                this.set_active_thread(id);
+                if !this.call_next_tls_dtor()? {
+                    this.warn_pending_tls_dtors();
+                    this.set_active_thread(waiting);
+                    trace!("Thread {} terminated", id);
+                    let thread = this.machine.threads.get_mut(id);
+                    thread.state = ThreadState::Terminated;
+                    if thread.detached {
+                        // Nobody can read the return value anymore. The thread does not
+                        // synchronize with the waiting thread, which frees it on its behalf.
+                        let ret_place = thread.ret_place.take().expect("thread was already joined");
+                        let ret_ptr = ret_place.ptr.assert_ptr();
+                        this.allow_data_races_mut(|this| {
+                            this.memory.deallocate(ret_ptr, None, MiriMemoryKind::Env.into())
+                        })?;
+                    }
+                    return Ok(false);
+                }
+            }
+            ThreadState::Terminated => return Ok(false),
+        }
+        this.frame_mut().extra.resumes_thread = Some(waiting);
+        this.machine.threads.get_mut(waiting).blocked_on = Some(reason);
+        Ok(true)
+    }
+
+    /// Ends the active thread with the return value `ret`, like `ExitThread` and `pthread_exit`:
+    /// its frames are popped without running any more of their code, so the destructors of their
+    /// locals do not run either, and then the thread runs its thread-local destructors and
+    /// terminates as if its start routine had returned `ret`. The popping happens in the next
+    /// steps, see `step_with_hooks`; the shim must not jump anywhere.
+    fn exit_thread(&mut self, ret: Scalar<Tag>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread;
+        if active == ThreadId::MAIN {
+            throw_unsup_format!("Miri does not support exiting the main thread early");
+        }
+        let thread = this.machine.threads.get_mut(active);
+        thread.exits_early = true;
+        let ret_place = thread.ret_place.expect("only the main thread has no return place");
+        this.write_scalar(ret, ret_place.into())?;
+        // A frame without a current block is unwinding, so the next step pops it without running
+        // any code. This starts with the frame that called the shim.
+        this.frame_mut().block = None;
+        Ok(())
+    }
+
+    /// Lets the active thread wait for the thread `id` to terminate, like `run_thread_for_wait`. If
+    /// `id` did not start yet, the active thread blocks, so the scheduler decides which of the
+    /// threads that did not start yet runs first. That is `id` by default, but need not be.
+    /// Returns `true` if a frame of some thread was pushed, and the wait has to be repeated once
+    /// it returns.
+    fn wait_for_thread(&mut self, id: ThreadId) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        let pending = match this.machine.threads.get(id).map(|thread| &thread.state) {
+            Some(ThreadState::Pending { .. }) => true,
+            _ => false,
+        };
+        if pending {
+            let chosen =
+                this.choose_pending_thread(Some(id))?.expect("the joined thread is pending");
+            this.run_thread_for_wait(chosen, BlockReason::Join(id))
+        } else {
+            this.run_thread_for_wait(id, BlockReason::Join(id))
+        }
+    }
+
+    /// Like `wait_for_thread`, but gives up once `timeout` has passed on the monotonic clock since
+    /// the wait started. Returns `None` if the wait has to be repeated, and otherwise whether `id`
+    /// terminated in time. Threads are not interleaved, so a thread that runs on top of the
+    /// waiting thread always runs to completion; if that takes longer than `timeout`, the wait
+    /// still times out, as if the waiting thread woke up but did not get to run before.
+    fn wait_for_thread_until(
+        &mut self,
+        id: ThreadId,
+        timeout: Duration,
+    ) -> InterpResult<'tcx, Option<bool>> {
+        let this = self.eval_context_mut();
+
+        let active = this.machine.threads.active_thread;
+        let now = this.machine.clock.monotonic();
+        let deadline =
+            *this.machine.threads.get_mut(active).wait_deadline.get_or_insert(now + timeout);
+        let terminated = this.machine.threads.get(id).map_or(false, Thread::is_terminated);
+        let result = if !terminated && now >= deadline {
+            false
+        } else if this.wait_for_thread(id)? {
+            return Ok(None);
+        } else {
+            true
+        };
+        this.machine.threads.get_mut(active).wait_deadline = None;
+        Ok(Some(result))
+    }
+
+    /// Decides how the active thread continues, which blocks for `reason` until it is `woken` up
+    /// or `timeout` expires. `timeout` is the time remaining until the deadline, if there is one.
+    fn block_active_thread(
+        &mut self,
+        woken: bool,
+        timeout: Option<Duration>,
+        reason: BlockReason,
+    ) -> InterpResult<'tcx, BlockResult> {
+        let this = self.eval_context_mut();
+
+        if woken {
+            Ok(BlockResult::Woken)
+        } else if timeout == Some(Duration::from_secs(0)) {
+            Ok(BlockResult::TimedOut)
+        } else if let Some(id) = this.choose_pending_thread(None)? {
+            // Threads run one after the other, so only a thread that did not start yet can still
+            // wake us up. Let it run until it finishes, and then check again.
+            this.run_thread_for_wait(id, reason)?;
+            Ok(BlockResult::Retry)
+        } else if let Some(timeout) = timeout {
+            // Nobody can wake us up anymore, so we wait until the deadline.
+            this.machine.clock.sleep(timeout);
+            Ok(BlockResult::TimedOut)
+        } else {
+            this.deadlock(reason)
+        }
+    }
+
+    /// Lets the threads that did not start yet run at a yield point of the active thread, e.g.
+    /// `sched_yield` or a spin loop hint, so that spin loops waiting for them make progress.
+    /// Returns `true` if a frame of such a thread was pushed; the yield has to be repeated once
+    /// that frame returns.
+    fn yield_active_thread(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        match this.choose_pending_thread(None)? {
+            Some(id) => this.run_thread_for_wait(id, BlockReason::Yield),
+            None => Ok(false),
+        }
+    }
+
+    /// Chooses which of the threads that did not start yet runs next, see `crate::schedule`. The
+    /// default choice is `preferred` if it is given, and the oldest thread otherwise. Returns
+    /// `None` if there is no such thread.
+    fn choose_pending_thread(
+        &mut self,
+        preferred: Option<ThreadId>,
+    ) -> InterpResult<'tcx, Option<ThreadId>> {
+        let this = self.eval_context_mut();
+
+        let mut pending = this.machine.threads.pending_threads();
+        if let Some(pos) = preferred.and_then(|id| pending.iter().position(|&p| p == id)) {
+            let preferred = pending.remove(pos);
+            pending.insert(0, preferred);
+        }
+        if pending.is_empty() {
+            return Ok(None);
+        }
+        match this.machine.schedule.choose(&pending) {
+            Ok(id) => Ok(Some(id)),
+            Err(msg) => throw_unsup_format!("the replayed schedule does not fit the program: {}", msg),
+        }
+    }
+
+    /// Stops the program because the active thread blocked for `reason`, and no thread can ever
+    /// wake it up.
+    fn deadlock<T>(&mut self, reason: BlockReason) -> InterpResult<'tcx, T> {
+        let this = self.eval_context_mut();
+        let active = this.machine.threads.active_thread;
+        this.machine.threads.get_mut(active).blocked_on = Some(reason);
+        throw_machine_stop!(TerminationInfo::Deadlock { suspended_below: None })
+    }
+
+    /// Checks that the thread `id` exists and can still be joined or detached. Returns the error
+    /// code to report otherwise.
+    fn check_joinable(&mut self, id: ThreadId) -> InterpResult<'tcx, Option<i32>> {
+        let this = self.eval_context_mut();
+
+        let error = match this.machine.threads.get(id) {
+            None => "ESRCH",
+            Some(thread) if thread.detached => "EINVAL",
+            Some(_) if id == this.machine.threads.active_thread => "EDEADLK",
+            Some(_) => return Ok(None),
+        };
+        Ok(Some(this.eval_libc_i32(error)?))
+    }
+
+    /// Marks the terminated thread `id` as joined, and returns the value its start routine
+    /// returned.
+    fn join_thread(&mut self, id: ThreadId) -> InterpResult<'tcx, Scalar<Tag>> {
+        let this = self.eval_context_mut();
+
+        this.thread_joined(id);
+        let thread = this.machine.threads.get_mut(id);
+        thread.detached = true;
+        let ret_place = thread.ret_place.take().expect("thread was already joined");
+        let ret = this.read_scalar(ret_place.into())?.not_undef()?;
+        this.memory.deallocate(ret_place.ptr.assert_ptr(), None, MiriMemoryKind::Env.into())?;
+        Ok(ret)
+    }
+
+    /// Marks the thread `id` as detached, so that it cannot be joined anymore.
+    fn detach_thread(&mut self, id: ThreadId) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let thread = this.machine.threads.get_mut(id);
+        thread.detached = true;
+        if let ThreadState::Terminated = thread.state {
+            let ret_ptr = thread.ret_place.take().expect("thread was already joined").ptr;
+            this.allow_data_races_mut(|this| {
+                this.memory.deallocate(ret_ptr.assert_ptr(), None, MiriMemoryKind::Env.into())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Sets the name of the thread `id`.
+    fn set_thread_name(&mut self, id: ThreadId, name: Vec<u8>) {
+        let this = self.eval_context_mut();
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().set_thread_name(id, name.clone());
+        }
+        this.machine.threads.get_mut(id).name = Some(name);
+    }
+
+    /// Called when the active thread learned that the thread `id` terminated, e.g. by joining
+    /// it: everything that thread did happens before what the active thread does from now on.
+    fn thread_joined(&mut self, id: ThreadId) {
+        let this = self.eval_context_mut();
+        if let Some(data_race) = &this.memory.extra.data_race {
+            data_race.borrow_mut().thread_joined(id);
+        }
+    }
+}
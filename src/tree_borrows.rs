@@ -0,0 +1,339 @@
+//! Implements an experimental "Tree Borrows" aliasing model, selected with `-Zmiri-tree-borrows`
+//! instead of Stacked Borrows.
+//!
+//! Every allocation has a tree of tags: the base tag is the root, and reborrowing a pointer adds
+//! the new tag as a child of the tag it was derived from. Every tag has a permission for every
+//! location. An access through a tag is a *child access* for that tag and all its ancestors, and
+//! a *foreign access* for all other tags; both kinds of accesses update the permissions, and
+//! child accesses fail if the permission does not allow them.
+//!
+//! Compared to Stacked Borrows, raw pointers keep the tag of the reference they are derived from,
+//! and mutable references only become unique on their first write. This accepts two-phase-like
+//! reborrows and raw pointer round trips that Stacked Borrows rejects.
+//!
+//! The tags, protectors and tag histories are shared with Stacked Borrows.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use rustc::ty::layout::Size;
+
+use crate::stacked_borrows::{AccessKind, CallId, GlobalState, MemoryExtra, RefKind, TagRange};
+use crate::*;
+
+pub type AllocExtra = Tree;
+
+/// The permission of a tag for a location.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Permission {
+    /// A mutable reference that was not written to yet. Tolerates foreign reads, so that the
+    /// parent can still be read while the reference is only reserved (like two-phase borrows).
+    Reserved,
+    /// A mutable reference that was written to, or the base tag of an allocation.
+    Active,
+    /// A shared reference, or a mutable reference that saw a foreign read after being written to.
+    Frozen,
+    /// No access is allowed any more.
+    Disabled,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Reserved => write!(f, "Reserved"),
+            Permission::Active => write!(f, "Active"),
+            Permission::Frozen => write!(f, "Frozen"),
+            Permission::Disabled => write!(f, "Disabled"),
+        }
+    }
+}
+
+impl Permission {
+    /// The new permission after an access through this tag or one of its children, or `None`
+    /// if the access is not allowed.
+    fn child_access(self, access: AccessKind) -> Option<Permission> {
+        match (self, access) {
+            (Permission::Disabled, _) => None,
+            (Permission::Frozen, AccessKind::Write) => None,
+            (Permission::Reserved, AccessKind::Write) => Some(Permission::Active),
+            (perm, _) => Some(perm),
+        }
+    }
+
+    /// The new permission after an access through a tag that is not this one or one of its
+    /// children.
+    fn foreign_access(self, access: AccessKind) -> Permission {
+        match (self, access) {
+            (Permission::Active, AccessKind::Read) => Permission::Frozen,
+            (perm, AccessKind::Read) => perm,
+            (_, AccessKind::Write) => Permission::Disabled,
+        }
+    }
+
+    /// Whether a protected tag may go from this permission to `new`: protected references must
+    /// keep the permissions they had when the call started.
+    fn may_protected_become(self, new: Permission) -> bool {
+        match (self, new) {
+            (_, Permission::Disabled) => false,
+            (Permission::Active, Permission::Frozen) => false,
+            _ => true,
+        }
+    }
+}
+
+/// A tag in the tree.
+#[derive(Clone, Debug)]
+struct Node {
+    tag: Tag,
+    /// The tag this one was derived from, `None` for the root.
+    parent: Option<usize>,
+    /// An optional protector, ensuring the tag keeps its permissions until `CallId` is over.
+    protector: Option<CallId>,
+}
+
+/// Extra per-allocation state.
+#[derive(Clone, Debug)]
+pub struct Tree {
+    // Even reading memory can update permissions, so we need a `RefCell` here.
+    inner: RefCell<TreeInner>,
+    // Pointer to global state
+    global: MemoryExtra,
+}
+
+#[derive(Clone, Debug)]
+struct TreeInner {
+    /// The root comes first, and every tag comes after its parent.
+    nodes: Vec<Node>,
+    /// The index in `nodes` of every tag.
+    indices: HashMap<Tag, usize>,
+    /// For every location, the permission of every tag, indexed like `nodes`.
+    perms: RangeMap<Vec<Permission>>,
+}
+
+impl<'tcx> TreeInner {
+    /// Finds the node of `tag`. Untagged and wildcard pointers (e.g. from integer casts) can use
+    /// the permissions of the base tag.
+    fn index(&self, tag: Tag, global: &mut GlobalState) -> InterpResult<'tcx, usize> {
+        match self.indices.get(&tag) {
+            Some(&idx) => Ok(idx),
+            None if tag == Tag::Untagged || tag == Tag::Wildcard => Ok(0),
+            None => {
+                global.note_failed(tag);
+                throw_ub!(UbExperimental(format!(
+                    "tag {:?} is not part of the borrow tree of this allocation",
+                    tag
+                )))
+            }
+        }
+    }
+
+    /// Performs an access through `tag` on `range`.
+    fn access(
+        &mut self,
+        access: AccessKind,
+        tag: Tag,
+        range: TagRange,
+        global: &mut GlobalState,
+    ) -> InterpResult<'tcx> {
+        let idx = self.index(tag, global)?;
+        // Find the tags for which this is a child access.
+        let mut is_child_access = vec![false; self.nodes.len()];
+        let mut cur = Some(idx);
+        while let Some(ancestor) = cur {
+            is_child_access[ancestor] = true;
+            cur = self.nodes[ancestor].parent;
+        }
+
+        let nodes = &self.nodes;
+        for perms in self.perms.iter_mut(range.offset, range.size) {
+            for ((node, perm), &is_child_access) in
+                nodes.iter().zip(perms.iter_mut()).zip(&is_child_access)
+            {
+                let new = if is_child_access {
+                    match perm.child_access(access) {
+                        Some(new) => new,
+                        None => {
+                            global.note_failed(node.tag);
+                            throw_ub!(UbExperimental(format!(
+                                "{} through tag {:?} is not allowed because tag {:?} has permission {}",
+                                access, tag, node.tag, perm,
+                            )));
+                        }
+                    }
+                } else {
+                    perm.foreign_access(access)
+                };
+                if new == *perm {
+                    continue;
+                }
+                if let Some(call) = node.protector {
+                    if global.is_active(call) && !perm.may_protected_become(new) {
+                        throw_ub!(UbExperimental(format!(
+                            "{} through tag {:?} would change the permission of protected tag {:?} from {} to {}",
+                            access, tag, node.tag, perm, new,
+                        )));
+                    }
+                }
+                if new == Permission::Disabled {
+                    global.note_invalidated(node.tag, Some(access));
+                }
+                *perm = new;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Map per-tree operations to the accesses and reborrows of the memory hooks.
+impl<'tcx> Tree {
+    pub fn new_allocation(
+        id: AllocId,
+        size: Size,
+        extra: MemoryExtra,
+        kind: MemoryKind<MiriMemoryKind>,
+    ) -> (Self, Tag) {
+        // Use the same base tags as Stacked Borrows, in particular for statics that `tcx` has
+        // pointers to.
+        let tag = match kind {
+            MemoryKind::Stack => Tag::Tagged(extra.borrow_mut().new_ptr(None)),
+            MemoryKind::Machine(MiriMemoryKind::Static) => extra.borrow_mut().static_base_ptr(id),
+            _ => Tag::Untagged,
+        };
+        if extra.borrow().is_tracked(tag) {
+            let range = TagRange { alloc_id: id, offset: Size::ZERO, size };
+            register_diagnostic(NonHaltingDiagnostic::CreatedPointerTag(tag, range));
+        }
+        let root = Node { tag, parent: None, protector: None };
+        let mut indices = HashMap::default();
+        indices.insert(tag, 0);
+        let inner = TreeInner {
+            nodes: vec![root],
+            indices,
+            perms: RangeMap::new(size, vec![Permission::Active]),
+        };
+        (Tree { inner: RefCell::new(inner), global: extra }, tag)
+    }
+
+    #[inline(always)]
+    pub fn memory_read(&self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
+        trace!("read access with tag {:?}: {:?}, size {}", ptr.tag, ptr.erase_tag(), size.bytes());
+        let range = TagRange { alloc_id: ptr.alloc_id, offset: ptr.offset, size };
+        let mut global = self.global.borrow_mut();
+        if global.is_exempt() {
+            return Ok(());
+        }
+        self.inner.borrow_mut().access(AccessKind::Read, ptr.tag, range, &mut global)
+    }
+
+    #[inline(always)]
+    pub fn memory_written(&mut self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
+        trace!("write access with tag {:?}: {:?}, size {}", ptr.tag, ptr.erase_tag(), size.bytes());
+        let range = TagRange { alloc_id: ptr.alloc_id, offset: ptr.offset, size };
+        let mut global = self.global.borrow_mut();
+        if global.is_exempt() {
+            return Ok(());
+        }
+        self.inner.get_mut().access(AccessKind::Write, ptr.tag, range, &mut global)
+    }
+
+    #[inline(always)]
+    pub fn memory_deallocated(&mut self, ptr: Pointer<Tag>, size: Size) -> InterpResult<'tcx> {
+        trace!("deallocation with tag {:?}: {:?}, size {}", ptr.tag, ptr.erase_tag(), size.bytes());
+        // Like a write access, but also no tag may be protected any more.
+        let range = TagRange { alloc_id: ptr.alloc_id, offset: ptr.offset, size };
+        let mut global = self.global.borrow_mut();
+        if global.is_exempt() {
+            return Ok(());
+        }
+        let inner = self.inner.get_mut();
+        inner.access(AccessKind::Write, ptr.tag, range, &mut global)?;
+        for node in &inner.nodes {
+            if let Some(call) = node.protector {
+                if global.is_active(call) {
+                    throw_ub!(UbExperimental(format!(
+                        "deallocating while tag {:?} is protected",
+                        node.tag
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `new_tag` with permission `perm` as a child of the tag of `ptr`. Creating the
+    /// reference counts as a read of `size` bytes at `ptr`.
+    fn reborrow(
+        &self,
+        ptr: Pointer<Tag>,
+        size: Size,
+        kind: RefKind,
+        new_tag: Tag,
+        perm: Permission,
+        protector: Option<CallId>,
+    ) -> InterpResult<'tcx> {
+        let range = TagRange { alloc_id: ptr.alloc_id, offset: ptr.offset, size };
+        let mut global = self.global.borrow_mut();
+        if global.is_tracked(new_tag) || global.is_tracked(ptr.tag) {
+            register_diagnostic(NonHaltingDiagnostic::RetaggedPointerTag {
+                new_tag,
+                derived_from: ptr.tag,
+                kind,
+                range,
+            });
+        }
+        let mut inner = self.inner.borrow_mut();
+        let parent = inner.index(ptr.tag, &mut global)?;
+        // The new tag gets its permission for all locations, so pointer arithmetic outside of
+        // `size` behaves like inside.
+        let idx = inner.nodes.len();
+        inner.nodes.push(Node { tag: new_tag, parent: Some(parent), protector });
+        inner.indices.insert(new_tag, idx);
+        for perms in inner.perms.iter_mut_all() {
+            perms.push(perm);
+        }
+        inner.access(AccessKind::Read, new_tag, range, &mut global)
+    }
+}
+
+/// Retagging/reborrowing. Which pointers get retagged is decided by Stacked Borrows.
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Retags the reference to `place` (of `size` bytes), returning the retagged version.
+    fn tb_retag_reference(
+        &mut self,
+        place: MPlaceTy<'tcx, Tag>,
+        size: Size,
+        kind: RefKind,
+        protect: bool,
+    ) -> InterpResult<'tcx, Immediate<Tag>> {
+        let this = self.eval_context_mut();
+        let perm = match kind {
+            // Raw pointers keep the tag of the reference they are derived from.
+            RefKind::Raw { .. } => return Ok(place.to_ref()),
+            // Shared references with interior mutability give no guarantees.
+            RefKind::Shared if !this.type_is_freeze(place.layout.ty) => return Ok(place.to_ref()),
+            RefKind::Shared => Permission::Frozen,
+            RefKind::Unique { .. } => Permission::Reserved,
+        };
+        let protector = if protect { Some(this.frame().extra.call_id) } else { None };
+        let ptr = place.ptr.assert_ptr();
+        trace!(
+            "reborrow: {} reference derived from {:?} (pointee {}): {:?}, size {}",
+            kind,
+            ptr.tag,
+            place.layout.ty,
+            ptr.erase_tag(),
+            size.bytes()
+        );
+
+        // Get the allocation. It might not be mutable, so we cannot use `get_mut`.
+        let extra = &this.memory.get_raw(ptr.alloc_id)?.extra;
+        let tree = extra.tree_borrows.as_ref().expect("we should have Tree Borrows data");
+        let new_tag = Tag::Tagged(tree.global.borrow_mut().new_ptr(Some(kind)));
+        tree.reborrow(ptr, size, kind, new_tag, perm, protector)?;
+
+        // Return new pointer.
+        Ok(place.replace_tag(new_tag).to_ref())
+    }
+}
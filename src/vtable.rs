@@ -0,0 +1,136 @@
+//! Checks that the vtables of `dyn Trait` pointers are vtables of the right trait, when they are
+//! used for dynamic dispatch, for dropping, and for `size_of_val`/`align_of_val`.
+
+use rustc::mir;
+use rustc::ty::{self, layout::Size, Instance, Ty};
+
+use crate::*;
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Checks that `vtable` is a vtable for `dyn_ty`: it must have the same entries as the vtable
+    /// for `dyn_ty` and the type whose drop function is in the vtable.
+    fn check_vtable(&mut self, vtable: Scalar<Tag>, dyn_ty: Ty<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let trait_ref = match dyn_ty.kind {
+            ty::Dynamic(predicates, _) => predicates.principal(),
+            _ => bug!("checking the vtable of non-`dyn` type `{}`", dyn_ty),
+        };
+        let vtable_ptr = match vtable.to_ptr() {
+            Ok(ptr) if ptr.offset == Size::ZERO => ptr,
+            _ => throw_ub_format!(
+                "using {:?} as vtable for `{}`, but it does not point to the start of an allocation",
+                vtable,
+                dyn_ty
+            ),
+        };
+        // Most vtables are used over and over again.
+        if this.machine.checked_vtables.contains(&(vtable_ptr.alloc_id, dyn_ty)) {
+            return Ok(());
+        }
+
+        let ty = match this.read_drop_type_from_vtable(vtable) {
+            Ok((_, ty)) => ty,
+            Err(_) => throw_ub_format!(
+                "using {:?} as vtable for `{}`, but it does not start with a drop function",
+                vtable_ptr.erase_tag(),
+                dyn_ty
+            ),
+        };
+        let expected = this.get_vtable(ty, trait_ref)?;
+        // Vtables of constants were created by the compiler, so identical vtables can be
+        // different allocations. Compare their entries instead.
+        if expected.alloc_id != vtable_ptr.alloc_id {
+            let ptr_size = this.pointer_size();
+            let expected_size = this.memory.get_raw(expected.alloc_id)?.size;
+            let actual_size = this.memory.get_raw(vtable_ptr.alloc_id)?.size;
+            let mut matches = expected_size == actual_size;
+            let mut offset = Size::ZERO;
+            while matches && offset < expected_size {
+                let expected_entry = this
+                    .memory
+                    .get_raw(expected.alloc_id)?
+                    .read_ptr_sized(this, expected.offset(offset, this)?)?
+                    .not_undef()?;
+                let actual_entry = this
+                    .memory
+                    .get_raw(vtable_ptr.alloc_id)?
+                    .read_ptr_sized(this, vtable_ptr.offset(offset, this)?)?;
+                matches = match (expected_entry, actual_entry.not_undef()) {
+                    (Scalar::Ptr(_), Ok(actual_entry @ Scalar::Ptr(_))) =>
+                        match (this.memory.get_fn(expected_entry)?, this.memory.get_fn(actual_entry)) {
+                            (FnVal::Instance(expected), Ok(FnVal::Instance(actual))) =>
+                                expected == actual,
+                            _ => false,
+                        },
+                    (Scalar::Raw { .. }, Ok(actual_entry @ Scalar::Raw { .. })) =>
+                        expected_entry == actual_entry,
+                    _ => false,
+                };
+                offset += ptr_size;
+            }
+            if !matches {
+                throw_ub_format!(
+                    "using vtable {:?} as vtable for `{}`, but it is not the vtable of `{}` for that trait",
+                    vtable_ptr.erase_tag(),
+                    dyn_ty,
+                    ty
+                );
+            }
+        }
+        this.machine.checked_vtables.insert((vtable_ptr.alloc_id, dyn_ty));
+        Ok(())
+    }
+
+    /// Checks the vtable of the `dyn` receiver of the virtual call, or of the `dyn` place that is
+    /// dropped, by the terminator that is about to be executed.
+    fn check_terminator_vtable(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let kind = {
+            let frame = this.frame();
+            let terminator = match frame.block {
+                Some(block) => frame.body.basic_blocks()[block].terminator(),
+                None => return Ok(()),
+            };
+            match terminator.kind {
+                mir::TerminatorKind::Call { .. } | mir::TerminatorKind::Drop { .. } =>
+                    terminator.kind.clone(),
+                _ => return Ok(()),
+            }
+        };
+        match &kind {
+            mir::TerminatorKind::Call { func, args, .. } => {
+                let func = this.eval_operand(func, None)?;
+                let (def_id, substs) = match func.layout.ty.kind {
+                    ty::FnDef(def_id, substs) => (def_id, substs),
+                    _ => return Ok(()),
+                };
+                let instance =
+                    Instance::resolve(*this.tcx, ty::ParamEnv::reveal_all(), def_id, substs);
+                if let Some(ty::InstanceDef::Virtual(..)) = instance.map(|instance| instance.def) {
+                    let receiver = this.eval_operand(&args[0], None)?;
+                    // Receivers like `Pin<&mut Self>` are not checked.
+                    let pointee = match receiver.layout.ty.builtin_deref(true) {
+                        Some(pointee) => pointee.ty,
+                        None if receiver.layout.ty.is_box() => receiver.layout.ty.boxed_ty(),
+                        None => return Ok(()),
+                    };
+                    if let ty::Dynamic(..) = pointee.kind {
+                        if let Immediate::ScalarPair(_, vtable) = *this.read_immediate(receiver)? {
+                            this.check_vtable(vtable.not_undef()?, pointee)?;
+                        }
+                    }
+                }
+            }
+            mir::TerminatorKind::Drop { location, .. } => {
+                let place = this.eval_place(location)?;
+                if let ty::Dynamic(..) = place.layout.ty.kind {
+                    let place = this.force_allocation(place)?;
+                    this.check_vtable(place.meta.unwrap_meta(), place.layout.ty)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
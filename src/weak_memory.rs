@@ -0,0 +1,383 @@
+//! Weak memory emulation for atomics, in the style of the C++11 memory model.
+//!
+//! Every location that is accessed atomically while the program has several threads gets a store
+//! buffer that remembers the most recent atomic stores to it, in modification order. An atomic
+//! load does not necessarily read the latest store, but any store in the buffer that the coherence
+//! rules allow, chosen with the RNG configured by `-Zmiri-seed`:
+//!
+//! * a load cannot read a store older than the latest one that happens before it,
+//! * nor one older than a store that was read by a load that happens before it (this includes
+//!   earlier loads of the same thread),
+//! * and a sequentially consistent load cannot read a store older than the latest sequentially
+//!   consistent store.
+//!
+//! Sequentially consistent fences are totally ordered by when they execute. A load cannot read a
+//! store older than one sequenced before a sequentially consistent fence that precedes the last
+//! such fence of the loading thread, or, if the load is sequentially consistent, precedes the
+//! load itself. Neither can it read a sequentially consistent store older than one that precedes
+//! the last sequentially consistent fence of the loading thread.
+//!
+//! Read-modify-write operations always read the latest store. A load that reads an older store
+//! synchronizes with the clock that store published, instead of the latest clock of the location.
+//!
+//! Non-atomic writes clear the buffers they overlap, since the race detector already ensures that
+//! every store before them happens before every later access. The emulation therefore requires
+//! the race detector.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use rand::Rng;
+use rustc::ty::layout::Size;
+
+use crate::data_race::{self, VClock};
+use crate::*;
+
+/// The number of stores a buffer remembers. Loads cannot read older stores than these.
+const STORE_BUFFER_LIMIT: usize = 128;
+
+/// An atomic store to a location.
+#[derive(Clone, Debug)]
+struct StoreElement {
+    /// The thread that performed the store, and its timestamp at the time.
+    thread: ThreadId,
+    timestamp: u32,
+    seq_cst: bool,
+    val: ScalarMaybeUndef<Tag>,
+    /// The clock that the location published with this store, which loads reading it acquire.
+    sync: VClock,
+    /// The timestamp of every thread when it last loaded this store.
+    loads: HashMap<ThreadId, u32>,
+}
+
+impl StoreElement {
+    /// Whether this store, or a load of it, happens before the point of a thread at `clock`.
+    fn visible_to(&self, clock: &VClock) -> bool {
+        self.timestamp <= clock.get(self.thread)
+            || self.loads.iter().any(|(&thread, &timestamp)| timestamp <= clock.get(thread))
+    }
+
+    /// Whether a load with `view` cannot read a store older than this one.
+    fn bounds(&self, view: &LoadView) -> bool {
+        let sequenced_before = |clock: &VClock| self.timestamp <= clock.get(self.thread);
+        self.visible_to(&view.clock)
+            || sequenced_before(&view.fence_seqcst)
+            || (self.seq_cst && sequenced_before(&view.write_seqcst))
+            || (view.seq_cst && (self.seq_cst || sequenced_before(&view.last_sc_fence)))
+    }
+}
+
+/// What the thread performing a load knows, which limits the stores it can read.
+#[derive(Clone, Debug, Default)]
+struct LoadView {
+    thread: ThreadId,
+    clock: VClock,
+    seq_cst: bool,
+    /// The clocks of the sequentially consistent fences, see `crate::data_race`.
+    fence_seqcst: VClock,
+    write_seqcst: VClock,
+    last_sc_fence: VClock,
+}
+
+/// The recent stores to a location, oldest first.
+#[derive(Clone, Debug)]
+struct StoreBuffer {
+    size: Size,
+    stores: VecDeque<StoreElement>,
+}
+
+impl StoreBuffer {
+    /// Creates a buffer for a location that currently holds `val`. The store that put it there
+    /// happens before every access that creates the buffer.
+    fn new(size: Size, val: ScalarMaybeUndef<Tag>) -> Self {
+        let init = StoreElement {
+            thread: ThreadId::MAIN,
+            timestamp: 0,
+            seq_cst: false,
+            val,
+            sync: VClock::default(),
+            loads: HashMap::new(),
+        };
+        StoreBuffer { size, stores: vec![init].into() }
+    }
+
+    /// The index of the oldest store that a load with `view` may read.
+    fn oldest_candidate(&self, view: &LoadView) -> usize {
+        self.stores.iter().rposition(|store| store.bounds(view)).unwrap_or(0)
+    }
+
+    /// Picks the store that a load with `view` reads, and returns its index.
+    fn load(&mut self, view: &LoadView, rng: &mut impl Rng) -> usize {
+        let oldest = self.oldest_candidate(view);
+        let idx = rng.gen_range(oldest, self.stores.len());
+        self.stores[idx].loads.insert(view.thread, view.clock.get(view.thread));
+        idx
+    }
+
+    fn store(&mut self, store: StoreElement) {
+        if self.stores.len() == STORE_BUFFER_LIMIT {
+            self.stores.pop_front();
+        }
+        self.stores.push_back(store);
+    }
+}
+
+/// The store buffers of an allocation, by offset.
+#[derive(Clone, Debug, Default)]
+pub struct AllocExtra {
+    buffers: RefCell<BTreeMap<Size, StoreBuffer>>,
+}
+
+impl AllocExtra {
+    /// Forgets the buffers of the locations that overlap `offset..offset+size`.
+    pub fn forget(&mut self, offset: Size, size: Size) {
+        remove_overlapping(self.buffers.get_mut(), offset, size);
+    }
+
+    /// Runs `f` on the buffer of the location at `offset` with `size`, creating it with the
+    /// current value `latest` if needed. An access of a different size makes a new location.
+    fn with_buffer<R>(
+        &self,
+        offset: Size,
+        size: Size,
+        latest: ScalarMaybeUndef<Tag>,
+        f: impl FnOnce(&mut StoreBuffer) -> R,
+    ) -> R {
+        let mut buffers = self.buffers.borrow_mut();
+        if buffers.get(&offset).map_or(true, |buffer| buffer.size != size) {
+            remove_overlapping(&mut buffers, offset, size);
+            buffers.insert(offset, StoreBuffer::new(size, latest));
+        }
+        f(buffers.get_mut(&offset).unwrap())
+    }
+}
+
+fn remove_overlapping(buffers: &mut BTreeMap<Size, StoreBuffer>, offset: Size, size: Size) {
+    let overlapping: Vec<Size> = buffers
+        .range(..offset + size)
+        .filter(|(&start, buffer)| start + buffer.size > offset)
+        .map(|(&start, _)| start)
+        .collect();
+    for start in overlapping {
+        buffers.remove(&start);
+    }
+}
+
+/// The store buffers of the allocation of `place`, if weak memory is emulated right now.
+fn store_buffers<'a, 'mir, 'tcx>(
+    ecx: &'a MiriEvalContext<'mir, 'tcx>,
+    place: MPlaceTy<'tcx, Tag>,
+) -> InterpResult<'tcx, Option<(&'a AllocExtra, Pointer<Tag>)>> {
+    match &ecx.memory.extra.data_race {
+        Some(data_race) if data_race.borrow().tracking() => {}
+        _ => return Ok(None),
+    }
+    let ptr = ecx.force_ptr(place.ptr)?;
+    let extra = ecx.memory.get_raw(ptr.alloc_id)?.extra.data_race.as_ref();
+    Ok(extra.and_then(|extra| extra.weak_memory.as_ref()).map(|buffers| (buffers, ptr)))
+}
+
+/// The active thread and its clock.
+fn active_clock(ecx: &MiriEvalContext<'_, '_>) -> (ThreadId, VClock) {
+    let global = ecx.memory.extra.data_race.as_ref().unwrap().borrow();
+    (global.active_thread(), global.active_clock().clone())
+}
+
+/// What the active thread knows for a load with `ordering`.
+fn load_view(ecx: &MiriEvalContext<'_, '_>, ordering: AtomicOrdering) -> LoadView {
+    let global = ecx.memory.extra.data_race.as_ref().unwrap().borrow();
+    let (fence_seqcst, write_seqcst, last_sc_fence) = global.active_seq_cst_clocks();
+    LoadView {
+        thread: global.active_thread(),
+        clock: global.active_clock().clone(),
+        seq_cst: ordering == AtomicOrdering::SeqCst,
+        fence_seqcst: fence_seqcst.clone(),
+        write_seqcst: write_seqcst.clone(),
+        last_sc_fence: last_sc_fence.clone(),
+    }
+}
+
+impl VisitTags for AllocExtra {
+    fn visit_tags(&self, visit: &mut dyn FnMut(Tag)) {
+        for buffer in self.buffers.borrow().values() {
+            for store in &buffer.stores {
+                store.val.visit_tags(visit);
+            }
+        }
+    }
+}
+
+impl<'mir, 'tcx> EvalContextExt<'mir, 'tcx> for crate::MiriEvalContext<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriEvalContextExt<'mir, 'tcx> {
+    /// Performs an atomic load of `place`, which currently holds `latest`, and returns the value
+    /// it reads. Also checks the load for races.
+    fn buffered_atomic_load(
+        &self,
+        place: MPlaceTy<'tcx, Tag>,
+        ordering: AtomicOrdering,
+        latest: ScalarMaybeUndef<Tag>,
+    ) -> InterpResult<'tcx, ScalarMaybeUndef<Tag>> {
+        let this = self.eval_context_ref();
+        let (buffers, ptr) = match store_buffers(this, place)? {
+            Some(buffers) => buffers,
+            None => {
+                this.validate_atomic_load(place, ordering)?;
+                return Ok(latest);
+            }
+        };
+        let view = load_view(this, ordering);
+        let mut rng = this.memory.extra.rngs.get(RngPurpose::WeakMemory).borrow_mut();
+        let read = buffers.with_buffer(ptr.offset, place.layout.size, latest, |buffer| {
+            let idx = buffer.load(&view, &mut *rng);
+            let store = &buffer.stores[idx];
+            if idx + 1 == buffer.stores.len() {
+                None
+            } else {
+                Some((store.val, store.sync.clone()))
+            }
+        });
+        match read {
+            Some((val, sync)) => {
+                data_race::validate_atomic_load_from(this, place, ordering, Some(&sync))?;
+                Ok(val)
+            }
+            None => {
+                this.validate_atomic_load(place, ordering)?;
+                Ok(latest)
+            }
+        }
+    }
+
+    /// Checks an atomic store or read-modify-write that writes `val` to `place` for races, and
+    /// adds it to the store buffer. Must be called before the memory is written.
+    fn buffered_atomic_write(
+        &self,
+        place: MPlaceTy<'tcx, Tag>,
+        ordering: AtomicOrdering,
+        val: ScalarMaybeUndef<Tag>,
+        rmw: bool,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        let target = match store_buffers(this, place)? {
+            // The store happens at the timestamp from before a release increments it.
+            Some((buffers, ptr)) => Some((buffers, ptr, active_clock(this))),
+            None => None,
+        };
+        if rmw {
+            this.validate_atomic_rmw(place, ordering)?;
+        } else {
+            this.validate_atomic_store(place, ordering)?;
+        }
+        if let Some((buffers, ptr, (thread, clock))) = target {
+            let latest = this.allow_data_races_ref(|this| this.read_scalar(place.into()))?;
+            let store = StoreElement {
+                thread,
+                timestamp: clock.get(thread),
+                seq_cst: ordering == AtomicOrdering::SeqCst,
+                val,
+                sync: data_race::atomic_sync_clock(this, place)?,
+                loads: HashMap::new(),
+            };
+            buffers.with_buffer(ptr.offset, place.layout.size, latest, |buffer| {
+                buffer.store(store)
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn buffer(stores: &[(u32, u32, bool)]) -> StoreBuffer {
+        let mut buffer = StoreBuffer::new(Size::from_bytes(4), ScalarMaybeUndef::Undef);
+        for &(thread, timestamp, seq_cst) in stores {
+            buffer.store(StoreElement {
+                thread: ThreadId::from(thread),
+                timestamp,
+                seq_cst,
+                val: ScalarMaybeUndef::Undef,
+                sync: VClock::default(),
+                loads: HashMap::new(),
+            });
+        }
+        buffer
+    }
+
+    fn clock(timestamps: &[u32]) -> VClock {
+        let mut clock = VClock::default();
+        for (thread, &timestamp) in timestamps.iter().enumerate() {
+            for _ in 0..timestamp {
+                clock.increment(ThreadId::from(thread as u32));
+            }
+        }
+        clock
+    }
+
+    /// The view of a load at `clock` without sequentially consistent fences.
+    fn view(timestamps: &[u32], seq_cst: bool) -> LoadView {
+        LoadView { clock: clock(timestamps), seq_cst, ..Default::default() }
+    }
+
+    #[test]
+    fn loads_read_at_least_the_latest_store_that_happens_before() {
+        let buffer = buffer(&[(1, 1, false), (1, 2, false), (2, 1, false)]);
+        assert_eq!(buffer.oldest_candidate(&view(&[1], false)), 0);
+        assert_eq!(buffer.oldest_candidate(&view(&[1, 1], false)), 1);
+        assert_eq!(buffer.oldest_candidate(&view(&[1, 2], false)), 2);
+        assert_eq!(buffer.oldest_candidate(&view(&[1, 0, 1], false)), 3);
+    }
+
+    #[test]
+    fn seq_cst_loads_read_at_least_the_latest_seq_cst_store() {
+        let buffer = buffer(&[(1, 1, true), (2, 1, false)]);
+        assert_eq!(buffer.oldest_candidate(&view(&[1], false)), 0);
+        assert_eq!(buffer.oldest_candidate(&view(&[1], true)), 1);
+    }
+
+    #[test]
+    fn seq_cst_fences_limit_later_loads() {
+        let buffer = buffer(&[(1, 1, false), (1, 2, true), (2, 1, false)]);
+        // Thread 1 executed a fence after its first store, followed by a fence of the loading
+        // thread.
+        let fenced = LoadView { fence_seqcst: clock(&[0, 1]), ..view(&[1], false) };
+        assert_eq!(buffer.oldest_candidate(&fenced), 1);
+        // The fence of the loading thread came after the sequentially consistent store.
+        let fenced = LoadView { write_seqcst: clock(&[0, 2]), ..view(&[1], false) };
+        assert_eq!(buffer.oldest_candidate(&fenced), 2);
+        // A sequentially consistent load comes after the fence of thread 2.
+        let fenced = LoadView { last_sc_fence: clock(&[0, 0, 1]), ..view(&[1], true) };
+        assert_eq!(buffer.oldest_candidate(&fenced), 3);
+    }
+
+    #[test]
+    fn loads_are_coherent() {
+        let mut buffer = buffer(&[(1, 1, false), (1, 2, false)]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let read = buffer.load(&view(&[1], false), &mut rng);
+        // The main thread cannot read anything older from now on, and neither can the threads
+        // that synchronized with it afterwards.
+        assert_eq!(buffer.oldest_candidate(&view(&[1], false)), read);
+        assert_eq!(buffer.oldest_candidate(&view(&[1, 0, 5], false)), read);
+        assert_eq!(buffer.oldest_candidate(&view(&[0, 0, 5], false)), 0);
+    }
+
+    #[test]
+    fn buffers_are_bounded() {
+        let mut buffer = buffer(&[]);
+        for timestamp in 0..2 * STORE_BUFFER_LIMIT as u32 {
+            buffer.store(StoreElement {
+                thread: ThreadId::from(1),
+                timestamp,
+                seq_cst: false,
+                val: ScalarMaybeUndef::Undef,
+                sync: VClock::default(),
+                loads: HashMap::new(),
+            });
+        }
+        assert_eq!(buffer.stores.len(), STORE_BUFFER_LIMIT);
+    }
+}
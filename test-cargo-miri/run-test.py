@@ -17,13 +17,17 @@ def cargo_miri(cmd):
         args += ["--target", os.environ['MIRI_TEST_TARGET']]
     return args
 
-def test(name, cmd, stdout_ref, stderr_ref):
+def test(name, cmd, stdout_ref, stderr_ref, miriflags=None):
     print("==> Testing `{}` <==".format(name))
+    env = dict(os.environ)
+    if miriflags is not None:
+        env['MIRIFLAGS'] = miriflags
     ## Call `cargo miri`, capture all output
     p = subprocess.Popen(
         cmd,
         stdout=subprocess.PIPE,
-        stderr=subprocess.PIPE
+        stderr=subprocess.PIPE,
+        env=env
     )
     (stdout, stderr) = p.communicate()
     stdout = stdout.decode("UTF-8")
@@ -47,20 +51,30 @@ def test_cargo_miri_run():
         "stdout.ref", "stderr.ref"
     )
     test("cargo miri run (with arguments)",
+        cargo_miri("run") + ["--", "hello world", '"hello world"'],
+        "stdout.ref", "stderr.ref2"
+    )
+    test("cargo miri run (with arguments after a second `--`)",
         cargo_miri("run") + ["--", "--", "hello world", '"hello world"'],
         "stdout.ref", "stderr.ref2"
     )
 
 def test_cargo_miri_test():
     test("cargo miri test",
-        cargo_miri("test") + ["--", "-Zmiri-seed=feed"],
-        "test.stdout.ref", "test.stderr.ref"
+        cargo_miri("test"),
+        "test.stdout.ref", "test.stderr.ref",
+        miriflags="-Zmiri-seed=feed"
     )
     test("cargo miri test (with filter)",
-        cargo_miri("test") + ["--", "--", "le1"],
+        cargo_miri("test") + ["--", "le1"],
         "test.stdout.ref2", "test.stderr.ref"
     )
     test("cargo miri test (without isolation)",
+        cargo_miri("test") + ["--", "num_cpus"],
+        "test.stdout.ref3", "test.stderr.ref",
+        miriflags="-Zmiri-disable-isolation"
+    )
+    test("cargo miri test (Miri flags between `--`)",
         cargo_miri("test") + ["--", "-Zmiri-disable-isolation", "--", "num_cpus"],
         "test.stdout.ref3", "test.stderr.ref"
     )
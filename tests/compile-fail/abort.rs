@@ -0,0 +1,10 @@
+// ignore-windows: Uses POSIX APIs
+// failure-status: 134
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    unsafe { libc::abort() } //~ ERROR the evaluated program aborted execution
+}
@@ -0,0 +1,23 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+extern "C" {
+    fn alloca(size: libc::size_t) -> *mut libc::c_void;
+}
+
+fn make_buf() -> *mut u8 {
+    unsafe {
+        let buf = alloca(4) as *mut u8;
+        *buf = 42;
+        buf
+    }
+}
+
+fn main() {
+    let buf = make_buf();
+    // The memory was freed when `make_buf` returned.
+    let _val = unsafe { *buf }; //~ ERROR dangling pointer was dereferenced
+}
@@ -0,0 +1,16 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+extern "C" {
+    fn alloca(size: libc::size_t) -> *mut libc::c_void;
+}
+
+fn main() {
+    unsafe {
+        let buf = alloca(4);
+        libc::free(buf); //~ ERROR tried to deallocate `Machine(Alloca)` memory but gave `Machine(C)` as the kind
+    }
+}
@@ -0,0 +1,9 @@
+extern "Rust" {
+    fn miri_assert_mem_eq(ptr: *const u8, expected: *const u8, len: usize);
+}
+
+fn main() {
+    let actual = [1u8, 2, 3, 4];
+    let expected = [1u8, 2, 0, 0];
+    unsafe { miri_assert_mem_eq(actual.as_ptr(), expected.as_ptr(), 4) }; //~ ERROR byte 2 of 4 differs: expected 0x00, found 0x03 (1 more bytes differ)
+}
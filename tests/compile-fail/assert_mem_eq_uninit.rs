@@ -0,0 +1,10 @@
+use std::mem::MaybeUninit;
+
+extern "Rust" {
+    fn miri_assert_mem_eq(ptr: *const u8, expected: *const u8, len: usize);
+}
+
+fn main() {
+    let buf = MaybeUninit::<[u8; 2]>::uninit();
+    unsafe { miri_assert_mem_eq(buf.as_ptr() as *const u8, [0, 0].as_ptr(), 2) }; //~ ERROR byte 0 of 2 differs: expected 0x00, found an uninitialized byte (1 more bytes differ)
+}
@@ -0,0 +1,21 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+
+fn main() {
+    let a = AtomicU32::new(0);
+    let b = EvilSend(&a as *const AtomicU32);
+    let writer = thread::spawn(move || unsafe {
+        (*b.0).store(1, Ordering::SeqCst);
+    });
+    let reader = thread::spawn(move || unsafe {
+        // Nothing orders the atomic store before this non-atomic read.
+        *(b.0 as *const u32) //~ ERROR data race detected between read on thread 2 and atomic store on thread 1
+    });
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
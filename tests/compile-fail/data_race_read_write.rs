@@ -0,0 +1,17 @@
+use std::thread;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+
+fn main() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    let handle = thread::spawn(move || unsafe {
+        *b.0 //~ ERROR data race detected between read on thread 1 and write on thread 0
+    });
+    // The thread only runs when it is joined, but nothing orders this write before its read.
+    unsafe { *b.0 = 32 };
+    handle.join().unwrap();
+}
@@ -0,0 +1,21 @@
+// ignore-windows: The standard library does not name threads on Windows
+
+use std::thread;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+
+fn main() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    let handle = thread::Builder::new()
+        .name("reader".to_owned())
+        .spawn(move || unsafe {
+            *b.0 //~ ERROR data race detected between read on thread 1 `reader` and write on thread 0 `main`
+        })
+        .unwrap();
+    unsafe { *b.0 = 32 };
+    handle.join().unwrap();
+}
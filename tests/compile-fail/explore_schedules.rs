@@ -0,0 +1,25 @@
+// compile-flags: -Zmiri-explore-schedules=1
+
+// error-pattern: schedule 2 of the exploration fails
+// error-pattern: invalid use of NULL pointer
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+static SECOND_DONE: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let first = thread::spawn(|| {
+        if SECOND_DONE.load(Ordering::SeqCst) {
+            let _x: i32 = unsafe { *std::ptr::null() };
+        }
+    });
+    let second = thread::spawn(|| {
+        SECOND_DONE.store(true, Ordering::SeqCst);
+    });
+    // Neither thread started yet, so joining `first` lets the scheduler pick which one runs. By
+    // default, that is the joined thread, and the program passes; the exploration then starts
+    // `second` first instead.
+    first.join().unwrap();
+    second.join().unwrap();
+}
@@ -0,0 +1,27 @@
+use std::mem;
+
+trait A {
+    fn a(&self) -> i32 {
+        1
+    }
+}
+
+trait B {
+    fn b(&self) -> i32 {
+        2
+    }
+}
+
+impl A for i32 {}
+impl B for i32 {}
+
+fn main() {
+    let x = 0i32;
+    let a: &dyn A = &x;
+    let b: &dyn B = &x;
+    // Combine the data pointer of `a` with the vtable of `b`.
+    let (data, _): (*const (), *const ()) = unsafe { mem::transmute(a) };
+    let (_, vtable): (*const (), *const ()) = unsafe { mem::transmute(b) };
+    let forged: &dyn A = unsafe { mem::transmute((data, vtable)) };
+    forged.a(); //~ ERROR is not the vtable of `i32` for that trait
+}
@@ -0,0 +1,7 @@
+// An integer that was never obtained with an `as` cast does not expose its allocation.
+fn main() {
+    let x = 42u32;
+    let addr: usize = unsafe { std::mem::transmute(&x as *const u32) };
+    let ptr = addr as *const u32; //~ ERROR no pointer to that allocation was cast to an integer before
+    let _val = unsafe { *ptr };
+}
@@ -0,0 +1,9 @@
+// compile-flags: -Zmiri-live-threads=error
+// error-pattern: the main thread terminated without waiting for all remaining threads
+
+use std::thread;
+
+fn main() {
+    // Nobody waits for the thread, so it is still live when `main` returns.
+    thread::spawn(|| {});
+}
@@ -0,0 +1,9 @@
+// ignore-windows: We do not check leaks on Windows
+
+//error-pattern: the evaluated program leaked memory
+
+fn main() {
+    // `exit` does not return, so this vector is never freed.
+    let v = vec![1, 2, 3];
+    std::process::exit(v.len() as i32 - 3)
+}
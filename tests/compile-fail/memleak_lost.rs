@@ -0,0 +1,9 @@
+// ignore-windows: We do not check leaks on Windows
+
+// error-pattern: the evaluated program leaked memory
+// error-pattern: definitely lost
+
+fn main() {
+    let v = vec![1u8, 2, 3];
+    std::mem::forget(v);
+}
@@ -1,5 +1,4 @@
 // ignore-windows: We do not check leaks on Windows
-// ignore-macos: We do not check leaks on macOS
 
 //error-pattern: the evaluated program leaked memory
 
@@ -0,0 +1,12 @@
+// ignore-windows: We do not check leaks on Windows
+
+// error-pattern: the evaluated program leaked memory
+// error-pattern: still reachable from a static
+
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+static CACHE: AtomicPtr<u32> = AtomicPtr::new(std::ptr::null_mut());
+
+fn main() {
+    CACHE.store(Box::into_raw(Box::new(42)), Ordering::Relaxed);
+}
@@ -0,0 +1,16 @@
+// ignore-windows: We do not check leaks on Windows
+// compile-flags: -Zmiri-leak-suppressions=tests/compile-fail/memleak_suppressions.supp
+// Leaks outside of the suppressed paths are still reported.
+
+//error-pattern: the evaluated program leaked memory
+
+mod cache {
+    pub fn global() -> &'static mut u32 {
+        Box::leak(Box::new(1))
+    }
+}
+
+fn main() {
+    *cache::global() += 1;
+    std::mem::forget(Box::new(42));
+}
@@ -0,0 +1,22 @@
+// ignore-windows: We do not check leaks on Windows
+
+//error-pattern: arena: 2 allocations, 12 bytes
+
+extern "Rust" {
+    fn miri_tag_allocation(ptr: *const u8, tag: &str);
+}
+
+fn main() {
+    let a = Box::into_raw(Box::new(1u32));
+    let b = Box::into_raw(Box::new(2u64));
+    let freed = Box::into_raw(Box::new(3u8));
+    unsafe {
+        miri_tag_allocation(a as *const u8, "arena");
+        miri_tag_allocation(b as *const u8, "arena");
+        // Freed allocations are not reported.
+        miri_tag_allocation(freed as *const u8, "arena");
+        drop(Box::from_raw(freed));
+    }
+    // This one is counted as untagged.
+    std::mem::forget(Box::new(42));
+}
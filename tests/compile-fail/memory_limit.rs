@@ -0,0 +1,12 @@
+// compile-flags: -Zmiri-memory-limit=1000000
+// The huge allocation is refused before it is made, with a report of what is allocated so far.
+
+// error-pattern: the evaluated program exceeded the memory limit of 1000000 bytes: allocating 1073741824 bytes
+// error-pattern: bytes are allocated
+// error-pattern: holds 4000 bytes
+
+fn main() {
+    let small = vec![0u32; 1000];
+    let huge: Vec<u8> = Vec::with_capacity(1 << 30);
+    assert_eq!(small.len() + huge.capacity(), 1000 + (1 << 30));
+}
@@ -0,0 +1,9 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-net=error
+// error-pattern: `getaddrinfo` not available when network access is an error
+
+use std::net::ToSocketAddrs;
+
+fn main() {
+    let _ = ("localhost", 8080).to_socket_addrs();
+}
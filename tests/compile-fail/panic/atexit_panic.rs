@@ -0,0 +1,17 @@
+// ignore-windows: Unwind panicking does not currently work on Windows
+// failure-status: 134
+// error-pattern: the evaluated program aborted execution
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+extern "C" fn handler() {
+    panic!("panic in an atexit handler");
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::atexit(handler), 0);
+    }
+}
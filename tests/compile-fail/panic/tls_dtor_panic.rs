@@ -0,0 +1,19 @@
+// ignore-windows: Unwind panicking does not currently work on Windows
+// failure-status: 134
+// error-pattern: the evaluated program aborted execution
+
+struct PanicOnDrop;
+
+impl Drop for PanicOnDrop {
+    fn drop(&mut self) {
+        panic!("panic in a TLS destructor");
+    }
+}
+
+thread_local! {
+    static VALUE: PanicOnDrop = PanicOnDrop;
+}
+
+fn main() {
+    VALUE.with(|_| {});
+}
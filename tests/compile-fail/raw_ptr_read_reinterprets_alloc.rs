@@ -0,0 +1,8 @@
+// Reading through a pointer cast to a pointer to a larger type reports the type read and the
+// allocation the pointer points to.
+
+fn main() {
+    let x = [1u16; 3];
+    let p = &x[2] as *const u16 as *const u32;
+    let _y = unsafe { *p }; //~ ERROR reading `u32` (size 4, align 4) through a raw pointer reinterprets the last 2 bytes of
+}
@@ -0,0 +1,15 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    unsafe {
+        let p = libc::malloc(4) as *mut u8;
+        *p = 42;
+        // Even if this happens to return `p` again, `p` itself must not be used anymore.
+        let _q = libc::realloc(p as *mut libc::c_void, 2);
+        let _val = *p; //~ ERROR dangling pointer was dereferenced. If this pointer was passed to `realloc`
+    }
+}
@@ -0,0 +1,10 @@
+// compile-flags: -Zmiri-dump-borrow-stacks=1
+// Dumping the borrow stacks of an allocation only adds notes, and does not affect what gets
+// reported as an error.
+fn main() {
+    let mut x = 0;
+    let raw = &mut x as *mut i32;
+    let xref = unsafe { &mut *raw };
+    unsafe { *raw = 1 };
+    let _val = *xref; //~ ERROR borrow stack
+}
@@ -0,0 +1,10 @@
+// compile-flags: -Zmiri-permissive-provenance -Zmiri-raw-pointer-tagging=on
+fn main() {
+    let mut x = 0i32;
+    let addr = &mut x as *mut i32 as usize;
+    // Writing to `x` directly invalidates the only pointer whose address was exposed.
+    x = 1;
+    let ptr = addr as *mut i32;
+    unsafe { *ptr = 2 }; //~ ERROR to a pointer cast from an integer
+    let _ = x;
+}
@@ -0,0 +1,10 @@
+// compile-flags: -Zmiri-raw-pointer-tagging=on
+fn main() {
+    let mut x = 0;
+    let raw1 = &mut x as *mut i32;
+    // Creating a new mutable reference invalidates `raw1`. Without raw pointer tagging, `raw1`
+    // would be confused with `raw2`.
+    let raw2 = &mut x as *mut i32;
+    unsafe { *raw2 = 1 };
+    unsafe { *raw1 = 2 }; //~ ERROR borrow stack
+}
@@ -0,0 +1,16 @@
+use std::cell::Cell;
+
+struct S {
+    frozen: u32,
+    cell: Cell<u32>,
+}
+
+fn main() {
+    let x = Some(S { frozen: 0, cell: Cell::new(0) });
+    let r = &x;
+    // Only the `Cell` may be mutated through a shared reference, not the rest of the enum.
+    r.as_ref().unwrap().cell.set(1);
+    let p = r as *const Option<S> as *mut Option<S>;
+    unsafe { *p = None }; //~ ERROR borrow stack
+    let _val = x.is_some();
+}
@@ -0,0 +1,9 @@
+// compile-flags: -Zmiri-track-alloc-id=all
+// The error names the allocation of `x`, so its allocation backtrace is printed along with it.
+fn main() {
+    let mut x = Box::new(0);
+    let raw = &mut *x as *mut i32;
+    let xref = &mut *x;
+    *xref = 1;
+    unsafe { *raw = 2 }; //~ ERROR borrow stack
+}
@@ -0,0 +1,16 @@
+// compile-flags: -Zmiri-no-protectors
+// Activating a two-phase borrow invalidates the pointers derived from its parent.
+
+trait WriteThrough: Sized {
+    fn write_through(&mut self, raw: *mut i32) {
+        unsafe { *raw = 2 }; //~ ERROR borrow stack
+    }
+}
+
+impl WriteThrough for i32 {}
+
+fn main() {
+    let mut x = 0;
+    let raw = &mut x as *mut i32;
+    x.write_through(raw);
+}
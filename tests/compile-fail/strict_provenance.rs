@@ -0,0 +1,7 @@
+// compile-flags: -Zmiri-strict-provenance
+fn main() {
+    let x = 42u32;
+    let addr = &x as *const u32 as usize;
+    let ptr = addr as *const u32; //~ ERROR strict provenance
+    let _val = unsafe { *ptr };
+}
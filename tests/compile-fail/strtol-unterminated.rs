@@ -0,0 +1,11 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    let digits = [b'4', b'2'];
+    // The string lacks its terminating NUL, so `strtol` reads past the end of `digits`.
+    let _val = unsafe { libc::strtol(digits.as_ptr() as *const libc::c_char, std::ptr::null_mut(), 10) }; //~ ERROR no null found before end of allocation
+}
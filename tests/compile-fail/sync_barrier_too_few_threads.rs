@@ -0,0 +1,18 @@
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::mem::MaybeUninit;
+use std::ptr;
+
+fn main() {
+    let mut barrier = MaybeUninit::<libc::pthread_barrier_t>::uninit();
+    unsafe {
+        assert_eq!(libc::pthread_barrier_init(barrier.as_mut_ptr(), ptr::null(), 2), 0);
+        // No other thread arrives at the barrier.
+        libc::pthread_barrier_wait(barrier.as_mut_ptr()); //~ ERROR deadlocked
+    }
+}
@@ -0,0 +1,17 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    unsafe {
+        let mut cond: libc::pthread_cond_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_cond_init(&mut cond, std::ptr::null()), 0);
+        let mut mutex: libc::pthread_mutex_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutex_init(&mut mutex, std::ptr::null()), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex), 0);
+        // There is no other thread that could signal us.
+        libc::pthread_cond_wait(&mut cond, &mut mutex); //~ ERROR deadlocked
+    }
+}
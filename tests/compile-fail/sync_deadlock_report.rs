@@ -0,0 +1,28 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::{mem, ptr};
+
+extern "C" fn thread_start(mutex: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        // The main thread holds the lock while it waits for us.
+        libc::pthread_mutex_lock(mutex as *mut libc::pthread_mutex_t); //~ ERROR deadlocked: thread 1 is blocked waiting to lock a mutex
+    }
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let mut mutex: libc::pthread_mutex_t = mem::zeroed();
+        assert_eq!(libc::pthread_mutex_init(&mut mutex as *mut _, ptr::null()), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        let arg = &mut mutex as *mut libc::pthread_mutex_t as *mut libc::c_void;
+        assert_eq!(libc::pthread_create(&mut native, &attr, thread_start, arg), 0);
+        libc::pthread_join(native, ptr::null_mut());
+    }
+}
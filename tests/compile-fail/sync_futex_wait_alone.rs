@@ -0,0 +1,20 @@
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    let futex: i32 = 0;
+    unsafe {
+        // There is no other thread that could wake us up.
+        libc::syscall( //~ ERROR deadlocked
+            libc::SYS_futex,
+            &futex as *const i32,
+            libc::FUTEX_WAIT,
+            0,
+            std::ptr::null::<libc::timespec>(),
+        );
+    }
+}
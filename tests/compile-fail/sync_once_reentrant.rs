@@ -0,0 +1,20 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+static mut ONCE: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+
+extern "C" fn init() {
+    unsafe {
+        // The initialization waits for itself.
+        libc::pthread_once(&mut ONCE, init); //~ ERROR deadlocked
+    }
+}
+
+fn main() {
+    unsafe {
+        libc::pthread_once(&mut ONCE, init);
+    }
+}
@@ -0,0 +1,14 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    unsafe {
+        let mut rwlock: libc::pthread_rwlock_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_rwlock_init(&mut rwlock, std::ptr::null()), 0);
+        assert_eq!(libc::pthread_rwlock_rdlock(&mut rwlock), 0);
+        libc::pthread_rwlock_wrlock(&mut rwlock); //~ ERROR deadlocked
+    }
+}
@@ -0,0 +1,17 @@
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::mem::MaybeUninit;
+
+fn main() {
+    let mut sem = MaybeUninit::<libc::sem_t>::uninit();
+    unsafe {
+        assert_eq!(libc::sem_init(sem.as_mut_ptr(), 0, 0), 0);
+        // There is no other thread that could post the semaphore.
+        libc::sem_wait(sem.as_mut_ptr()); //~ ERROR deadlocked
+    }
+}
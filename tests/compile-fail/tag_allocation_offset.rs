@@ -0,0 +1,8 @@
+extern "Rust" {
+    fn miri_tag_allocation(ptr: *const u8, tag: &str);
+}
+
+fn main() {
+    let v = vec![0u8; 4];
+    unsafe { miri_tag_allocation(v.as_ptr().add(1), "buffer") }; //~ ERROR must be called with a pointer to the start of an allocation
+}
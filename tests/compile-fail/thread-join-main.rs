@@ -0,0 +1,28 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::{mem, ptr};
+
+extern "C" fn thread_start(main_thread: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        // The main thread is waiting for this thread, so this never returns.
+        libc::pthread_join(main_thread as libc::pthread_t, ptr::null_mut()); //~ ERROR deadlocked
+    }
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let main_thread = libc::pthread_self();
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(
+            libc::pthread_create(&mut native, &attr, thread_start, main_thread as *mut _),
+            0
+        );
+        assert_eq!(libc::pthread_join(native, ptr::null_mut()), 0);
+    }
+}
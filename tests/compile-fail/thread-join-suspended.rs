@@ -0,0 +1,34 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::{mem, ptr};
+
+extern "C" fn yield_start(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        libc::sched_yield();
+    }
+    ptr::null_mut()
+}
+
+extern "C" fn join_start(other: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        // The other thread yielded to us, so it is suspended below us and cannot terminate before
+        // we do. With threads interleaved arbitrarily, it would.
+        libc::pthread_join(other as libc::pthread_t, ptr::null_mut()); //~ ERROR a thread suspended below the active one (unsupported interleaving)
+    }
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let mut yielding: libc::pthread_t = mem::zeroed();
+        let mut joining: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut yielding, &attr, yield_start, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_create(&mut joining, &attr, join_start, yielding as *mut _), 0);
+        assert_eq!(libc::pthread_join(yielding, ptr::null_mut()), 0);
+    }
+}
@@ -0,0 +1,9 @@
+// compile-flags: -Zmiri-tree-borrows
+fn main() {
+    let mut x = 0;
+    let r = &mut x;
+    let r2 = &mut *r;
+    *r2 = 1;
+    *r = 2; // a foreign write for `r2`, which disables it
+    let _val = *r2; //~ ERROR is not allowed because tag
+}
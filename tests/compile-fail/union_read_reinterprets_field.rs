@@ -0,0 +1,11 @@
+// Reading a larger union field than the one that was written reports both fields.
+
+union U {
+    small: u8,
+    large: u32,
+}
+
+fn main() {
+    let u = U { small: 1 };
+    let _x = unsafe { u.large }; //~ ERROR reading union field `large` of type `u32` (size 4, align 4) reinterprets field `small` of type `u8` (size 1, align 1)
+}
@@ -0,0 +1,29 @@
+// compile-flags: -Zmiri-alloc-stats
+
+extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+}
+
+fn alloc_small() -> *mut u8 {
+    unsafe { malloc(8) }
+}
+
+fn alloc_large() -> *mut u8 {
+    unsafe { malloc(100) }
+}
+
+fn main() {
+    // Three small allocations from one site, and one large allocation from another.
+    let mut small = [std::ptr::null_mut(); 3];
+    for ptr in small.iter_mut() {
+        *ptr = alloc_small();
+    }
+    let large = alloc_large();
+    unsafe {
+        for &ptr in small.iter() {
+            free(ptr);
+        }
+        free(large);
+    }
+}
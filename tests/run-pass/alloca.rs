@@ -0,0 +1,30 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+extern "C" {
+    fn alloca(size: libc::size_t) -> *mut libc::c_void;
+}
+
+fn sum(n: usize) -> u64 {
+    unsafe {
+        let buf = alloca(n * 8) as *mut u64;
+        assert_eq!(buf as usize % 16, 0);
+        for i in 0..n {
+            buf.add(i).write(i as u64);
+        }
+        (0..n).map(|i| *buf.add(i)).sum()
+    }
+}
+
+fn main() {
+    // Every call gets fresh memory, which is freed when the call returns.
+    for _ in 0..3 {
+        assert_eq!(sum(10), 45);
+    }
+    // Small allocations are aligned, too.
+    let p = unsafe { alloca(1) };
+    assert_eq!(p as usize % 16, 0);
+}
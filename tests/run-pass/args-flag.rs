@@ -0,0 +1,6 @@
+// compile-flags: -Zmiri-args=first --second
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    assert_eq!(args, ["first", "--second"]);
+}
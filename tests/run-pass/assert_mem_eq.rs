@@ -0,0 +1,31 @@
+use std::mem::MaybeUninit;
+
+extern "Rust" {
+    fn miri_assert_mem_eq(ptr: *const u8, expected: *const u8, len: usize);
+}
+
+fn assert_mem_eq<T>(val: &T, expected: &[u8]) {
+    assert_eq!(std::mem::size_of::<T>(), expected.len());
+    unsafe { miri_assert_mem_eq(val as *const T as *const u8, expected.as_ptr(), expected.len()) };
+}
+
+#[repr(C)]
+struct Packet {
+    tag: u8,
+    flags: u8,
+    len: u16,
+    payload: [u8; 4],
+}
+
+fn main() {
+    assert_mem_eq(&0x12345678u32.to_le(), &[0x78, 0x56, 0x34, 0x12]);
+    assert_mem_eq(&Packet { tag: 1, flags: 0, len: 4u16.to_be(), payload: *b"miri" }, b"\x01\x00\x00\x04miri");
+
+    // Comparing zero bytes always succeeds, even for dangling pointers.
+    unsafe { miri_assert_mem_eq(std::ptr::NonNull::dangling().as_ptr(), [].as_ptr(), 0) };
+
+    // Partially initialized memory compares equal on the initialized part.
+    let mut buf = MaybeUninit::<[u8; 4]>::uninit();
+    unsafe { (buf.as_mut_ptr() as *mut u8).write(42) };
+    unsafe { miri_assert_mem_eq(buf.as_ptr() as *const u8, [42].as_ptr(), 1) };
+}
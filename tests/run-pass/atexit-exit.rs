@@ -0,0 +1,16 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+extern "C" fn handler() {
+    println!("handler");
+}
+
+fn main() {
+    unsafe { libc::atexit(handler) };
+    println!("exiting");
+    // `exit` runs the handler before ending the program.
+    std::process::exit(0);
+}
@@ -0,0 +1,40 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+extern "C" {
+    fn __cxa_atexit(
+        func: extern "C" fn(*mut libc::c_void),
+        arg: *mut libc::c_void,
+        dso_handle: *mut libc::c_void,
+    ) -> libc::c_int;
+}
+
+extern "C" fn first() {
+    println!("first");
+}
+
+extern "C" fn second() {
+    println!("second");
+    // Handlers registered while the handlers run are called, too.
+    unsafe { libc::atexit(late) };
+}
+
+extern "C" fn late() {
+    println!("late");
+}
+
+extern "C" fn with_arg(arg: *mut libc::c_void) {
+    println!("with_arg({})", arg as usize);
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::atexit(first), 0);
+        assert_eq!(__cxa_atexit(with_arg, 42 as *mut _, std::ptr::null_mut()), 0);
+        assert_eq!(libc::atexit(second), 0);
+    }
+    println!("main");
+}
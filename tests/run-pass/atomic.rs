@@ -1,10 +1,15 @@
-use std::sync::atomic::{compiler_fence, fence, AtomicBool, AtomicIsize, AtomicU64, Ordering::*};
+#![feature(atomic_min_max)]
+
+use std::sync::atomic::{
+    compiler_fence, fence, AtomicBool, AtomicIsize, AtomicU64, AtomicUsize, Ordering::*,
+};
 
 fn main() {
     atomic_bool();
     atomic_isize();
     atomic_u64();
     atomic_fences();
+    atomic_rmw();
 }
 
 fn atomic_bool() {
@@ -75,3 +80,30 @@ fn atomic_fences() {
     compiler_fence(Acquire);
     compiler_fence(AcqRel);
 }
+
+fn atomic_rmw() {
+    static SIGNED: AtomicIsize = AtomicIsize::new(0);
+    static UNSIGNED: AtomicUsize = AtomicUsize::new(0);
+
+    for &ordering in &[Relaxed, Acquire, Release, AcqRel, SeqCst] {
+        SIGNED.store(0, SeqCst);
+        assert_eq!(SIGNED.fetch_max(-5, ordering), 0);
+        assert_eq!(SIGNED.fetch_min(-5, ordering), 0);
+        assert_eq!(SIGNED.fetch_max(3, ordering), -5);
+        assert_eq!(SIGNED.fetch_min(7, ordering), 3);
+        assert_eq!(SIGNED.load(SeqCst), 3);
+
+        // As unsigned numbers, `usize::max_value()` is larger than everything.
+        UNSIGNED.store(usize::max_value(), SeqCst);
+        assert_eq!(UNSIGNED.fetch_max(5, ordering), usize::max_value());
+        assert_eq!(UNSIGNED.fetch_min(5, ordering), usize::max_value());
+        assert_eq!(UNSIGNED.fetch_max(2, ordering), 5);
+        assert_eq!(UNSIGNED.load(SeqCst), 5);
+
+        assert_eq!(UNSIGNED.fetch_nand(6, ordering), 5);
+        assert_eq!(UNSIGNED.load(SeqCst), !4);
+        assert_eq!(UNSIGNED.swap(1, ordering), !4);
+        assert_eq!(UNSIGNED.fetch_sub(2, ordering), 1);
+        assert_eq!(UNSIGNED.load(SeqCst), usize::max_value());
+    }
+}
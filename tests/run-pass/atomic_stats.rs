@@ -0,0 +1,13 @@
+// compile-flags: -Zmiri-atomic-stats
+
+use std::sync::atomic::{AtomicUsize, Ordering::*};
+
+fn main() {
+    let a = AtomicUsize::new(0);
+    a.store(1, SeqCst);
+    assert_eq!(a.load(Relaxed), 1);
+    assert_eq!(a.fetch_add(1, SeqCst), 1);
+    assert_eq!(a.compare_and_swap(2, 3, SeqCst), 2);
+    // This one fails and is counted as such.
+    assert_eq!(a.compare_exchange(2, 4, SeqCst, SeqCst), Err(3));
+}
@@ -22,5 +22,14 @@ fn main() {
         let slice = slice::from_raw_parts(p4 as *const u8, 4 * 8);
         assert_eq!(&slice, &[0_u8; 4 * 8]);
         libc::free(p4);
+
+        // Sizes that overflow or exceed `isize::MAX` fail with `ENOMEM`.
+        let p5 = libc::calloc(usize::max_value() / 4, 8);
+        assert!(p5.is_null());
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENOMEM));
+
+        let p6 = libc::calloc(1, isize::max_value() as usize + 1);
+        assert!(p6.is_null());
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENOMEM));
     }
 }
@@ -0,0 +1,30 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // With isolation, the virtual clock starts at 2020-01-01 00:00:00 UTC...
+    let start = SystemTime::now();
+    let since_epoch = start.duration_since(UNIX_EPOCH).unwrap();
+    assert!(since_epoch >= Duration::from_secs(1_577_836_800));
+    assert!(since_epoch < Duration::from_secs(1_577_836_800 + 60));
+    // ...and moves forward as the program executes.
+    let mut x = 0u64;
+    for i in 0..100 {
+        x = x.wrapping_add(i);
+    }
+    assert_eq!(x, 4950);
+    assert!(SystemTime::now() > start);
+
+    test_instant();
+}
+
+fn test_instant() {
+    use std::time::Instant;
+
+    let start = Instant::now();
+    let mut x = 0u64;
+    for i in 0..100 {
+        x = x.wrapping_add(i);
+    }
+    assert_eq!(x, 4950);
+    assert!(start.elapsed() > Duration::from_secs(0));
+}
@@ -0,0 +1,25 @@
+// compile-flags: -Zmiri-clock-start=1000000000 -Zmiri-clock-step=0
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+extern "Rust" {
+    fn miri_advance_clock(nanoseconds: u64);
+}
+
+fn main() {
+    // The clock starts at the given time and does not move on its own.
+    let start = SystemTime::now();
+    assert_eq!(start.duration_since(UNIX_EPOCH).unwrap(), Duration::from_secs(1_000_000_000));
+    let instant = Instant::now();
+    let mut x = 0u64;
+    for i in 0..100 {
+        x = x.wrapping_add(i);
+    }
+    assert_eq!(x, 4950);
+    assert_eq!(instant.elapsed(), Duration::from_secs(0));
+
+    // The program can make time pass deliberately.
+    unsafe { miri_advance_clock(1_500_000_000) };
+    assert_eq!(instant.elapsed(), Duration::from_millis(1500));
+    assert_eq!(SystemTime::now().duration_since(start).unwrap(), Duration::from_millis(1500));
+}
@@ -0,0 +1,80 @@
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn notify_one() {
+    let pair = Arc::new((Mutex::new(false), Condvar::new()));
+    let pair2 = Arc::clone(&pair);
+    let handle = thread::spawn(move || {
+        let (lock, cvar) = &*pair2;
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+    });
+    let (lock, cvar) = &*pair;
+    let mut started = lock.lock().unwrap();
+    while !*started {
+        started = cvar.wait(started).unwrap();
+    }
+    drop(started);
+    handle.join().unwrap();
+}
+
+fn notify_all() {
+    let pair = Arc::new((Mutex::new(0), Condvar::new()));
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let pair = Arc::clone(&pair);
+            thread::spawn(move || {
+                let (lock, cvar) = &*pair;
+                *lock.lock().unwrap() += 1;
+                cvar.notify_all();
+            })
+        })
+        .collect();
+    let (lock, cvar) = &*pair;
+    let mut count = lock.lock().unwrap();
+    while *count < 3 {
+        count = cvar.wait(count).unwrap();
+    }
+    drop(count);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn wait_timeout() {
+    let pair = Arc::new((Mutex::new(()), Condvar::new()));
+    let pair2 = Arc::clone(&pair);
+    // The thread takes longer than the timeout and never notifies, so the wait times out once it
+    // finished.
+    let handle = thread::spawn(move || {
+        let _pair = pair2;
+        thread::sleep(Duration::from_secs(1));
+    });
+    let (lock, cvar) = &*pair;
+    let now = Instant::now();
+    let guard = lock.lock().unwrap();
+    let (_guard, result) = cvar.wait_timeout(guard, Duration::from_millis(100)).unwrap();
+    assert!(result.timed_out());
+    assert!(now.elapsed() >= Duration::from_secs(1));
+    handle.join().unwrap();
+}
+
+fn channel() {
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+    });
+    assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+    handle.join().unwrap();
+}
+
+fn main() {
+    notify_one();
+    notify_all();
+    wait_timeout();
+    channel();
+}
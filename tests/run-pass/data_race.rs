@@ -0,0 +1,111 @@
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+
+/// Spawning a thread orders what happened before before the thread, and joining it orders the
+/// thread before what happens after.
+fn spawn_join() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    unsafe { *b.0 = 1 };
+    let handle = thread::spawn(move || unsafe { *b.0 += 1 });
+    handle.join().unwrap();
+    assert_eq!(a, 2);
+}
+
+fn mutex() {
+    let data = Arc::new(Mutex::new(0));
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let data = Arc::clone(&data);
+            thread::spawn(move || *data.lock().unwrap() += 1)
+        })
+        .collect();
+    *data.lock().unwrap() += 1;
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*data.lock().unwrap(), 4);
+}
+
+/// A release store publishes the non-atomic write before it to the acquire load that reads it.
+fn release_acquire() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    let ready = Arc::new(AtomicBool::new(false));
+    let ready2 = Arc::clone(&ready);
+    let writer = thread::spawn(move || {
+        unsafe { *b.0 = 42 };
+        ready2.store(true, Ordering::Release);
+    });
+    let reader = thread::spawn(move || {
+        while !ready.load(Ordering::Acquire) {
+            thread::yield_now();
+        }
+        unsafe { *b.0 }
+    });
+    writer.join().unwrap();
+    assert_eq!(reader.join().unwrap(), 42);
+}
+
+/// The same with relaxed atomics and fences.
+fn fences() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    let ready = Arc::new(AtomicUsize::new(0));
+    let ready2 = Arc::clone(&ready);
+    let writer = thread::spawn(move || {
+        unsafe { *b.0 = 42 };
+        fence(Ordering::Release);
+        ready2.store(1, Ordering::Relaxed);
+    });
+    let reader = thread::spawn(move || {
+        while ready.load(Ordering::Relaxed) == 0 {
+            thread::yield_now();
+        }
+        fence(Ordering::Acquire);
+        unsafe { *b.0 }
+    });
+    writer.join().unwrap();
+    assert_eq!(reader.join().unwrap(), 42);
+}
+
+/// Read-modify-writes continue the chain of releases at a location, even when they are relaxed.
+fn release_sequence() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let counter2 = Arc::clone(&counter);
+    let counter3 = Arc::clone(&counter);
+    let writer = thread::spawn(move || {
+        unsafe { *b.0 = 42 };
+        counter2.store(1, Ordering::Release);
+    });
+    let incrementer = thread::spawn(move || {
+        while counter3.compare_exchange(1, 2, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            thread::yield_now();
+        }
+    });
+    let reader = thread::spawn(move || {
+        while counter.load(Ordering::Acquire) != 2 {
+            thread::yield_now();
+        }
+        unsafe { *b.0 }
+    });
+    writer.join().unwrap();
+    incrementer.join().unwrap();
+    assert_eq!(reader.join().unwrap(), 42);
+}
+
+fn main() {
+    spawn_join();
+    mutex();
+    release_acquire();
+    fences();
+    release_sequence();
+}
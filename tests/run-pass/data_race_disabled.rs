@@ -0,0 +1,17 @@
+// compile-flags: -Zmiri-disable-data-race-detector
+
+use std::thread;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+
+fn main() {
+    let mut a = 0u32;
+    let b = EvilSend(&mut a as *mut u32);
+    let handle = thread::spawn(move || unsafe { *b.0 });
+    // This write races with the read of the thread.
+    unsafe { *b.0 = 32 };
+    assert_eq!(handle.join().unwrap(), 32);
+}
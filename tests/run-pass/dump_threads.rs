@@ -0,0 +1,9 @@
+extern "Rust" {
+    fn miri_dump_threads();
+}
+
+fn main() {
+    // Dumping the threads just prints a note and lets the program continue.
+    unsafe { miri_dump_threads() };
+    println!("still running");
+}
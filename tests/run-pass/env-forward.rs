@@ -0,0 +1,10 @@
+// compile-flags: -Zmiri-env-forward=MIRI_ENV_VAR_TEST -Zmiri-env-set=MIRI_SET_VAR=set -Zmiri-env-set=MIRI_EMPTY_VAR=
+
+fn main() {
+    // Forwarded from the host, even though isolation is enabled.
+    assert_eq!(std::env::var("MIRI_ENV_VAR_TEST"), Ok("0".to_owned()));
+    assert_eq!(std::env::var("MIRI_SET_VAR"), Ok("set".to_owned()));
+    assert_eq!(std::env::var("MIRI_EMPTY_VAR"), Ok("".to_owned()));
+    // Everything else stays isolated.
+    assert!(std::env::var("PATH").is_err());
+}
@@ -0,0 +1,5 @@
+// compile-flags: -Zmiri-disable-isolation -Zmiri-env-set=MIRI_ENV_VAR_TEST=1
+
+fn main() {
+    assert_eq!(std::env::var("MIRI_ENV_VAR_TEST"), Ok("1".to_owned()));
+}
@@ -0,0 +1,13 @@
+// ignore-windows: Uses POSIX APIs
+// compile-flags: -Zmiri-ignore-leaks-on-_exit
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    // The memory of this vector is never freed, which the flag allows when calling `_exit`.
+    let v = vec![1, 2, 3];
+    println!("exiting with {}", v.len());
+    unsafe { libc::_exit(0) }
+}
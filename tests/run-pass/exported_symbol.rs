@@ -0,0 +1,33 @@
+#[no_mangle]
+extern "C" fn foo() -> i32 {
+    -1
+}
+
+#[export_name = "bar"]
+extern "C" fn bar_but_with_a_different_name() -> i32 {
+    -2
+}
+
+#[no_mangle]
+extern "C" fn call_back(f: extern "C" fn(i32) -> i32, x: i32) -> i32 {
+    f(x)
+}
+
+extern "C" fn double(x: i32) -> i32 {
+    2 * x
+}
+
+fn main() {
+    extern "C" {
+        fn foo() -> i32;
+        fn bar() -> i32;
+        fn call_back(f: extern "C" fn(i32) -> i32, x: i32) -> i32;
+    }
+    unsafe {
+        assert_eq!(foo(), -1);
+        assert_eq!(bar(), -2);
+        // Calling a second time uses the same definition.
+        assert_eq!(foo(), -1);
+        assert_eq!(call_back(double, 21), 42);
+    }
+}
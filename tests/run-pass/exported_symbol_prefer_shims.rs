@@ -0,0 +1,14 @@
+// compile-flags: -Zmiri-prefer-shims
+
+// Miri has no shim for `foo`, so the program's definition is used even with `-Zmiri-prefer-shims`.
+#[no_mangle]
+extern "C" fn foo() -> i32 {
+    -1
+}
+
+fn main() {
+    extern "C" {
+        fn foo() -> i32;
+    }
+    assert_eq!(unsafe { foo() }, -1);
+}
@@ -0,0 +1,74 @@
+// Calls from Rust to the program's own `extern "C"` functions and back.
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[no_mangle]
+pub extern "C" fn point_add(a: Point, b: Point) -> Point {
+    Point { x: a.x + b.x, y: a.y + b.y }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn point_scale(p: *mut Point, factor: i32) {
+    (*p).x *= factor;
+    (*p).y *= factor;
+}
+
+mod private {
+    #[no_mangle]
+    extern "C" fn apply_twice(f: extern "C" fn(i32) -> i32, x: i32) -> i32 {
+        f(f(x))
+    }
+}
+
+mod ffi {
+    use super::Point;
+
+    extern "C" {
+        pub fn point_add(a: Point, b: Point) -> Point;
+        pub fn point_scale(p: *mut Point, factor: i32);
+        pub fn apply_twice(f: extern "C" fn(i32) -> i32, x: i32) -> i32;
+        #[link_name = "point_add"]
+        pub fn add_points_renamed(a: Point, b: Point) -> Point;
+        pub fn nested_in_main() -> i32;
+    }
+}
+
+extern "C" fn increment(x: i32) -> i32 {
+    // Call back into an exported function through its declaration.
+    unsafe { ffi::point_add(Point { x, y: 0 }, Point { x: 1, y: 0 }) }.x
+}
+
+fn main() {
+    #[no_mangle]
+    extern "C" fn nested_in_main() -> i32 {
+        7
+    }
+
+    let a = Point { x: 1, y: 2 };
+    let b = Point { x: 10, y: 20 };
+    unsafe {
+        assert_eq!(ffi::point_add(a, b), Point { x: 11, y: 22 });
+        assert_eq!(ffi::add_points_renamed(a, b), Point { x: 11, y: 22 });
+
+        let mut p = a;
+        ffi::point_scale(&mut p, 3);
+        assert_eq!(p, Point { x: 3, y: 6 });
+
+        assert_eq!(ffi::apply_twice(increment, 40), 42);
+        assert_eq!(ffi::nested_in_main(), 7);
+    }
+
+    // Calls through function pointers to the declarations.
+    let add: unsafe extern "C" fn(Point, Point) -> Point = ffi::point_add;
+    let scale: unsafe extern "C" fn(*mut Point, i32) = ffi::point_scale;
+    unsafe {
+        let mut p = add(a, b);
+        scale(&mut p, 2);
+        assert_eq!(p, Point { x: 22, y: 44 });
+    }
+}
@@ -0,0 +1,14 @@
+// Pointers cast from integers may access every allocation that was exposed by a cast to an integer.
+fn main() {
+    let v = [1u16, 2, 3, 4];
+    let addr = v.as_ptr() as usize;
+    let third = (addr + 2 * std::mem::size_of::<u16>()) as *const u16;
+    assert_eq!(unsafe { *third }, 3);
+
+    // Exposing one pointer exposes the entire allocation.
+    let b = Box::new((1u8, 2u8));
+    let addr = &b.0 as *const u8 as usize;
+    let second = unsafe { std::mem::transmute::<&u8, usize>(&b.1) };
+    assert_eq!(second, addr + 1);
+    assert_eq!(unsafe { *(second as *const u8) }, 2);
+}
@@ -1,7 +1,7 @@
 // ignore-windows: File handling is not implemented yet
 // compile-flags: -Zmiri-disable-isolation
 
-use std::fs::{File, create_dir, read_dir, remove_dir, remove_dir_all, remove_file, rename};
+use std::fs::{File, create_dir, read_dir, read_link, remove_dir, remove_dir_all, remove_file, rename};
 use std::io::{Read, Write, ErrorKind, Result, Seek, SeekFrom};
 use std::path::{PathBuf, Path};
 
@@ -14,6 +14,10 @@ fn main() {
     test_errors();
     test_rename();
     test_directory();
+    #[cfg(target_os = "linux")]
+    test_proc_self_fd();
+    #[cfg(target_os = "linux")]
+    test_proc_self_files();
 }
 
 /// Prepare: compute filename and make sure the file does not exist.
@@ -152,6 +156,8 @@ fn test_symlink() {
     check_metadata(bytes, &symlink_path).unwrap();
     // Test that the metadata of a symbolic link is correct when not following it.
     assert!(symlink_path.symlink_metadata().unwrap().file_type().is_symlink());
+    // Test that reading the symbolic link gives back its target.
+    assert_eq!(read_link(&symlink_path).unwrap(), path);
     // Removing symbolic link should succeed.
     remove_file(&symlink_path).unwrap();
 
@@ -221,3 +227,53 @@ fn test_directory() {
     // Reading the metadata of a non-existent file should fail with a "not found" error.
     assert_eq!(ErrorKind::NotFound, check_metadata(&[], &dir_path).unwrap_err().kind());
 }
+
+#[cfg(target_os = "linux")]
+fn test_proc_self_fd() {
+    use std::os::unix::io::AsRawFd;
+
+    let path = prepare_with_content("miri_test_fs_proc_self_fd.txt", b"");
+    let file = File::open(&path).unwrap();
+    // Reading the link of an open file descriptor gives back the path it was opened with.
+    let link = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+    assert_eq!(read_link(&link).unwrap(), path);
+    // The path is also remembered for duplicated file descriptors.
+    let cloned = file.try_clone().unwrap();
+    let link = PathBuf::from(format!("/proc/self/fd/{}", cloned.as_raw_fd()));
+    assert_eq!(read_link(&link).unwrap(), path);
+
+    drop(file);
+    drop(cloned);
+    // Removing file should succeed.
+    remove_file(&path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+fn test_proc_self_files() {
+    use std::fs::{read, read_to_string, OpenOptions};
+
+    // The command line is a list of null-terminated arguments.
+    let cmdline = read("/proc/self/cmdline").unwrap();
+    assert_eq!(cmdline.last(), Some(&0));
+    assert_eq!(cmdline.iter().filter(|&&b| b == 0).count(), std::env::args().count());
+    // The memory map describes at least the stack.
+    let maps = read_to_string("/proc/self/maps").unwrap();
+    assert!(maps.lines().any(|line| line.ends_with("[stack]")));
+    // The status file starts with the program name.
+    let status = read_to_string("/proc/self/status").unwrap();
+    assert!(status.starts_with("Name:"));
+    // Duplicated descriptors share their offset.
+    let mut file = File::open("/proc/self/status").unwrap();
+    let mut cloned = file.try_clone().unwrap();
+    let mut name = [0; 5];
+    file.read_exact(&mut name).unwrap();
+    assert_eq!(&name, b"Name:");
+    let mut rest = String::new();
+    cloned.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, status["Name:".len()..]);
+    // These files cannot be written to.
+    assert_eq!(
+        ErrorKind::PermissionDenied,
+        OpenOptions::new().write(true).open("/proc/self/maps").unwrap_err().kind(),
+    );
+}
@@ -0,0 +1,29 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation -Zmiri-deterministic-readdir
+
+use std::fs::{File, create_dir, read_dir, remove_dir, remove_dir_all, remove_file};
+
+fn main() {
+    let dir_path = std::env::temp_dir().join("miri_test_fs_deterministic_readdir");
+    // Clean the directory for robustness.
+    remove_dir_all(&dir_path).ok();
+    create_dir(&dir_path).unwrap();
+
+    // Create the files in an order that is unlikely to match the sorted one.
+    let names = ["c", "a", "d", "b"];
+    for name in &names {
+        drop(File::create(dir_path.join(name)).unwrap());
+    }
+    // The entries should come back sorted by name, without sorting them ourselves.
+    let file_names = read_dir(&dir_path)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(file_names, vec!["a", "b", "c", "d"]);
+
+    // Clean up the files and the directory.
+    for name in &names {
+        remove_file(dir_path.join(name)).unwrap();
+    }
+    remove_dir(&dir_path).unwrap();
+}
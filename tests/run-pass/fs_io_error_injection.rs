@@ -0,0 +1,17 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation -Zmiri-io-error-rate=1.0
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::fs::File;
+
+fn main() {
+    let path = std::env::temp_dir().join("miri_test_fs_io_error_injection.txt");
+    // Every `open` fails. `std` retries on `EINTR`, so we eventually see the other error.
+    let err = File::create(&path).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EMFILE));
+    // Nothing was created on the host.
+    assert!(!path.exists());
+}
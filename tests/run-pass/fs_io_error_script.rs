@@ -0,0 +1,26 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation -Zmiri-io-error=write:2=ENOSPC -Zmiri-io-error=fsync:1=EIO -Zmiri-io-error=open:3=EACCES
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::fs::{remove_file, File};
+use std::io::Write;
+
+fn main() {
+    let path = std::env::temp_dir().join("miri_test_fs_io_error_script.txt");
+    let mut file = File::create(&path).unwrap();
+    // Only the scripted calls fail, each with the scripted error.
+    file.write_all(b"a").unwrap();
+    assert_eq!(file.write(b"b").unwrap_err().raw_os_error(), Some(libc::ENOSPC));
+    file.write_all(b"c").unwrap();
+    assert_eq!(file.sync_all().unwrap_err().raw_os_error(), Some(libc::EIO));
+    file.sync_all().unwrap();
+    drop(file);
+
+    File::open(&path).unwrap();
+    assert_eq!(File::open(&path).unwrap_err().raw_os_error(), Some(libc::EACCES));
+    File::open(&path).unwrap();
+    remove_file(&path).unwrap();
+}
@@ -0,0 +1,35 @@
+// ignore-windows: File handling is not implemented yet
+// compile-flags: -Zmiri-disable-isolation -Zmiri-short-io
+
+use std::fs::{File, remove_file};
+use std::io::{Read, Write};
+
+fn main() {
+    let path = std::env::temp_dir().join("miri_test_fs_short_io.txt");
+    // Clean the path for robustness.
+    remove_file(&path).ok();
+
+    let bytes: Vec<u8> = (0..=255).cycle().take(4096).collect();
+    // `write_all` and `read_to_end` cope with short transfers and `EINTR`.
+    File::create(&path).unwrap().write_all(&bytes).unwrap();
+    let mut contents = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(bytes, contents);
+
+    // A single `read` never transfers more than requested, but it may transfer less.
+    let mut file = File::open(&path).unwrap();
+    let mut buf = [0u8; 1024];
+    let mut saw_short_read = false;
+    for _ in 0..16 {
+        match file.read(&mut buf) {
+            Ok(n) => {
+                assert!(n <= buf.len());
+                saw_short_read |= n < buf.len();
+            }
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::Interrupted),
+        }
+    }
+    assert!(saw_short_read);
+
+    remove_file(&path).unwrap();
+}
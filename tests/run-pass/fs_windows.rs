@@ -0,0 +1,47 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+// compile-flags: -Zmiri-disable-isolation
+
+use std::ptr;
+
+extern "system" {
+    fn DeleteFileW(path: *const u16) -> i32;
+    fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    fn GetFullPathNameW(path: *const u16, size: u32, buf: *mut u16, file_part: *mut *mut u16) -> u32;
+    fn GetLastError() -> u32;
+}
+
+const ERROR_FILE_NOT_FOUND: u32 = 2;
+const MOVEFILE_REPLACE_EXISTING: u32 = 1;
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+fn main() {
+    let missing = to_wide("\\miri_test_fs_windows_missing.txt");
+    let other = to_wide("\\miri_test_fs_windows_other.txt");
+    unsafe {
+        // Deleting or moving a file that does not exist should fail with "not found".
+        assert_eq!(DeleteFileW(missing.as_ptr()), 0);
+        assert_eq!(GetLastError(), ERROR_FILE_NOT_FOUND);
+        assert_eq!(MoveFileExW(missing.as_ptr(), other.as_ptr(), MOVEFILE_REPLACE_EXISTING), 0);
+        assert_eq!(GetLastError(), ERROR_FILE_NOT_FOUND);
+
+        // `.` and `..` components are resolved without touching the file system.
+        let path = to_wide("\\tmp\\.\\a\\..\\b.txt");
+        // Asking with a too small buffer returns the required size.
+        let size = GetFullPathNameW(path.as_ptr(), 0, ptr::null_mut(), ptr::null_mut());
+        assert!(size > 0);
+        let mut buf = vec![0u16; size as usize];
+        let mut file_part = ptr::null_mut();
+        let len = GetFullPathNameW(path.as_ptr(), size, buf.as_mut_ptr(), &mut file_part);
+        assert_eq!(len + 1, size);
+        let full_path = String::from_utf16(&buf[..len as usize]).unwrap();
+        assert!(full_path.ends_with("\\tmp\\b.txt"), "{}", full_path);
+        // The file part points to the last component.
+        assert_eq!(file_part, buf.as_mut_ptr().add(len as usize - "b.txt".len()));
+    }
+}
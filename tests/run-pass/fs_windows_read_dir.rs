@@ -0,0 +1,23 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+// compile-flags: -Zmiri-disable-isolation
+
+use std::fs::read_dir;
+
+fn main() {
+    // The test suite runs in the root of the repository, which is not empty.
+    let entries = read_dir(".").unwrap().map(|e| e.unwrap()).collect::<Vec<_>>();
+    assert!(!entries.is_empty());
+    // `std` filters out `.` and `..`.
+    assert!(entries.iter().all(|e| e.file_name() != "." && e.file_name() != ".."));
+    // Every entry comes with a file type.
+    for entry in &entries {
+        let file_type = entry.file_type().unwrap();
+        assert!(file_type.is_dir() || file_type.is_file() || file_type.is_symlink());
+    }
+    // The `tests` directory is where this very file lives.
+    let tests = entries.iter().find(|e| e.file_name() == "tests").unwrap();
+    assert!(tests.file_type().unwrap().is_dir());
+}
@@ -37,7 +37,484 @@ fn test_posix_fadvise() {
     assert_eq!(result, 0);
 }
 
+fn test_symlinkat_readlinkat() {
+    use std::env::temp_dir;
+    use std::ffi::CString;
+    use std::fs::remove_file;
+    use std::os::unix::ffi::OsStrExt;
+
+    let target = temp_dir().join("miri_test_libc_symlinkat_target.txt");
+    let link = temp_dir().join("miri_test_libc_symlinkat_link.txt");
+    // Cleanup before test
+    remove_file(&link).ok();
+
+    let target_c = CString::new(target.as_os_str().as_bytes()).unwrap();
+    let link_c = CString::new(link.as_os_str().as_bytes()).unwrap();
+
+    // Creating a link relative to `AT_FDCWD` should succeed.
+    let result = unsafe { libc::symlinkat(target_c.as_ptr(), libc::AT_FDCWD, link_c.as_ptr()) };
+    assert_eq!(result, 0);
+
+    // Reading the link back should give us its target, without a null terminator.
+    let mut buf = vec![0u8; 1024];
+    let len = unsafe {
+        libc::readlinkat(libc::AT_FDCWD, link_c.as_ptr(), buf.as_mut_ptr().cast(), buf.len())
+    };
+    assert_eq!(&buf[..len as usize], target.as_os_str().as_bytes());
+
+    // A buffer that is too small silently truncates the result.
+    let mut small_buf = [0u8; 4];
+    let len = unsafe {
+        libc::readlinkat(libc::AT_FDCWD, link_c.as_ptr(), small_buf.as_mut_ptr().cast(), small_buf.len())
+    };
+    assert_eq!(len, 4);
+    assert_eq!(&small_buf, &target.as_os_str().as_bytes()[..4]);
+
+    remove_file(&link).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+fn test_o_tmpfile() {
+    use std::env::temp_dir;
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::FromRawFd;
+
+    let dir = CString::new(temp_dir().as_os_str().as_bytes()).unwrap();
+
+    // Creating an unnamed temporary file in a directory should succeed.
+    let fd = unsafe { libc::open(dir.as_ptr(), libc::O_TMPFILE | libc::O_RDWR) };
+    assert!(fd >= 0);
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    // The file should behave like any other file.
+    let bytes = b"Hello, anonymous World!\n";
+    file.write_all(bytes).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).unwrap();
+    assert_eq!(bytes, contents.as_slice());
+
+    // `O_TMPFILE` requires write access.
+    let fd = unsafe { libc::open(dir.as_ptr(), libc::O_TMPFILE | libc::O_RDONLY) };
+    assert_eq!(fd, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+}
+
+#[cfg(target_os = "macos")]
+fn test_f_getpath() {
+    use std::env::temp_dir;
+    use std::ffi::CStr;
+    use std::fs::{File, remove_file};
+    use std::os::unix::io::AsRawFd;
+
+    let path = temp_dir().join("miri_test_libc_f_getpath.txt");
+    // Cleanup before test
+    remove_file(&path).ok();
+
+    let file = File::create(&path).unwrap();
+    // `F_GETPATH` should recover the path that was used to open the file.
+    let mut buf = vec![0 as libc::c_char; libc::PATH_MAX as usize];
+    let result = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETPATH, buf.as_mut_ptr()) };
+    assert_eq!(result, 0);
+    let recovered = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    assert_eq!(recovered.to_str().unwrap(), path.to_str().unwrap());
+
+    drop(file);
+    remove_file(&path).unwrap();
+}
+
+fn test_cloexec() {
+    use std::env::temp_dir;
+    use std::fs::{File, remove_file};
+    use std::os::unix::io::AsRawFd;
+
+    let path = temp_dir().join("miri_test_libc_cloexec.txt");
+    // Cleanup before test
+    remove_file(&path).ok();
+
+    let file = File::create(&path).unwrap();
+    let fd = file.as_raw_fd();
+    unsafe {
+        // `std` always opens files with `O_CLOEXEC`.
+        assert_eq!(libc::fcntl(fd, libc::F_GETFD), libc::FD_CLOEXEC);
+        // The flag is per descriptor: `dup` clears it on the new one...
+        let dup = libc::dup(fd);
+        assert!(dup > fd);
+        assert_eq!(libc::fcntl(dup, libc::F_GETFD), 0);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFD), libc::FD_CLOEXEC);
+        // ...while `F_DUPFD_CLOEXEC` sets it.
+        let dup_cloexec = libc::fcntl(dup, libc::F_DUPFD_CLOEXEC, 0);
+        assert_eq!(libc::fcntl(dup_cloexec, libc::F_GETFD), libc::FD_CLOEXEC);
+        // It can be changed with `F_SETFD`.
+        assert_eq!(libc::fcntl(fd, libc::F_SETFD, 0), 0);
+        assert_eq!(libc::fcntl(fd, libc::F_GETFD), 0);
+        assert_eq!(libc::fcntl(dup_cloexec, libc::F_GETFD), libc::FD_CLOEXEC);
+        // `dup2` replaces the target descriptor and clears the flag on it.
+        assert_eq!(libc::dup2(fd, dup_cloexec), dup_cloexec);
+        assert_eq!(libc::fcntl(dup_cloexec, libc::F_GETFD), 0);
+
+        assert_eq!(libc::close(dup), 0);
+        assert_eq!(libc::close(dup_cloexec), 0);
+    }
+
+    drop(file);
+    remove_file(&path).unwrap();
+}
+
+fn test_mutex_libc_init_recursive() {
+    unsafe {
+        let mut attr: libc::pthread_mutexattr_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutexattr_init(&mut attr as *mut _), 0);
+        assert_eq!(libc::pthread_mutexattr_settype(&mut attr as *mut _, libc::PTHREAD_MUTEX_RECURSIVE), 0);
+        let mut mutex: libc::pthread_mutex_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutex_init(&mut mutex as *mut _, &mut attr as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_trylock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_trylock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), libc::EPERM);
+        assert_eq!(libc::pthread_mutex_destroy(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutexattr_destroy(&mut attr as *mut _), 0);
+    }
+}
+
+fn test_mutex_libc_init_errorcheck() {
+    unsafe {
+        let mut attr: libc::pthread_mutexattr_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutexattr_init(&mut attr as *mut _), 0);
+        assert_eq!(libc::pthread_mutexattr_settype(&mut attr as *mut _, libc::PTHREAD_MUTEX_ERRORCHECK), 0);
+        // Unknown types are rejected and leave the attributes unchanged.
+        assert_eq!(libc::pthread_mutexattr_settype(&mut attr as *mut _, 0x1234), libc::EINVAL);
+        let mut mutex: libc::pthread_mutex_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutex_init(&mut mutex as *mut _, &mut attr as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_trylock(&mut mutex as *mut _), libc::EBUSY);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), libc::EDEADLK);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), libc::EPERM);
+        assert_eq!(libc::pthread_mutex_destroy(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutexattr_destroy(&mut attr as *mut _), 0);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn test_mutex_libc_init_robust() {
+    unsafe {
+        let mut attr: libc::pthread_mutexattr_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutexattr_init(&mut attr as *mut _), 0);
+        assert_eq!(libc::pthread_mutexattr_setrobust(&mut attr as *mut _, libc::PTHREAD_MUTEX_ROBUST), 0);
+        // Setting the type keeps the mutex robust.
+        assert_eq!(libc::pthread_mutexattr_settype(&mut attr as *mut _, libc::PTHREAD_MUTEX_ERRORCHECK), 0);
+        let mut mutex: libc::pthread_mutex_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutex_init(&mut mutex as *mut _, &mut attr as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+        // The mutex is consistent, since its owner is still alive.
+        assert_eq!(libc::pthread_mutex_consistent(&mut mutex as *mut _), libc::EINVAL);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), libc::EDEADLK);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_destroy(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutexattr_destroy(&mut attr as *mut _), 0);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn test_condattr_clock() {
+    unsafe {
+        let mut attr: libc::pthread_condattr_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_condattr_init(&mut attr as *mut _), 0);
+        let mut clock_id = -1;
+        assert_eq!(libc::pthread_condattr_getclock(&attr as *const _, &mut clock_id as *mut _), 0);
+        assert_eq!(clock_id, libc::CLOCK_REALTIME);
+        assert_eq!(libc::pthread_condattr_setclock(&mut attr as *mut _, libc::CLOCK_MONOTONIC), 0);
+        assert_eq!(libc::pthread_condattr_getclock(&attr as *const _, &mut clock_id as *mut _), 0);
+        assert_eq!(clock_id, libc::CLOCK_MONOTONIC);
+        assert_eq!(libc::pthread_condattr_setclock(&mut attr as *mut _, 0x1234), libc::EINVAL);
+
+        let mut cond: libc::pthread_cond_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_cond_init(&mut cond as *mut _, &attr as *const _), 0);
+        let mut mutex: libc::pthread_mutex_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_mutex_init(&mut mutex as *mut _, std::ptr::null()), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+
+        // The deadline is interpreted according to the monotonic clock.
+        let mut now: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now as *mut _), 0);
+        let deadline = libc::timespec { tv_sec: now.tv_sec + 1, tv_nsec: now.tv_nsec };
+        assert_eq!(
+            libc::pthread_cond_timedwait(&mut cond as *mut _, &mut mutex as *mut _, &deadline as *const _),
+            libc::ETIMEDOUT,
+        );
+        let mut after: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut after as *mut _), 0);
+        assert!((after.tv_sec, after.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec));
+
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_mutex_destroy(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_cond_destroy(&mut cond as *mut _), 0);
+        assert_eq!(libc::pthread_condattr_destroy(&mut attr as *mut _), 0);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn test_clock_getres_nanosleep() {
+    unsafe {
+        let mut res: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_getres(libc::CLOCK_MONOTONIC, &mut res as *mut _), 0);
+        assert_eq!((res.tv_sec, res.tv_nsec), (0, 1));
+        assert_eq!(libc::clock_getres(libc::CLOCK_REALTIME, std::ptr::null_mut()), 0);
+        assert_eq!(libc::clock_getres(0x1234, &mut res as *mut _), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+
+        let mut start: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut start as *mut _), 0);
+        // Relative sleep.
+        let req = libc::timespec { tv_sec: 1, tv_nsec: 0 };
+        assert_eq!(libc::clock_nanosleep(libc::CLOCK_MONOTONIC, 0, &req, std::ptr::null_mut()), 0);
+        let mut now: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now as *mut _), 0);
+        assert!(now.tv_sec >= start.tv_sec + 1);
+        // Absolute sleep.
+        let deadline = libc::timespec { tv_sec: now.tv_sec + 2, tv_nsec: now.tv_nsec };
+        assert_eq!(
+            libc::clock_nanosleep(libc::CLOCK_MONOTONIC, libc::TIMER_ABSTIME, &deadline, std::ptr::null_mut()),
+            0,
+        );
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now as *mut _), 0);
+        assert!((now.tv_sec, now.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec));
+        // A deadline in the past returns immediately.
+        assert_eq!(
+            libc::clock_nanosleep(libc::CLOCK_MONOTONIC, libc::TIMER_ABSTIME, &start, std::ptr::null_mut()),
+            0,
+        );
+        // Errors are returned, not stored in `errno`.
+        let invalid = libc::timespec { tv_sec: 0, tv_nsec: 1_000_000_000 };
+        assert_eq!(libc::clock_nanosleep(libc::CLOCK_MONOTONIC, 0, &invalid, std::ptr::null_mut()), libc::EINVAL);
+    }
+}
+
+fn test_cpu_time() {
+    fn user_time() -> libc::timeval {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage as *mut _) }, 0);
+        usage.ru_utime
+    }
+
+    // The CPU time grows as the program executes.
+    let before = user_time();
+    let mut x = 0u64;
+    for i in 0..1000 {
+        x = x.wrapping_add(i);
+    }
+    assert_eq!(x, 499500);
+    let after = user_time();
+    assert!((after.tv_sec, after.tv_usec) > (before.tv_sec, before.tv_usec));
+
+    let mut tms: libc::tms = unsafe { std::mem::zeroed() };
+    assert!(unsafe { libc::times(&mut tms as *mut _) } >= 0);
+    assert_eq!(tms.tms_cutime, 0);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_CLK_TCK) }, 100);
+}
+
+#[cfg(target_os = "linux")]
+fn test_timerfd() {
+    fn read_expirations(fd: libc::c_int) -> (isize, u64) {
+        let mut expirations = 0u64;
+        let res = unsafe { libc::read(fd, &mut expirations as *mut u64 as *mut libc::c_void, 8) };
+        (res, expirations)
+    }
+
+    unsafe {
+        let fd = libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC);
+        assert!(fd >= 0);
+        // A disarmed timer never expires.
+        assert_eq!(read_expirations(fd).0, -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EAGAIN));
+
+        // Arm the timer to first expire after one second, and then every 100ms.
+        let new_value = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 100_000_000 },
+            it_value: libc::timespec { tv_sec: 1, tv_nsec: 0 },
+        };
+        assert_eq!(libc::timerfd_settime(fd, 0, &new_value, std::ptr::null_mut()), 0);
+        let mut curr_value: libc::itimerspec = std::mem::zeroed();
+        assert_eq!(libc::timerfd_gettime(fd, &mut curr_value), 0);
+        assert_eq!(curr_value.it_interval.tv_nsec, 100_000_000);
+        assert!(curr_value.it_value.tv_sec == 0 || curr_value.it_value.tv_nsec == 0);
+        assert_eq!(read_expirations(fd).0, -1);
+
+        // After sleeping past the first deadline and three more periods, we see all expirations
+        // at once.
+        std::thread::sleep(std::time::Duration::from_millis(1350));
+        assert_eq!(read_expirations(fd), (8, 4));
+        assert_eq!(read_expirations(fd).0, -1);
+
+        // Buffers that cannot hold the count are rejected.
+        let mut small = 0u32;
+        assert_eq!(libc::read(fd, &mut small as *mut u32 as *mut libc::c_void, 4), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+
+        // Disarm the timer again; the old value is reported.
+        let disarm: libc::itimerspec = std::mem::zeroed();
+        let mut old_value: libc::itimerspec = std::mem::zeroed();
+        assert_eq!(libc::timerfd_settime(fd, 0, &disarm, &mut old_value), 0);
+        assert_eq!(old_value.it_interval.tv_nsec, 100_000_000);
+        assert_eq!(libc::close(fd), 0);
+
+        // A blocking one-shot timer with an absolute deadline waits for it.
+        let fd = libc::timerfd_create(libc::CLOCK_MONOTONIC, 0);
+        let mut now: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now), 0);
+        let new_value = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: libc::timespec { tv_sec: now.tv_sec + 5, tv_nsec: now.tv_nsec },
+        };
+        assert_eq!(libc::timerfd_settime(fd, libc::TFD_TIMER_ABSTIME, &new_value, std::ptr::null_mut()), 0);
+        assert_eq!(read_expirations(fd), (8, 1));
+        let mut after: libc::timespec = std::mem::zeroed();
+        assert_eq!(libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut after), 0);
+        assert!(after.tv_sec >= now.tv_sec + 5);
+        assert_eq!(libc::close(fd), 0);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn test_environ() {
+    use std::ffi::CStr;
+
+    extern "C" {
+        static environ: *const *const libc::c_char;
+    }
+
+    fn find_in_environ(var: &str) -> bool {
+        unsafe {
+            let mut var_ptr = environ;
+            while !(*var_ptr).is_null() {
+                if CStr::from_ptr(*var_ptr).to_bytes() == var.as_bytes() {
+                    return true;
+                }
+                var_ptr = var_ptr.add(1);
+            }
+        }
+        false
+    }
+
+    // `environ` follows changes to the environment.
+    std::env::set_var("MIRI_ENVIRON_TEST", "1");
+    assert!(find_in_environ("MIRI_ENVIRON_TEST=1"));
+    std::env::remove_var("MIRI_ENVIRON_TEST");
+    assert!(!find_in_environ("MIRI_ENVIRON_TEST=1"));
+}
+
+fn test_getpid() {
+    let pid = unsafe { libc::getpid() };
+    assert_eq!(pid as u32, std::process::id());
+
+    // The main thread's id is the process id.
+    #[cfg(target_os = "linux")]
+    assert_eq!(unsafe { libc::syscall(libc::SYS_gettid) }, pid as libc::c_long);
+}
+
+fn test_sysconf() {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    assert_eq!(page_size, 4096);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_PAGE_SIZE) }, page_size);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }, 1);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }, 1);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) }, -1);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_GETGR_R_SIZE_MAX) }, -1);
+}
+
+fn test_sched_yield() {
+    assert_eq!(unsafe { libc::sched_yield() }, 0);
+    std::thread::yield_now();
+}
+
+fn test_uname() {
+    use std::ffi::CStr;
+
+    fn field(f: &[libc::c_char]) -> &str {
+        unsafe { CStr::from_ptr(f.as_ptr()) }.to_str().unwrap()
+    }
+
+    let mut buf: libc::utsname = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::uname(&mut buf) }, 0);
+    let sysname = if cfg!(target_os = "linux") { "Linux" } else { "Darwin" };
+    assert_eq!(field(&buf.sysname), sysname);
+    assert!(!field(&buf.release).is_empty());
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(field(&buf.machine), "x86_64");
+}
+
+fn test_getifaddrs() {
+    use std::ffi::CStr;
+
+    let mut ifap = std::ptr::null_mut();
+    assert_eq!(unsafe { libc::getifaddrs(&mut ifap) }, 0);
+    let mut families = Vec::new();
+    let mut ifa = ifap;
+    while !ifa.is_null() {
+        let entry = unsafe { &*ifa };
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }.to_str().unwrap();
+        assert_eq!(name, if cfg!(target_os = "macos") { "lo0" } else { "lo" });
+        assert_ne!(entry.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint, 0);
+        let family = i32::from(unsafe { (*entry.ifa_addr).sa_family });
+        if family == libc::AF_INET {
+            let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+            assert_eq!(u32::from_be(addr.sin_addr.s_addr), 0x7f00_0001);
+        }
+        families.push(family);
+        ifa = entry.ifa_next;
+    }
+    assert_eq!(families, [libc::AF_INET, libc::AF_INET6]);
+    unsafe { libc::freeifaddrs(ifap) };
+}
+
 fn main() {
     #[cfg(not(target_os = "macos"))]
     test_posix_fadvise();
+
+    #[cfg(target_os = "linux")]
+    test_o_tmpfile();
+
+    test_symlinkat_readlinkat();
+
+    test_cloexec();
+
+    #[cfg(target_os = "macos")]
+    test_f_getpath();
+
+    test_mutex_libc_init_recursive();
+    test_mutex_libc_init_errorcheck();
+    #[cfg(target_os = "linux")]
+    test_mutex_libc_init_robust();
+
+    #[cfg(target_os = "linux")]
+    test_condattr_clock();
+
+    #[cfg(target_os = "linux")]
+    test_clock_getres_nanosleep();
+
+    test_cpu_time();
+
+    #[cfg(target_os = "linux")]
+    test_timerfd();
+
+    #[cfg(target_os = "linux")]
+    test_environ();
+
+    test_getpid();
+
+    test_sysconf();
+
+    test_sched_yield();
+
+    test_uname();
+
+    test_getifaddrs();
 }
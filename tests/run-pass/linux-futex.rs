@@ -0,0 +1,108 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn futex_wait(futex: &AtomicI32, val: i32, timeout: Option<&libc::timespec>) -> libc::c_long {
+    let timeout = timeout.map_or(ptr::null(), |t| t as *const libc::timespec);
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            futex as *const AtomicI32,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            val,
+            timeout,
+        )
+    }
+}
+
+fn futex_wake(futex: &AtomicI32, count: i32) -> libc::c_long {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            futex as *const AtomicI32,
+            libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+            count,
+        )
+    }
+}
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn wait_wrong_value() {
+    let futex = AtomicI32::new(1);
+    assert_eq!(futex_wait(&futex, 0, None), -1);
+    assert_eq!(errno(), libc::EAGAIN);
+}
+
+fn wake_nobody() {
+    let futex = AtomicI32::new(0);
+    assert_eq!(futex_wake(&futex, 1), 0);
+}
+
+fn wait_timeout() {
+    let futex = AtomicI32::new(0);
+    let timeout = libc::timespec { tv_sec: 0, tv_nsec: 200_000_000 };
+    let start = Instant::now();
+    assert_eq!(futex_wait(&futex, 0, Some(&timeout)), -1);
+    assert_eq!(errno(), libc::ETIMEDOUT);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+fn wait_invalid_timeout() {
+    let futex = AtomicI32::new(0);
+    let timeout = libc::timespec { tv_sec: 0, tv_nsec: 1_000_000_000 };
+    assert_eq!(futex_wait(&futex, 0, Some(&timeout)), -1);
+    assert_eq!(errno(), libc::EINVAL);
+}
+
+fn wait_wake() {
+    let futex = Arc::new(AtomicI32::new(0));
+    let futex2 = Arc::clone(&futex);
+    let handle = thread::spawn(move || {
+        futex2.store(1, Ordering::Release);
+        assert_eq!(futex_wake(&futex2, 1), 1);
+    });
+    while futex.load(Ordering::Acquire) == 0 {
+        futex_wait(&futex, 0, None);
+    }
+    handle.join().unwrap();
+}
+
+fn wake_some() {
+    let futex = Arc::new(AtomicI32::new(0));
+    let waiters: Vec<_> = (0..3)
+        .map(|_| {
+            let futex = Arc::clone(&futex);
+            thread::spawn(move || {
+                let timeout = libc::timespec { tv_sec: 1, tv_nsec: 0 };
+                futex_wait(&futex, 0, Some(&timeout))
+            })
+        })
+        .collect();
+    let futex2 = Arc::clone(&futex);
+    let waker = thread::spawn(move || futex_wake(&futex2, 2));
+    // Each waiter blocks until the waker ran, which wakes the first two of them.
+    let results: Vec<_> = waiters.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(waker.join().unwrap(), 2);
+    assert_eq!(results, [0, 0, -1]);
+}
+
+fn main() {
+    wait_wrong_value();
+    wake_nobody();
+    wait_timeout();
+    wait_invalid_timeout();
+    wait_wake();
+    wake_some();
+}
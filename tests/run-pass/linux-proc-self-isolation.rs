@@ -0,0 +1,22 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+
+use std::fs::read_to_string;
+use std::thread;
+
+fn threads() -> usize {
+    let status = read_to_string("/proc/self/status").unwrap();
+    let line = status.lines().find(|line| line.starts_with("Threads:")).unwrap();
+    line["Threads:".len()..].trim().parse().unwrap()
+}
+
+fn main() {
+    // The files in `/proc/self` are served by Miri, so they can be read with isolation.
+    assert!(read_to_string("/proc/self/maps").unwrap().contains("[stack]"));
+    assert_eq!(threads(), 1);
+    // The main thread still exists while it waits for the other one.
+    assert_eq!(thread::spawn(threads).join().unwrap(), 2);
+    assert_eq!(threads(), 1);
+}
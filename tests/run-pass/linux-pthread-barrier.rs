@@ -0,0 +1,103 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::Arc;
+use std::thread;
+
+struct Barrier(UnsafeCell<libc::pthread_barrier_t>);
+
+unsafe impl Send for Barrier {}
+unsafe impl Sync for Barrier {}
+
+impl Barrier {
+    fn new(count: u32) -> Barrier {
+        unsafe {
+            let mut barrier = MaybeUninit::<libc::pthread_barrier_t>::uninit();
+            assert_eq!(libc::pthread_barrier_init(barrier.as_mut_ptr(), ptr::null(), count), 0);
+            Barrier(UnsafeCell::new(barrier.assume_init()))
+        }
+    }
+
+    /// Returns whether this thread is the one that got `PTHREAD_BARRIER_SERIAL_THREAD`.
+    fn wait(&self) -> bool {
+        match unsafe { libc::pthread_barrier_wait(self.0.get()) } {
+            0 => false,
+            libc::PTHREAD_BARRIER_SERIAL_THREAD => true,
+            error => panic!("pthread_barrier_wait failed with {}", error),
+        }
+    }
+}
+
+impl Drop for Barrier {
+    fn drop(&mut self) {
+        unsafe {
+            assert_eq!(libc::pthread_barrier_destroy(self.0.get()), 0);
+        }
+    }
+}
+
+fn init_zero() {
+    let mut barrier = MaybeUninit::<libc::pthread_barrier_t>::uninit();
+    unsafe {
+        assert_eq!(libc::pthread_barrier_init(barrier.as_mut_ptr(), ptr::null(), 0), libc::EINVAL);
+    }
+}
+
+fn single_thread() {
+    let barrier = Barrier::new(1);
+    assert!(barrier.wait());
+    assert!(barrier.wait());
+}
+
+fn one_serial_thread() {
+    const THREADS: usize = 4;
+    let barrier = Arc::new(Barrier::new(THREADS as u32));
+    let handles: Vec<_> = (1..THREADS)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || barrier.wait())
+        })
+        .collect();
+    let mut serial = barrier.wait() as usize;
+    for handle in handles {
+        serial += handle.join().unwrap() as usize;
+    }
+    assert_eq!(serial, 1);
+}
+
+fn reuse() {
+    struct Shared {
+        barrier: Barrier,
+        data: UnsafeCell<[i32; 2]>,
+    }
+    unsafe impl Sync for Shared {}
+
+    let shared = Arc::new(Shared { barrier: Barrier::new(2), data: UnsafeCell::new([0; 2]) });
+    let shared2 = Arc::clone(&shared);
+    let handle = thread::spawn(move || {
+        unsafe { (*shared2.data.get())[1] = 1 };
+        shared2.barrier.wait();
+        // The barrier orders the writes before it with the reads after it.
+        assert_eq!(unsafe { (*shared2.data.get())[0] }, 1);
+        shared2.barrier.wait();
+    });
+    unsafe { (*shared.data.get())[0] = 1 };
+    shared.barrier.wait();
+    assert_eq!(unsafe { (*shared.data.get())[1] }, 1);
+    shared.barrier.wait();
+    handle.join().unwrap();
+}
+
+fn main() {
+    init_zero();
+    single_thread();
+    one_serial_thread();
+    reuse();
+}
@@ -0,0 +1,156 @@
+// Unfortunately, compiletest_rs does not support 'only-linux',
+// so we need to ignore Windows and macOS instead.
+// ignore-macos: Uses Linux-only APIs
+// ignore-windows: Uses Linux-only APIs
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Semaphore(UnsafeCell<libc::sem_t>);
+
+unsafe impl Send for Semaphore {}
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+    fn new(value: u32) -> Semaphore {
+        unsafe {
+            let mut sem = MaybeUninit::<libc::sem_t>::uninit();
+            assert_eq!(libc::sem_init(sem.as_mut_ptr(), 0, value), 0);
+            Semaphore(UnsafeCell::new(sem.assume_init()))
+        }
+    }
+
+    fn wait(&self) -> i32 {
+        unsafe { libc::sem_wait(self.0.get()) }
+    }
+
+    fn timed_wait(&self, timeout: Duration) -> i32 {
+        let mut now = MaybeUninit::<libc::timespec>::uninit();
+        unsafe {
+            assert_eq!(libc::clock_gettime(libc::CLOCK_REALTIME, now.as_mut_ptr()), 0);
+            let now = now.assume_init();
+            let deadline = Duration::new(now.tv_sec as u64, now.tv_nsec as u32) + timeout;
+            let deadline = libc::timespec {
+                tv_sec: deadline.as_secs() as libc::time_t,
+                tv_nsec: deadline.subsec_nanos() as libc::c_long,
+            };
+            libc::sem_timedwait(self.0.get(), &deadline)
+        }
+    }
+
+    fn try_wait(&self) -> i32 {
+        unsafe { libc::sem_trywait(self.0.get()) }
+    }
+
+    fn post(&self) -> i32 {
+        unsafe { libc::sem_post(self.0.get()) }
+    }
+
+    fn value(&self) -> i32 {
+        let mut value = 0;
+        unsafe {
+            assert_eq!(libc::sem_getvalue(self.0.get(), &mut value), 0);
+        }
+        value
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        unsafe {
+            assert_eq!(libc::sem_destroy(self.0.get()), 0);
+        }
+    }
+}
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn init_too_large() {
+    let mut sem = MaybeUninit::<libc::sem_t>::uninit();
+    unsafe {
+        assert_eq!(libc::sem_init(sem.as_mut_ptr(), 0, i32::max_value() as u32 + 1), -1);
+    }
+    assert_eq!(errno(), libc::EINVAL);
+}
+
+fn try_wait_post() {
+    let sem = Semaphore::new(1);
+    assert_eq!(sem.try_wait(), 0);
+    assert_eq!(sem.try_wait(), -1);
+    assert_eq!(errno(), libc::EAGAIN);
+    assert_eq!(sem.post(), 0);
+    assert_eq!(sem.post(), 0);
+    assert_eq!(sem.value(), 2);
+    assert_eq!(sem.wait(), 0);
+    assert_eq!(sem.value(), 1);
+}
+
+fn wait_timeout() {
+    let sem = Semaphore::new(0);
+    let start = Instant::now();
+    assert_eq!(sem.timed_wait(Duration::from_millis(200)), -1);
+    assert_eq!(errno(), libc::ETIMEDOUT);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+fn wait_invalid_timeout() {
+    let sem = Semaphore::new(0);
+    let deadline = libc::timespec { tv_sec: 0, tv_nsec: 1_000_000_000 };
+    unsafe {
+        assert_eq!(libc::sem_timedwait(sem.0.get(), &deadline), -1);
+    }
+    assert_eq!(errno(), libc::EINVAL);
+}
+
+fn wait_post() {
+    struct Shared {
+        sem: Semaphore,
+        data: UnsafeCell<i32>,
+    }
+    unsafe impl Sync for Shared {}
+
+    let shared = Arc::new(Shared { sem: Semaphore::new(0), data: UnsafeCell::new(0) });
+    let shared2 = Arc::clone(&shared);
+    let handle = thread::spawn(move || {
+        unsafe { *shared2.data.get() = 42 };
+        assert_eq!(shared2.sem.post(), 0);
+    });
+    // Posting the semaphore synchronizes the threads, so this read does not race.
+    assert_eq!(shared.sem.wait(), 0);
+    assert_eq!(unsafe { *shared.data.get() }, 42);
+    assert_eq!(shared.sem.value(), 0);
+    handle.join().unwrap();
+}
+
+fn post_wakes_first_waiter() {
+    let sem = Arc::new(Semaphore::new(0));
+    let waiters: Vec<_> = (0..2)
+        .map(|_| {
+            let sem = Arc::clone(&sem);
+            thread::spawn(move || sem.timed_wait(Duration::from_secs(1)))
+        })
+        .collect();
+    let sem2 = Arc::clone(&sem);
+    let poster = thread::spawn(move || sem2.post());
+    // The unit that is posted goes to the thread that waited first; the other one times out.
+    let results: Vec<_> = waiters.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(poster.join().unwrap(), 0);
+    assert_eq!(results, [0, -1]);
+    assert_eq!(sem.value(), 0);
+}
+
+fn main() {
+    init_too_large();
+    try_wait_post();
+    wait_timeout();
+    wait_invalid_timeout();
+    wait_post();
+    post_wakes_first_waiter();
+}
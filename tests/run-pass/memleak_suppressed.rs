@@ -0,0 +1,18 @@
+// ignore-windows: We do not check leaks on Windows
+// compile-flags: -Zmiri-leak-suppressions=tests/run-pass/memleak_suppressed.supp
+// Allocations made while a suppressed function runs may leak.
+
+mod cache {
+    pub fn global() -> &'static mut Vec<u32> {
+        Box::leak(Box::new(vec![1, 2, 3]))
+    }
+}
+
+fn main() {
+    let v = cache::global();
+    v[0] = 4;
+    assert_eq!(v[..], [4, 2, 3]);
+    // Memory that is freed is not affected.
+    let b = Box::new(5);
+    assert_eq!(*b, 5);
+}
@@ -0,0 +1,13 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-net=deny
+
+use std::io::ErrorKind;
+use std::net::{TcpStream, ToSocketAddrs};
+
+fn main() {
+    let err = ("localhost", 8080).to_socket_addrs().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+    let err = TcpStream::connect("127.0.0.1:8080").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,14 @@
+// ignore-windows: No libc on Windows
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+fn main() {
+    let addrs: Vec<SocketAddr> = ("localhost", 8080).to_socket_addrs().unwrap().collect();
+    assert_eq!(addrs, [
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8080),
+        SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 8080),
+    ]);
+
+    // There is no DNS on the virtual network.
+    assert!(("rust-lang.org", 443).to_socket_addrs().is_err());
+}
@@ -0,0 +1,20 @@
+extern "Rust" {
+    fn miri_nondet_bytes(ptr: *mut u8, len: usize);
+}
+
+fn nondet_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    unsafe { miri_nondet_bytes(bytes.as_mut_ptr(), bytes.len()) };
+    bytes
+}
+
+fn main() {
+    // Asking for nothing is fine.
+    assert!(nondet_bytes(0).is_empty());
+
+    // Without `-Zmiri-nondet-input`, the bytes are random.
+    let a = nondet_bytes(256);
+    let b = nondet_bytes(256);
+    assert!(a.iter().any(|&byte| byte != 0));
+    assert_ne!(a, b);
+}
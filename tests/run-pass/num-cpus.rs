@@ -0,0 +1,30 @@
+// ignore-windows: Uses POSIX APIs
+// compile-flags: -Zmiri-num-cpus=4
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+#[cfg(target_os = "linux")]
+fn test_sched_getaffinity() {
+    use std::mem;
+
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let size = mem::size_of::<libc::cpu_set_t>();
+    assert_eq!(unsafe { libc::sched_getaffinity(0, size, &mut set) }, 0);
+    for cpu in 0..8 {
+        assert_eq!(unsafe { libc::CPU_ISSET(cpu, &set) }, cpu < 4);
+    }
+
+    // Other processes do not exist.
+    assert_eq!(unsafe { libc::sched_getaffinity(1, size, &mut set) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ESRCH));
+}
+
+fn main() {
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }, 4);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }, 4);
+
+    #[cfg(target_os = "linux")]
+    test_sched_getaffinity();
+}
@@ -0,0 +1,4 @@
+fn main() {
+    // Miri reports a fixed process id, so this is stable across runs.
+    assert_eq!(std::process::id(), 1000);
+}
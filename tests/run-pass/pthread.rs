@@ -0,0 +1,180 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::{mem, ptr};
+
+static mut STARTED: u32 = 0;
+static mut SELF_IN_THREAD: Option<libc::pthread_t> = None;
+
+extern "C" fn thread_start(arg: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        STARTED += 1;
+        SELF_IN_THREAD = Some(libc::pthread_self());
+    }
+    arg
+}
+
+fn create() -> libc::pthread_t {
+    unsafe {
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut native, &attr, thread_start, 7 as *mut _), 0);
+        native
+    }
+}
+
+fn test_join() {
+    unsafe {
+        let thread = create();
+        let mut ret = ptr::null_mut();
+        assert_eq!(libc::pthread_join(thread, &mut ret), 0);
+        assert_eq!(ret as usize, 7);
+        assert_eq!(SELF_IN_THREAD, Some(thread));
+        assert_ne!(libc::pthread_self(), thread);
+        // A thread can only be joined once.
+        assert_eq!(libc::pthread_join(thread, ptr::null_mut()), libc::EINVAL);
+        // A thread cannot join itself.
+        assert_eq!(libc::pthread_join(libc::pthread_self(), ptr::null_mut()), libc::EDEADLK);
+    }
+}
+
+fn test_detach() {
+    unsafe {
+        let thread = create();
+        assert_eq!(libc::pthread_detach(thread), 0);
+        assert_eq!(libc::pthread_detach(thread), libc::EINVAL);
+        assert_eq!(libc::pthread_join(thread, ptr::null_mut()), libc::EINVAL);
+    }
+}
+
+fn spawn(
+    start: extern "C" fn(*mut libc::c_void) -> *mut libc::c_void,
+    arg: *mut libc::c_void,
+) -> libc::pthread_t {
+    unsafe {
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut native, &attr, start, arg), 0);
+        native
+    }
+}
+
+fn join(thread: libc::pthread_t) {
+    unsafe {
+        assert_eq!(libc::pthread_join(thread, ptr::null_mut()), 0);
+    }
+}
+
+fn exit_from_nested_call(arg: *mut libc::c_void) -> ! {
+    unsafe { libc::pthread_exit(arg) }
+}
+
+extern "C" fn exit_early(arg: *mut libc::c_void) -> *mut libc::c_void {
+    exit_from_nested_call(arg);
+}
+
+fn test_exit() {
+    unsafe {
+        let thread = spawn(exit_early, 11 as *mut _);
+        let mut ret = ptr::null_mut();
+        assert_eq!(libc::pthread_join(thread, &mut ret), 0);
+        assert_eq!(ret as usize, 11);
+    }
+}
+
+fn new_mutex(kind: libc::c_int, robust: bool) -> Box<libc::pthread_mutex_t> {
+    unsafe {
+        let mut attr: libc::pthread_mutexattr_t = mem::zeroed();
+        assert_eq!(libc::pthread_mutexattr_init(&mut attr), 0);
+        assert_eq!(libc::pthread_mutexattr_settype(&mut attr, kind), 0);
+        #[cfg(target_os = "linux")]
+        {
+            if robust {
+                assert_eq!(libc::pthread_mutexattr_setrobust(&mut attr, libc::PTHREAD_MUTEX_ROBUST), 0);
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        assert!(!robust);
+        let mut mutex: Box<libc::pthread_mutex_t> = Box::new(mem::zeroed());
+        assert_eq!(libc::pthread_mutex_init(&mut *mutex, &attr), 0);
+        assert_eq!(libc::pthread_mutexattr_destroy(&mut attr), 0);
+        mutex
+    }
+}
+
+extern "C" fn lock_and_exit(mutex: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        assert_eq!(libc::pthread_mutex_lock(mutex as *mut libc::pthread_mutex_t), 0);
+    }
+    ptr::null_mut()
+}
+
+extern "C" fn try_foreign_mutex(mutex: *mut libc::c_void) -> *mut libc::c_void {
+    let mutex = mutex as *mut libc::pthread_mutex_t;
+    unsafe {
+        // The main thread holds the lock.
+        assert_eq!(libc::pthread_mutex_trylock(mutex), libc::EBUSY);
+        assert_eq!(libc::pthread_mutex_unlock(mutex), libc::EPERM);
+    }
+    ptr::null_mut()
+}
+
+fn test_mutex_owner() {
+    for &kind in &[libc::PTHREAD_MUTEX_ERRORCHECK, libc::PTHREAD_MUTEX_RECURSIVE] {
+        let mut mutex = new_mutex(kind, false);
+        unsafe {
+            assert_eq!(libc::pthread_mutex_lock(&mut *mutex), 0);
+            join(spawn(try_foreign_mutex, &mut *mutex as *mut _ as *mut _));
+            assert_eq!(libc::pthread_mutex_unlock(&mut *mutex), 0);
+            assert_eq!(libc::pthread_mutex_destroy(&mut *mutex), 0);
+        }
+    }
+
+    // A mutex that a thread released can be locked by another one.
+    let mut mutex = new_mutex(libc::PTHREAD_MUTEX_NORMAL, false);
+    unsafe {
+        assert_eq!(libc::pthread_mutex_lock(&mut *mutex), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut *mutex), 0);
+        join(spawn(lock_and_exit, &mut *mutex as *mut _ as *mut _));
+        // The thread terminated without unlocking the mutex, so it stays locked forever.
+        assert_eq!(libc::pthread_mutex_trylock(&mut *mutex), libc::EBUSY);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn test_mutex_robust() {
+    let mut mutex = new_mutex(libc::PTHREAD_MUTEX_ERRORCHECK, true);
+    unsafe {
+        join(spawn(lock_and_exit, &mut *mutex as *mut _ as *mut _));
+        // The owner terminated, so we get the lock, but have to repair the state it protects.
+        assert_eq!(libc::pthread_mutex_lock(&mut *mutex), libc::EOWNERDEAD);
+        assert_eq!(libc::pthread_mutex_consistent(&mut *mutex), 0);
+        assert_eq!(libc::pthread_mutex_consistent(&mut *mutex), libc::EINVAL);
+        assert_eq!(libc::pthread_mutex_unlock(&mut *mutex), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut *mutex), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&mut *mutex), 0);
+
+        // Releasing the mutex without repairing it makes it unusable.
+        join(spawn(lock_and_exit, &mut *mutex as *mut _ as *mut _));
+        assert_eq!(libc::pthread_mutex_trylock(&mut *mutex), libc::EOWNERDEAD);
+        assert_eq!(libc::pthread_mutex_unlock(&mut *mutex), 0);
+        assert_eq!(libc::pthread_mutex_lock(&mut *mutex), libc::ENOTRECOVERABLE);
+        assert_eq!(libc::pthread_mutex_destroy(&mut *mutex), 0);
+    }
+}
+
+fn main() {
+    test_join();
+    test_detach();
+    test_exit();
+    test_mutex_owner();
+    #[cfg(target_os = "linux")]
+    test_mutex_robust();
+    unsafe {
+        // The detached thread never ran, because nobody waited for it.
+        assert_eq!(STARTED, 1);
+    }
+}
@@ -0,0 +1,59 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::ptr;
+
+static mut KEY: libc::pthread_key_t = 0;
+static mut DTOR_ARGS: Vec<usize> = Vec::new();
+
+unsafe extern "C" fn dtor(ptr: *mut libc::c_void) {
+    // The value is NULL by the time the destructor runs.
+    assert!(libc::pthread_getspecific(KEY).is_null());
+    DTOR_ARGS.push(ptr as usize);
+}
+
+extern "C" fn thread_start(arg: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        assert!(libc::pthread_getspecific(KEY).is_null());
+        assert_eq!(libc::pthread_setspecific(KEY, 7 as *const libc::c_void), 0);
+        assert_eq!(libc::pthread_setspecific(KEY, arg), 0);
+    }
+    ptr::null_mut()
+}
+
+fn run_thread(arg: usize) {
+    unsafe {
+        let mut thread: libc::pthread_t = std::mem::zeroed();
+        let attr: libc::pthread_attr_t = std::mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut thread, &attr, thread_start, arg as *mut _), 0);
+        assert_eq!(libc::pthread_join(thread, ptr::null_mut()), 0);
+    }
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::pthread_key_create(&mut KEY, Some(dtor)), 0);
+        run_thread(42);
+        assert_eq!(DTOR_ARGS, [42]);
+        // A thread that sets the value back to NULL gets no destructor call.
+        run_thread(0);
+        assert_eq!(DTOR_ARGS, [42]);
+
+        // A deleted key is reused, and its values are NULL again.
+        let old_key = KEY;
+        assert_eq!(libc::pthread_setspecific(KEY, 7 as *const libc::c_void), 0);
+        assert_eq!(libc::pthread_key_delete(KEY), 0);
+        assert_eq!(libc::pthread_key_delete(KEY), libc::EINVAL);
+        assert_eq!(libc::pthread_key_create(&mut KEY, Some(dtor)), 0);
+        assert_eq!(KEY, old_key);
+        assert!(libc::pthread_getspecific(KEY).is_null());
+
+        // Deleting a key does not call its destructor.
+        assert_eq!(libc::pthread_setspecific(KEY, 9 as *const libc::c_void), 0);
+        assert_eq!(libc::pthread_key_delete(KEY), 0);
+        assert_eq!(DTOR_ARGS, [42]);
+    }
+}
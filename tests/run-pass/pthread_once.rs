@@ -0,0 +1,58 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::thread;
+
+static mut ONCE: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+static mut INITIALIZED: u32 = 0;
+
+extern "C" fn init() {
+    unsafe {
+        INITIALIZED += 1;
+    }
+}
+
+fn initialized() -> u32 {
+    unsafe {
+        assert_eq!(libc::pthread_once(&mut ONCE, init), 0);
+        INITIALIZED
+    }
+}
+
+static mut OUTER: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+static mut INNER: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+static mut NESTED: Vec<&str> = Vec::new();
+
+extern "C" fn init_outer() {
+    unsafe {
+        NESTED.push("outer");
+        assert_eq!(libc::pthread_once(&mut INNER, init_inner), 0);
+        NESTED.push("outer done");
+    }
+}
+
+extern "C" fn init_inner() {
+    unsafe {
+        NESTED.push("inner");
+    }
+}
+
+fn main() {
+    // The initialization happens before all the calls return, also in other threads.
+    let handles: Vec<_> = (0..3).map(|_| thread::spawn(initialized)).collect();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+    assert_eq!(initialized(), 1);
+
+    // An initialization routine can run the initialization of another object.
+    unsafe {
+        assert_eq!(libc::pthread_once(&mut OUTER, init_outer), 0);
+        assert_eq!(libc::pthread_once(&mut INNER, init_inner), 0);
+        assert_eq!(libc::pthread_once(&mut OUTER, init_outer), 0);
+        assert_eq!(NESTED, ["outer", "inner", "outer done"]);
+    }
+}
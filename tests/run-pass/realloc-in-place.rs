@@ -0,0 +1,36 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn main() {
+    // `realloc` sometimes resizes in place and sometimes moves, whether the allocation shrinks
+    // or grows.
+    for &(old_size, new_size) in &[(16, 8), (16, 32)] {
+        let (mut saw_in_place, mut saw_move) = (false, false);
+        for _ in 0..64 {
+            unsafe {
+                let p = libc::malloc(old_size);
+                let old_addr = p as usize;
+                let q = libc::realloc(p, new_size);
+                if q as usize == old_addr {
+                    saw_in_place = true;
+                } else {
+                    saw_move = true;
+                }
+                libc::free(q);
+            }
+        }
+        assert!(saw_in_place && saw_move);
+    }
+
+    // The contents survive either way.
+    unsafe {
+        let p = libc::malloc(4) as *mut u8;
+        p.copy_from([1, 2, 3, 4].as_ptr(), 4);
+        let q = libc::realloc(p as *mut libc::c_void, 2) as *mut u8;
+        assert_eq!(std::slice::from_raw_parts(q, 2), &[1, 2]);
+        libc::free(q as *mut libc::c_void);
+    }
+}
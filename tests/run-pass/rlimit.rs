@@ -0,0 +1,37 @@
+// ignore-windows: No libc on Windows
+// compile-flags: -Zmiri-rlimit=NOFILE=256:512
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+fn getrlimit(resource: i32) -> libc::rlimit {
+    let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    assert_eq!(unsafe { libc::getrlimit(resource as _, &mut rlim) }, 0);
+    rlim
+}
+
+fn main() {
+    let stack = getrlimit(libc::RLIMIT_STACK as i32);
+    assert_eq!(stack.rlim_cur, 8 * 1024 * 1024);
+    assert_eq!(stack.rlim_max, libc::RLIM_INFINITY);
+
+    let nofile = getrlimit(libc::RLIMIT_NOFILE as i32);
+    assert_eq!((nofile.rlim_cur, nofile.rlim_max), (256, 512));
+
+    // Lowering the limits is observed by later calls.
+    let new = libc::rlimit { rlim_cur: 128, rlim_max: 256 };
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &new) }, 0);
+    let nofile = getrlimit(libc::RLIMIT_NOFILE as i32);
+    assert_eq!((nofile.rlim_cur, nofile.rlim_max), (128, 256));
+
+    // The hard limit cannot be raised again.
+    let new = libc::rlimit { rlim_cur: 128, rlim_max: 512 };
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &new) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+
+    // The soft limit cannot exceed the hard limit.
+    let new = libc::rlimit { rlim_cur: 512, rlim_max: 256 };
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &new) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+}
@@ -0,0 +1,72 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::sync::{Arc, RwLock};
+use std::{mem, ptr, thread};
+
+fn test_std() {
+    let lock = Arc::new(RwLock::new(0));
+    {
+        let a = lock.read().unwrap();
+        let b = lock.read().unwrap();
+        assert_eq!(*a + *b, 0);
+        assert!(lock.try_write().is_err());
+    }
+    {
+        let mut guard = lock.write().unwrap();
+        *guard += 1;
+        assert!(lock.try_read().is_err());
+        assert!(lock.try_write().is_err());
+    }
+
+    let reader = {
+        let lock = Arc::clone(&lock);
+        thread::spawn(move || *lock.read().unwrap())
+    };
+    assert_eq!(reader.join().unwrap(), 1);
+}
+
+extern "C" fn unlock_foreign(rwlock: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        // The main thread holds the lock, this thread does not.
+        assert_eq!(libc::pthread_rwlock_unlock(rwlock as *mut libc::pthread_rwlock_t), libc::EPERM);
+    }
+    ptr::null_mut()
+}
+
+fn test_libc() {
+    unsafe {
+        let mut rwlock: libc::pthread_rwlock_t = mem::zeroed();
+        assert_eq!(libc::pthread_rwlock_init(&mut rwlock, ptr::null()), 0);
+
+        assert_eq!(libc::pthread_rwlock_rdlock(&mut rwlock), 0);
+        assert_eq!(libc::pthread_rwlock_tryrdlock(&mut rwlock), 0);
+        assert_eq!(libc::pthread_rwlock_trywrlock(&mut rwlock), libc::EBUSY);
+
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        let arg = &mut rwlock as *mut libc::pthread_rwlock_t as *mut libc::c_void;
+        assert_eq!(libc::pthread_create(&mut native, &attr, unlock_foreign, arg), 0);
+        assert_eq!(libc::pthread_join(native, ptr::null_mut()), 0);
+
+        assert_eq!(libc::pthread_rwlock_unlock(&mut rwlock), 0);
+        assert_eq!(libc::pthread_rwlock_unlock(&mut rwlock), 0);
+        assert_eq!(libc::pthread_rwlock_unlock(&mut rwlock), libc::EPERM);
+
+        assert_eq!(libc::pthread_rwlock_wrlock(&mut rwlock), 0);
+        assert_eq!(libc::pthread_rwlock_wrlock(&mut rwlock), libc::EDEADLK);
+        assert_eq!(libc::pthread_rwlock_rdlock(&mut rwlock), libc::EDEADLK);
+        assert_eq!(libc::pthread_rwlock_tryrdlock(&mut rwlock), libc::EBUSY);
+        assert_eq!(libc::pthread_rwlock_unlock(&mut rwlock), 0);
+
+        assert_eq!(libc::pthread_rwlock_destroy(&mut rwlock), 0);
+    }
+}
+
+fn main() {
+    test_std();
+    test_libc();
+}
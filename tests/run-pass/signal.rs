@@ -0,0 +1,73 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::mem;
+use std::ptr;
+
+extern "C" fn handler(_signum: libc::c_int) {
+    unreachable!("signals are never delivered");
+}
+
+fn test_signal() {
+    // This is what the standard library does at startup.
+    assert_ne!(unsafe { libc::signal(libc::SIGPIPE, libc::SIG_IGN) }, libc::SIG_ERR);
+    assert_eq!(unsafe { libc::signal(libc::SIGPIPE, libc::SIG_IGN) }, libc::SIG_IGN);
+
+    let old = unsafe { libc::signal(libc::SIGUSR1, handler as libc::sighandler_t) };
+    assert_eq!(old, libc::SIG_DFL);
+    let old = unsafe { libc::signal(libc::SIGUSR1, libc::SIG_DFL) };
+    assert_eq!(old, handler as libc::sighandler_t);
+
+    // `SIGKILL` cannot be caught.
+    assert_eq!(unsafe { libc::signal(libc::SIGKILL, libc::SIG_IGN) }, libc::SIG_ERR);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+}
+
+fn test_sigaction() {
+    unsafe {
+        let mut act: libc::sigaction = mem::zeroed();
+        act.sa_sigaction = handler as libc::sighandler_t;
+        act.sa_flags = libc::SA_RESTART;
+        assert_eq!(libc::sigaction(libc::SIGUSR2, &act, ptr::null_mut()), 0);
+
+        // Install the default action again, and get back the one installed above.
+        let mut old: libc::sigaction = mem::zeroed();
+        act.sa_sigaction = libc::SIG_DFL;
+        act.sa_flags = 0;
+        assert_eq!(libc::sigaction(libc::SIGUSR2, &act, &mut old), 0);
+        assert_eq!(old.sa_sigaction, handler as libc::sighandler_t);
+        assert_eq!(old.sa_flags, libc::SA_RESTART);
+
+        // Querying does not change anything.
+        assert_eq!(libc::sigaction(libc::SIGUSR2, ptr::null(), &mut old), 0);
+        assert_eq!(old.sa_sigaction, libc::SIG_DFL);
+        assert_eq!(libc::signal(libc::SIGUSR2, libc::SIG_IGN), libc::SIG_DFL);
+
+        assert_eq!(libc::sigaction(0, ptr::null(), &mut old), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+    }
+}
+
+fn test_sigaltstack() {
+    unsafe {
+        let mut buf = vec![0u8; libc::SIGSTKSZ];
+        let mut stack: libc::stack_t = mem::zeroed();
+        stack.ss_sp = buf.as_mut_ptr().cast();
+        stack.ss_size = buf.len();
+        assert_eq!(libc::sigaltstack(&stack, ptr::null_mut()), 0);
+
+        let mut old: libc::stack_t = mem::zeroed();
+        assert_eq!(libc::sigaltstack(ptr::null(), &mut old), 0);
+        assert_eq!(old.ss_sp, stack.ss_sp);
+        assert_eq!(old.ss_size, buf.len());
+    }
+}
+
+fn main() {
+    test_signal();
+    test_sigaction();
+    test_sigaltstack();
+}
@@ -0,0 +1,19 @@
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+fn main() {
+    // With isolation, sleeping just advances the virtual clock, so this does not take an hour.
+    let start = SystemTime::now();
+    thread::sleep(Duration::from_secs(3600));
+    assert!(SystemTime::now().duration_since(start).unwrap() >= Duration::from_secs(3600));
+
+    test_instant();
+}
+
+fn test_instant() {
+    use std::time::Instant;
+
+    let start = Instant::now();
+    thread::sleep(Duration::from_millis(1500));
+    assert!(start.elapsed() >= Duration::from_millis(1500));
+}
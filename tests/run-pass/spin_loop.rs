@@ -0,0 +1,19 @@
+use std::sync::atomic::{spin_loop_hint, AtomicBool, Ordering};
+use std::thread;
+
+/// Spin loops let the threads they wait for run at their yield points, or once they are recognized
+/// without one.
+fn spin_until_set(yield_point: fn()) {
+    let flag = Box::leak(Box::new(AtomicBool::new(false)));
+    let handle = thread::spawn(move || flag.store(true, Ordering::Release));
+    while !flag.load(Ordering::Acquire) {
+        yield_point();
+    }
+    handle.join().unwrap();
+}
+
+fn main() {
+    spin_until_set(spin_loop_hint);
+    spin_until_set(thread::yield_now);
+    spin_until_set(|| {});
+}
@@ -0,0 +1,12 @@
+// compile-flags: -Zmiri-atomic-stats
+
+use std::sync::atomic::spin_loop_hint;
+
+fn main() {
+    // Exponential backoff, as used by lock-free data structures.
+    for step in 0..2 {
+        for _ in 0..(1 << step) {
+            spin_loop_hint();
+        }
+    }
+}
@@ -0,0 +1,22 @@
+// compile-flags: -Zmiri-disable-stacked-borrows-for=exempt::buggy
+// Code below `buggy` is not checked, the rest of the program is.
+
+mod buggy {
+    pub fn alias(x: &mut i32) -> i32 {
+        let raw = x as *mut i32;
+        let a = unsafe { &mut *raw };
+        let b = unsafe { &mut *raw };
+        *a = 1;
+        *b = 2;
+        // Using `a` again is a Stacked Borrows violation.
+        *a
+    }
+}
+
+fn main() {
+    let mut x = 0;
+    assert_eq!(buggy::alias(&mut x), 2);
+    let r = &mut x;
+    *r += 1;
+    assert_eq!(x, 3);
+}
@@ -0,0 +1,45 @@
+#![feature(untagged_unions)]
+#![allow(dead_code)]
+// The `UnsafeCell`s inside enums and unions are found at their actual offsets.
+use std::cell::{Cell, RefCell};
+
+fn main() {
+    option_cell();
+    result_refcell();
+    union_cell();
+}
+
+fn option_cell() {
+    let x = Some((1u32, Cell::new(2u32)));
+    let r1 = &x;
+    let r2 = &x;
+    let first = match r1 {
+        Some((first, _)) => first,
+        None => unreachable!(),
+    };
+    r2.as_ref().unwrap().1.set(3);
+    assert_eq!(*first, 1);
+    assert_eq!(r1.as_ref().unwrap().1.get(), 3);
+}
+
+fn result_refcell() {
+    let x: Result<RefCell<Vec<u8>>, u64> = Ok(RefCell::new(vec![]));
+    let r = &x;
+    if let Ok(cell) = r {
+        cell.borrow_mut().push(1);
+    }
+    assert!(r.is_ok());
+    assert_eq!(*x.as_ref().unwrap().borrow(), [1]);
+}
+
+union U {
+    int: u32,
+    cell: Cell<u16>,
+}
+
+fn union_cell() {
+    let u = U { int: 0 };
+    let r = &u;
+    unsafe { r.cell.set(7) };
+    assert_eq!(unsafe { r.cell.get() }, 7);
+}
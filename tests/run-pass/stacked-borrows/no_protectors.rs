@@ -0,0 +1,15 @@
+// compile-flags: -Zmiri-no-protectors
+// Without protectors, references passed to a function may be invalidated while it runs,
+// as long as they are not used afterwards.
+
+fn write_while_shared(x: &i32, y: *mut i32) {
+    unsafe { *y = 2 };
+    let _ = x;
+}
+
+fn main() {
+    let mut v = 1;
+    let raw = &mut v as *mut i32;
+    write_while_shared(unsafe { &*raw }, raw);
+    assert_eq!(v, 2);
+}
@@ -0,0 +1,22 @@
+// compile-flags: -Zmiri-permissive-provenance -Zmiri-raw-pointer-tagging=on
+// Pointers cast from integers may use the items of all pointers that were cast to integers.
+fn main() {
+    let mut x = 0i32;
+    let addr = &mut x as *mut i32 as usize;
+    let ptr = addr as *mut i32;
+    unsafe { *ptr = 1 };
+    assert_eq!(x, 1);
+
+    let b = Box::new(5);
+    let addr = Box::into_raw(b) as usize;
+    let b = unsafe { Box::from_raw(addr as *mut i32) };
+    assert_eq!(*b, 5);
+
+    // Two exposed pointers to the same location: the cast pointer may act like either one.
+    let mut y = [0u8; 2];
+    let first = &mut y[0] as *mut u8 as usize;
+    let whole = &mut y as *mut [u8; 2] as usize;
+    unsafe { *(first as *mut u8) = 1 };
+    unsafe { (*(whole as *mut [u8; 2]))[1] = 2 };
+    assert_eq!(y, [1, 2]);
+}
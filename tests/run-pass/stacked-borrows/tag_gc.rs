@@ -0,0 +1,49 @@
+// compile-flags: -Zmiri-tag-gc=1 -Zmiri-raw-pointer-tagging=on
+// Collecting after every basic block must not remove items of tags that are still in use.
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+fn main() {
+    pointers_in_memory();
+    pointers_in_locals();
+    interior_mutability();
+    two_phase();
+}
+
+fn pointers_in_memory() {
+    let mut values = vec![1, 2, 3];
+    let raw: Vec<*mut i32> = values.iter_mut().map(|x| x as *mut i32).collect();
+    let boxed = Box::new(raw);
+    for &ptr in boxed.iter() {
+        unsafe { *ptr += 1 };
+    }
+    assert_eq!(values, [2, 3, 4]);
+}
+
+fn pointers_in_locals() {
+    let mut x = 0;
+    let r = &mut x;
+    let raw = r as *mut i32;
+    for i in 0..10 {
+        unsafe { *raw += i };
+    }
+    *r += 1;
+    assert_eq!(x, 46);
+}
+
+fn interior_mutability() {
+    let cell = RefCell::new(HashMap::new());
+    for i in 0..10 {
+        cell.borrow_mut().insert(i, i * 2);
+    }
+    let map = cell.borrow();
+    assert_eq!(map[&3], 6);
+}
+
+fn two_phase() {
+    let mut v = vec![];
+    for i in 0..5 {
+        v.push(v.len() + i);
+    }
+    assert_eq!(v, [0, 2, 4, 6, 8]);
+}
@@ -0,0 +1,13 @@
+// compile-flags: -Zmiri-disable-isolation -Zmiri-start-time=1000000000
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Even with the host clock, the realtime clock starts at the given time...
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    assert!(since_epoch >= Duration::from_secs(1_000_000_000));
+    assert!(since_epoch < Duration::from_secs(1_000_000_000 + 3600));
+    // ...and moves forward from there.
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(SystemTime::now().duration_since(UNIX_EPOCH).unwrap() >= since_epoch + Duration::from_millis(10));
+}
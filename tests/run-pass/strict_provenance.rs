@@ -0,0 +1,11 @@
+// compile-flags: -Zmiri-strict-provenance
+// Casting pointers to integers is fine, as long as no integer is used as a pointer.
+fn main() {
+    let v = [1u32, 2, 3];
+    let base = v.as_ptr();
+    let addr = base as usize;
+    assert_eq!(addr % std::mem::align_of::<u32>(), 0);
+    let second = base.wrapping_offset(1);
+    assert_eq!(second as usize - addr, 4);
+    assert_eq!(unsafe { *second }, 2);
+}
@@ -0,0 +1,71 @@
+// ignore-windows: Uses POSIX APIs
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::ffi::CStr;
+use std::ptr;
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn strtol(s: &CStr, base: i32) -> (libc::c_long, usize) {
+    let mut end = ptr::null_mut();
+    let val = unsafe { libc::strtol(s.as_ptr(), &mut end, base) };
+    (val, end as usize - s.as_ptr() as usize)
+}
+
+fn strtoul(s: &CStr, base: i32) -> (libc::c_ulong, usize) {
+    let mut end = ptr::null_mut();
+    let val = unsafe { libc::strtoul(s.as_ptr(), &mut end, base) };
+    (val, end as usize - s.as_ptr() as usize)
+}
+
+fn strtod(s: &CStr) -> (f64, usize) {
+    let mut end = ptr::null_mut();
+    let val = unsafe { libc::strtod(s.as_ptr(), &mut end) };
+    (val, end as usize - s.as_ptr() as usize)
+}
+
+fn c(s: &[u8]) -> &CStr {
+    CStr::from_bytes_with_nul(s).unwrap()
+}
+
+fn main() {
+    assert_eq!(strtol(c(b"  -42 apples\0"), 10), (-42, 5));
+    assert_eq!(strtol(c(b"0x1f\0"), 0), (31, 4));
+    assert_eq!(strtol(c(b"0755\0"), 0), (0o755, 4));
+    assert_eq!(strtol(c(b"zz\0"), 36), (35 * 36 + 35, 2));
+    // Without a number, `endptr` is the start of the string.
+    assert_eq!(strtol(c(b"  +\0"), 10), (0, 0));
+    // `endptr` may be NULL.
+    assert_eq!(unsafe { libc::strtol(c(b"7\0").as_ptr(), ptr::null_mut(), 10) }, 7);
+
+    assert_eq!(strtol(c(b"99999999999999999999999\0"), 10), (libc::c_long::max_value(), 23));
+    assert_eq!(errno(), libc::ERANGE);
+    assert_eq!(strtol(c(b"-99999999999999999999999\0"), 10), (libc::c_long::min_value(), 24));
+    assert_eq!(errno(), libc::ERANGE);
+
+    assert_eq!(strtol(c(b"1\0"), 1), (0, 0));
+    assert_eq!(errno(), libc::EINVAL);
+
+    assert_eq!(strtoul(c(b"18446744073709551615\0"), 10).0, u64::max_value() as libc::c_ulong);
+    // Negative numbers wrap around.
+    assert_eq!(strtoul(c(b"-1\0"), 10), (libc::c_ulong::max_value(), 2));
+
+    assert_eq!(strtod(c(b" 1.5e3x\0")), (1500.0, 6));
+    assert_eq!(strtod(c(b"-.25\0")), (-0.25, 4));
+    assert_eq!(strtod(c(b"1.e\0")), (1.0, 2));
+    assert_eq!(strtod(c(b"-Infinity\0")), (std::f64::NEG_INFINITY, 9));
+    assert!(strtod(c(b"nan(123)\0")).0.is_nan());
+    assert_eq!(strtod(c(b"abc\0")), (0.0, 0));
+    // Parsing does not depend on the locale, the decimal point is always a `.`.
+    assert_eq!(strtod(c(b"1,5\0")), (1.0, 1));
+
+    assert_eq!(strtod(c(b"1e999\0")), (std::f64::INFINITY, 5));
+    assert_eq!(errno(), libc::ERANGE);
+    assert_eq!(strtod(c(b"1e-999\0")), (0.0, 6));
+    assert_eq!(errno(), libc::ERANGE);
+}
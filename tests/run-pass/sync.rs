@@ -8,11 +8,25 @@ fn main() {
     drop(m.lock());
     drop(m);
 
-    #[cfg(not(target_os = "windows"))] // TODO: implement RwLock on Windows
-    {
-        let rw = sync::RwLock::new(0);
-        drop(rw.read());
-        drop(rw.write());
-        drop(rw);
-    }
+    let rw = sync::RwLock::new(0);
+    drop(rw.read());
+    drop(rw.write());
+    drop(rw);
+
+    check_condvar_timeout();
+}
+
+fn check_condvar_timeout() {
+    use std::time::{Duration, Instant};
+
+    let m = sync::Mutex::new(());
+    let cv = sync::Condvar::new();
+    let now = Instant::now();
+    // Nobody can notify us, so this times out, measured by the monotonic clock.
+    let (_guard, timeout) = cv.wait_timeout(m.lock().unwrap(), Duration::from_millis(100)).unwrap();
+    assert!(timeout.timed_out());
+    assert!(now.elapsed() >= Duration::from_millis(100));
+    // Notifying without waiters does nothing.
+    cv.notify_one();
+    cv.notify_all();
 }
@@ -0,0 +1,81 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+
+#[repr(C)]
+struct CriticalSection([usize; 5]);
+
+extern "system" {
+    fn InitializeCriticalSection(section: *mut CriticalSection);
+    fn EnterCriticalSection(section: *mut CriticalSection);
+    fn TryEnterCriticalSection(section: *mut CriticalSection) -> i32;
+    fn LeaveCriticalSection(section: *mut CriticalSection);
+    fn DeleteCriticalSection(section: *mut CriticalSection);
+    fn SleepConditionVariableSRW(cond: *mut usize, lock: *mut usize, ms: u32, flags: u32) -> i32;
+    fn AcquireSRWLockExclusive(lock: *mut usize);
+    fn TryAcquireSRWLockShared(lock: *mut usize) -> u8;
+    fn ReleaseSRWLockExclusive(lock: *mut usize);
+    fn GetLastError() -> u32;
+}
+
+const ERROR_TIMEOUT: u32 = 1460;
+
+fn std_primitives() {
+    let data = Arc::new((Mutex::new(0), Condvar::new(), RwLock::new(0)));
+    let handles: Vec<_> = (1..=3)
+        .map(|i| {
+            let data = Arc::clone(&data);
+            thread::spawn(move || {
+                *data.0.lock().unwrap() += i;
+                *data.2.write().unwrap() += i;
+                data.1.notify_all();
+            })
+        })
+        .collect();
+    // Waiting for the condition variable lets the threads run.
+    let mut sum = data.0.lock().unwrap();
+    while *sum != 6 {
+        sum = data.1.wait(sum).unwrap();
+    }
+    drop(sum);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*data.2.read().unwrap(), 6);
+}
+
+fn critical_section() {
+    unsafe {
+        let mut section = CriticalSection([0; 5]);
+        InitializeCriticalSection(&mut section);
+        EnterCriticalSection(&mut section);
+        // Critical sections are recursive.
+        assert_ne!(TryEnterCriticalSection(&mut section), 0);
+        LeaveCriticalSection(&mut section);
+        LeaveCriticalSection(&mut section);
+        DeleteCriticalSection(&mut section);
+    }
+}
+
+fn srwlock_timeout() {
+    unsafe {
+        let mut lock = 0usize;
+        let mut cond = 0usize;
+        AcquireSRWLockExclusive(&mut lock);
+        assert_eq!(TryAcquireSRWLockShared(&mut lock), 0);
+        // Nobody wakes us up.
+        assert_eq!(SleepConditionVariableSRW(&mut cond, &mut lock, 10, 0), 0);
+        assert_eq!(GetLastError(), ERROR_TIMEOUT);
+        ReleaseSRWLockExclusive(&mut lock);
+    }
+}
+
+fn main() {
+    std_primitives();
+    critical_section();
+    srwlock_timeout();
+}
@@ -0,0 +1,39 @@
+// ignore-windows: Miri does not call `on_tls_callback`, so thread-local destructors do not run on Windows
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct Noisy(&'static str);
+
+impl Drop for Noisy {
+    fn drop(&mut self) {
+        println!("dropping {}", self.0);
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+        // Destructors may initialize other thread-locals, whose destructors then run as well.
+        if self.0 == "first" {
+            LATE.with(|late| *late.borrow_mut() = Some(Noisy("late")));
+        }
+    }
+}
+
+thread_local! {
+    static FIRST: RefCell<Option<Noisy>> = RefCell::new(None);
+    static LATE: RefCell<Option<Noisy>> = RefCell::new(None);
+}
+
+fn main() {
+    FIRST.with(|first| *first.borrow_mut() = Some(Noisy("main")));
+
+    let handle = thread::spawn(|| {
+        FIRST.with(|first| *first.borrow_mut() = Some(Noisy("first")));
+        // The value of the main thread is separate.
+        LATE.with(|late| assert!(late.borrow().is_none()));
+    });
+    handle.join().unwrap();
+    // Both destructors of the thread ran when it terminated.
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+    println!("thread joined");
+}
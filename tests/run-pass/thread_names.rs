@@ -0,0 +1,34 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+use std::ffi::CStr;
+use std::thread;
+
+fn name_of(thread: libc::pthread_t) -> String {
+    let mut buf = [0u8; 16];
+    unsafe {
+        assert_eq!(libc::pthread_getname_np(thread, buf.as_mut_ptr().cast(), buf.len()), 0);
+        CStr::from_ptr(buf.as_ptr().cast()).to_str().unwrap().to_owned()
+    }
+}
+
+fn main() {
+    assert_eq!(name_of(unsafe { libc::pthread_self() }), "main");
+
+    // `Builder::name` tells the platform the name of the new thread.
+    let handle = thread::Builder::new()
+        .name("worker".to_owned())
+        .spawn(|| name_of(unsafe { libc::pthread_self() }))
+        .unwrap();
+    assert_eq!(handle.join().unwrap(), "worker");
+
+    // The buffer has to fit the name and its null terminator.
+    let mut buf = [0u8; 4];
+    let res = unsafe {
+        libc::pthread_getname_np(libc::pthread_self(), buf.as_mut_ptr().cast(), buf.len())
+    };
+    assert_eq!(res, libc::ERANGE);
+}
@@ -0,0 +1,75 @@
+//! The ways of waiting for other threads that Miri supports: a thread that waits only ever needs
+//! threads that did not start yet, which then run on top of it. Waiting for a thread that is
+//! suspended below the active one is reported as a deadlock, see
+//! `compile-fail/thread-join-suspended.rs`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Threads can be joined in any order.
+fn join_in_reverse_order() {
+    let first = thread::spawn(|| 1);
+    let second = thread::spawn(|| 2);
+    assert_eq!(second.join().unwrap(), 2);
+    assert_eq!(first.join().unwrap(), 1);
+}
+
+/// A consumer can wait for a producer that did not start yet.
+fn producer_consumer() {
+    let (sender, receiver) = mpsc::channel();
+    let producer = thread::spawn(move || {
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+    });
+    assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+    producer.join().unwrap();
+}
+
+/// A thread waiting for a condition variable is woken by a thread that starts afterwards.
+fn condvar_notified_later() {
+    let pair = Arc::new((Mutex::new(false), Condvar::new()));
+    let notifier = {
+        let pair = Arc::clone(&pair);
+        thread::spawn(move || {
+            let (ready, condvar) = &*pair;
+            *ready.lock().unwrap() = true;
+            condvar.notify_one();
+        })
+    };
+    let (ready, condvar) = &*pair;
+    let mut guard = ready.lock().unwrap();
+    while !*guard {
+        guard = condvar.wait(guard).unwrap();
+    }
+    drop(guard);
+    notifier.join().unwrap();
+}
+
+/// A thread can wait for a thread that another thread spawned, as long as it did not start yet.
+fn join_thread_spawned_elsewhere() {
+    let spawner = thread::spawn(|| thread::spawn(|| 42));
+    let spawned = spawner.join().unwrap();
+    assert_eq!(spawned.join().unwrap(), 42);
+}
+
+/// A spin loop waits for a thread that did not start yet, with or without a spin loop hint.
+fn spin_until_set() {
+    let flag = Arc::new(AtomicBool::new(false));
+    let setter = {
+        let flag = Arc::clone(&flag);
+        thread::spawn(move || flag.store(true, Ordering::Release))
+    };
+    while !flag.load(Ordering::Acquire) {}
+    setter.join().unwrap();
+}
+
+fn main() {
+    join_in_reverse_order();
+    producer_consumer();
+    condvar_notified_later();
+    join_thread_spawned_elsewhere();
+    spin_until_set();
+}
@@ -0,0 +1,70 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+thread_local! {
+    static COUNTER: Cell<u32> = Cell::new(0);
+}
+
+fn spawn_and_join() {
+    let handle = thread::spawn(|| 42);
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+fn share_data() {
+    let data = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let data = Arc::clone(&data);
+            thread::spawn(move || {
+                data.fetch_add(i, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(data.load(Ordering::SeqCst), 0 + 1 + 2 + 3);
+}
+
+fn nested_spawn() {
+    let outer = thread::spawn(|| {
+        let inner = thread::spawn(|| thread::current().id());
+        let inner_id = inner.join().unwrap();
+        assert_ne!(inner_id, thread::current().id());
+        inner_id
+    });
+    let inner_id = outer.join().unwrap();
+    assert_ne!(inner_id, thread::current().id());
+}
+
+fn thread_locals() {
+    COUNTER.with(|counter| counter.set(1));
+    thread::spawn(|| {
+        // A new thread starts with the initial value.
+        COUNTER.with(|counter| {
+            assert_eq!(counter.get(), 0);
+            counter.set(2);
+        });
+    })
+    .join()
+    .unwrap();
+    COUNTER.with(|counter| assert_eq!(counter.get(), 1));
+}
+
+fn panicking_thread() {
+    let result = thread::spawn(|| {
+        panic!("thread panicked");
+    })
+    .join();
+    assert!(result.is_err());
+}
+
+fn main() {
+    spawn_and_join();
+    share_data();
+    nested_spawn();
+    thread_locals();
+    panicking_thread();
+}
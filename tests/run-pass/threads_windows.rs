@@ -0,0 +1,86 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{ptr, thread};
+
+extern "system" {
+    fn CreateThread(
+        security: *mut u8,
+        stack_size: usize,
+        start: extern "system" fn(*mut u8) -> u32,
+        arg: *mut u8,
+        flags: u32,
+        thread_id: *mut u32,
+    ) -> *mut u8;
+    fn WaitForSingleObject(handle: *mut u8, timeout: u32) -> u32;
+    fn CloseHandle(handle: *mut u8) -> i32;
+    fn GetCurrentThreadId() -> u32;
+    fn ExitThread(code: u32) -> !;
+}
+
+const INFINITE: u32 = 0xFFFFFFFF;
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+
+static mut THREAD_ID: u32 = 0;
+
+extern "system" fn thread_start(arg: *mut u8) -> u32 {
+    unsafe {
+        THREAD_ID = GetCurrentThreadId();
+    }
+    arg as u32
+}
+
+fn raw_threads() {
+    unsafe {
+        let mut id = 0;
+        let handle = CreateThread(ptr::null_mut(), 0, thread_start, 7 as *mut u8, 0, &mut id);
+        assert!(!handle.is_null());
+        assert_ne!(id, GetCurrentThreadId());
+        assert_eq!(WaitForSingleObject(handle, INFINITE), WAIT_OBJECT_0);
+        assert_eq!(THREAD_ID, id);
+        // Waiting again for a terminated thread returns right away.
+        assert_eq!(WaitForSingleObject(handle, 0), WAIT_OBJECT_0);
+        assert_ne!(CloseHandle(handle), 0);
+    }
+}
+
+extern "system" fn exit_early(arg: *mut u8) -> u32 {
+    unsafe { ExitThread(arg as u32) }
+}
+
+fn timeouts_and_exit() {
+    unsafe {
+        let handle =
+            CreateThread(ptr::null_mut(), 0, exit_early, 3 as *mut u8, 0, ptr::null_mut());
+        assert!(!handle.is_null());
+        // The thread did not get to run yet.
+        assert_eq!(WaitForSingleObject(handle, 0), WAIT_TIMEOUT);
+        assert_eq!(WaitForSingleObject(handle, 1000), WAIT_OBJECT_0);
+        assert_ne!(CloseHandle(handle), 0);
+    }
+}
+
+fn std_threads() {
+    let data = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let data = Arc::clone(&data);
+            thread::spawn(move || data.fetch_add(i, Ordering::SeqCst))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(data.load(Ordering::SeqCst), 0 + 1 + 2 + 3);
+}
+
+fn main() {
+    raw_threads();
+    timeouts_and_exit();
+    std_threads();
+}
@@ -0,0 +1,22 @@
+// ignore-windows: No libc on Windows
+
+#![feature(rustc_private)]
+
+extern crate libc;
+
+static mut KEY: libc::pthread_key_t = 0;
+static mut CALLS: u32 = 0;
+
+unsafe extern "C" fn dtor(ptr: *mut libc::c_void) {
+    CALLS += 1;
+    println!("destructor call {}", CALLS);
+    // Set the key again, so that we would be called forever if nobody stopped us.
+    assert_eq!(libc::pthread_setspecific(KEY, ptr), 0);
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::pthread_key_create(&mut KEY, Some(dtor)), 0);
+        assert_eq!(libc::pthread_setspecific(KEY, 1 as *const libc::c_void), 0);
+    }
+}
@@ -0,0 +1,47 @@
+// compile-flags: -Zmiri-tree-borrows
+// Test patterns that Tree Borrows accepts, including some that Stacked Borrows rejects.
+fn main() {
+    read_parent_while_reserved();
+    raw_round_trip();
+    sibling_shared_refs();
+    two_phase_push();
+}
+
+// Stacked Borrows disables `r2` when `r` is read; Tree Borrows keeps it reserved.
+fn read_parent_while_reserved() {
+    let mut x = 0;
+    let r = &mut x;
+    let raw = r as *mut i32;
+    let r2 = unsafe { &mut *raw };
+    let _val = *r;
+    *r2 = 5;
+    assert_eq!(x, 5);
+}
+
+// Raw pointers keep the tag of the reference they come from.
+fn raw_round_trip() {
+    let mut x = [0u8; 4];
+    let r = &mut x;
+    let raw = r.as_mut_ptr();
+    unsafe {
+        *raw.add(1) = 1;
+        let back = &mut *(raw as *mut [u8; 4]);
+        back[2] = 2;
+    }
+    r[3] = 3;
+    assert_eq!(x, [0, 1, 2, 3]);
+}
+
+fn sibling_shared_refs() {
+    let x = 42;
+    let a = &x;
+    let b = &x;
+    assert_eq!(*a + *b, 84);
+}
+
+fn two_phase_push() {
+    let mut v = vec![];
+    v.push(v.len());
+    v.push(v.len());
+    assert_eq!(v, [0, 1]);
+}
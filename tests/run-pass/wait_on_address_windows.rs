@@ -0,0 +1,41 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+extern "system" {
+    fn WaitOnAddress(address: *const u8, compare: *const u8, size: usize, ms: u32) -> i32;
+    fn WakeByAddressSingle(address: *const u8);
+    fn GetLastError() -> u32;
+}
+
+const INFINITE: u32 = 0xFFFFFFFF;
+const ERROR_TIMEOUT: u32 = 1460;
+
+static FLAG: AtomicU32 = AtomicU32::new(0);
+
+fn wait_for_flag(expected: u32, ms: u32) -> i32 {
+    let flag = &FLAG as *const AtomicU32 as *const u8;
+    unsafe { WaitOnAddress(flag, &expected as *const u32 as *const u8, 4, ms) }
+}
+
+fn main() {
+    // The value differs, so there is no waiting.
+    assert_ne!(wait_for_flag(1, INFINITE), 0);
+
+    // Nobody wakes us up.
+    assert_eq!(wait_for_flag(0, 10), 0);
+    assert_eq!(unsafe { GetLastError() }, ERROR_TIMEOUT);
+
+    let handle = thread::spawn(|| {
+        FLAG.store(1, Ordering::Release);
+        unsafe { WakeByAddressSingle(&FLAG as *const AtomicU32 as *const u8) };
+    });
+    while FLAG.load(Ordering::Acquire) == 0 {
+        wait_for_flag(0, INFINITE);
+    }
+    handle.join().unwrap();
+}
@@ -0,0 +1,85 @@
+// The reader threads are spawned before the writers run, and joined only after them, so nothing
+// orders their loads after the stores of the writers except what they synchronize with.
+
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+/// The loads of a thread never go back in the modification order of a location.
+fn coherence() {
+    static X: AtomicUsize = AtomicUsize::new(0);
+    let writer = thread::spawn(|| {
+        for i in 1..=5 {
+            X.store(i, Ordering::Relaxed);
+        }
+    });
+    let reader = thread::spawn(|| {
+        let mut last = 0;
+        for _ in 0..10 {
+            let val = X.load(Ordering::Relaxed);
+            assert!(val >= last && val <= 5);
+            last = val;
+        }
+    });
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
+
+/// An acquire load that reads a release store also sees everything before the store.
+fn message_passing() {
+    static DATA: AtomicUsize = AtomicUsize::new(0);
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let writer = thread::spawn(|| {
+        DATA.store(1, Ordering::Relaxed);
+        FLAG.store(true, Ordering::Release);
+    });
+    let reader = thread::spawn(|| {
+        if FLAG.load(Ordering::Acquire) {
+            assert_eq!(DATA.load(Ordering::Relaxed), 1);
+        }
+    });
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
+
+/// Sequentially consistent loads read the latest sequentially consistent store, and
+/// read-modify-writes always read the latest store.
+fn latest() {
+    static X: AtomicUsize = AtomicUsize::new(0);
+    static Y: AtomicUsize = AtomicUsize::new(0);
+    let writer = thread::spawn(|| {
+        X.store(1, Ordering::SeqCst);
+        Y.store(1, Ordering::Relaxed);
+    });
+    let reader = thread::spawn(|| {
+        assert_eq!(X.load(Ordering::SeqCst), 1);
+        assert_eq!(Y.fetch_add(0, Ordering::Relaxed), 1);
+    });
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
+
+/// Sequentially consistent fences are totally ordered, so the thread whose fence comes second
+/// sees the store before the other fence (the "store buffering" litmus test).
+fn seq_cst_fences() {
+    static X: AtomicUsize = AtomicUsize::new(0);
+    static Y: AtomicUsize = AtomicUsize::new(0);
+    let first = thread::spawn(|| {
+        X.store(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        Y.load(Ordering::Relaxed)
+    });
+    let second = thread::spawn(|| {
+        Y.store(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        X.load(Ordering::Relaxed)
+    });
+    let (x, y) = (second.join().unwrap(), first.join().unwrap());
+    assert!(x == 1 || y == 1);
+}
+
+fn main() {
+    coherence();
+    message_passing();
+    latest();
+    seq_cst_fences();
+}
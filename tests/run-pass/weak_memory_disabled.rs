@@ -0,0 +1,14 @@
+// compile-flags: -Zmiri-disable-weak-memory-emulation
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+static X: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let writer = thread::spawn(|| X.store(1, Ordering::Relaxed));
+    // Nothing orders this load after the store, but it reads the latest store anyway.
+    let reader = thread::spawn(|| X.load(Ordering::Relaxed));
+    writer.join().unwrap();
+    assert_eq!(reader.join().unwrap(), 1);
+}
@@ -0,0 +1,65 @@
+// Unfortunately, compiletest_rs does not support 'only-windows',
+// so we need to ignore Linux and macOS instead.
+// ignore-linux: Uses Windows-only APIs
+// ignore-macos: Uses Windows-only APIs
+
+use std::ptr;
+use std::thread;
+
+extern "system" {
+    fn CreatePipe(read: *mut isize, write: *mut isize, attributes: *mut u8, size: u32) -> i32;
+    fn ReadFile(handle: isize, buf: *mut u8, len: u32, read: *mut u32, overlapped: *mut u8) -> i32;
+    fn WriteFile(handle: isize, buf: *const u8, len: u32, written: *mut u32, overlapped: *mut u8) -> i32;
+    fn CloseHandle(handle: isize) -> i32;
+    fn GetLastError() -> u32;
+}
+
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_BROKEN_PIPE: u32 = 109;
+
+fn main() {
+    unsafe {
+        let (mut read, mut write) = (0, 0);
+        assert_ne!(CreatePipe(&mut read, &mut write, ptr::null_mut(), 0), 0);
+
+        // Data written to one end comes out of the other end.
+        let bytes = b"Hello, pipe!";
+        let mut written = 0;
+        assert_ne!(WriteFile(write, bytes.as_ptr(), bytes.len() as u32, &mut written, ptr::null_mut()), 0);
+        assert_eq!(written as usize, bytes.len());
+        // Reading can happen in smaller chunks.
+        let mut buf = [0u8; 5];
+        let mut read_bytes = 0;
+        assert_ne!(ReadFile(read, buf.as_mut_ptr(), buf.len() as u32, &mut read_bytes, ptr::null_mut()), 0);
+        assert_eq!(&buf[..read_bytes as usize], b"Hello");
+
+        // Once the write end is closed, the rest of the data can still be read...
+        assert_ne!(CloseHandle(write), 0);
+        let mut buf = [0u8; 32];
+        assert_ne!(ReadFile(read, buf.as_mut_ptr(), buf.len() as u32, &mut read_bytes, ptr::null_mut()), 0);
+        assert_eq!(&buf[..read_bytes as usize], b", pipe!");
+        // ...after which reading reports a broken pipe.
+        assert_eq!(ReadFile(read, buf.as_mut_ptr(), buf.len() as u32, &mut read_bytes, ptr::null_mut()), 0);
+        assert_eq!(GetLastError(), ERROR_BROKEN_PIPE);
+        assert_eq!(read_bytes, 0);
+
+        assert_ne!(CloseHandle(read), 0);
+        // Closing it again fails.
+        assert_eq!(CloseHandle(read), 0);
+        assert_eq!(GetLastError(), ERROR_INVALID_HANDLE);
+
+        // Reading from an empty pipe waits for another thread to write to it.
+        let (mut read, mut write) = (0, 0);
+        assert_ne!(CreatePipe(&mut read, &mut write, ptr::null_mut(), 0), 0);
+        let writer = thread::spawn(move || {
+            let mut written = 0;
+            assert_ne!(WriteFile(write, b"ping".as_ptr(), 4, &mut written, ptr::null_mut()), 0);
+            assert_ne!(CloseHandle(write), 0);
+        });
+        let mut buf = [0u8; 8];
+        assert_ne!(ReadFile(read, buf.as_mut_ptr(), buf.len() as u32, &mut read_bytes, ptr::null_mut()), 0);
+        assert_eq!(&buf[..read_bytes as usize], b"ping");
+        writer.join().unwrap();
+        assert_ne!(CloseHandle(read), 0);
+    }
+}